@@ -0,0 +1,73 @@
+//! Compares adding large batches to a window at once, which merges each run
+//! into the window's aggregates, with adding the same values one at a time.
+//!
+//! Run with `cargo bench --bench ingest`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use trading_service::{Batch, TradingDataBuffer};
+
+const BATCH: usize = 10_000;
+const BATCHES: usize = 50;
+
+fn batches(weighted: bool) -> Vec<Batch> {
+    (0..BATCHES)
+        .map(|b| {
+            let values: Vec<f64> = (0..BATCH).map(|i| 100.0 + (((b * BATCH + i) * 7919) % 1000) as f64 * 0.01).collect();
+            let mut batch = Batch::new(values);
+            if weighted {
+                batch.weights = Some((0..BATCH).map(|i| 1.0 + (i % 5) as f64).collect());
+                batch.volumes = Some((0..BATCH).map(|i| (i % 100) as f64).collect());
+            }
+            batch
+        })
+        .collect()
+}
+
+fn single(batch: &Batch, i: usize) -> Batch {
+    Batch {
+        weights: batch.weights.as_ref().map(|weights| vec![weights[i]]),
+        volumes: batch.volumes.as_ref().map(|volumes| vec![volumes[i]]),
+        ..Batch::new(vec![batch.values[i]])
+    }
+}
+
+/// Time to add every batch to a fresh window of `capacity`, either whole
+/// or split into single values.
+fn time(capacity: usize, batches: &[Batch], whole: bool) -> Duration {
+    let singles: Vec<Batch> = match whole {
+        true => Vec::new(),
+        false => batches.iter().flat_map(|batch| (0..batch.values.len()).map(move |i| single(batch, i))).collect(),
+    };
+    let mut buffer = TradingDataBuffer::new(capacity);
+    let started = Instant::now();
+    if whole {
+        batches.iter().for_each(|batch| buffer.add_points(batch));
+    } else {
+        singles.iter().for_each(|single| buffer.add_points(single));
+    }
+    black_box(buffer.get_stats());
+    started.elapsed()
+}
+
+fn main() {
+    println!("{:>10} {:>9} {:>14} {:>14} {:>8}", "window", "fields", "single ns/val", "batch ns/val", "speedup");
+    for capacity in [1_000, 10_000, 100_000] {
+        for weighted in [false, true] {
+            let batches = batches(weighted);
+            let values = (BATCH * BATCHES) as f64;
+            // Best of three, after a warm-up run.
+            let best = |whole| (0..4).map(|_| time(capacity, &batches, whole)).skip(1).min().unwrap();
+            let (single, batch) = (best(false), best(true));
+            println!(
+                "{:>10} {:>9} {:>14.1} {:>14.1} {:>7.1}x",
+                capacity,
+                if weighted { "weighted" } else { "values" },
+                single.as_nanos() as f64 / values,
+                batch.as_nanos() as f64 / values,
+                single.as_secs_f64() / batch.as_secs_f64(),
+            );
+        }
+    }
+}
@@ -0,0 +1,6 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Bundled so building doesn't need protoc installed.
+    std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path()?);
+    tonic_build::configure().build_client(false).compile_protos(&["proto/trading.proto"], &["proto"])?;
+    Ok(())
+}
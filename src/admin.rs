@@ -0,0 +1,116 @@
+//! Operator endpoints under `/admin` for changing the service's settings and
+//! namespace limits at runtime, without a restart losing the in-memory
+//! windows.
+//!
+//! Off unless `ADMIN_TOKEN` is set; requests then need an
+//! `Authorization: Bearer <token>` header with that token.
+
+use std::future::{ready, Ready};
+
+use actix_web::dev::Payload;
+use actix_web::error::InternalError;
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::{web, FromRequest, HttpRequest, HttpResponse, Responder};
+use trading_service::namespaces::{NamespaceLimits, Namespaces};
+use trading_service::settings::Settings;
+use trading_service::ServiceError;
+
+use crate::body::{respond, Body};
+use crate::error_response;
+
+const TOKEN_VAR: &str = "ADMIN_TOKEN";
+
+/// The token operator requests must carry, `None` when the admin API is off.
+pub struct AdminToken(Option<String>);
+
+impl AdminToken {
+    pub fn from_env() -> AdminToken {
+        AdminToken(std::env::var(TOKEN_VAR).ok().filter(|token| !token.is_empty()))
+    }
+}
+
+/// Compares in time independent of where the inputs first differ, so the
+/// token can't be guessed byte by byte from response times.
+fn same_token(given: &[u8], expected: &[u8]) -> bool {
+    given.len() == expected.len() && given.iter().zip(expected).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Proof that the request carries the admin token.
+pub struct Admin;
+
+impl Admin {
+    fn authorize(req: &HttpRequest) -> Result<Admin, ServiceError> {
+        let token = req.app_data::<web::Data<AdminToken>>().and_then(|token| token.0.as_deref());
+        let Some(expected) = token else {
+            return Err(ServiceError::NotFound(format!("The admin API is off. Set {} to turn it on.", TOKEN_VAR)));
+        };
+        let given = req
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        match given {
+            Some(given) if same_token(given.as_bytes(), expected.as_bytes()) => Ok(Admin),
+            _ => Err(ServiceError::Unauthorized("A valid admin token is required".to_string())),
+        }
+    }
+}
+
+impl FromRequest for Admin {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(Admin::authorize(req).map_err(|e| {
+            let response = error_response(e.clone());
+            InternalError::from_response(e, response).into()
+        }))
+    }
+}
+
+async fn get_settings(_: Admin, namespaces: web::Data<Namespaces>, req: HttpRequest) -> impl Responder {
+    respond(&req, &namespaces.settings())
+}
+
+async fn set_settings(_: Admin, namespaces: web::Data<Namespaces>, settings: Body<Settings>) -> impl Responder {
+    match namespaces.set_settings(settings.into_inner()) {
+        Ok(_) => HttpResponse::Ok().body("Settings updated successfully"),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn list_namespaces(_: Admin, namespaces: web::Data<Namespaces>, req: HttpRequest) -> impl Responder {
+    respond(&req, &namespaces.list().await)
+}
+
+async fn set_namespace_limits(
+    _: Admin,
+    namespaces: web::Data<Namespaces>,
+    name: web::Path<String>,
+    limits: Body<NamespaceLimits>,
+) -> impl Responder {
+    match namespaces.set_limits(&name.into_inner(), limits.into_inner()) {
+        Ok(_) => HttpResponse::Ok().body("Namespace limits updated successfully"),
+        Err(e) => error_response(e),
+    }
+}
+
+pub fn routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/admin/settings", web::get().to(get_settings))
+        .route("/admin/settings", web::put().to(set_settings))
+        .route("/admin/namespaces", web::get().to(list_namespaces))
+        .route("/admin/namespaces/{ns}", web::put().to(set_namespace_limits));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_token() {
+        assert!(same_token(b"secret", b"secret"));
+        assert!(!same_token(b"secreT", b"secret"));
+        assert!(!same_token(b"secret2", b"secret"));
+        assert!(!same_token(b"", b"secret"));
+    }
+}
@@ -0,0 +1,207 @@
+//! On-demand analytics over a snapshot of a window's values. These are
+//! O(n) or worse, so the service runs them off the async executor.
+
+use crate::SeriesKind;
+
+/// Smallest chunk size used in rescaled-range analysis.
+const MIN_RS_CHUNK: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Hurst {
+    /// Hurst exponent estimate: ~0.5 for a random walk, above for trending
+    /// and below for mean-reverting series. `None` with too few points.
+    pub hurst: Option<f64>,
+    /// Number of log returns analysed.
+    pub count: usize,
+}
+
+/// Hurst exponent of the log returns of `prices`, estimated by rescaled-range
+/// (R/S) analysis: the slope of log(mean R/S) against log(chunk size) over
+/// chunk sizes 8, 16, 32, ... up to half the series.
+pub fn hurst(prices: &[f64]) -> Hurst {
+    let returns = log_returns(prices);
+    let count = returns.len();
+
+    let mut points = Vec::new();
+    let mut size = MIN_RS_CHUNK;
+    while size <= count / 2 {
+        let ratios: Vec<f64> = returns.chunks_exact(size).filter_map(rescaled_range).collect();
+        if !ratios.is_empty() {
+            let mean = ratios.iter().sum::<f64>() / ratios.len() as f64;
+            points.push(((size as f64).ln(), mean.ln()));
+        }
+        size *= 2;
+    }
+
+    Hurst { hurst: slope(&points), count }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Autocorrelation {
+    /// Sample autocorrelation of the log returns at lags 1, 2, ... in order.
+    /// `None` for lags the window is too short for, or for flat returns.
+    pub acf: Vec<Option<f64>>,
+    /// Number of log returns analysed.
+    pub count: usize,
+}
+
+/// Autocorrelation function of the log returns of `prices` at lags
+/// `1..=max_lag`, using the standard biased estimator (every lag is
+/// normalized by the full-series variance).
+pub fn autocorrelation(prices: &[f64], max_lag: usize) -> Autocorrelation {
+    let returns = log_returns(prices);
+    let count = returns.len();
+    let mean = returns.iter().sum::<f64>() / count.max(1) as f64;
+    let deviations: Vec<f64> = returns.iter().map(|r| r - mean).collect();
+    let variance: f64 = deviations.iter().map(|d| d * d).sum();
+
+    let acf = (1..=max_lag)
+        .map(|lag| {
+            if lag >= count || variance <= 0.0 {
+                return None;
+            }
+            let cov: f64 = deviations.iter().zip(&deviations[lag..]).map(|(a, b)| a * b).sum();
+            Some(cov / variance)
+        })
+        .collect();
+    Autocorrelation { acf, count }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Entropy {
+    /// Shannon entropy of the binned values, in bits.
+    pub entropy: f64,
+    /// `entropy / log2(bins)`: 0 when every value is in one bin, 1 when
+    /// they are spread evenly over all of them.
+    pub normalized: f64,
+    pub bins: usize,
+    /// Number of values binned.
+    pub count: usize,
+}
+
+/// Shannon entropy of `values` over `bins` equal-width bins spanning their
+/// min to max. A flat or empty window has zero entropy.
+pub fn entropy(values: &[f64], bins: usize) -> Entropy {
+    let count = values.len();
+    let lo = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let hi = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let mut entropy = 0.0;
+    if count > 0 && hi > lo {
+        let mut counts = vec![0usize; bins];
+        let width = (hi - lo) / bins as f64;
+        for &v in values {
+            let bin = (((v - lo) / width) as usize).min(bins - 1);
+            counts[bin] += 1;
+        }
+        for &c in counts.iter().filter(|&&c| c > 0) {
+            let p = c as f64 / count as f64;
+            entropy -= p * p.log2();
+        }
+    }
+    Entropy {
+        entropy,
+        normalized: entropy / (bins as f64).log2(),
+        bins,
+        count,
+    }
+}
+
+fn log_returns(prices: &[f64]) -> Vec<f64> {
+    prices.windows(2).map(|w| SeriesKind::LogReturns.step(w[0], w[1])).collect()
+}
+
+/// Range of cumulative deviations from the chunk mean divided by the chunk's
+/// standard deviation. `None` for a flat chunk.
+fn rescaled_range(chunk: &[f64]) -> Option<f64> {
+    let n = chunk.len() as f64;
+    let mean = chunk.iter().sum::<f64>() / n;
+    let mut cumulative = 0.0;
+    let mut lo = 0.0f64;
+    let mut hi = 0.0f64;
+    let mut sum_squares = 0.0;
+    for &x in chunk {
+        cumulative += x - mean;
+        lo = lo.min(cumulative);
+        hi = hi.max(cumulative);
+        sum_squares += (x - mean) * (x - mean);
+    }
+    let std_dev = (sum_squares / n).sqrt();
+    (std_dev > 0.0).then(|| (hi - lo) / std_dev)
+}
+
+/// Least-squares slope of y on x; `None` with fewer than two points.
+fn slope(points: &[(f64, f64)]) -> Option<f64> {
+    if points.len() < 2 {
+        return None;
+    }
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|p| p.0).sum::<f64>() / n;
+    let mean_y = points.iter().map(|p| p.1).sum::<f64>() / n;
+    let cov: f64 = points.iter().map(|p| (p.0 - mean_x) * (p.1 - mean_y)).sum();
+    let var: f64 = points.iter().map(|p| (p.0 - mean_x) * (p.0 - mean_x)).sum();
+    Some(cov / var)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hurst_needs_enough_points() {
+        let prices: Vec<f64> = (1..=20).map(|i| i as f64).collect();
+        let result = hurst(&prices);
+        assert_eq!(19, result.count);
+        assert_eq!(None, result.hurst);
+    }
+
+    #[test]
+    fn test_hurst_of_alternating_returns_is_low() {
+        let prices: Vec<f64> = (0..4096).map(|i| if i % 2 == 0 { 100.0 } else { 101.0 }).collect();
+        let result = hurst(&prices);
+        assert!(result.hurst.unwrap() < 0.2, "{:?}", result.hurst);
+    }
+
+    #[test]
+    fn test_hurst_of_persistent_returns_is_high() {
+        // Log returns drift slowly up and down in long regimes.
+        let mut price = 100.0f64;
+        let prices: Vec<f64> = (0..4096)
+            .map(|i| {
+                let regime = if (i / 512) % 2 == 0 { 1.0 } else { -1.0 };
+                price *= (regime * 0.001 + ((i * 7919) % 13) as f64 * 1e-5).exp();
+                price
+            })
+            .collect();
+        let result = hurst(&prices);
+        assert!(result.hurst.unwrap() > 0.7, "{:?}", result.hurst);
+    }
+
+    #[test]
+    fn test_autocorrelation_of_alternating_returns() {
+        let prices: Vec<f64> = (0..101).map(|i| if i % 2 == 0 { 100.0 } else { 101.0 }).collect();
+        let result = autocorrelation(&prices, 3);
+        assert_eq!(100, result.count);
+        assert!((result.acf[0].unwrap() + 0.99).abs() < 1e-9, "{:?}", result.acf);
+        assert!((result.acf[1].unwrap() - 0.98).abs() < 1e-9, "{:?}", result.acf);
+    }
+
+    #[test]
+    fn test_entropy() {
+        let spread: Vec<f64> = (0..400).map(|i| (i % 4) as f64).collect();
+        let result = entropy(&spread, 4);
+        assert!((result.entropy - 2.0).abs() < 1e-9);
+        assert!((result.normalized - 1.0).abs() < 1e-9);
+
+        let stuck = entropy(&[5.0; 100], 4);
+        assert_eq!(0.0, stuck.entropy);
+        assert_eq!(100, stuck.count);
+    }
+
+    #[test]
+    fn test_autocorrelation_undefined_lags() {
+        let result = autocorrelation(&[100.0, 101.0, 102.0], 2);
+        assert_eq!(2, result.count);
+        assert_eq!(None, result.acf[1]);
+        assert_eq!(vec![None], autocorrelation(&[5.0; 10], 1).acf);
+    }
+}
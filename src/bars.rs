@@ -0,0 +1,138 @@
+//! Time-bucketed OHLC bars.
+//!
+//! Unlike the count-based windows, bars group values by the time they were
+//! received into fixed intervals aligned to the Unix epoch. Each interval
+//! keeps its most recent bars in a ring buffer.
+
+use std::collections::VecDeque;
+
+/// Completed and in-progress bars retained per interval.
+pub const BAR_HISTORY: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Bar {
+    /// Start of the bar's interval, in milliseconds since the Unix epoch.
+    pub start: u64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    /// Number of values that fell into the bar.
+    pub ticks: u64,
+}
+
+impl Bar {
+    fn new(start: u64, value: f64) -> Self {
+        Bar { start, open: value, high: value, low: value, close: value, ticks: 1 }
+    }
+
+    fn update(&mut self, value: f64) {
+        self.high = self.high.max(value);
+        self.low = self.low.min(value);
+        self.close = value;
+        self.ticks += 1;
+    }
+}
+
+/// Bars of a single interval for one symbol.
+#[derive(Debug, Clone)]
+pub struct BarSeries {
+    interval_ms: u64,
+    bars: VecDeque<Bar>,
+}
+
+impl BarSeries {
+    pub fn new(interval_ms: u64) -> Self {
+        BarSeries { interval_ms, bars: VecDeque::new() }
+    }
+
+    pub fn heap_bytes(&self) -> usize {
+        self.bars.capacity() * std::mem::size_of::<Bar>()
+    }
+
+    pub fn interval_ms(&self) -> u64 {
+        self.interval_ms
+    }
+
+    /// Adds a value received at `timestamp_ms`. Values older than the current
+    /// bar are folded into it rather than reopening a closed bar.
+    pub fn add(&mut self, timestamp_ms: u64, value: f64) {
+        let start = timestamp_ms - timestamp_ms % self.interval_ms;
+        match self.bars.back_mut() {
+            Some(bar) if bar.start >= start => bar.update(value),
+            _ => {
+                if self.bars.len() >= BAR_HISTORY {
+                    self.bars.pop_front();
+                }
+                self.bars.push_back(Bar::new(start, value));
+            }
+        }
+    }
+
+    /// The most recent `limit` bars, oldest first. The last bar may still be
+    /// in progress.
+    pub fn recent(&self, limit: usize) -> Vec<Bar> {
+        let skip = self.bars.len().saturating_sub(limit);
+        self.bars.iter().skip(skip).copied().collect()
+    }
+}
+
+/// Parses an interval such as `500ms`, `1s`, `5m` or `1h` into milliseconds.
+/// A bare number is taken as milliseconds.
+pub fn parse_interval(raw: &str) -> Result<u64, String> {
+    let raw = raw.trim();
+    let split = raw.find(|c: char| !c.is_ascii_digit()).unwrap_or(raw.len());
+    let (number, unit) = raw.split_at(split);
+    let multiplier = match unit {
+        "" | "ms" => 1,
+        "s" => 1_000,
+        "m" => 60_000,
+        "h" => 3_600_000,
+        _ => return Err(format!("Invalid interval '{}'", raw)),
+    };
+    match number.parse::<u64>() {
+        Ok(n) if n > 0 => Ok(n * multiplier),
+        _ => Err(format!("Invalid interval '{}'", raw)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bars_bucket_by_interval() {
+        let mut series = BarSeries::new(1000);
+        series.add(10_100, 5.0);
+        series.add(10_200, 7.0);
+        series.add(10_900, 4.0);
+        series.add(11_000, 6.0);
+
+        let bars = series.recent(10);
+        assert_eq!(2, bars.len());
+        assert_eq!(Bar { start: 10_000, open: 5.0, high: 7.0, low: 4.0, close: 4.0, ticks: 3 }, bars[0]);
+        assert_eq!(Bar { start: 11_000, open: 6.0, high: 6.0, low: 6.0, close: 6.0, ticks: 1 }, bars[1]);
+        assert_eq!(1, series.recent(1).len());
+    }
+
+    #[test]
+    fn test_bar_history_is_bounded() {
+        let mut series = BarSeries::new(1);
+        for t in 0..(BAR_HISTORY as u64 + 10) {
+            series.add(t, t as f64);
+        }
+        let bars = series.recent(usize::MAX);
+        assert_eq!(BAR_HISTORY, bars.len());
+        assert_eq!(10, bars[0].start);
+    }
+
+    #[test]
+    fn test_parse_interval() {
+        assert_eq!(Ok(1_000), parse_interval("1s"));
+        assert_eq!(Ok(300_000), parse_interval("5m"));
+        assert_eq!(Ok(250), parse_interval("250ms"));
+        assert_eq!(Ok(250), parse_interval("250"));
+        assert!(parse_interval("0s").is_err());
+        assert!(parse_interval("5d").is_err());
+    }
+}
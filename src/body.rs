@@ -0,0 +1,280 @@
+//! Request and response bodies in JSON, MessagePack or CBOR, picked by the
+//! `Content-Type` and `Accept` headers. Request bodies may be compressed
+//! with gzip or zstd as their `Content-Encoding` says.
+//!
+//! Endpoints on the hot ingestion path also take Protocol Buffers bodies,
+//! decoded with [`Proto`].
+
+use std::future::Future;
+use std::ops::Deref;
+
+use actix_web::dev::Payload;
+use actix_web::error::PayloadError;
+use actix_web::http::header::{EntityTag, Header, IfNoneMatch, TryIntoHeaderPair, HeaderValue, ACCEPT, CONTENT_ENCODING, CONTENT_TYPE, ETag, VARY};
+use actix_web::{web, FromRequest, HttpRequest, HttpResponse};
+use futures::future::LocalBoxFuture;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use trading_service::ServiceError;
+
+use crate::error_response;
+
+/// Largest request body accepted once decompressed, in bytes.
+pub const MAX_BODY_BYTES: usize = 2 * 1024 * 1024;
+
+const JSON: &str = "application/json";
+const MSGPACK: &str = "application/msgpack";
+const CBOR: &str = "application/cbor";
+const PROTOBUF: &str = "application/x-protobuf";
+
+/// Content encodings decompressed before parsing.
+const ENCODINGS: [&str; 3] = ["identity", "gzip", "zstd"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    MessagePack,
+    Cbor,
+}
+
+impl Format {
+    /// The format a media type names, ignoring parameters such as `charset`.
+    fn from_media_type(media_type: &str) -> Option<Format> {
+        match media_type.split(';').next()?.trim() {
+            JSON => Some(Format::Json),
+            MSGPACK | "application/x-msgpack" | "application/vnd.msgpack" => Some(Format::MessagePack),
+            CBOR => Some(Format::Cbor),
+            _ => None,
+        }
+    }
+
+    /// The request body's format; JSON when the request doesn't say.
+    fn of_request(req: &HttpRequest) -> Result<Format, String> {
+        let Some(value) = req.headers().get(CONTENT_TYPE) else {
+            return Ok(Format::Json);
+        };
+        value
+            .to_str()
+            .ok()
+            .and_then(Format::from_media_type)
+            .ok_or_else(|| format!("Unsupported Content-Type. Expected {}, {} or {}.", JSON, MSGPACK, CBOR))
+    }
+
+    /// The first supported format the client accepts, else JSON.
+    pub fn of_response(req: &HttpRequest) -> Format {
+        req.headers()
+            .get(ACCEPT)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|accept| accept.split(',').find_map(Format::from_media_type))
+            .unwrap_or(Format::Json)
+    }
+
+    fn decode<T: DeserializeOwned>(self, bytes: &[u8]) -> Result<T, String> {
+        match self {
+            Format::Json => serde_json::from_slice(bytes).map_err(|e| e.to_string()),
+            Format::MessagePack => rmp_serde::from_slice(bytes).map_err(|e| e.to_string()),
+            Format::Cbor => ciborium::from_reader(bytes).map_err(|e| e.to_string()),
+        }
+    }
+
+    fn encode<T: Serialize>(self, value: &T) -> Result<Vec<u8>, String> {
+        match self {
+            Format::Json => serde_json::to_vec(value).map_err(|e| e.to_string()),
+            // Named, so structs become maps like in JSON rather than arrays.
+            Format::MessagePack => rmp_serde::to_vec_named(value).map_err(|e| e.to_string()),
+            Format::Cbor => {
+                let mut bytes = Vec::new();
+                ciborium::into_writer(value, &mut bytes).map_err(|e| e.to_string())?;
+                Ok(bytes)
+            }
+        }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            Format::Json => JSON,
+            Format::MessagePack => MSGPACK,
+            Format::Cbor => CBOR,
+        }
+    }
+}
+
+/// A request body decoded from the format its `Content-Type` names.
+pub struct Body<T>(pub T);
+
+impl<T> Body<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for Body<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: DeserializeOwned + 'static> FromRequest for Body<T> {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let format = Format::of_request(req).and_then(|format| check_encoding(req).map(|_| format));
+        let bytes = read(req, payload);
+        Box::pin(async move {
+            let format = format.map_err(|e| reject(e.into()))?;
+            let bytes = bytes.await?;
+            format.decode(&bytes).map(Body).map_err(|e| reject(format!("Invalid request body: {}", e).into()))
+        })
+    }
+}
+
+/// A request body decoded from Protocol Buffers.
+pub struct Proto<T>(pub T);
+
+impl<T> Proto<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T: prost::Message + Default + 'static> FromRequest for Proto<T> {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let checked = check_protobuf(req).and_then(|_| check_encoding(req));
+        let bytes = read(req, payload);
+        Box::pin(async move {
+            checked.map_err(|e| reject(e.into()))?;
+            let bytes = bytes.await?;
+            T::decode(bytes).map(Proto).map_err(|e| reject(format!("Invalid request body: {}", e).into()))
+        })
+    }
+}
+
+fn reject(e: ServiceError) -> actix_web::Error {
+    let response = error_response(e.clone());
+    actix_web::error::InternalError::from_response(e, response).into()
+}
+
+/// Reads the whole body, decompressing the payload as it's read so the size
+/// limit applies to the decompressed bytes.
+fn read(req: &HttpRequest, payload: &mut Payload) -> impl Future<Output = Result<web::Bytes, actix_web::Error>> {
+    let bytes = web::Bytes::from_request(req, payload);
+    async move {
+        bytes.await.map_err(|e| match e.as_error::<PayloadError>() {
+            Some(PayloadError::Overflow) => reject(ServiceError::BatchTooLarge(format!(
+                "Request body exceeds {} bytes once decompressed",
+                MAX_BODY_BYTES
+            ))),
+            _ => reject(format!("Invalid request body: {}", e).into()),
+        })
+    }
+}
+
+/// Protobuf bodies must say so, as nothing in the bytes tells them apart.
+fn check_protobuf(req: &HttpRequest) -> Result<(), String> {
+    let media_type = req.headers().get(CONTENT_TYPE).and_then(|value| value.to_str().ok());
+    match media_type.and_then(|value| value.split(';').next()).map(str::trim) {
+        Some(PROTOBUF | "application/protobuf" | "application/vnd.google.protobuf") => Ok(()),
+        _ => Err(format!("Unsupported Content-Type. Expected {}.", PROTOBUF)),
+    }
+}
+
+/// Checks the body is compressed, if at all, with one of [`ENCODINGS`],
+/// as streamed bodies are decompressed by whichever decoders actix has.
+pub fn check_encoding(req: &HttpRequest) -> Result<(), String> {
+    let Some(value) = req.headers().get(CONTENT_ENCODING) else {
+        return Ok(());
+    };
+    match value.to_str() {
+        Ok(encoding) if ENCODINGS.contains(&encoding.trim()) => Ok(()),
+        _ => Err(format!("Unsupported Content-Encoding. Expected one of {}.", ENCODINGS.join(", "))),
+    }
+}
+
+/// A 200 response with the value in the format the client accepts.
+pub fn respond<T: Serialize>(req: &HttpRequest, value: &T) -> HttpResponse {
+    let format = Format::of_response(req);
+    match format.encode(value) {
+        Ok(bytes) => HttpResponse::Ok().content_type(format.content_type()).body(bytes),
+        Err(e) => error_response(ServiceError::Internal(e)),
+    }
+}
+
+/// Like [`respond`], with a weak `ETag` of the tag, or a `304 Not
+/// Modified` without a body when the request's `If-None-Match` has it.
+/// Every format shares the tag, so the response varies on `Accept` to keep
+/// caches from answering one format's request with another's body.
+pub fn respond_tagged<T: Serialize>(req: &HttpRequest, value: &T, tag: &str) -> HttpResponse {
+    let etag = EntityTag::new_weak(tag.to_string());
+    let cached = match IfNoneMatch::parse(req) {
+        Ok(IfNoneMatch::Any) => true,
+        Ok(IfNoneMatch::Items(tags)) => tags.iter().any(|cached| cached.weak_eq(&etag)),
+        Err(_) => false,
+    };
+    if cached {
+        return HttpResponse::NotModified().insert_header(ETag(etag)).insert_header((VARY, "accept")).finish();
+    }
+    let mut response = respond(req, value);
+    if let Ok((name, value)) = ETag(etag).try_into_pair() {
+        response.headers_mut().insert(name, value);
+    }
+    response.headers_mut().insert(VARY, HeaderValue::from_static("accept"));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let values = vec![1.5, -2.0, 1e9];
+        for format in [Format::Json, Format::MessagePack, Format::Cbor] {
+            let bytes = format.encode(&values).unwrap();
+            assert_eq!(values, format.decode::<Vec<f64>>(&bytes).unwrap());
+        }
+        assert_eq!(Some(Format::Json), Format::from_media_type("application/json; charset=utf-8"));
+        assert_eq!(None, Format::from_media_type("text/plain"));
+    }
+
+    #[actix_rt::test]
+    async fn test_protobuf() {
+        use actix_web::test::TestRequest;
+        use prost::Message;
+
+        use crate::grpc::proto::AddBatchRequest;
+
+        let batch = AddBatchRequest { symbol: "AAPL".to_string(), values: vec![1.0, 2.5], ..Default::default() };
+        let (req, mut payload) = TestRequest::post()
+            .insert_header((CONTENT_TYPE, PROTOBUF))
+            .set_payload(batch.encode_to_vec())
+            .to_http_parts();
+        let decoded = Proto::<AddBatchRequest>::from_request(&req, &mut payload).await.unwrap();
+        assert_eq!(batch, decoded.into_inner());
+
+        let (req, mut payload) = TestRequest::post().set_payload(batch.encode_to_vec()).to_http_parts();
+        assert!(Proto::<AddBatchRequest>::from_request(&req, &mut payload).await.is_err());
+    }
+
+    #[actix_rt::test]
+    async fn test_respond_tagged_varies_on_accept() {
+        use actix_web::http::header::IF_NONE_MATCH;
+        use actix_web::http::StatusCode;
+        use actix_web::test::TestRequest;
+
+        let req = TestRequest::get().insert_header((ACCEPT, CBOR)).to_http_request();
+        let response = respond_tagged(&req, &vec![1.0], "7");
+        assert_eq!(CBOR, response.headers().get(CONTENT_TYPE).unwrap());
+        assert_eq!("accept", response.headers().get(VARY).unwrap());
+
+        let req = TestRequest::get().insert_header((IF_NONE_MATCH, "W/\"7\"")).to_http_request();
+        let response = respond_tagged(&req, &vec![1.0], "7");
+        assert_eq!(StatusCode::NOT_MODIFIED, response.status());
+        assert_eq!("accept", response.headers().get(VARY).unwrap());
+    }
+}
@@ -0,0 +1,304 @@
+//! Trading-session calendar: local exchange time in a handful of market
+//! time zones, and weekly session schedules in that local time.
+//!
+//! The zones carry their daylight-saving rules directly (US rules for the
+//! American zones, EU rules for the European ones) rather than a tz database.
+//! Holidays aren't modelled.
+
+const SECS_PER_DAY: i64 = 86_400;
+const HOUR: i64 = 3_600;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum TimeZone {
+    #[serde(rename = "UTC")]
+    Utc,
+    #[serde(rename = "America/New_York")]
+    NewYork,
+    #[serde(rename = "America/Chicago")]
+    Chicago,
+    #[serde(rename = "Europe/London")]
+    London,
+    /// Central European Time, as kept by Frankfurt, Paris and Amsterdam.
+    #[serde(rename = "Europe/Berlin", alias = "Europe/Paris", alias = "Europe/Amsterdam")]
+    Berlin,
+    #[serde(rename = "Asia/Tokyo")]
+    Tokyo,
+    #[serde(rename = "Asia/Hong_Kong")]
+    HongKong,
+}
+
+enum DstRule {
+    None,
+    Us,
+    Eu,
+}
+
+impl TimeZone {
+    fn standard_offset(&self) -> i64 {
+        match self {
+            TimeZone::Utc | TimeZone::London => 0,
+            TimeZone::NewYork => -5 * HOUR,
+            TimeZone::Chicago => -6 * HOUR,
+            TimeZone::Berlin => HOUR,
+            TimeZone::Tokyo => 9 * HOUR,
+            TimeZone::HongKong => 8 * HOUR,
+        }
+    }
+
+    fn dst_rule(&self) -> DstRule {
+        match self {
+            TimeZone::NewYork | TimeZone::Chicago => DstRule::Us,
+            TimeZone::London | TimeZone::Berlin => DstRule::Eu,
+            TimeZone::Utc | TimeZone::Tokyo | TimeZone::HongKong => DstRule::None,
+        }
+    }
+
+    /// Offset from UTC in seconds at the given instant.
+    pub fn utc_offset(&self, utc_secs: i64) -> i64 {
+        let standard = self.standard_offset();
+        let (year, _, _) = civil_from_days(utc_secs.div_euclid(SECS_PER_DAY));
+        // Transition instants in UTC for the year.
+        let (start, end) = match self.dst_rule() {
+            DstRule::None => return standard,
+            // 02:00 local on the second Sunday of March until 02:00 local
+            // (daylight time) on the first Sunday of November.
+            DstRule::Us => (
+                nth_sunday(year, 3, 2) * SECS_PER_DAY + 2 * HOUR - standard,
+                nth_sunday(year, 11, 1) * SECS_PER_DAY + 2 * HOUR - (standard + HOUR),
+            ),
+            // 01:00 UTC on the last Sunday of March until 01:00 UTC on the
+            // last Sunday of October.
+            DstRule::Eu => (
+                last_sunday(year, 3) * SECS_PER_DAY + HOUR,
+                last_sunday(year, 10) * SECS_PER_DAY + HOUR,
+            ),
+        };
+        if (start..end).contains(&utc_secs) {
+            standard + HOUR
+        } else {
+            standard
+        }
+    }
+
+    /// Local weekday and minute of the day at the given instant.
+    pub fn local_time(&self, timestamp_ns: u64) -> (Weekday, u32) {
+        let utc_secs = (timestamp_ns / 1_000_000_000) as i64;
+        let local = utc_secs + self.utc_offset(utc_secs);
+        let days = local.div_euclid(SECS_PER_DAY);
+        (Weekday::from_days(days), (local.rem_euclid(SECS_PER_DAY) / 60) as u32)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Weekday {
+    Mon,
+    Tue,
+    Wed,
+    Thu,
+    Fri,
+    Sat,
+    Sun,
+}
+
+impl Weekday {
+    const ALL: [Weekday; 7] = [
+        Weekday::Mon,
+        Weekday::Tue,
+        Weekday::Wed,
+        Weekday::Thu,
+        Weekday::Fri,
+        Weekday::Sat,
+        Weekday::Sun,
+    ];
+
+    /// Weekday of a day counted from 1970-01-01, a Thursday.
+    fn from_days(days: i64) -> Self {
+        Self::ALL[(days + 3).rem_euclid(7) as usize]
+    }
+
+    fn previous(self) -> Self {
+        Self::ALL[(self as usize + 6) % 7]
+    }
+}
+
+/// Year, month and day of a day counted from 1970-01-01 (proleptic
+/// Gregorian calendar).
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Days from 1970-01-01 to the given date.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (month as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Day number of the `n`th Sunday of the month.
+fn nth_sunday(year: i64, month: u32, n: i64) -> i64 {
+    let first = days_from_civil(year, month, 1);
+    let to_sunday = (6 - Weekday::from_days(first) as i64).rem_euclid(7);
+    first + to_sunday + 7 * (n - 1)
+}
+
+/// Day number of the last Sunday of the month.
+fn last_sunday(year: i64, month: u32) -> i64 {
+    let (next_year, next_month) = if month == 12 { (year + 1, 1) } else { (year, month + 1) };
+    let last = days_from_civil(next_year, next_month, 1) - 1;
+    last - (Weekday::from_days(last) as i64 + 1) % 7
+}
+
+/// A weekly trading session in exchange-local time. A session whose end is
+/// before its start runs overnight into the next day.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SessionConfig {
+    /// Opening time, `HH:MM` local.
+    pub start: String,
+    /// Closing time, `HH:MM` local; the close itself is outside the session.
+    pub end: String,
+    pub time_zone: TimeZone,
+    /// Days the session opens on, Monday to Friday by default.
+    #[serde(default = "default_days")]
+    pub days: Vec<Weekday>,
+    /// What happens to out-of-session values.
+    #[serde(default)]
+    pub outside: SessionAction,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionAction {
+    /// Drop them from every window, counting them.
+    #[default]
+    Exclude,
+    /// Keep them in a separate set of extended-hours windows.
+    Extended,
+}
+
+fn default_days() -> Vec<Weekday> {
+    Weekday::ALL[..5].to_vec()
+}
+
+/// A validated session, ready to test timestamps against.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Session {
+    start: u32,
+    end: u32,
+    time_zone: TimeZone,
+    /// Bit `d` set when the session opens on weekday `d`.
+    days: u8,
+}
+
+impl Session {
+    pub fn new(config: &SessionConfig) -> Result<Self, String> {
+        let start = parse_minute(&config.start)?;
+        let end = parse_minute(&config.end)?;
+        if start == end {
+            return Err("Invalid session input. start and end must differ.".to_string());
+        }
+        if config.days.is_empty() {
+            return Err("Invalid session input. At least one day is required.".to_string());
+        }
+        let days = config.days.iter().fold(0, |mask, &day| mask | 1 << day as u8);
+        Ok(Session { start, end, time_zone: config.time_zone, days })
+    }
+
+    fn opens_on(&self, day: Weekday) -> bool {
+        self.days & 1 << day as u8 != 0
+    }
+
+    /// Whether the instant falls inside a session.
+    pub fn contains(&self, timestamp_ns: u64) -> bool {
+        let (day, minute) = self.time_zone.local_time(timestamp_ns);
+        if self.start < self.end {
+            self.opens_on(day) && (self.start..self.end).contains(&minute)
+        } else {
+            (self.opens_on(day) && minute >= self.start) || (self.opens_on(day.previous()) && minute < self.end)
+        }
+    }
+}
+
+fn parse_minute(raw: &str) -> Result<u32, String> {
+    let invalid = || format!("Invalid session time '{}'. Expected HH:MM.", raw);
+    let (hours, minutes) = raw.split_once(':').ok_or_else(invalid)?;
+    let hours: u32 = hours.parse().map_err(|_| invalid())?;
+    let minutes: u32 = minutes.parse().map_err(|_| invalid())?;
+    if hours > 23 || minutes > 59 {
+        return Err(invalid());
+    }
+    Ok(hours * 60 + minutes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Nanoseconds since the epoch of a UTC date and time.
+    fn utc(year: i64, month: u32, day: u32, hour: i64, minute: i64) -> u64 {
+        ((days_from_civil(year, month, day) * SECS_PER_DAY + hour * HOUR + minute * 60) * 1_000_000_000) as u64
+    }
+
+    #[test]
+    fn test_civil_round_trip() {
+        assert_eq!(0, days_from_civil(1970, 1, 1));
+        assert_eq!((2024, 2, 29), civil_from_days(days_from_civil(2024, 2, 29)));
+        assert_eq!(Weekday::Tue, Weekday::from_days(days_from_civil(2024, 10, 15)));
+        assert_eq!(days_from_civil(2024, 3, 10), nth_sunday(2024, 3, 2));
+        assert_eq!(days_from_civil(2024, 11, 3), nth_sunday(2024, 11, 1));
+        assert_eq!(days_from_civil(2024, 3, 31), last_sunday(2024, 3));
+        assert_eq!(days_from_civil(2024, 10, 27), last_sunday(2024, 10));
+    }
+
+    #[test]
+    fn test_daylight_saving() {
+        let ny = TimeZone::NewYork;
+        // 2024-03-10 06:59 UTC is 01:59 EST; a minute later it's 03:00 EDT.
+        assert_eq!((Weekday::Sun, 119), ny.local_time(utc(2024, 3, 10, 6, 59)));
+        assert_eq!((Weekday::Sun, 180), ny.local_time(utc(2024, 3, 10, 7, 0)));
+        assert_eq!(-5 * HOUR, ny.utc_offset(utc(2024, 11, 3, 6, 0) as i64 / 1_000_000_000));
+        assert_eq!(HOUR, TimeZone::London.utc_offset(utc(2024, 7, 1, 0, 0) as i64 / 1_000_000_000));
+        assert_eq!(9 * HOUR, TimeZone::Tokyo.utc_offset(0));
+    }
+
+    #[test]
+    fn test_session_contains() {
+        let config = SessionConfig {
+            start: "09:30".to_string(),
+            end: "16:00".to_string(),
+            time_zone: TimeZone::NewYork,
+            days: default_days(),
+            outside: SessionAction::Exclude,
+        };
+        let session = Session::new(&config).unwrap();
+        // Tuesday 2024-10-15, EDT (UTC-4).
+        assert!(!session.contains(utc(2024, 10, 15, 13, 29)));
+        assert!(session.contains(utc(2024, 10, 15, 13, 30)));
+        assert!(!session.contains(utc(2024, 10, 15, 20, 0)));
+        // Saturday.
+        assert!(!session.contains(utc(2024, 10, 19, 15, 0)));
+
+        let overnight = SessionConfig { start: "18:00".to_string(), end: "17:00".to_string(), ..config.clone() };
+        let overnight = Session::new(&overnight).unwrap();
+        // Friday 18:30 local runs into Saturday, which isn't a session day of its own.
+        assert!(overnight.contains(utc(2024, 10, 18, 22, 30)));
+        assert!(overnight.contains(utc(2024, 10, 19, 12, 0)));
+        assert!(!overnight.contains(utc(2024, 10, 19, 22, 30)));
+
+        let bad = SessionConfig { start: "24:00".to_string(), ..config };
+        assert!(Session::new(&bad).is_err());
+    }
+}
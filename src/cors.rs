@@ -0,0 +1,102 @@
+//! CORS, so browser dashboards can call the API without a proxy.
+//!
+//! Off unless `CORS_ALLOWED_ORIGINS` names the allowed origins, comma
+//! separated (e.g. `https://dash.example.com,http://localhost:3000`), or is
+//! `*` to allow any. `CORS_ALLOWED_METHODS` lists the allowed methods,
+//! `GET` by default.
+
+use actix_cors::Cors;
+use actix_web::http::header::{ACCEPT, CONTENT_ENCODING, CONTENT_TYPE, ETAG, IF_NONE_MATCH};
+use actix_web::http::{Method, Uri};
+
+use crate::request_id::REQUEST_ID_HEADER;
+
+const ORIGINS_VAR: &str = "CORS_ALLOWED_ORIGINS";
+const METHODS_VAR: &str = "CORS_ALLOWED_METHODS";
+
+/// How long browsers may cache a preflight response, in seconds.
+const MAX_AGE_SECS: usize = 3600;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Origins {
+    Any,
+    List(Vec<String>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CorsConfig {
+    pub origins: Origins,
+    pub methods: Vec<Method>,
+}
+
+impl CorsConfig {
+    /// The configuration from the environment, `None` when CORS is off.
+    pub fn from_env() -> Result<Option<CorsConfig>, String> {
+        let Ok(origins) = std::env::var(ORIGINS_VAR) else {
+            return Ok(None);
+        };
+        let methods = std::env::var(METHODS_VAR).ok();
+        CorsConfig::parse(&origins, methods.as_deref()).map(Some)
+    }
+
+    fn parse(origins: &str, methods: Option<&str>) -> Result<CorsConfig, String> {
+        let origins = match origins.trim() {
+            "*" => Origins::Any,
+            list => Origins::List(list.split(',').map(|o| parse_origin(o.trim())).collect::<Result<_, _>>()?),
+        };
+        let methods = match methods {
+            None => vec![Method::GET],
+            Some(list) => list
+                .split(',')
+                .map(|m| {
+                    Method::from_bytes(m.trim().to_uppercase().as_bytes())
+                        .map_err(|_| format!("Invalid {} input. {:?} isn't a method.", METHODS_VAR, m.trim()))
+                })
+                .collect::<Result<_, _>>()?,
+        };
+        Ok(CorsConfig { origins, methods })
+    }
+
+    pub fn middleware(&self) -> Cors {
+        let cors = match &self.origins {
+            Origins::Any => Cors::default().allow_any_origin(),
+            Origins::List(origins) => origins.iter().fold(Cors::default(), |cors, origin| cors.allowed_origin(origin)),
+        };
+        cors.allowed_methods(self.methods.clone())
+            .allowed_headers([ACCEPT, CONTENT_TYPE, CONTENT_ENCODING, IF_NONE_MATCH])
+            .allowed_header(crate::NAMESPACE_HEADER)
+            .allowed_header(crate::IDEMPOTENCY_KEY_HEADER)
+            .allowed_header(REQUEST_ID_HEADER)
+            .expose_headers([ETAG.as_str(), crate::API_VERSION_HEADER, REQUEST_ID_HEADER])
+            .max_age(MAX_AGE_SECS)
+    }
+}
+
+/// An origin is a scheme and host with an optional port, and nothing else.
+fn parse_origin(origin: &str) -> Result<String, String> {
+    let invalid = || format!("Invalid {} input. {:?} isn't an origin such as https://example.com.", ORIGINS_VAR, origin);
+    let uri: Uri = origin.parse().map_err(|_| invalid())?;
+    match (uri.scheme_str(), uri.authority()) {
+        (Some("http" | "https"), Some(authority)) if origin.ends_with(authority.as_str()) => Ok(origin.to_string()),
+        _ => Err(invalid()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let config = CorsConfig::parse("https://dash.example.com, http://localhost:3000", None).unwrap();
+        let origins = vec!["https://dash.example.com".to_string(), "http://localhost:3000".to_string()];
+        assert_eq!(CorsConfig { origins: Origins::List(origins), methods: vec![Method::GET] }, config);
+
+        let config = CorsConfig::parse("*", Some("get, POST")).unwrap();
+        assert_eq!(CorsConfig { origins: Origins::Any, methods: vec![Method::GET, Method::POST] }, config);
+
+        assert!(CorsConfig::parse("dash.example.com", None).is_err());
+        assert!(CorsConfig::parse("https://dash.example.com/stats", None).is_err());
+        assert!(CorsConfig::parse("*", Some("GET,")).is_err());
+    }
+}
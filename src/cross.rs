@@ -0,0 +1,96 @@
+//! Statistics across several symbols' windows.
+//!
+//! Windows are aligned on their most recent values: with windows of `n` and
+//! `m` points, the last `min(n, m)` points of each are paired up.
+
+use crate::TradingDataBuffer;
+
+/// Population covariance matrix of the windows over their aligned tails,
+/// with the number of aligned points. Computed in one pass with the
+/// multivariate Welford update.
+pub fn covariance_matrix(windows: &[&TradingDataBuffer]) -> (usize, Vec<Vec<f64>>) {
+    let m = windows.len();
+    let n = windows.iter().map(|w| w.len()).min().unwrap_or(0);
+    let mut iters: Vec<_> = windows.iter().map(|w| w.values().skip(w.len() - n)).collect();
+    let mut means = vec![0.0; m];
+    let mut deltas = vec![0.0; m];
+    let mut row = vec![0.0; m];
+    let mut co_moments = vec![vec![0.0; m]; m];
+
+    for count in 1..=n {
+        for (i, iter) in iters.iter_mut().enumerate() {
+            row[i] = iter.next().unwrap();
+            deltas[i] = row[i] - means[i];
+            means[i] += deltas[i] / count as f64;
+        }
+        for i in 0..m {
+            for j in 0..m {
+                co_moments[i][j] += deltas[i] * (row[j] - means[j]);
+            }
+        }
+    }
+
+    if n > 0 {
+        for value in co_moments.iter_mut().flatten() {
+            *value /= n as f64;
+        }
+    }
+    (n, co_moments)
+}
+
+/// Pearson correlation of two windows over their aligned tails, with the
+/// number of paired points. `None` when fewer than two points pair up or
+/// either side is flat.
+pub fn correlation(a: &TradingDataBuffer, b: &TradingDataBuffer) -> (usize, Option<f64>) {
+    let (n, cov) = covariance_matrix(&[a, b]);
+    if n < 2 || cov[0][0] <= 0.0 || cov[1][1] <= 0.0 {
+        return (n, None);
+    }
+    (n, Some(cov[0][1] / (cov[0][0] * cov[1][1]).sqrt()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_correlation_of_aligned_tails() {
+        let mut a = TradingDataBuffer::new(10);
+        let mut b = TradingDataBuffer::new(10);
+        a.add_batch(&[100.0, 1.0, 2.0, 3.0, 4.0]);
+        b.add_batch(&[8.0, 6.0, 4.0, 2.0]);
+
+        let (n, corr) = correlation(&a, &b);
+        assert_eq!(4, n);
+        assert!((corr.unwrap() + 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_covariance_matrix() {
+        let mut a = TradingDataBuffer::new(10);
+        let mut b = TradingDataBuffer::new(10);
+        let mut c = TradingDataBuffer::new(10);
+        a.add_batch(&[1.0, 2.0, 3.0]);
+        b.add_batch(&[2.0, 4.0, 6.0]);
+        c.add_batch(&[5.0, 5.0, 5.0]);
+
+        let (n, cov) = covariance_matrix(&[&a, &b, &c]);
+        assert_eq!(3, n);
+        let expected = [[2.0 / 3.0, 4.0 / 3.0, 0.0], [4.0 / 3.0, 8.0 / 3.0, 0.0], [0.0, 0.0, 0.0]];
+        for i in 0..3 {
+            for j in 0..3 {
+                assert!((cov[i][j] - expected[i][j]).abs() < 1e-9, "cov[{}][{}] = {}", i, j, cov[i][j]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_correlation_of_flat_window() {
+        let mut a = TradingDataBuffer::new(10);
+        let mut b = TradingDataBuffer::new(10);
+        a.add_batch(&[1.0, 1.0, 1.0]);
+        b.add_batch(&[1.0, 2.0, 3.0]);
+
+        assert_eq!((3, None), correlation(&a, &b));
+    }
+}
@@ -0,0 +1,161 @@
+//! Synthetic symbols computed from the prices of other symbols: spreads and
+//! ratios of two legs, and weighted baskets such as indices.
+//!
+//! Legs are aligned as of their latest price: every tick of a leg
+//! recomputes the synthetic value against the other legs' last prices and
+//! stamps it with the tick's own time. Nothing is emitted until every leg
+//! has a price.
+
+/// Most constituents a basket holds.
+pub const MAX_BASKET_CONSTITUENTS: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DerivedOp {
+    /// First leg minus second leg.
+    Spread,
+    /// First leg divided by second leg.
+    Ratio,
+}
+
+impl DerivedOp {
+    /// `None` for a ratio over a zero second leg.
+    pub fn apply(self, a: f64, b: f64) -> Option<f64> {
+        match self {
+            DerivedOp::Spread => Some(a - b),
+            DerivedOp::Ratio => Some(a / b).filter(|_| b != 0.0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct DerivedConfig {
+    pub op: DerivedOp,
+    pub legs: [String; 2],
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Constituent {
+    pub symbol: String,
+    /// Units of the symbol per unit of the basket, e.g. index shares.
+    pub weight: f64,
+}
+
+/// A basket worth the weighted sum of its constituents' prices over the
+/// divisor.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct BasketConfig {
+    pub constituents: Vec<Constituent>,
+    #[serde(default = "default_divisor")]
+    pub divisor: f64,
+}
+
+fn default_divisor() -> f64 {
+    1.0
+}
+
+impl BasketConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.constituents.is_empty() || self.constituents.len() > MAX_BASKET_CONSTITUENTS {
+            return Err(format!(
+                "Invalid constituents input. Expected 1 to {} symbols.",
+                MAX_BASKET_CONSTITUENTS
+            ));
+        }
+        for (i, constituent) in self.constituents.iter().enumerate() {
+            if self.constituents[..i].iter().any(|c| c.symbol == constituent.symbol) {
+                return Err(format!("Invalid constituents input. {} is repeated.", constituent.symbol));
+            }
+            if !constituent.weight.is_finite() {
+                return Err(format!("Invalid weight for {}. Only finite values are accepted.", constituent.symbol));
+            }
+        }
+        if !self.divisor.is_finite() || self.divisor == 0.0 {
+            return Err("Invalid divisor input. Only finite non-zero values are accepted.".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// What a synthetic symbol is computed from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Definition {
+    Pair(DerivedConfig),
+    Basket(BasketConfig),
+}
+
+impl Definition {
+    pub fn legs(&self) -> Vec<&str> {
+        match self {
+            Definition::Pair(config) => config.legs.iter().map(String::as_str).collect(),
+            Definition::Basket(config) => config.constituents.iter().map(|c| c.symbol.as_str()).collect(),
+        }
+    }
+}
+
+/// A synthetic symbol's definition with the latest price of each leg.
+#[derive(Debug, Clone)]
+pub struct Derivation {
+    definition: Definition,
+    last: Vec<Option<f64>>,
+}
+
+impl Derivation {
+    pub fn new(definition: Definition) -> Self {
+        let legs = definition.legs().len();
+        Derivation { definition, last: vec![None; legs] }
+    }
+
+    pub fn definition(&self) -> &Definition {
+        &self.definition
+    }
+
+    /// Records a tick of `leg` and returns the synthetic value it produces,
+    /// if any. Ticks of symbols that aren't legs are ignored.
+    pub fn update(&mut self, leg: &str, price: f64) -> Option<f64> {
+        let i = self.definition.legs().iter().position(|&l| l == leg)?;
+        self.last[i] = Some(price);
+        match &self.definition {
+            Definition::Pair(config) => config.op.apply(self.last[0]?, self.last[1]?),
+            Definition::Basket(config) => {
+                let mut sum = 0.0;
+                for (constituent, last) in config.constituents.iter().zip(&self.last) {
+                    sum += constituent.weight * (*last)?;
+                }
+                Some(sum / config.divisor)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update_waits_for_both_legs() {
+        let config = DerivedConfig { op: DerivedOp::Ratio, legs: ["A".to_string(), "B".to_string()] };
+        let mut derivation = Derivation::new(Definition::Pair(config));
+        assert_eq!(None, derivation.update("A", 10.0));
+        assert_eq!(None, derivation.update("C", 1.0));
+        assert_eq!(Some(2.0), derivation.update("B", 5.0));
+        assert_eq!(Some(4.0), derivation.update("A", 20.0));
+        assert_eq!(None, derivation.update("B", 0.0));
+        assert_eq!(Some(-20.0), DerivedOp::Spread.apply(0.0, 20.0));
+    }
+
+    #[test]
+    fn test_basket_value() {
+        let constituent = |symbol: &str, weight| Constituent { symbol: symbol.to_string(), weight };
+        let config = BasketConfig { constituents: vec![constituent("A", 2.0), constituent("B", 1.0)], divisor: 4.0 };
+        assert!(config.validate().is_ok());
+        let mut derivation = Derivation::new(Definition::Basket(config));
+        assert_eq!(None, derivation.update("A", 10.0));
+        assert_eq!(Some(7.0), derivation.update("B", 8.0));
+        assert_eq!(Some(8.0), derivation.update("A", 12.0));
+
+        let repeated = BasketConfig { constituents: vec![constituent("A", 1.0), constituent("A", 1.0)], divisor: 1.0 };
+        assert!(repeated.validate().is_err());
+        assert!(BasketConfig { constituents: vec![constituent("A", 1.0)], divisor: 0.0 }.validate().is_err());
+    }
+}
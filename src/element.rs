@@ -0,0 +1,142 @@
+//! Element types a window can store its values in.
+//!
+//! A window keeps its per-point values, weights and volumes as its element
+//! type and converts them to `f64` for every aggregate, so an `f32` window
+//! halves that storage when single precision is enough, and an `i64` window
+//! holds integer values such as prices in ticks exactly.
+
+use std::collections::VecDeque;
+
+pub trait Element: Copy + Default + PartialOrd + std::fmt::Debug + Send + Sync + 'static {
+    /// Converts an ingested value, rounding to the nearest representable one.
+    fn from_f64(x: f64) -> Self;
+
+    fn to_f64(self) -> f64;
+}
+
+impl Element for f64 {
+    fn from_f64(x: f64) -> Self {
+        x
+    }
+
+    fn to_f64(self) -> f64 {
+        self
+    }
+}
+
+impl Element for f32 {
+    fn from_f64(x: f64) -> Self {
+        x as f32
+    }
+
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+}
+
+impl Element for i64 {
+    /// Rounds to the nearest integer, saturating at the `i64` range; NaN
+    /// becomes 0.
+    fn from_f64(x: f64) -> Self {
+        x.round() as i64
+    }
+
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+}
+
+/// A per-point column such as the weights, which holds nothing until a
+/// point differs from the column's default; until then every point has it.
+#[derive(Debug, Clone)]
+pub struct Column<T> {
+    points: Option<VecDeque<T>>,
+    default: f64,
+}
+
+impl<T: Element> Column<T> {
+    pub fn new(default: f64) -> Self {
+        Column { points: None, default }
+    }
+
+    /// Appends the value of the point after the `len` already held.
+    pub fn push(&mut self, value: f64, len: usize) {
+        match &mut self.points {
+            Some(points) => points.push_back(T::from_f64(value)),
+            None if value == self.default => {}
+            None => {
+                let mut points: VecDeque<T> = std::iter::repeat_n(T::from_f64(self.default), len).collect();
+                points.push_back(T::from_f64(value));
+                self.points = Some(points);
+            }
+        }
+    }
+
+    /// Appends the values of a batch of points after the `len` already held.
+    pub fn extend(&mut self, values: &[f64], len: usize) {
+        match &mut self.points {
+            Some(points) => points.extend(values.iter().map(|&value| T::from_f64(value))),
+            None => {
+                let Some(first) = values.iter().position(|&value| value != self.default) else { return };
+                let backfill = std::iter::repeat_n(self.default, len + first);
+                self.points = Some(backfill.chain(values[first..].iter().copied()).map(T::from_f64).collect());
+            }
+        }
+    }
+
+    /// Removes and returns the oldest point's value.
+    pub fn pop_front(&mut self) -> T {
+        self.points.as_mut().and_then(|points| points.pop_front()).unwrap_or(T::from_f64(self.default))
+    }
+
+    /// Removes the `n` oldest points and returns their values.
+    pub fn drain_front(&mut self, n: usize) -> Vec<f64> {
+        match &mut self.points {
+            Some(points) => points.drain(..n).map(|value| value.to_f64()).collect(),
+            None => vec![self.default; n],
+        }
+    }
+
+    pub fn get(&self, i: usize) -> T {
+        self.points.as_ref().map_or(T::from_f64(self.default), |points| points[i])
+    }
+
+    /// Points the column has room for without reallocating; 0 until a point
+    /// differs from the default.
+    pub fn capacity(&self) -> usize {
+        self.points.as_ref().map_or(0, |points| points.capacity())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trips() {
+        assert_eq!(0.1, f64::from_f64(0.1).to_f64());
+        assert_eq!(0.1f32 as f64, f32::from_f64(0.1).to_f64());
+        assert_eq!(3.0, i64::from_f64(2.5).to_f64());
+        assert_eq!(-3, i64::from_f64(-2.7));
+        assert_eq!(0, i64::from_f64(f64::NAN));
+    }
+
+    #[test]
+    fn test_column_allocates_on_first_other_value() {
+        let mut weights = Column::<f64>::new(1.0);
+        weights.push(1.0, 0);
+        weights.push(1.0, 1);
+        assert_eq!(0, weights.capacity());
+        weights.push(2.0, 2);
+        assert!(weights.capacity() >= 3);
+        assert_eq!((1.0, 2.0), (weights.get(0), weights.get(2)));
+
+        let mut volumes = Column::<f64>::new(0.0);
+        volumes.extend(&[0.0, 0.0], 1);
+        assert_eq!(0, volumes.capacity());
+        volumes.extend(&[0.0, 5.0, 6.0], 3);
+        assert_eq!((0.0, 5.0, 6.0), (volumes.get(3), volumes.get(4), volumes.get(5)));
+        assert_eq!(1.0, weights.pop_front());
+        assert_eq!(2.0, weights.get(1));
+    }
+}
@@ -0,0 +1,70 @@
+//! Errors returned by the data service, each with a stable code clients can
+//! branch on.
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ServiceError {
+    /// The symbol isn't tracked.
+    UnknownSymbol,
+    /// Something else the request names doesn't exist, e.g. a field, venue,
+    /// alias or bar interval.
+    NotFound(String),
+    /// A window exponent outside 1..=8.
+    InvalidK,
+    /// A batch over its size limit.
+    BatchTooLarge(String),
+    /// Input that fails validation.
+    InvalidValue(String),
+    /// The request clashes with the current state, e.g. a symbol still used
+    /// by another.
+    Conflict(String),
+    /// A configured limit, such as the number of symbols, is reached.
+    LimitReached(String),
+    /// The request lacks valid credentials for an operator endpoint.
+    Unauthorized(String),
+    /// A failure on the service's side, e.g. a panicked computation.
+    Internal(String),
+}
+
+impl ServiceError {
+    /// Stable machine-readable code of the error kind.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ServiceError::UnknownSymbol => "unknown_symbol",
+            ServiceError::NotFound(_) => "not_found",
+            ServiceError::InvalidK => "invalid_k",
+            ServiceError::BatchTooLarge(_) => "batch_too_large",
+            ServiceError::InvalidValue(_) => "invalid_value",
+            ServiceError::Conflict(_) => "conflict",
+            ServiceError::LimitReached(_) => "limit_reached",
+            ServiceError::Unauthorized(_) => "unauthorized",
+            ServiceError::Internal(_) => "internal",
+        }
+    }
+}
+
+impl fmt::Display for ServiceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServiceError::UnknownSymbol => write!(f, "Symbol not found"),
+            ServiceError::InvalidK => write!(f, "Invalid k input. Only values 1-8 are accepted."),
+            ServiceError::NotFound(message)
+            | ServiceError::BatchTooLarge(message)
+            | ServiceError::InvalidValue(message)
+            | ServiceError::Conflict(message)
+            | ServiceError::LimitReached(message)
+            | ServiceError::Unauthorized(message)
+            | ServiceError::Internal(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for ServiceError {}
+
+/// Validation messages from the config and input types.
+impl From<String> for ServiceError {
+    fn from(message: String) -> Self {
+        ServiceError::InvalidValue(message)
+    }
+}
@@ -0,0 +1,149 @@
+//! Windows, bars and batches as Arrow record batches, for loading into
+//! pandas or polars without parsing JSON, over `/export/arrow` or Flight.
+
+use std::sync::Arc;
+
+use actix_web::{web, HttpResponse, Responder};
+use arrow_array::cast::AsArray;
+use arrow_array::types::{Float64Type, Int64Type, TimestampNanosecondType, UInt64Type};
+use arrow_array::{Array, ArrayRef, Float64Array, RecordBatch, TimestampMillisecondArray, TimestampNanosecondArray, UInt64Array};
+use arrow_ipc::writer::StreamWriter;
+use arrow_schema::{ArrowError, DataType, Field, Schema, TimeUnit};
+use serde::Deserialize;
+use trading_service::bars::Bar;
+use trading_service::{Batch, ServiceError};
+
+use crate::{error_response, Tenant};
+
+const ARROW_STREAM: &str = "application/vnd.apache.arrow.stream";
+
+/// A window's points as `timestamp`, `value`, `weight` and `volume`
+/// columns, oldest first.
+pub fn window_batch(batch: Batch) -> Result<RecordBatch, ArrowError> {
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("timestamp", DataType::Timestamp(TimeUnit::Nanosecond, Some("UTC".into())), false),
+        Field::new("value", DataType::Float64, false),
+        Field::new("weight", DataType::Float64, false),
+        Field::new("volume", DataType::Float64, false),
+    ]));
+    // Nanoseconds since the epoch fit an i64 until the year 2262.
+    let timestamps = batch.timestamps.unwrap_or_default().into_iter().map(|ts| ts as i64).collect::<Vec<_>>();
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(TimestampNanosecondArray::from(timestamps).with_timezone("UTC")),
+        Arc::new(Float64Array::from(batch.values)),
+        Arc::new(Float64Array::from(batch.weights.unwrap_or_default())),
+        Arc::new(Float64Array::from(batch.volumes.unwrap_or_default())),
+    ];
+    RecordBatch::try_new(schema, columns)
+}
+
+/// Bars as `start` (milliseconds, UTC), `open`, `high`, `low`, `close` and
+/// `ticks` columns.
+pub fn bars_batch(bars: &[Bar]) -> Result<RecordBatch, ArrowError> {
+    let prices = |price: fn(&Bar) -> f64| Arc::new(Float64Array::from_iter_values(bars.iter().map(price))) as ArrayRef;
+    let starts = bars.iter().map(|bar| bar.start as i64).collect::<Vec<_>>();
+    RecordBatch::try_from_iter([
+        ("start", Arc::new(TimestampMillisecondArray::from(starts).with_timezone("UTC")) as ArrayRef),
+        ("open", prices(|bar| bar.open)),
+        ("high", prices(|bar| bar.high)),
+        ("low", prices(|bar| bar.low)),
+        ("close", prices(|bar| bar.close)),
+        ("ticks", Arc::new(UInt64Array::from_iter_values(bars.iter().map(|bar| bar.ticks)))),
+    ])
+}
+
+/// The batch a record batch of a `value` column and optional `timestamp`,
+/// `weight` and `volume` columns holds, laid out like [`window_batch`]'s.
+pub fn from_record_batch(record_batch: &RecordBatch) -> Result<Batch, String> {
+    let float_column = |name: &str| -> Result<Option<Vec<f64>>, String> {
+        let Some(column) = record_batch.column_by_name(name) else {
+            return Ok(None);
+        };
+        match column.as_primitive_opt::<Float64Type>() {
+            Some(column) if column.null_count() == 0 => Ok(Some(column.values().to_vec())),
+            _ => Err(format!("Invalid {} column. Expected non-null float64 values.", name)),
+        }
+    };
+    let values = float_column("value")?.ok_or_else(|| "Invalid record batch. A value column is required.".to_string())?;
+    let timestamps = match record_batch.column_by_name("timestamp") {
+        None => None,
+        Some(column) if column.null_count() > 0 => return Err("Invalid timestamp column. Nulls aren't accepted.".to_string()),
+        Some(column) => {
+            let timestamps = match column.data_type() {
+                DataType::UInt64 => Some(column.as_primitive::<UInt64Type>().values().to_vec()),
+                DataType::Int64 => column.as_primitive::<Int64Type>().values().iter().map(|&ts| u64::try_from(ts).ok()).collect(),
+                DataType::Timestamp(TimeUnit::Nanosecond, _) => column
+                    .as_primitive::<TimestampNanosecondType>()
+                    .values()
+                    .iter()
+                    .map(|&ts| u64::try_from(ts).ok())
+                    .collect(),
+                _ => return Err("Invalid timestamp column. Expected nanoseconds since the Unix epoch.".to_string()),
+            };
+            Some(timestamps.ok_or_else(|| "Invalid timestamp column. Timestamps before 1970 aren't accepted.".to_string())?)
+        }
+    };
+    Ok(Batch { values, timestamps, weights: float_column("weight")?, volumes: float_column("volume")?, ..Batch::default() })
+}
+
+/// The window's points as an Arrow IPC stream of one record batch.
+fn to_arrow(batch: Batch) -> Result<Vec<u8>, ArrowError> {
+    let record_batch = window_batch(batch)?;
+    let mut writer = StreamWriter::try_new(Vec::new(), &record_batch.schema())?;
+    writer.write(&record_batch)?;
+    writer.into_inner()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    symbol: String,
+    k: u8,
+}
+
+/// Exports the symbol's 10^k window as an Arrow IPC stream. The window is
+/// copied under the read lock and encoded off the async workers.
+pub async fn export_arrow(service: Tenant, query: web::Query<ExportQuery>) -> impl Responder {
+    let ExportQuery { symbol, k } = query.into_inner();
+    let batch = match service.get_window(symbol, k as usize).await {
+        Ok(batch) => batch,
+        Err(e) => return error_response(e),
+    };
+    match web::block(move || to_arrow(batch)).await {
+        Ok(Ok(bytes)) => HttpResponse::Ok().content_type(ARROW_STREAM).body(bytes),
+        Ok(Err(e)) => error_response(ServiceError::Internal(e.to_string())),
+        Err(e) => error_response(ServiceError::Internal(e.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_ipc::reader::StreamReader;
+
+    #[test]
+    fn test_to_arrow() {
+        let batch = Batch {
+            values: vec![1.0, 2.5],
+            weights: Some(vec![1.0, 1.0]),
+            volumes: Some(vec![100.0, 0.0]),
+            timestamps: Some(vec![1_000, 2_000]),
+            ..Batch::default()
+        };
+        let bytes = to_arrow(batch).unwrap();
+        let batches = StreamReader::try_new(bytes.as_slice(), None).unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(1, batches.len());
+        let record_batch = &batches[0];
+        assert_eq!(2, record_batch.num_rows());
+        assert_eq!(&[1_000, 2_000], record_batch.column(0).as_primitive::<TimestampNanosecondType>().values().as_ref());
+        assert_eq!(&[1.0, 2.5], record_batch.column(1).as_primitive::<Float64Type>().values().as_ref());
+        assert_eq!(&[100.0, 0.0], record_batch.column(3).as_primitive::<Float64Type>().values().as_ref());
+
+        let batch = from_record_batch(record_batch).unwrap();
+        assert_eq!(vec![1.0, 2.5], batch.values);
+        assert_eq!(Some(vec![1_000, 2_000]), batch.timestamps);
+        assert_eq!(Some(vec![100.0, 0.0]), batch.volumes);
+
+        let values_only = RecordBatch::try_from_iter([("price", Arc::new(Float64Array::from(vec![1.0])) as ArrayRef)]);
+        assert!(from_record_batch(&values_only.unwrap()).is_err());
+    }
+}
@@ -0,0 +1,538 @@
+//! Formulas computing extra series from every ingested point.
+//!
+//! A formula is an arithmetic expression over the point's `price`, `volume`
+//! and extra fields, the formulas before it, and `prev(x)`, the last value
+//! `x` had before this point. It supports `+ - * / ^`, parentheses and the
+//! functions `ln`, `log10`, `exp`, `sqrt`, `abs`, `min` and `max`, e.g.
+//! `(bid + ask) / 2` or `ln(price / prev(price))`. A point yields no value
+//! for a formula when an input is missing or the result isn't finite.
+//!
+//! A condition compares two such expressions over window stats named
+//! `<stat>_<k>`, e.g. `abs(avg_2 - avg_6) > 3 * std_dev_6`, for pushing
+//! only the updates that matter to a subscriber.
+
+/// Most formulas a symbol evaluates.
+pub const MAX_FORMULAS: usize = 16;
+
+/// Longest expression accepted.
+pub const MAX_EXPRESSION_LEN: usize = 256;
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct FormulaConfig {
+    /// Name of the series the formula feeds, queried like an extra field.
+    pub name: String,
+    pub expression: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Func {
+    Ln,
+    Log10,
+    Exp,
+    Sqrt,
+    Abs,
+    Min,
+    Max,
+}
+
+impl Func {
+    fn parse(name: &str) -> Option<Func> {
+        Some(match name {
+            "ln" => Func::Ln,
+            "log10" => Func::Log10,
+            "exp" => Func::Exp,
+            "sqrt" => Func::Sqrt,
+            "abs" => Func::Abs,
+            "min" => Func::Min,
+            "max" => Func::Max,
+            _ => return None,
+        })
+    }
+
+    fn arity(self) -> usize {
+        match self {
+            Func::Min | Func::Max => 2,
+            _ => 1,
+        }
+    }
+
+    fn apply(self, args: &[f64]) -> f64 {
+        match self {
+            Func::Ln => args[0].ln(),
+            Func::Log10 => args[0].log10(),
+            Func::Exp => args[0].exp(),
+            Func::Sqrt => args[0].sqrt(),
+            Func::Abs => args[0].abs(),
+            Func::Min => args[0].min(args[1]),
+            Func::Max => args[0].max(args[1]),
+        }
+    }
+}
+
+/// A parsed expression whose variables are resolved to slots.
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Number(f64),
+    Var(usize),
+    Prev(usize),
+    Neg(Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+    Call(Func, Vec<Expr>),
+}
+
+impl Expr {
+    fn eval(&self, current: &[Option<f64>], prev: &[Option<f64>]) -> Option<f64> {
+        Some(match self {
+            Expr::Number(x) => *x,
+            Expr::Var(slot) => current[*slot]?,
+            Expr::Prev(slot) => prev[*slot]?,
+            Expr::Neg(e) => -e.eval(current, prev)?,
+            Expr::Binary(op, a, b) => {
+                let (a, b) = (a.eval(current, prev)?, b.eval(current, prev)?);
+                match op {
+                    BinOp::Add => a + b,
+                    BinOp::Sub => a - b,
+                    BinOp::Mul => a * b,
+                    BinOp::Div => a / b,
+                    BinOp::Pow => a.powf(b),
+                }
+            }
+            Expr::Call(func, args) => {
+                let args = args.iter().map(|a| a.eval(current, prev)).collect::<Option<Vec<f64>>>()?;
+                func.apply(&args)
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Symbol(char),
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() || c == '.' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            let number = text.parse().map_err(|_| format!("Invalid number '{}'", text))?;
+            tokens.push(Token::Number(number));
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else if "+-*/^(),<>=".contains(c) {
+            tokens.push(Token::Symbol(c));
+            i += 1;
+        } else {
+            return Err(format!("Unexpected character '{}'", c));
+        }
+    }
+    Ok(tokens)
+}
+
+/// Recursive-descent parser over the tokens, resolving names against the
+/// slots defined so far.
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    slots: &'a [String],
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn eat(&mut self, symbol: char) -> bool {
+        if self.peek() == Some(&Token::Symbol(symbol)) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn expect(&mut self, symbol: char) -> Result<(), String> {
+        if self.eat(symbol) {
+            return Ok(());
+        }
+        Err(format!("Expected '{}'", symbol))
+    }
+
+    fn slot(&self, name: &str) -> Result<usize, String> {
+        self.slots.iter().position(|s| s == name).ok_or_else(|| format!("Unknown variable '{}'", name))
+    }
+
+    /// condition := expr ('<' | '<=' | '>' | '>=') expr
+    fn condition(&mut self) -> Result<(Expr, Comparison, Expr), String> {
+        let left = self.expr()?;
+        let comparison = if self.eat('<') {
+            if self.eat('=') { Comparison::LessOrEqual } else { Comparison::Less }
+        } else if self.eat('>') {
+            if self.eat('=') { Comparison::GreaterOrEqual } else { Comparison::Greater }
+        } else {
+            return Err("Expected a comparison: <, <=, > or >=".to_string());
+        };
+        let right = self.expr()?;
+        if self.pos < self.tokens.len() {
+            return Err("Unexpected input after the condition".to_string());
+        }
+        Ok((left, comparison, right))
+    }
+
+    /// expr := term (('+' | '-') term)*
+    fn expr(&mut self) -> Result<Expr, String> {
+        let mut left = self.term()?;
+        loop {
+            let op = if self.eat('+') {
+                BinOp::Add
+            } else if self.eat('-') {
+                BinOp::Sub
+            } else {
+                return Ok(left);
+            };
+            left = Expr::Binary(op, Box::new(left), Box::new(self.term()?));
+        }
+    }
+
+    /// term := unary (('*' | '/') unary)*
+    fn term(&mut self) -> Result<Expr, String> {
+        let mut left = self.unary()?;
+        loop {
+            let op = if self.eat('*') {
+                BinOp::Mul
+            } else if self.eat('/') {
+                BinOp::Div
+            } else {
+                return Ok(left);
+            };
+            left = Expr::Binary(op, Box::new(left), Box::new(self.unary()?));
+        }
+    }
+
+    /// unary := '-' unary | power
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.eat('-') {
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        self.power()
+    }
+
+    /// power := primary ('^' unary)?, so `^` binds right to left.
+    fn power(&mut self) -> Result<Expr, String> {
+        let base = self.primary()?;
+        if self.eat('^') {
+            return Ok(Expr::Binary(BinOp::Pow, Box::new(base), Box::new(self.unary()?)));
+        }
+        Ok(base)
+    }
+
+    /// primary := number | name | name '(' args ')' | '(' expr ')'
+    fn primary(&mut self) -> Result<Expr, String> {
+        match self.peek().cloned() {
+            Some(Token::Number(x)) => {
+                self.pos += 1;
+                Ok(Expr::Number(x))
+            }
+            Some(Token::Symbol('(')) => {
+                self.pos += 1;
+                let inner = self.expr()?;
+                self.expect(')')?;
+                Ok(inner)
+            }
+            Some(Token::Ident(name)) => {
+                self.pos += 1;
+                if !self.eat('(') {
+                    return Ok(Expr::Var(self.slot(&name)?));
+                }
+                if name == "prev" {
+                    let Some(Token::Ident(arg)) = self.peek().cloned() else {
+                        return Err("prev() takes a variable name".to_string());
+                    };
+                    self.pos += 1;
+                    self.expect(')')?;
+                    return Ok(Expr::Prev(self.slot(&arg)?));
+                }
+                let func = Func::parse(&name).ok_or_else(|| format!("Unknown function '{}'", name))?;
+                let mut args = vec![self.expr()?];
+                while self.eat(',') {
+                    args.push(self.expr()?);
+                }
+                self.expect(')')?;
+                if args.len() != func.arity() {
+                    return Err(format!("{}() takes {} argument(s)", name, func.arity()));
+                }
+                Ok(Expr::Call(func, args))
+            }
+            Some(Token::Symbol(c)) => Err(format!("Unexpected '{}'", c)),
+            None => Err("Unexpected end of expression".to_string()),
+        }
+    }
+}
+
+fn parse(source: &str, slots: &[String]) -> Result<Expr, String> {
+    let mut parser = Parser { tokens: tokenize(source)?, pos: 0, slots };
+    let expr = parser.expr()?;
+    if parser.pos < parser.tokens.len() {
+        return Err("Unexpected input after the expression".to_string());
+    }
+    Ok(expr)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Comparison {
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+/// A compiled condition over window stats, remembering the variables' last
+/// values for `prev` and whether it held at the last check.
+#[derive(Debug, Clone)]
+pub struct Condition {
+    /// The stat and window exponent of every variable, by slot.
+    slots: Vec<(String, usize)>,
+    left: Expr,
+    comparison: Comparison,
+    right: Expr,
+    prev: Vec<Option<f64>>,
+    held: bool,
+}
+
+impl Condition {
+    /// Compiles the condition; `is_stat` says which stat names exist.
+    pub fn parse(source: &str, is_stat: impl Fn(&str) -> bool) -> Result<Condition, String> {
+        if source.len() > MAX_EXPRESSION_LEN {
+            return Err(format!("Invalid condition. Conditions are up to {} characters.", MAX_EXPRESSION_LEN));
+        }
+        let tokens = tokenize(source).map_err(|e| format!("Invalid condition: {}", e))?;
+        // Every name not called as a function is a variable, including the
+        // argument of `prev`.
+        let mut names: Vec<String> = Vec::new();
+        for (i, token) in tokens.iter().enumerate() {
+            if let Token::Ident(name) = token {
+                if tokens.get(i + 1) != Some(&Token::Symbol('(')) && !names.contains(name) {
+                    names.push(name.clone());
+                }
+            }
+        }
+        let slots = names
+            .iter()
+            .map(|name| {
+                let window = name.rsplit_once('_').and_then(|(stat, k)| Some((stat, k.parse::<usize>().ok()?)));
+                match window {
+                    Some((stat, k)) if is_stat(stat) && (1..=8).contains(&k) => Ok((stat.to_string(), k)),
+                    _ => Err(format!("Invalid condition: '{}' isn't a stat and window such as avg_2", name)),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut parser = Parser { tokens, pos: 0, slots: &names };
+        let (left, comparison, right) = parser.condition().map_err(|e| format!("Invalid condition: {}", e))?;
+        let prev = vec![None; slots.len()];
+        Ok(Condition { slots, left, comparison, right, prev, held: false })
+    }
+
+    /// Window exponents the condition reads, in ascending order.
+    pub fn windows(&self) -> Vec<usize> {
+        let mut windows: Vec<usize> = self.slots.iter().map(|(_, k)| *k).collect();
+        windows.sort_unstable();
+        windows.dedup();
+        windows
+    }
+
+    /// Checks the condition against the current stats, given by stat name
+    /// and window exponent, and returns whether it just came to hold: true
+    /// once when it goes from false to true, and false while it keeps
+    /// holding. It doesn't hold while a stat it reads is missing.
+    pub fn check(&mut self, stat: impl Fn(&str, usize) -> Option<f64>) -> bool {
+        let current: Vec<Option<f64>> = self.slots.iter().map(|(name, k)| stat(name, *k)).collect();
+        let holds = match (self.left.eval(&current, &self.prev), self.right.eval(&current, &self.prev)) {
+            (Some(a), Some(b)) => match self.comparison {
+                Comparison::Less => a < b,
+                Comparison::LessOrEqual => a <= b,
+                Comparison::Greater => a > b,
+                Comparison::GreaterOrEqual => a >= b,
+            },
+            _ => false,
+        };
+        for (prev, value) in self.prev.iter_mut().zip(current) {
+            if value.is_some() {
+                *prev = value;
+            }
+        }
+        let fired = holds && !self.held;
+        self.held = holds;
+        fired
+    }
+}
+
+/// A symbol's compiled formulas with the last value of every variable.
+#[derive(Debug, Clone, Default)]
+pub struct Formulas {
+    /// `price`, `volume`, the extra fields, then the formulas.
+    slots: Vec<String>,
+    inputs: usize,
+    compiled: Vec<Expr>,
+    prev: Vec<Option<f64>>,
+}
+
+impl Formulas {
+    /// Compiles the formulas over the symbol's extra fields. Formula names
+    /// can't clash with the inputs, each other or the functions.
+    pub fn new(formulas: &[FormulaConfig], fields: &[String]) -> Result<Self, String> {
+        if formulas.len() > MAX_FORMULAS {
+            return Err(format!("Invalid formulas input. Only up to {} formulas are accepted.", MAX_FORMULAS));
+        }
+        let mut slots = vec!["price".to_string(), "volume".to_string()];
+        slots.extend(fields.iter().cloned());
+        let inputs = slots.len();
+        let mut compiled = Vec::with_capacity(formulas.len());
+        for formula in formulas {
+            let name = &formula.name;
+            let is_ident = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if !is_ident || slots.contains(name) || name == "prev" || Func::parse(name).is_some() {
+                return Err(format!("Invalid formula name '{}'. It's not a name or already taken.", name));
+            }
+            if formula.expression.len() > MAX_EXPRESSION_LEN {
+                return Err(format!("Invalid formula {}. Expressions are up to {} characters.", name, MAX_EXPRESSION_LEN));
+            }
+            let expr = parse(&formula.expression, &slots).map_err(|e| format!("Invalid formula {}: {}", name, e))?;
+            compiled.push(expr);
+            slots.push(name.clone());
+        }
+        let prev = vec![None; slots.len()];
+        Ok(Formulas { slots, inputs, compiled, prev })
+    }
+
+    /// Keeps the last values of the variables `old` had in common with
+    /// these formulas, so `prev` carries over a config change.
+    pub fn carry_over(&mut self, old: &Formulas) {
+        for (slot, prev) in self.slots.iter().zip(self.prev.iter_mut()) {
+            if let Some(i) = old.slots.iter().position(|s| s == slot) {
+                *prev = old.prev[i];
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.compiled.is_empty()
+    }
+
+    pub fn names(&self) -> &[String] {
+        &self.slots[self.inputs..]
+    }
+
+    /// Evaluates every formula for one point, given its inputs by name, and
+    /// returns their values in order.
+    pub fn evaluate(&mut self, input: impl Fn(&str) -> Option<f64>) -> Vec<Option<f64>> {
+        let mut current: Vec<Option<f64>> = self.slots[..self.inputs].iter().map(|name| input(name)).collect();
+        for expr in &self.compiled {
+            let value = expr.eval(&current, &self.prev).filter(|v| v.is_finite());
+            current.push(value);
+        }
+        for (prev, value) in self.prev.iter_mut().zip(&current) {
+            if value.is_some() {
+                *prev = *value;
+            }
+        }
+        current.split_off(self.inputs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn formula(name: &str, expression: &str) -> FormulaConfig {
+        FormulaConfig { name: name.to_string(), expression: expression.to_string() }
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let fields = vec!["bid".to_string(), "ask".to_string()];
+        let configs = [
+            formula("mid", "(bid + ask) / 2"),
+            formula("logret", "ln(price / prev(price))"),
+            formula("skew", "-2 ^ 2 + max(mid - price, 0) * 10"),
+        ];
+        let mut formulas = Formulas::new(&configs, &fields).unwrap();
+        let point = |price: f64| move |name: &str| match name {
+            "price" => Some(price),
+            "bid" => Some(price - 1.0),
+            "ask" => Some(price + 2.0),
+            _ => None,
+        };
+        assert_eq!(vec![Some(100.5), None, Some(1.0)], formulas.evaluate(point(100.0)));
+        let values = formulas.evaluate(point(110.0));
+        assert!((values[1].unwrap() - (1.1f64).ln()).abs() < 1e-12);
+        assert_eq!(vec![None, None, None], formulas.evaluate(|_| None));
+    }
+
+    #[test]
+    fn test_invalid_formulas() {
+        let fields = vec!["bid".to_string()];
+        for config in [
+            formula("bid", "price"),
+            formula("x", "price +"),
+            formula("x", "ask * 2"),
+            formula("x", "sqrt(price, 2)"),
+            formula("x", "prev(2)"),
+            formula("x", "x + 1"),
+            formula("ln", "price"),
+        ] {
+            assert!(Formulas::new(&[config], &fields).is_err());
+        }
+    }
+
+    #[test]
+    fn test_condition() {
+        let is_stat = |name: &str| ["avg", "std_dev"].contains(&name);
+        let mut condition = Condition::parse("abs(avg_2 - avg_6) > 3 * std_dev_6", is_stat).unwrap();
+        assert_eq!(vec![2, 6], condition.windows());
+        let stats = |avg_2: f64| move |stat: &str, k: usize| match (stat, k) {
+            ("avg", 2) => Some(avg_2),
+            ("avg", 6) => Some(100.0),
+            ("std_dev", 6) => Some(1.0),
+            _ => None,
+        };
+        assert!(!condition.check(stats(102.0)));
+        assert!(condition.check(stats(96.0)));
+        // Fires again only after it stopped holding.
+        assert!(!condition.check(stats(95.0)));
+        assert!(!condition.check(stats(100.0)));
+        assert!(condition.check(stats(104.0)));
+
+        let mut rising = Condition::parse("avg_2 >= prev(avg_2) + 1", is_stat).unwrap();
+        assert!(!rising.check(stats(100.0)));
+        assert!(rising.check(stats(101.0)));
+
+        for invalid in ["avg_2", "avg_9 > 1", "vwap_2 > 1", "avg > 1", "avg_2 > 1 > 2", "avg_2 = 1"] {
+            assert!(Condition::parse(invalid, is_stat).is_err());
+        }
+    }
+}
@@ -0,0 +1,462 @@
+//! A lock-free ingestion path for the hottest symbols. Each hot symbol gets
+//! a single-producer single-consumer ring of raw values and a consumer
+//! thread of its own, pinned to a CPU core, that adds them to the symbol's
+//! windows. A handler publishing a batch runs the symbol's checks, copies
+//! the values into the ring and returns: it waits on neither the symbol's
+//! lock nor its ingestion task, so handler latency stays flat under bursts.
+//! A full ring turns batches away rather than making handlers wait.
+//!
+//! The ring has one producer at a time; concurrent handlers take turns
+//! through a mutex held only while they copy their values in. Only batches
+//! of values alone take the fast path. Batches with weights, timestamps,
+//! IDs or the like go the usual way and may overtake values still in the
+//! ring.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex, OnceLock, RwLock};
+use std::thread::{self, JoinHandle, Thread};
+use std::time::Duration;
+
+use crate::filters::NonFiniteAction;
+use crate::meta::SymbolMeta;
+use crate::{Batch, ServiceError};
+
+/// Values a symbol's ring holds; a power of two.
+pub const RING_CAPACITY: usize = 1 << 16;
+
+/// Most symbols with a fast path at once.
+pub const MAX_FAST_PATHS: usize = 64;
+
+/// Rounds an idle consumer spins for new values before it parks.
+const IDLE_SPINS: u32 = 1000;
+
+/// How long a parked consumer sleeps, unless a producer wakes it first.
+const IDLE_PARK: Duration = Duration::from_millis(1);
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct FastPathStats {
+    /// Values the ring holds.
+    pub capacity: usize,
+    /// Values waiting in the ring.
+    pub queued: usize,
+    /// Batches queued and not yet added.
+    pub pending: u64,
+    /// Values added to the windows.
+    pub applied: u64,
+    /// Batches turned away while the ring was full.
+    pub rejected: u64,
+    /// Values that passed the checks when queued but that the windows
+    /// didn't take, e.g. because the symbol's config changed meanwhile.
+    pub failed: u64,
+    /// CPU core the consumer thread is pinned to, if any.
+    pub core: Option<usize>,
+}
+
+/// The checks a batch of values alone must pass before it's queued, as of
+/// the symbol's latest config and reference data. Nothing else adding a
+/// batch checks can fail for values alone.
+#[derive(Debug, Clone, Default)]
+pub struct Screen {
+    pub non_finite: NonFiniteAction,
+    pub meta: SymbolMeta,
+}
+
+impl Screen {
+    pub fn check(&self, values: &[f64]) -> Result<(), ServiceError> {
+        if let (NonFiniteAction::Reject, Some(i)) = (self.non_finite, values.iter().position(|v| !v.is_finite())) {
+            return Err(format!("Invalid value at index {}. Only finite values are accepted.", i).into());
+        }
+        let finite: Vec<f64> = values.iter().copied().filter(|v| v.is_finite()).collect();
+        if let Some(price) = self.meta.first_off_tick(&finite) {
+            return Err(format!("Value {} is not a multiple of the tick size", price).into());
+        }
+        Ok(())
+    }
+}
+
+/// A bounded single-producer single-consumer ring of values, each slot
+/// holding an `f64`'s bits. The producer alone moves `tail` and the
+/// consumer alone moves `head`; each only reads the other's, so neither
+/// ever waits on the other.
+struct Ring {
+    slots: Box<[AtomicU64]>,
+    /// Position of the next value to read.
+    head: AtomicUsize,
+    /// Position of the next value to write.
+    tail: AtomicUsize,
+}
+
+impl Ring {
+    fn new() -> Ring {
+        Ring { slots: (0..RING_CAPACITY).map(|_| AtomicU64::new(0)).collect(), head: AtomicUsize::new(0), tail: AtomicUsize::new(0) }
+    }
+
+    fn len(&self) -> usize {
+        self.tail.load(Ordering::Acquire).wrapping_sub(self.head.load(Ordering::Acquire))
+    }
+
+    /// Producer side: copies every value in, or none if they don't fit.
+    fn push(&self, values: &[f64]) -> bool {
+        let tail = self.tail.load(Ordering::Relaxed);
+        let head = self.head.load(Ordering::Acquire);
+        if RING_CAPACITY - tail.wrapping_sub(head) < values.len() {
+            return false;
+        }
+        for (i, value) in values.iter().enumerate() {
+            self.slots[tail.wrapping_add(i) % RING_CAPACITY].store(value.to_bits(), Ordering::Relaxed);
+        }
+        self.tail.store(tail.wrapping_add(values.len()), Ordering::Release);
+        true
+    }
+
+    /// Consumer side: moves every value published so far into `out`.
+    fn pop_all(&self, out: &mut Vec<f64>) {
+        let head = self.head.load(Ordering::Relaxed);
+        let tail = self.tail.load(Ordering::Acquire);
+        for i in 0..tail.wrapping_sub(head) {
+            out.push(f64::from_bits(self.slots[head.wrapping_add(i) % RING_CAPACITY].load(Ordering::Relaxed)));
+        }
+        self.head.store(tail, Ordering::Release);
+    }
+}
+
+/// Adds values from a symbol's ring to its windows, failing with
+/// [`ServiceError::UnknownSymbol`] once the flag says the fast path was
+/// cancelled by the symbol's removal.
+pub type Apply = Box<dyn Fn(Vec<f64>, &AtomicBool) -> Result<(), ServiceError> + Send>;
+
+/// A hot symbol's ring and consumer.
+struct FastPath {
+    ring: Ring,
+    /// Held while publishing, making its holder the ring's one producer.
+    producer: Mutex<()>,
+    screen: RwLock<Screen>,
+    core: Option<usize>,
+    consumer: OnceLock<Thread>,
+    /// Set when the fast path is disabled: the consumer adds what's left
+    /// and ends.
+    closing: AtomicBool,
+    /// Set when the symbol is removed: the consumer drops what's left and
+    /// ends.
+    cancelled: AtomicBool,
+    /// Batches queued, bumped once their values are in the ring.
+    published: AtomicU64,
+    /// Batches whose values the consumer has taken from the ring.
+    done: AtomicU64,
+    applied: AtomicU64,
+    rejected: AtomicU64,
+    failed: AtomicU64,
+}
+
+impl FastPath {
+    fn stats(&self) -> FastPathStats {
+        FastPathStats {
+            capacity: RING_CAPACITY,
+            queued: self.ring.len(),
+            pending: self.pending(),
+            applied: self.applied.load(Ordering::Relaxed),
+            rejected: self.rejected.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+            core: self.core,
+        }
+    }
+
+    fn pending(&self) -> u64 {
+        let done = self.done.load(Ordering::Acquire);
+        self.published.load(Ordering::Acquire).saturating_sub(done)
+    }
+
+    fn wake(&self) {
+        if let Some(consumer) = self.consumer.get() {
+            consumer.unpark();
+        }
+    }
+}
+
+/// What became of a published batch.
+pub enum Published {
+    Queued,
+    /// The symbol has no fast path or the batch carries more than values;
+    /// it's handed back to be added the usual way.
+    Declined(Box<Batch>),
+}
+
+struct Entry {
+    path: Arc<FastPath>,
+    consumer: JoinHandle<()>,
+}
+
+/// The fast paths of the hot symbols, by symbol.
+#[derive(Default)]
+pub struct FastPaths {
+    paths: RwLock<HashMap<String, Entry>>,
+    /// Spreads consumers over the cores they may run on when not told one.
+    next_core: AtomicUsize,
+}
+
+impl FastPaths {
+    /// Gives the symbol a fast path whose consumer adds values with
+    /// `apply`, pinned to `core` or else to the next core in turn.
+    pub fn enable(&self, symbol: &str, core: Option<usize>, screen: Screen, apply: Apply) -> Result<(), ServiceError> {
+        let mut paths = self.paths.write().unwrap();
+        if paths.contains_key(symbol) {
+            return Err(ServiceError::Conflict(format!("Symbol {} already has a fast path", symbol)));
+        }
+        if paths.len() >= MAX_FAST_PATHS {
+            return Err(ServiceError::LimitReached(format!("Maximum of {} fast paths reached", MAX_FAST_PATHS)));
+        }
+        let cores = allowed_cores();
+        if let Some(core) = core.filter(|core| !cores.contains(core)) {
+            return Err(format!("Core {} is not available. Available cores: {:?}", core, cores).into());
+        }
+        let core = core.or_else(|| (!cores.is_empty()).then(|| cores[self.next_core.fetch_add(1, Ordering::Relaxed) % cores.len()]));
+
+        let path = Arc::new(FastPath {
+            ring: Ring::new(),
+            producer: Mutex::new(()),
+            screen: RwLock::new(screen),
+            core,
+            consumer: OnceLock::new(),
+            closing: AtomicBool::new(false),
+            cancelled: AtomicBool::new(false),
+            published: AtomicU64::new(0),
+            done: AtomicU64::new(0),
+            applied: AtomicU64::new(0),
+            rejected: AtomicU64::new(0),
+            failed: AtomicU64::new(0),
+        });
+        let (pinned, pinning) = mpsc::channel();
+        let consumed = path.clone();
+        let consumer = thread::Builder::new()
+            .name(format!("fast-path-{}", symbol))
+            .spawn(move || {
+                let result = core.map_or(Ok(()), pin);
+                let failed = result.is_err();
+                let _ = pinned.send(result);
+                if !failed {
+                    consume(&consumed, apply);
+                }
+            })
+            .map_err(|e| ServiceError::Internal(format!("Fast path thread failed to start: {}", e)))?;
+        pinning
+            .recv()
+            .map_err(|_| ServiceError::Internal("Fast path thread ended while starting".to_string()))?
+            .map_err(|e| ServiceError::Internal(format!("Fast path thread couldn't be pinned to core {}: {}", core.unwrap_or_default(), e)))?;
+        let _ = path.consumer.set(consumer.thread().clone());
+        paths.insert(symbol.to_string(), Entry { path, consumer });
+        Ok(())
+    }
+
+    /// Removes the symbol's fast path, returning its consumer thread, which
+    /// ends once it has added the values left in the ring.
+    pub fn disable(&self, symbol: &str) -> Option<JoinHandle<()>> {
+        let entry = self.paths.write().unwrap().remove(symbol)?;
+        // Under the producer lock, so no batch is queued after the consumer
+        // has seen the ring's last values.
+        let producing = entry.path.producer.lock().unwrap();
+        entry.path.closing.store(true, Ordering::Release);
+        drop(producing);
+        entry.path.wake();
+        Some(entry.consumer)
+    }
+
+    /// Removes the symbol's fast path, dropping the values left in the ring.
+    pub fn cancel(&self, symbol: &str) {
+        if let Some(entry) = self.paths.write().unwrap().remove(symbol) {
+            entry.path.cancelled.store(true, Ordering::Release);
+            entry.path.wake();
+        }
+    }
+
+    /// Replaces the checks the symbol's batches must pass to be queued.
+    pub fn rescreen(&self, symbol: &str, screen: Screen) {
+        if let Some(entry) = self.paths.read().unwrap().get(symbol) {
+            *entry.path.screen.write().unwrap() = screen;
+        }
+    }
+
+    pub fn stats(&self, symbol: &str) -> Option<FastPathStats> {
+        self.paths.read().unwrap().get(symbol).map(|entry| entry.path.stats())
+    }
+
+    /// Batches queued on every ring and not yet added.
+    pub fn pending(&self) -> usize {
+        self.paths.read().unwrap().values().map(|entry| entry.path.pending() as usize).sum()
+    }
+
+    /// Queues the batch on the symbol's ring if it has one and the batch
+    /// is values alone. Fails if the values don't pass the symbol's checks
+    /// or the ring is full.
+    pub fn publish(&self, symbol: &str, batch: Batch) -> Result<Published, ServiceError> {
+        if !is_plain(&batch) || batch.values.len() > RING_CAPACITY {
+            return Ok(Published::Declined(Box::new(batch)));
+        }
+        let Some(path) = self.paths.read().unwrap().get(symbol).map(|entry| entry.path.clone()) else {
+            return Ok(Published::Declined(Box::new(batch)));
+        };
+        path.screen.read().unwrap().check(&batch.values)?;
+        let producing = path.producer.lock().unwrap();
+        // Disabled or cancelled since it was looked up.
+        if path.closing.load(Ordering::Acquire) || path.cancelled.load(Ordering::Acquire) {
+            return Ok(Published::Declined(Box::new(batch)));
+        }
+        if !path.ring.push(&batch.values) {
+            path.rejected.fetch_add(1, Ordering::Relaxed);
+            return Err(ServiceError::LimitReached(format!("Fast path of symbol {} is full", symbol)));
+        }
+        path.published.fetch_add(1, Ordering::Release);
+        drop(producing);
+        path.wake();
+        Ok(Published::Queued)
+    }
+}
+
+impl Drop for FastPaths {
+    fn drop(&mut self) {
+        for entry in self.paths.get_mut().unwrap().values() {
+            entry.path.cancelled.store(true, Ordering::Release);
+            entry.path.wake();
+        }
+    }
+}
+
+fn is_plain(batch: &Batch) -> bool {
+    batch.weights.is_none()
+        && batch.volumes.is_none()
+        && batch.timestamps.is_none()
+        && batch.sequences.is_none()
+        && batch.ids.is_none()
+        && batch.fields.is_empty()
+        && batch.venue.is_none()
+        && batch.batch_id.is_none()
+}
+
+/// The consumer thread: adds everything published since its last look as
+/// one batch, spinning briefly and then parking while the ring is empty.
+fn consume(path: &FastPath, apply: Apply) {
+    let mut values = Vec::new();
+    let mut idle = 0;
+    loop {
+        // Loaded before the ring is read: a batch is counted only once its
+        // values are in, so every batch counted here is taken below.
+        let published = path.published.load(Ordering::Acquire);
+        path.ring.pop_all(&mut values);
+        if path.cancelled.load(Ordering::Acquire) {
+            return;
+        }
+        if !values.is_empty() {
+            idle = 0;
+            let count = values.len() as u64;
+            match apply(std::mem::take(&mut values), &path.cancelled) {
+                Ok(()) => path.applied.fetch_add(count, Ordering::Relaxed),
+                Err(ServiceError::UnknownSymbol) => return,
+                Err(e) => {
+                    tracing::warn!(error = %e, values = count, "fast path values failed");
+                    path.failed.fetch_add(count, Ordering::Relaxed)
+                }
+            };
+        }
+        path.done.store(published, Ordering::Release);
+        if path.closing.load(Ordering::Acquire) && path.ring.len() == 0 {
+            return;
+        }
+        if idle < IDLE_SPINS {
+            idle += 1;
+            std::hint::spin_loop();
+        } else {
+            thread::park_timeout(IDLE_PARK);
+        }
+    }
+}
+
+/// Cores this process may run threads on.
+#[cfg(target_os = "linux")]
+fn allowed_cores() -> Vec<usize> {
+    use rustix::thread::{sched_getaffinity, CpuSet};
+    match sched_getaffinity(None) {
+        Ok(set) => (0..CpuSet::MAX_CPU).filter(|&core| set.is_set(core)).collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn allowed_cores() -> Vec<usize> {
+    Vec::new()
+}
+
+/// Pins the calling thread to the core.
+#[cfg(target_os = "linux")]
+fn pin(core: usize) -> Result<(), String> {
+    use rustix::thread::{sched_setaffinity, CpuSet};
+    let mut set = CpuSet::new();
+    set.set(core);
+    sched_setaffinity(None, &set).map_err(|e| e.to_string())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn pin(_core: usize) -> Result<(), String> {
+    Err("threads can only be pinned on Linux".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    fn wait_for(mut done: impl FnMut() -> bool) {
+        let started = Instant::now();
+        while !done() {
+            assert!(started.elapsed() < Duration::from_secs(5), "timed out");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn test_ring() {
+        let ring = Ring::new();
+        let mut out = Vec::new();
+        assert!(ring.push(&[1.0, 2.0]));
+        assert!(!ring.push(&vec![0.0; RING_CAPACITY - 1]));
+        assert!(ring.push(&vec![3.0; RING_CAPACITY - 2]));
+        ring.pop_all(&mut out);
+        assert_eq!((RING_CAPACITY, 1.0, 3.0), (out.len(), out[0], out[RING_CAPACITY - 1]));
+
+        // Positions wrap around the slots.
+        assert!(ring.push(&[4.0, 5.0, 6.0]));
+        out.clear();
+        ring.pop_all(&mut out);
+        assert_eq!(vec![4.0, 5.0, 6.0], out);
+        assert_eq!(0, ring.len());
+    }
+
+    #[test]
+    fn test_fast_paths() {
+        let applied = Arc::new(Mutex::new(Vec::new()));
+        let sink = applied.clone();
+        let apply: Apply = Box::new(move |values: Vec<f64>, _: &AtomicBool| {
+            sink.lock().unwrap().extend(values);
+            Ok(())
+        });
+        let paths = FastPaths::default();
+        assert!(matches!(paths.publish("A", Batch::new(vec![1.0])), Ok(Published::Declined(_))));
+        let screen = Screen { meta: SymbolMeta { tick_size: Some(0.5), reject_off_tick: true, ..SymbolMeta::default() }, ..Screen::default() };
+        paths.enable("A", None, screen, apply).unwrap();
+
+        assert!(matches!(paths.publish("A", Batch::new(vec![1.0, 1.5])), Ok(Published::Queued)));
+        assert!(matches!(paths.publish("A", Batch::new(vec![f64::NAN])), Err(ServiceError::InvalidValue(_))));
+        assert!(matches!(paths.publish("A", Batch::new(vec![1.2])), Err(ServiceError::InvalidValue(_))));
+        let timed = Batch { timestamps: Some(vec![1]), ..Batch::new(vec![3.0]) };
+        assert!(matches!(paths.publish("A", timed), Ok(Published::Declined(_))));
+        assert!(matches!(paths.publish("A", Batch::new(vec![2.0])), Ok(Published::Queued)));
+        wait_for(|| paths.pending() == 0);
+        assert_eq!(vec![1.0, 1.5, 2.0], *applied.lock().unwrap());
+        assert_eq!(3, paths.stats("A").unwrap().applied);
+
+        // A disabled fast path's consumer ends; its batches go the usual way.
+        paths.disable("A").unwrap().join().unwrap();
+        assert!(paths.disable("A").is_none());
+        assert!(paths.stats("A").is_none());
+        assert!(matches!(paths.publish("A", Batch::new(vec![1.0])), Ok(Published::Declined(_))));
+    }
+}
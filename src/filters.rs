@@ -0,0 +1,242 @@
+//! Ingestion-time filters applied before values reach the windows.
+
+use std::collections::{HashSet, VecDeque};
+
+/// Fewest reference values needed before the outlier filter engages.
+pub const MIN_REFERENCE_POINTS: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutlierAction {
+    /// Keep the value but count it.
+    #[default]
+    Flag,
+    /// Drop the value and count it.
+    Reject,
+}
+
+/// What ingestion does with NaN and infinite values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NonFiniteAction {
+    /// Fail the whole batch.
+    #[default]
+    Reject,
+    /// Drop the point and count it.
+    Skip,
+}
+
+/// Median-absolute-deviation outlier filter. A value is an outlier when it
+/// lies more than `threshold` MADs from the median of the symbol's 10^`window_k`
+/// window, as it was before the batch arrived.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct OutlierFilter {
+    pub threshold: f64,
+    #[serde(default)]
+    pub action: OutlierAction,
+    /// Reference window exponent, 1 to 4.
+    #[serde(default = "default_window_k")]
+    pub window_k: usize,
+}
+
+fn default_window_k() -> usize {
+    2
+}
+
+impl OutlierFilter {
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.threshold.is_finite() || self.threshold <= 0.0 {
+            return Err("Invalid outlier threshold. Only positive values are accepted.".to_string());
+        }
+        if !(1..=4).contains(&self.window_k) {
+            return Err("Invalid outlier window_k. Only values 1-4 are accepted.".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Median and median absolute deviation of `values`.
+pub fn median_mad(values: impl Iterator<Item = f64>) -> Option<(f64, f64)> {
+    let mut sorted: Vec<f64> = values.collect();
+    if sorted.is_empty() {
+        return None;
+    }
+    sorted.sort_by(f64::total_cmp);
+    let median = median_of_sorted(&sorted);
+
+    let mut deviations: Vec<f64> = sorted.iter().map(|v| (v - median).abs()).collect();
+    deviations.sort_by(f64::total_cmp);
+    Some((median, median_of_sorted(&deviations)))
+}
+
+fn median_of_sorted(sorted: &[f64]) -> f64 {
+    let n = sorted.len();
+    if n % 2 == 1 {
+        sorted[n / 2]
+    } else {
+        (sorted[n / 2 - 1] + sorted[n / 2]) / 2.0
+    }
+}
+
+/// Detects outliers against a fixed reference median and MAD. A zero MAD
+/// (flat reference) disables detection rather than flagging every change.
+#[derive(Debug, Clone, Copy)]
+pub struct OutlierDetector {
+    median: f64,
+    mad: f64,
+    threshold: f64,
+}
+
+impl OutlierDetector {
+    pub fn new(filter: &OutlierFilter, reference: impl ExactSizeIterator<Item = f64>) -> Option<Self> {
+        if reference.len() < MIN_REFERENCE_POINTS {
+            return None;
+        }
+        let (median, mad) = median_mad(reference)?;
+        if mad <= 0.0 {
+            return None;
+        }
+        Some(OutlierDetector { median, mad, threshold: filter.threshold })
+    }
+
+    pub fn is_outlier(&self, value: f64) -> bool {
+        (value - self.median).abs() > self.threshold * self.mad
+    }
+}
+
+/// Checks a feed's sequence numbers for gaps and duplicates. A number at or
+/// below the highest one seen counts as a duplicate (or late replay); one
+/// that skips ahead counts as a gap, along with how many numbers it skipped.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SequenceTracker {
+    last: Option<u64>,
+    pub gaps: u64,
+    pub missing: u64,
+    pub duplicates: u64,
+}
+
+impl SequenceTracker {
+    pub fn observe(&mut self, sequence: u64) {
+        match self.last {
+            Some(last) if sequence <= last => self.duplicates += 1,
+            Some(last) => {
+                if sequence > last + 1 {
+                    self.gaps += 1;
+                    self.missing += sequence - last - 1;
+                }
+                self.last = Some(sequence);
+            }
+            None => self.last = Some(sequence),
+        }
+    }
+}
+
+/// The most recent `capacity` distinct IDs, for dropping replayed values.
+#[derive(Debug, Clone, Default)]
+pub struct RecentIds {
+    seen: HashSet<String>,
+    order: VecDeque<String>,
+    capacity: usize,
+}
+
+impl RecentIds {
+    pub fn new(capacity: usize) -> Self {
+        RecentIds { capacity, ..RecentIds::default() }
+    }
+
+    /// Approximate heap size: each ID is stored twice, in the set and in
+    /// arrival order.
+    pub fn heap_bytes(&self) -> usize {
+        let ids: usize = self.order.iter().map(|id| id.capacity()).sum();
+        2 * ids + (self.seen.capacity() + self.order.capacity()) * std::mem::size_of::<String>()
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.seen.contains(id)
+    }
+
+    /// Records the ID, returning false if it is already among the recent
+    /// ones. Nothing is remembered with a zero capacity.
+    pub fn insert(&mut self, id: &str) -> bool {
+        if self.capacity == 0 {
+            return true;
+        }
+        if self.seen.contains(id) {
+            return false;
+        }
+        if self.order.len() >= self.capacity {
+            self.forget_oldest();
+        }
+        self.seen.insert(id.to_string());
+        self.order.push_back(id.to_string());
+        true
+    }
+
+    /// Changes how many IDs are remembered, forgetting the oldest ones when
+    /// it shrinks.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.order.len() > capacity {
+            self.forget_oldest();
+        }
+    }
+
+    fn forget_oldest(&mut self) {
+        if let Some(oldest) = self.order.pop_front() {
+            self.seen.remove(&oldest);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_median_mad() {
+        let values = [1.0, 1.0, 2.0, 2.0, 4.0, 6.0, 9.0];
+        assert_eq!(Some((2.0, 1.0)), median_mad(values.iter().copied()));
+        assert_eq!(None, median_mad(std::iter::empty()));
+    }
+
+    #[test]
+    fn test_outlier_detector() {
+        let filter = OutlierFilter { threshold: 3.0, action: OutlierAction::Reject, window_k: 1 };
+        let reference: Vec<f64> = (0..10).map(|i| 100.0 + (i % 3) as f64).collect();
+        let detector = OutlierDetector::new(&filter, reference.iter().copied()).unwrap();
+
+        assert!(!detector.is_outlier(102.5));
+        assert!(detector.is_outlier(104.5));
+        assert!(detector.is_outlier(0.0));
+
+        let short = OutlierDetector::new(&filter, reference[..5].iter().copied());
+        assert!(short.is_none());
+    }
+
+    #[test]
+    fn test_sequence_tracker() {
+        let mut tracker = SequenceTracker::default();
+        for sequence in [7, 8, 11, 11, 9, 12, 20] {
+            tracker.observe(sequence);
+        }
+        assert_eq!(2, tracker.gaps);
+        assert_eq!(2 + 7, tracker.missing);
+        assert_eq!(2, tracker.duplicates);
+    }
+
+    #[test]
+    fn test_recent_ids() {
+        let mut ids = RecentIds::new(2);
+        assert!(ids.insert("a"));
+        assert!(ids.insert("b"));
+        assert!(!ids.insert("a"));
+        assert!(ids.insert("c"));
+        // "a" has been forgotten by now.
+        assert!(ids.insert("a"));
+        assert!(!ids.insert("c"));
+
+        ids.set_capacity(0);
+        assert!(ids.insert("c"));
+        assert!(ids.insert("c"));
+    }
+}
@@ -0,0 +1,173 @@
+//! Arrow Flight, served on the gRPC address, for Flight-native clients to
+//! pull windows and bars and push batches as Arrow record batches.
+//!
+//! `DoGet` tickets are JSON: `{"symbol": "AAPL", "k": 6}` for a window or
+//! `{"symbol": "AAPL", "bars": "1m", "limit": 100}` for bars. `DoPut` takes
+//! record batches laid out like a window's for the symbol its descriptor's
+//! path names. Calls act on the namespace named by the `x-namespace`
+//! metadata, like the gRPC API's.
+
+use std::pin::Pin;
+use std::sync::Arc;
+
+use arrow_array::RecordBatch;
+use arrow_flight::decode::FlightRecordBatchStream;
+use arrow_flight::encode::FlightDataEncoderBuilder;
+use arrow_flight::error::FlightError;
+use arrow_flight::flight_service_server::{FlightService, FlightServiceServer};
+use arrow_flight::{
+    Action, ActionType, Criteria, Empty, FlightData, FlightDescriptor, FlightInfo, HandshakeRequest, HandshakeResponse,
+    PollInfo, PutResult, SchemaResult, Ticket,
+};
+use futures::{Stream, StreamExt, TryStreamExt};
+use serde::Deserialize;
+use tonic::{Request, Response, Status, Streaming};
+use trading_service::bars::parse_interval;
+use trading_service::namespaces::Namespaces;
+use trading_service::ServiceError;
+
+use crate::export::{bars_batch, from_record_batch, window_batch};
+use crate::grpc::{status, tenant};
+
+type BoxStream<T> = Pin<Box<dyn Stream<Item = Result<T, Status>> + Send>>;
+
+/// What a `DoGet` ticket asks for.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Query {
+    symbol: String,
+    /// The 10^k window's points.
+    k: Option<usize>,
+    /// Bars of this interval, e.g. `1m`.
+    bars: Option<String>,
+    /// Most recent bars to return, default 100.
+    limit: Option<usize>,
+}
+
+struct FlightApi {
+    namespaces: Arc<Namespaces>,
+}
+
+pub fn service(namespaces: Arc<Namespaces>) -> FlightServiceServer<impl FlightService> {
+    FlightServiceServer::new(FlightApi { namespaces })
+}
+
+fn invalid(e: impl Into<String>) -> Status {
+    status(ServiceError::InvalidValue(e.into()))
+}
+
+#[tonic::async_trait]
+impl FlightService for FlightApi {
+    type HandshakeStream = BoxStream<HandshakeResponse>;
+    type ListFlightsStream = BoxStream<FlightInfo>;
+    type DoGetStream = BoxStream<FlightData>;
+    type DoPutStream = BoxStream<PutResult>;
+    type DoActionStream = BoxStream<arrow_flight::Result>;
+    type ListActionsStream = BoxStream<ActionType>;
+    type DoExchangeStream = BoxStream<FlightData>;
+
+    async fn do_get(&self, req: Request<Ticket>) -> Result<Response<Self::DoGetStream>, Status> {
+        let service = tenant(&self.namespaces, req.metadata(), false).map_err(status)?;
+        let query: Query = serde_json::from_slice(&req.get_ref().ticket)
+            .map_err(|e| invalid(format!("Invalid ticket: {}", e)))?;
+        let record_batch = match (query.k, query.bars) {
+            (Some(k), None) => window_batch(service.get_window(query.symbol, k).await.map_err(status)?),
+            (None, Some(interval)) => {
+                let interval_ms = parse_interval(&interval).map_err(invalid)?;
+                let bars = service.get_bars(query.symbol, interval_ms, query.limit.unwrap_or(100)).await.map_err(status)?;
+                bars_batch(&bars)
+            }
+            _ => return Err(invalid("Invalid ticket. Give either k or bars.")),
+        };
+        let record_batch = record_batch.map_err(|e| status(ServiceError::Internal(e.to_string())))?;
+        let data = FlightDataEncoderBuilder::new()
+            .build(futures::stream::once(async move { Ok::<RecordBatch, FlightError>(record_batch) }))
+            .map_err(Status::from);
+        Ok(Response::new(Box::pin(data)))
+    }
+
+    /// Adds each record batch as one batch, acknowledging each. Batches
+    /// added before a failing one stay added.
+    async fn do_put(&self, req: Request<Streaming<FlightData>>) -> Result<Response<Self::DoPutStream>, Status> {
+        let service = tenant(&self.namespaces, req.metadata(), true).map_err(status)?;
+        let mut stream = req.into_inner();
+        let Some(first) = stream.message().await? else {
+            return Ok(Response::new(Box::pin(futures::stream::empty())));
+        };
+        let symbol = match first.flight_descriptor.as_ref().map(|descriptor| descriptor.path.as_slice()) {
+            Some([symbol]) => symbol.clone(),
+            _ => return Err(invalid("Invalid descriptor. Expected a path naming the symbol.")),
+        };
+        let data = futures::stream::once(async move { Ok(first) }).chain(stream.map_err(FlightError::from));
+        let mut record_batches = FlightRecordBatchStream::new_from_flight_data(data);
+
+        let mut acks = Vec::new();
+        while let Some(record_batch) = record_batches.try_next().await.map_err(Status::from)? {
+            let batch = from_record_batch(&record_batch).map_err(invalid)?;
+            service.add_batch(symbol.clone(), batch).await.map_err(status)?;
+            acks.push(Ok(PutResult::default()));
+        }
+        Ok(Response::new(Box::pin(futures::stream::iter(acks))))
+    }
+
+    async fn handshake(
+        &self,
+        _: Request<Streaming<HandshakeRequest>>,
+    ) -> Result<Response<Self::HandshakeStream>, Status> {
+        Err(Status::unimplemented("Flight handshakes aren't needed"))
+    }
+
+    async fn list_flights(&self, _: Request<Criteria>) -> Result<Response<Self::ListFlightsStream>, Status> {
+        Err(Status::unimplemented("Use DoGet with a ticket naming the symbol"))
+    }
+
+    async fn get_flight_info(&self, _: Request<FlightDescriptor>) -> Result<Response<FlightInfo>, Status> {
+        Err(Status::unimplemented("Use DoGet with a ticket naming the symbol"))
+    }
+
+    async fn poll_flight_info(&self, _: Request<FlightDescriptor>) -> Result<Response<PollInfo>, Status> {
+        Err(Status::unimplemented("Use DoGet with a ticket naming the symbol"))
+    }
+
+    async fn get_schema(&self, _: Request<FlightDescriptor>) -> Result<Response<SchemaResult>, Status> {
+        Err(Status::unimplemented("Schemas come with the DoGet stream"))
+    }
+
+    async fn do_action(&self, _: Request<Action>) -> Result<Response<Self::DoActionStream>, Status> {
+        Err(Status::unimplemented("No actions are offered"))
+    }
+
+    async fn list_actions(&self, _: Request<Empty>) -> Result<Response<Self::ListActionsStream>, Status> {
+        Ok(Response::new(Box::pin(futures::stream::empty())))
+    }
+
+    async fn do_exchange(&self, _: Request<Streaming<FlightData>>) -> Result<Response<Self::DoExchangeStream>, Status> {
+        Err(Status::unimplemented("Use DoGet and DoPut"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_rt::test]
+    async fn test_do_get() {
+        let namespaces = Arc::new(Namespaces::default());
+        let service = namespaces.get_or_create("default").unwrap();
+        service.add_batch_values("AAPL".to_string(), vec![1.0, 2.0, 3.0]).await.unwrap();
+        let api = FlightApi { namespaces };
+
+        let ticket = Ticket::new(r#"{"symbol": "AAPL", "k": 1}"#);
+        let data = api.do_get(Request::new(ticket)).await.unwrap().into_inner();
+        let record_batches: Vec<RecordBatch> = FlightRecordBatchStream::new_from_flight_data(data.map_err(FlightError::from))
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(3, record_batches.iter().map(RecordBatch::num_rows).sum::<usize>());
+        assert_eq!(vec![1.0, 2.0, 3.0], from_record_batch(&record_batches[0]).unwrap().values);
+
+        let ticket = Ticket::new(r#"{"symbol": "AAPL", "k": 1, "bars": "1m"}"#);
+        let e = api.do_get(Request::new(ticket)).await.err().unwrap();
+        assert_eq!(tonic::Code::InvalidArgument, e.code());
+    }
+}
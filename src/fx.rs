@@ -0,0 +1,82 @@
+//! Prices converted into another currency with an FX-rate symbol.
+//!
+//! Each ingested price is converted at the rate symbol's latest tick as of
+//! its ingestion; prices ingested before the rate's first tick aren't
+//! converted.
+
+use crate::meta::MAX_CURRENCY_LEN;
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct FxConfig {
+    /// Symbol whose prices are the exchange rate, e.g. `EURUSD`.
+    pub rate_symbol: String,
+    /// Currency the converted prices are in, e.g. `USD`.
+    pub currency: String,
+    /// Divides by the rate instead of multiplying, for rates quoted the
+    /// other way round (e.g. `USDJPY` to convert yen into dollars).
+    #[serde(default)]
+    pub invert: bool,
+}
+
+impl FxConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.rate_symbol.is_empty() {
+            return Err("Invalid rate_symbol input. A symbol is expected.".to_string());
+        }
+        if self.currency.is_empty() || self.currency.len() > MAX_CURRENCY_LEN {
+            return Err(format!("Invalid currency input. Expected 1 to {} characters.", MAX_CURRENCY_LEN));
+        }
+        Ok(())
+    }
+}
+
+/// A symbol's conversion with the rate symbol's latest price.
+#[derive(Debug, Clone)]
+pub struct FxConversion {
+    config: FxConfig,
+    rate: Option<f64>,
+}
+
+impl FxConversion {
+    pub fn new(config: FxConfig) -> Self {
+        FxConversion { config, rate: None }
+    }
+
+    pub fn config(&self) -> &FxConfig {
+        &self.config
+    }
+
+    /// Takes a rate tick; rates that aren't positive are ignored.
+    pub fn set_rate(&mut self, rate: f64) {
+        if rate.is_finite() && rate > 0.0 {
+            self.rate = Some(rate);
+        }
+    }
+
+    /// The price in the target currency, `None` before the first rate.
+    pub fn convert(&self, price: f64) -> Option<f64> {
+        let rate = self.rate?;
+        Some(if self.config.invert { price / rate } else { price * rate })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convert() {
+        let config = FxConfig { rate_symbol: "EURUSD".to_string(), currency: "USD".to_string(), invert: false };
+        assert!(config.validate().is_ok());
+        let mut fx = FxConversion::new(config);
+        assert_eq!(None, fx.convert(100.0));
+        fx.set_rate(1.25);
+        fx.set_rate(0.0);
+        assert_eq!(Some(125.0), fx.convert(100.0));
+
+        let config = FxConfig { rate_symbol: "USDJPY".to_string(), currency: "USD".to_string(), invert: true };
+        let mut fx = FxConversion::new(config);
+        fx.set_rate(150.0);
+        assert_eq!(Some(2.0), fx.convert(300.0));
+    }
+}
@@ -0,0 +1,133 @@
+//! GraphQL queries at `/graphql`, for asking for exactly the symbols,
+//! windows and fields needed in one request, e.g.
+//!
+//! ```graphql
+//! {
+//!   aapl: symbol(name: "AAPL") {
+//!     short: stats(k: 2) { avg stdDev }
+//!     long: stats(k: 5) { avg stdDev ohlc { open close } }
+//!     correlation(with: "MSFT", k: 3) { correlation count }
+//!   }
+//! }
+//! ```
+//!
+//! Failed fields come back as GraphQL errors with the error's `code` in
+//! their extensions, alongside the fields that succeeded.
+
+use std::sync::Arc;
+
+use actix_web::{web, Either, HttpRequest, HttpResponse};
+use async_graphql::{Context, EmptyMutation, EmptySubscription, ErrorExtensions, Object, Schema};
+use async_graphql_actix_web::{GraphQLRequest, GraphQLResponse};
+use trading_service::{CorrelationResponse, ServiceError, StatsOptions, StatsResponse, TradingDataService};
+
+use crate::{error_response, Tenant};
+
+/// Deepest nesting a query may have.
+const MAX_DEPTH: usize = 8;
+
+/// Most fields a query may select, counting each alias.
+const MAX_COMPLEXITY: usize = 2000;
+
+pub type TradingSchema = Schema<Query, EmptyMutation, EmptySubscription>;
+
+pub fn schema() -> TradingSchema {
+    Schema::build(Query, EmptyMutation, EmptySubscription)
+        .limit_depth(MAX_DEPTH)
+        .limit_complexity(MAX_COMPLEXITY)
+        .finish()
+}
+
+fn error(e: ServiceError) -> async_graphql::Error {
+    async_graphql::Error::new(e.to_string()).extend_with(|_, extensions| extensions.set("code", e.code()))
+}
+
+fn service<'a>(ctx: &Context<'a>) -> &'a TradingDataService {
+    ctx.data_unchecked::<Arc<TradingDataService>>()
+}
+
+pub struct Query;
+
+#[Object]
+impl Query {
+    /// A symbol or alias. Its fields fail with `unknown_symbol` if it isn't tracked.
+    async fn symbol(&self, ctx: &Context<'_>, name: String) -> Symbol {
+        Symbol(service(ctx).canonical(name))
+    }
+
+    /// The tracked symbols by name, optionally only those starting with `prefix`.
+    async fn symbols(
+        &self,
+        ctx: &Context<'_>,
+        prefix: Option<String>,
+        #[graphql(default = 0)] offset: usize,
+        #[graphql(default = 100)] limit: usize,
+    ) -> async_graphql::Result<Vec<Symbol>> {
+        let page = service(ctx).list_symbols(prefix.as_deref(), offset, limit).await.map_err(error)?;
+        Ok(page.symbols.into_iter().map(|info| Symbol(info.symbol)).collect())
+    }
+}
+
+/// A tracked symbol, named by its canonical name.
+pub struct Symbol(String);
+
+#[Object]
+impl Symbol {
+    async fn name(&self) -> &str {
+        &self.0
+    }
+
+    /// Stats of the symbol's 10^k window, with optional quantiles (0 to 1).
+    async fn stats(
+        &self,
+        ctx: &Context<'_>,
+        k: usize,
+        #[graphql(default)] quantiles: Vec<f64>,
+    ) -> async_graphql::Result<StatsResponse> {
+        let options = StatsOptions { quantiles, ..StatsOptions::default() };
+        service(ctx).get_stats_with(self.0.clone(), k, &options).await.map_err(error)
+    }
+
+    /// Stats of the symbol's last `n` values.
+    async fn stats_for_last(&self, ctx: &Context<'_>, n: usize) -> async_graphql::Result<StatsResponse> {
+        service(ctx).get_stats_for_last(self.0.clone(), n, &StatsOptions::default()).await.map_err(error)
+    }
+
+    /// Correlation of the symbol's 10^k window with another symbol's, aligned
+    /// on their most recent values.
+    async fn correlation(&self, ctx: &Context<'_>, with: String, k: usize) -> async_graphql::Result<CorrelationResponse> {
+        service(ctx).get_correlation(self.0.clone(), with, k).await.map_err(error)
+    }
+}
+
+/// Runs a query against the request's namespace. Queries only read, so
+/// unlike other `POST`s they don't create the namespace.
+pub async fn graphql(
+    req: HttpRequest,
+    schema: web::Data<TradingSchema>,
+    query: GraphQLRequest,
+) -> Either<GraphQLResponse, HttpResponse> {
+    match Tenant::resolve(&req, false) {
+        Ok(service) => Either::Left(schema.execute(query.into_inner().data(service.0)).await.into()),
+        Err(e) => Either::Right(error_response(e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_rt::test]
+    async fn test_query() {
+        let service = Arc::new(TradingDataService::new());
+        service.add_batch_values("AAPL".to_string(), vec![1.0, 2.0, 3.0]).await.unwrap();
+        let query = r#"{ symbol(name: "AAPL") { stats(k: 1) { avg count } } missing: symbol(name: "MSFT") { stats(k: 1) { avg } } }"#;
+        let response = schema().execute(async_graphql::Request::new(query).data(service)).await;
+        let data = response.data.into_json().unwrap();
+        assert_eq!(serde_json::json!({"avg": 2.0, "count": 3}), data["symbol"]["stats"]);
+        assert!(data["missing"].is_null());
+        assert_eq!(1, response.errors.len());
+        let code = response.errors[0].extensions.as_ref().and_then(|e| e.get("code")).cloned();
+        assert_eq!(Some(async_graphql::Value::from("unknown_symbol")), code);
+    }
+}
@@ -0,0 +1,96 @@
+//! Aggregates of a per-window metric over the symbols sharing a tag, e.g.
+//! the average volatility of the `sector=tech` symbols.
+
+use std::collections::BTreeMap;
+
+/// The groups a query covers: one per value of a tag, or just one value's.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TagFilter {
+    pub name: String,
+    pub value: Option<String>,
+}
+
+impl TagFilter {
+    /// Parses `name`, grouping by each of its values, or `name=value`.
+    pub fn parse(filter: &str) -> Result<TagFilter, String> {
+        let (name, value) = match filter.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (filter, None),
+        };
+        if name.is_empty() || value == Some("") {
+            return Err(format!("Invalid tag input. {:?} isn't a tag name or name=value.", filter));
+        }
+        Ok(TagFilter { name: name.to_string(), value: value.map(str::to_string) })
+    }
+
+    /// The value naming the group a symbol with these tags falls in, if any.
+    pub fn group<'a>(&self, tags: &'a BTreeMap<String, String>) -> Option<&'a str> {
+        let value = tags.get(&self.name)?;
+        match &self.value {
+            Some(wanted) if wanted != value => None,
+            _ => Some(value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct GroupStats {
+    /// The group's tag, as `name=value`.
+    pub tag: String,
+    /// Symbols in the group whose metric is defined.
+    pub count: usize,
+    pub mean: f64,
+    pub median: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+/// Each group's aggregate of its symbols' metric values, in tag value
+/// order. Groups without values are left out.
+pub fn aggregate(name: &str, groups: BTreeMap<&str, Vec<f64>>) -> Vec<GroupStats> {
+    groups
+        .into_iter()
+        .filter(|(_, values)| !values.is_empty())
+        .map(|(value, mut values)| {
+            values.sort_by(f64::total_cmp);
+            let count = values.len();
+            let median = match count % 2 {
+                0 => (values[count / 2 - 1] + values[count / 2]) / 2.0,
+                _ => values[count / 2],
+            };
+            GroupStats {
+                tag: format!("{}={}", name, value),
+                count,
+                mean: values.iter().sum::<f64>() / count as f64,
+                median,
+                min: values[0],
+                max: values[count - 1],
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tag_filter() {
+        let tags = BTreeMap::from([("sector".to_string(), "tech".to_string())]);
+        assert_eq!(Some("tech"), TagFilter::parse("sector").unwrap().group(&tags));
+        assert_eq!(Some("tech"), TagFilter::parse("sector=tech").unwrap().group(&tags));
+        assert_eq!(None, TagFilter::parse("sector=energy").unwrap().group(&tags));
+        assert_eq!(None, TagFilter::parse("asset").unwrap().group(&tags));
+        assert!(TagFilter::parse("").is_err());
+        assert!(TagFilter::parse("sector=").is_err());
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let groups = BTreeMap::from([("tech", vec![3.0, 1.0, 2.0, 6.0]), ("energy", vec![5.0]), ("none", vec![])]);
+        let stats = aggregate("sector", groups);
+        assert_eq!(2, stats.len());
+        assert_eq!(GroupStats { tag: "sector=energy".to_string(), count: 1, mean: 5.0, median: 5.0, min: 5.0, max: 5.0 }, stats[0]);
+        assert_eq!(GroupStats { tag: "sector=tech".to_string(), count: 4, mean: 3.0, median: 2.5, min: 1.0, max: 6.0 }, stats[1]);
+    }
+}
@@ -0,0 +1,220 @@
+//! The gRPC API, served alongside the HTTP one from `proto/trading.proto`.
+
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::Stream;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::Instant;
+use tonic::metadata::MetadataMap;
+use tonic::{Request, Response, Status};
+use trading_service::namespaces::{Namespaces, DEFAULT_NAMESPACE};
+use trading_service::{Batch, ServiceError, StatsOptions, StatsResponse, TradingDataService};
+
+pub mod proto {
+    tonic::include_proto!("trading.v1");
+}
+
+use proto::trading_server::{Trading, TradingServer};
+
+/// Address the gRPC server listens on.
+pub const GRPC_ADDR: &str = "127.0.0.1:50051";
+
+/// Metadata naming the request's namespace.
+const NAMESPACE_METADATA: &str = "x-namespace";
+
+/// Highest update rate a subscription can ask for.
+const MAX_HZ: f64 = 1000.0;
+
+pub fn status(e: ServiceError) -> Status {
+    let mut status = match e {
+        ServiceError::UnknownSymbol | ServiceError::NotFound(_) => Status::not_found(e.to_string()),
+        ServiceError::InvalidK | ServiceError::InvalidValue(_) => Status::invalid_argument(e.to_string()),
+        ServiceError::BatchTooLarge(_) => Status::out_of_range(e.to_string()),
+        ServiceError::Conflict(_) => Status::failed_precondition(e.to_string()),
+        ServiceError::LimitReached(_) => Status::resource_exhausted(e.to_string()),
+        ServiceError::Unauthorized(_) => Status::unauthenticated(e.to_string()),
+        ServiceError::Internal(_) => Status::internal(e.to_string()),
+    };
+    // The same machine-readable code as the HTTP API's.
+    status.metadata_mut().insert("x-error-code", e.code().parse().unwrap());
+    status
+}
+
+impl From<StatsResponse> for proto::Stats {
+    fn from(stats: StatsResponse) -> Self {
+        proto::Stats {
+            min: stats.min,
+            max: stats.max,
+            last: stats.last,
+            avg: stats.avg,
+            var: stats.var,
+            std_dev: stats.std_dev,
+            zscore_last: stats.zscore_last,
+            skewness: stats.skewness,
+            kurtosis: stats.kurtosis,
+            realized_vol: stats.realized_vol,
+            volume_sum: stats.volume_sum,
+            volume_avg: stats.volume_avg,
+            volume_max: stats.volume_max,
+            vwap: stats.vwap,
+            weighted_mean: stats.weighted_mean,
+            weighted_var: stats.weighted_var,
+            geometric_mean: stats.geometric_mean,
+            harmonic_mean: stats.harmonic_mean,
+            ewma: stats.ewma,
+            ew_var: stats.ew_var,
+            median: stats.median,
+            ohlc: Some(proto::Ohlc {
+                open: stats.ohlc.open,
+                high: stats.ohlc.high,
+                low: stats.ohlc.low,
+                close: stats.ohlc.close,
+            }),
+            pct_change: stats.pct_change,
+            max_abs_return: stats.max_abs_return,
+            jump_score: stats.jump_score,
+            trend: Some(proto::Trend {
+                slope: stats.trend.slope,
+                intercept: stats.trend.intercept,
+                r_squared: stats.trend.r_squared,
+            }),
+            max_drawdown: stats.max_drawdown,
+            max_drawdown_pct: stats.max_drawdown_pct,
+            count: stats.count as u64,
+            outliers_flagged: stats.outliers_flagged,
+            outliers_rejected: stats.outliers_rejected,
+            sequence_gaps: stats.sequence_gaps,
+            sequences_missing: stats.sequences_missing,
+            sequence_duplicates: stats.sequence_duplicates,
+            duplicates_dropped: stats.duplicates_dropped,
+            late_dropped: stats.late_dropped,
+            out_of_session: stats.out_of_session,
+            non_finite_dropped: stats.non_finite_dropped,
+            age_ms: stats.age_ms,
+            is_stale: stats.is_stale,
+            first_timestamp: stats.first_timestamp,
+            last_timestamp: stats.last_timestamp,
+            last_update: stats.last_update,
+            quantiles: stats.quantiles.into_iter().map(|q| proto::Quantile { q: q.q, value: q.value }).collect(),
+        }
+    }
+}
+
+/// A per-value list, which proto3 leaves empty when not sent.
+fn given<T>(list: Vec<T>) -> Option<Vec<T>> {
+    Some(list).filter(|list| !list.is_empty())
+}
+
+impl From<proto::AddBatchRequest> for Batch {
+    fn from(req: proto::AddBatchRequest) -> Self {
+        Batch {
+            values: req.values,
+            weights: given(req.weights),
+            volumes: given(req.volumes),
+            timestamps: given(req.timestamps),
+            sequences: given(req.sequences),
+            ids: given(req.ids),
+            fields: req.fields.into_iter().map(|(name, field)| (name, field.values)).collect(),
+            venue: req.venue,
+            batch_id: req.batch_id,
+        }
+    }
+}
+
+/// The service of the namespace a call's metadata names, created for
+/// writes if needed.
+pub fn tenant(namespaces: &Namespaces, metadata: &MetadataMap, write: bool) -> Result<Arc<TradingDataService>, ServiceError> {
+    let name = match metadata.get(NAMESPACE_METADATA) {
+        Some(value) => value.to_str().map_err(|_| "Invalid x-namespace metadata".to_string())?,
+        None => DEFAULT_NAMESPACE,
+    };
+    if write {
+        namespaces.get_or_create(name)
+    } else {
+        namespaces.get(name)
+    }
+}
+
+struct TradingApi {
+    namespaces: Arc<Namespaces>,
+}
+
+impl TradingApi {
+    fn tenant<T>(&self, req: &Request<T>, write: bool) -> Result<Arc<TradingDataService>, ServiceError> {
+        tenant(&self.namespaces, req.metadata(), write)
+    }
+}
+
+type StatsStream = Pin<Box<dyn Stream<Item = Result<proto::Stats, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl Trading for TradingApi {
+    async fn add_batch(&self, req: Request<proto::AddBatchRequest>) -> Result<Response<proto::AddBatchReply>, Status> {
+        let service = self.tenant(&req, true).map_err(status)?;
+        let mut req = req.into_inner();
+        let symbol = std::mem::take(&mut req.symbol);
+        service.add_batch(symbol, req.into()).await.map_err(status)?;
+        Ok(Response::new(proto::AddBatchReply {}))
+    }
+
+    async fn get_stats(&self, req: Request<proto::GetStatsRequest>) -> Result<Response<proto::Stats>, Status> {
+        let service = self.tenant(&req, false).map_err(status)?;
+        let req = req.into_inner();
+        let options = StatsOptions { quantiles: req.quantiles, ..StatsOptions::default() };
+        let stats = service.get_stats_with(req.symbol, req.k as usize, &options).await.map_err(status)?;
+        Ok(Response::new(stats.into()))
+    }
+
+    type SubscribeStream = StatsStream;
+
+    async fn subscribe(&self, req: Request<proto::SubscribeRequest>) -> Result<Response<StatsStream>, Status> {
+        let service = self.tenant(&req, false).map_err(status)?;
+        let req = req.into_inner();
+        if req.max_hz.is_some_and(|hz| !(hz > 0.0 && hz <= MAX_HZ)) {
+            let e = format!("Invalid max_hz input. Only values above 0 up to {} are accepted.", MAX_HZ);
+            return Err(Status::invalid_argument(e));
+        }
+        let min_gap = req.max_hz.map_or(Duration::ZERO, |hz| Duration::from_secs_f64(1.0 / hz));
+        let symbol = service.canonical(req.symbol);
+        let k = req.k as usize;
+        // Subscribed before the first read so no change in between is missed.
+        let updates = service.subscribe();
+        let first = service.get_stats(symbol.clone(), k).await.map_err(status)?;
+
+        let state = Some((updates, Instant::now()));
+        let rest = futures::stream::unfold(state, move |state| {
+            let (service, symbol) = (service.clone(), symbol.clone());
+            async move {
+                let (mut updates, last_sent) = state?;
+                loop {
+                    match updates.recv().await {
+                        Ok(updated) if updated != symbol => continue,
+                        Ok(_) | Err(RecvError::Lagged(_)) => break,
+                        Err(RecvError::Closed) => return None,
+                    }
+                }
+                // Changes until the next update is due are folded into it.
+                tokio::time::sleep_until(last_sent + min_gap).await;
+                match service.get_stats(symbol, k).await {
+                    Ok(stats) => Some((Ok(stats.into()), Some((updates.resubscribe(), Instant::now())))),
+                    Err(e) => Some((Err(status(e)), None)),
+                }
+            }
+        });
+        let first = futures::stream::once(async move { Ok(first.into()) });
+        Ok(Response::new(Box::pin(futures::StreamExt::chain(first, rest))))
+    }
+}
+
+/// Serves the gRPC API, and Arrow Flight on the same address, until the
+/// process exits.
+pub async fn serve(namespaces: Arc<Namespaces>, addr: SocketAddr) -> Result<(), tonic::transport::Error> {
+    tonic::transport::Server::builder()
+        .add_service(crate::flight::service(namespaces.clone()))
+        .add_service(TradingServer::new(TradingApi { namespaces }))
+        .serve(addr)
+        .await
+}
@@ -0,0 +1,285 @@
+//! Technical indicators derived from the windows.
+
+use crate::bars::Bar;
+use crate::TradingDataBuffer;
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct BollingerBands {
+    pub mid: f64,
+    pub upper: f64,
+    pub lower: f64,
+}
+
+/// Bollinger Bands over the window: the mean, plus and minus `width`
+/// standard deviations.
+pub fn bollinger(window: &TradingDataBuffer, width: f64) -> BollingerBands {
+    let mid = window.mean();
+    let band = width * window.std_dev();
+    BollingerBands {
+        mid,
+        upper: mid + band,
+        lower: mid - band,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Rsi {
+    /// Relative strength index, 0 to 100.
+    pub rsi: f64,
+    pub avg_gain: f64,
+    pub avg_loss: f64,
+}
+
+/// Relative strength index over the window, using the simple average of
+/// gains and losses between successive values (Cutler's RSI). A window with
+/// no price movement reports 50.
+pub fn rsi(window: &TradingDataBuffer) -> Rsi {
+    let (gains, losses) = window.gains_losses();
+    let steps = window.len().saturating_sub(1).max(1) as f64;
+    let rsi = if gains + losses > 0.0 {
+        100.0 * gains / (gains + losses)
+    } else {
+        50.0
+    };
+    Rsi {
+        rsi,
+        avg_gain: gains / steps,
+        avg_loss: losses / steps,
+    }
+}
+
+/// Periods of the MACD EMAs, in ticks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct MacdConfig {
+    pub fast: u32,
+    pub slow: u32,
+    pub signal: u32,
+}
+
+impl Default for MacdConfig {
+    fn default() -> Self {
+        MacdConfig { fast: 12, slow: 26, signal: 9 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, serde::Serialize, serde::Deserialize)]
+pub struct Macd {
+    /// `fast_ema - slow_ema`.
+    pub macd: f64,
+    /// EMA of `macd`.
+    pub signal: f64,
+    /// `macd - signal`.
+    pub histogram: f64,
+    pub fast_ema: f64,
+    pub slow_ema: f64,
+}
+
+/// Incrementally maintained MACD. The EMAs are seeded with the first value,
+/// and see every value ingested rather than a window.
+#[derive(Debug, Clone)]
+pub struct MacdState {
+    config: MacdConfig,
+    current: Option<Macd>,
+}
+
+impl MacdState {
+    pub fn new(config: MacdConfig) -> Self {
+        MacdState { config, current: None }
+    }
+
+    pub fn config(&self) -> MacdConfig {
+        self.config
+    }
+
+    pub fn update(&mut self, value: f64) {
+        let alpha = |period: u32| 2.0 / (period as f64 + 1.0);
+        let mut macd = match self.current {
+            None => Macd { fast_ema: value, slow_ema: value, ..Macd::default() },
+            Some(mut macd) => {
+                macd.fast_ema += alpha(self.config.fast) * (value - macd.fast_ema);
+                macd.slow_ema += alpha(self.config.slow) * (value - macd.slow_ema);
+                macd.macd = macd.fast_ema - macd.slow_ema;
+                macd.signal += alpha(self.config.signal) * (macd.macd - macd.signal);
+                macd
+            }
+        };
+        macd.histogram = macd.macd - macd.signal;
+        self.current = Some(macd);
+    }
+
+    pub fn current(&self) -> Option<Macd> {
+        self.current
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Atr {
+    /// Wilder-smoothed average true range.
+    pub atr: f64,
+    /// True range of the most recent bar.
+    pub true_range: f64,
+    /// Number of bars the ATR was computed over.
+    pub bars: usize,
+}
+
+/// Average true range over consecutive bars, oldest first. The first
+/// `period` true ranges are averaged and later ones folded in with Wilder's
+/// smoothing. `None` until there are `period` bars.
+pub fn atr(bars: &[Bar], period: usize) -> Option<Atr> {
+    if period == 0 || bars.len() < period {
+        return None;
+    }
+
+    let true_ranges = bars.iter().enumerate().map(|(i, bar)| {
+        let range = bar.high - bar.low;
+        match i.checked_sub(1).map(|j| bars[j].close) {
+            Some(prev_close) => range
+                .max((bar.high - prev_close).abs())
+                .max((bar.low - prev_close).abs()),
+            None => range,
+        }
+    });
+
+    let mut atr = 0.0;
+    let mut true_range = 0.0;
+    for (i, tr) in true_ranges.enumerate() {
+        true_range = tr;
+        atr = if i < period {
+            atr + (tr - atr) / (i + 1) as f64
+        } else {
+            (atr * (period - 1) as f64 + tr) / period as f64
+        };
+    }
+    Some(Atr { atr, true_range, bars: bars.len() })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MovingAverageKind {
+    #[default]
+    Sma,
+    Ema,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct MovingAverage {
+    #[serde(rename = "type")]
+    pub kind: MovingAverageKind,
+    pub value: f64,
+    /// Number of values averaged; less than the requested lookback while the
+    /// symbol has fewer values.
+    pub count: usize,
+}
+
+/// Moving average of the last `lookback` values of the window. The EMA uses
+/// `alpha = 2 / (lookback + 1)` seeded with the oldest value in range.
+pub fn moving_average(window: &TradingDataBuffer, lookback: usize, kind: MovingAverageKind) -> MovingAverage {
+    let count = lookback.min(window.len());
+    let values = window.values().skip(window.len() - count);
+    let value = match kind {
+        MovingAverageKind::Sma if count > 0 => values.sum::<f64>() / count as f64,
+        MovingAverageKind::Ema => {
+            let alpha = 2.0 / (lookback as f64 + 1.0);
+            values.fold(None, |ema: Option<f64>, v| Some(ema.map_or(v, |e| e + alpha * (v - e))))
+                .unwrap_or(0.0)
+        }
+        _ => 0.0,
+    };
+    MovingAverage { kind, value, count }
+}
+
+/// Indicator state kept per symbol, independent of the count windows.
+#[derive(Debug, Clone)]
+pub struct IndicatorState {
+    pub macd: MacdState,
+}
+
+impl IndicatorState {
+    pub fn new(macd: MacdConfig) -> Self {
+        IndicatorState { macd: MacdState::new(macd) }
+    }
+
+    pub fn update(&mut self, values: &[f64]) {
+        for &value in values {
+            self.macd.update(value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bollinger() {
+        let mut window = TradingDataBuffer::new(10);
+        window.add_batch(&[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]);
+
+        let bands = bollinger(&window, 2.0);
+        assert!((bands.mid - 5.0).abs() < 1e-9);
+        assert!((bands.upper - 9.0).abs() < 1e-9);
+        assert!((bands.lower - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_rsi_over_window() {
+        let mut window = TradingDataBuffer::new(4);
+        window.add_batch(&[50.0, 10.0, 11.0, 13.0, 12.0]);
+
+        let result = rsi(&window);
+        assert!((result.rsi - 75.0).abs() < 1e-9);
+        assert!((result.avg_gain - 1.0).abs() < 1e-9);
+        assert!((result.avg_loss - 1.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_rsi_of_flat_window() {
+        let mut window = TradingDataBuffer::new(4);
+        window.add_batch(&[5.0, 5.0]);
+        assert_eq!(50.0, rsi(&window).rsi);
+    }
+
+    #[test]
+    fn test_macd_tracks_emas() {
+        let mut state = MacdState::new(MacdConfig { fast: 1, slow: 3, signal: 1 });
+        assert_eq!(None, state.current());
+
+        state.update(10.0);
+        state.update(20.0);
+        let macd = state.current().unwrap();
+        assert_eq!(20.0, macd.fast_ema);
+        assert_eq!(15.0, macd.slow_ema);
+        assert_eq!(5.0, macd.macd);
+        assert_eq!(5.0, macd.signal);
+        assert_eq!(0.0, macd.histogram);
+    }
+
+    #[test]
+    fn test_atr_uses_previous_close() {
+        let bar = |high: f64, low: f64, close: f64| Bar { start: 0, open: close, high, low, close, ticks: 1 };
+        let bars = [bar(10.0, 8.0, 9.0), bar(12.0, 11.0, 11.5), bar(11.0, 10.0, 10.0)];
+
+        assert_eq!(None, atr(&bars, 4));
+        let result = atr(&bars, 2).unwrap();
+        // True ranges: 2, 3 (12 - prev close 9), 1.5 (|10 - 11.5|).
+        assert_eq!(1.5, result.true_range);
+        assert_eq!((2.5 + 1.5) / 2.0, result.atr);
+        assert_eq!(3, result.bars);
+    }
+
+    #[test]
+    fn test_moving_average_over_lookback() {
+        let mut window = TradingDataBuffer::new(100);
+        window.add_batch(&[100.0, 1.0, 2.0, 3.0]);
+
+        let sma = moving_average(&window, 3, MovingAverageKind::Sma);
+        assert_eq!(MovingAverage { kind: MovingAverageKind::Sma, value: 2.0, count: 3 }, sma);
+
+        let ema = moving_average(&window, 3, MovingAverageKind::Ema);
+        assert_eq!(2.25, ema.value);
+
+        let short = moving_average(&window, 10, MovingAverageKind::Sma);
+        assert_eq!(4, short.count);
+        assert_eq!(26.5, short.value);
+    }
+}
@@ -0,0 +1,163 @@
+//! Per-symbol ingestion tasks. Each symbol that takes data gets a task that
+//! owns its writes, fed batches through a bounded queue: writers to one
+//! symbol never contend for its lock, batches are added in the order they
+//! were queued, and a symbol whose queue is full holds up only its own
+//! writers. The task takes whatever has queued up at once and adds it under
+//! one acquisition of the symbol's lock, so a burst costs readers one wait
+//! rather than one per batch.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::{mpsc, oneshot};
+
+use crate::{Batch, ServiceError};
+
+/// Batches a symbol's queue holds before its writers wait.
+pub const QUEUE_CAPACITY: usize = 64;
+
+/// A batch for a symbol's task, with where to send how adding it went.
+pub struct Job {
+    pub batch: Batch,
+    pub reply: oneshot::Sender<Result<(), ServiceError>>,
+}
+
+/// A queued batch, to be waited on for how adding it went.
+pub struct Queued(oneshot::Receiver<Result<(), ServiceError>>);
+
+impl Queued {
+    pub async fn result(self) -> Result<(), ServiceError> {
+        self.0.await.map_err(|_| ended())?
+    }
+}
+
+fn ended() -> ServiceError {
+    ServiceError::Internal("Symbol ingestion task ended".to_string())
+}
+
+/// Queues the batch, waiting for room while the queue is full.
+pub async fn enqueue(queue: &mpsc::Sender<Job>, batch: Batch) -> Result<Queued, ServiceError> {
+    let (reply, result) = oneshot::channel();
+    queue.send(Job { batch, reply }).await.map_err(|_| ended())?;
+    Ok(Queued(result))
+}
+
+/// A task's end of its symbol's queue.
+pub struct Jobs {
+    jobs: mpsc::Receiver<Job>,
+    removed: Arc<AtomicBool>,
+}
+
+impl Jobs {
+    /// Waits for a job, then takes every other one already queued; `None`
+    /// once the queue is closed and empty.
+    pub async fn next(&mut self) -> Option<Vec<Job>> {
+        let mut jobs = vec![self.jobs.recv().await?];
+        while let Ok(job) = self.jobs.try_recv() {
+            jobs.push(job);
+        }
+        Some(jobs)
+    }
+
+    /// Whether the symbol was removed, after which queued jobs are failed
+    /// rather than added to a symbol of the same name tracked since.
+    pub fn removed(&self) -> bool {
+        self.removed.load(Ordering::Acquire)
+    }
+}
+
+/// Fails every job, as when the symbol was removed with the jobs queued.
+pub fn fail(jobs: Vec<Job>, error: impl Fn() -> ServiceError) {
+    for job in jobs {
+        let _ = job.reply.send(Err(error()));
+    }
+}
+
+struct Ingestor {
+    queue: mpsc::Sender<Job>,
+    removed: Arc<AtomicBool>,
+}
+
+/// The queue of each symbol's task, by symbol.
+#[derive(Default)]
+pub struct Ingestors {
+    queues: Mutex<HashMap<String, Ingestor>>,
+}
+
+impl Ingestors {
+    /// The symbol's queue, first spawning its task with `run` if it has
+    /// none or its task has ended.
+    pub fn queue<F, Fut>(&self, symbol: &str, run: F) -> mpsc::Sender<Job>
+    where
+        F: FnOnce(Jobs) -> Fut,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let mut queues = self.queues.lock().unwrap();
+        if let Some(ingestor) = queues.get(symbol).filter(|ingestor| !ingestor.queue.is_closed()) {
+            return ingestor.queue.clone();
+        }
+        let (queue, jobs) = mpsc::channel(QUEUE_CAPACITY);
+        let removed = Arc::new(AtomicBool::new(false));
+        tokio::spawn(run(Jobs { jobs, removed: removed.clone() }));
+        queues.insert(symbol.to_string(), Ingestor { queue: queue.clone(), removed });
+        queue
+    }
+
+    /// Closes the symbol's queue and cancels the batches still on it: its
+    /// task fails them and ends.
+    pub fn remove(&self, symbol: &str) {
+        if let Some(ingestor) = self.queues.lock().unwrap().remove(symbol) {
+            ingestor.removed.store(true, Ordering::Release);
+        }
+    }
+}
+
+/// Queues the batch and waits for it to be added.
+pub async fn submit(queue: &mpsc::Sender<Job>, batch: Batch) -> Result<(), ServiceError> {
+    enqueue(queue, batch).await?.result().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_ingestors() {
+        let ingestors = Ingestors::default();
+        let echo = |mut jobs: Jobs| async move {
+            while let Some(queued) = jobs.next().await {
+                if jobs.removed() {
+                    fail(queued, || ServiceError::UnknownSymbol);
+                    continue;
+                }
+                for job in queued {
+                    let result = if job.batch.values.is_empty() { Err(ServiceError::UnknownSymbol) } else { Ok(()) };
+                    let _ = job.reply.send(result);
+                }
+            }
+        };
+        let queue = ingestors.queue("A", echo);
+        let queued = enqueue(&queue, Batch::new(vec![1.0])).await.unwrap();
+        assert_eq!(Err(ServiceError::UnknownSymbol), submit(&queue, Batch::new(vec![])).await);
+        assert!(queued.result().await.is_ok());
+        assert!(ingestors.queue("A", |_| async { panic!("A has a task") }).same_channel(&queue));
+
+        // Batches still queued when the symbol goes are failed.
+        let (open, gate) = oneshot::channel::<()>();
+        let gated = ingestors.queue("B", move |jobs| async move {
+            let _ = gate.await;
+            echo(jobs).await
+        });
+        let queued = enqueue(&gated, Batch::new(vec![1.0])).await.unwrap();
+        ingestors.remove("B");
+        open.send(()).unwrap();
+        assert_eq!(Err(ServiceError::UnknownSymbol), queued.result().await);
+
+        ingestors.remove("A");
+        drop(queue);
+        let queue = ingestors.queue("A", echo);
+        assert!(submit(&queue, Batch::new(vec![1.0])).await.is_ok());
+    }
+}
@@ -1,206 +1,5230 @@
-use std::collections::VecDeque;
+pub mod analytics;
+pub mod bars;
+pub mod calendar;
+pub mod cross;
+pub mod derived;
+pub mod element;
+pub mod error;
+pub mod expr;
+pub mod fast_path;
+pub mod filters;
+pub mod fx;
+pub mod groups;
+pub mod indicators;
+pub mod ingest;
+pub mod meta;
+pub mod moments;
+pub mod namespaces;
+pub mod order_stats;
+pub mod quotes;
+pub mod ranking;
+pub mod reorder;
+pub mod risk;
+pub mod scans;
+pub mod series;
+pub mod settings;
+pub mod sketch;
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tokio::sync::{broadcast, mpsc, RwLock, RwLockReadGuard};
+
+use crate::analytics::{Autocorrelation, Entropy, Hurst};
+use crate::bars::{Bar, BarSeries};
+use crate::calendar::{Session, SessionAction, SessionConfig};
+use crate::derived::{BasketConfig, Definition, DerivedConfig, Derivation};
+pub use crate::element::Element;
+use crate::element::Column;
+pub use crate::error::ServiceError;
+use crate::expr::{Condition, FormulaConfig, Formulas};
+use crate::fast_path::{Apply, FastPathStats, FastPaths, Published, Screen};
+use crate::fx::{FxConfig, FxConversion};
+use crate::filters::{NonFiniteAction, OutlierAction, OutlierDetector, OutlierFilter, RecentIds, SequenceTracker};
+use crate::ingest::{Ingestors, Job, Jobs};
+use crate::indicators::{
+    Atr, BollingerBands, IndicatorState, Macd, MacdConfig, MovingAverage, MovingAverageKind, Rsi,
+};
+use crate::groups::{GroupStats, TagFilter};
+use crate::meta::{validate_tags, SymbolMeta};
+use crate::moments::{lane_sums, BatchSummary, CompensatedSum, FixedPointSums, PositiveMeans, WeightedMoments, MAX_FIXED_POINT_DECIMALS};
+use crate::order_stats::{SlidingDrawdown, SlidingMedian, SlidingMinMax};
+use crate::quotes::{Quote, QuoteBuffer, QuoteStats};
+use crate::ranking::{Mover, Order, RankMetric};
+use crate::scans::{Scan, Scans};
+use crate::reorder::ReorderBuffer;
+use crate::risk::{Sharpe, ValueAtRisk};
+use crate::series::RollingSeries;
+use crate::settings::Settings;
+pub use crate::series::SeriesKind;
+use crate::sketch::{QuantileConfig, SlidingQuantiles, WindowQuantiles};
+
+/// Batches at least this long are added to count windows at least this
+/// large by merging each run of points into the window's aggregates at
+/// once; see [`TradingDataBuffer::extend`]. Shorter ones don't repay the
+/// extra passes.
+const MIN_MERGED_BATCH: usize = 64;
+
+/// A window of values stored as `T` (`f64`, `f32` or `i64`); every aggregate
+/// is kept in `f64` whatever the element type.
+pub struct TradingDataBuffer<T = f64> {
+    values: VecDeque<T>,
+    /// Weight of each value in `values`, 1.0 unless the batch carried
+    /// weights; nothing is stored until a batch does.
+    weights: Column<T>,
+    /// Traded volume of each value in `values`, 0.0 unless the batch
+    /// carried volumes; nothing is stored until a batch does.
+    volumes: Column<T>,
+    /// Event time of each value in `values` in nanoseconds since the Unix
+    /// epoch; the receive time unless the batch carried timestamps.
+    timestamps: VecDeque<u64>,
+    capacity: usize,
+    /// For time windows: values older than this relative to the newest
+    /// timestamp seen are evicted.
+    max_age_ns: Option<u64>,
+    latest_ns: u64,
+    prices: RollingSeries,
+    returns: RollingSeries,
+    log_returns: RollingSeries,
+    gains: CompensatedSum,
+    losses: CompensatedSum,
+    /// Σ i·x with i the position in the window, 0 for the oldest value.
+    index_weighted_sum: CompensatedSum,
+    positive_means: PositiveMeans,
+    weighted: WeightedMoments,
+    /// Σ p·v and Σ v over the window, for the VWAP.
+    notional: CompensatedSum,
+    volume: CompensatedSum,
+    /// Exact sums for symbols kept in fixed point; values and volumes are
+    /// then rounded to its decimal places on the way in.
+    fixed: Option<FixedPointSums>,
+    volume_extrema: SlidingMinMax,
+    evictions_since_resync: usize,
+    quantiles: WindowQuantiles,
+    quantile_config: QuantileConfig,
+    return_quantiles: SlidingQuantiles,
+    /// Exact median, kept only while the window's quantiles are exact;
+    /// larger windows read the median from their quantile sketch rather
+    /// than hold every value in ordered trees.
+    median: Option<SlidingMedian>,
+    drawdown: SlidingDrawdown,
+    ewma_alpha: f64,
+    ewma: f64,
+    ew_var: f64,
+    seen_any: bool,
+    last_update: u64,
+    /// Identifies this window's contents for scans; a cleared window gets
+    /// a new one.
+    id: u64,
+    /// Points evicted since the window was created or cleared, which is
+    /// also the position of the oldest point held.
+    evicted: u64,
+    /// While a scan still needs them, points evicted from this position on
+    /// are kept in `retained` rather than dropped.
+    retain_from: Option<u64>,
+    /// Evicted points a scan still needs, oldest first, ending just before
+    /// the oldest point held; each as value, weight, volume and timestamp.
+    retained: VecDeque<(T, T, T, u64)>,
+}
+
+impl TradingDataBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self::with_capacity(capacity)
+    }
+}
+
+impl<T: Element> TradingDataBuffer<T> {
+    /// A count window of any element type, e.g.
+    /// `TradingDataBuffer::<f32>::with_capacity(100_000_000)`.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_limits(capacity, None)
+    }
+
+    /// A time window: holds the values whose timestamps are within `max_age_ns`
+    /// of the newest timestamp it has seen, and at most `capacity` of them.
+    pub fn with_max_age(max_age_ns: u64, capacity: usize) -> Self {
+        let mut buffer = Self::with_limits(capacity, Some(max_age_ns));
+        buffer.quantiles = WindowQuantiles::evicting_by_age(&buffer.quantile_config, capacity);
+        buffer.median = buffer.quantiles.is_exact().then(SlidingMedian::new);
+        buffer
+    }
+
+    fn with_limits(capacity: usize, max_age_ns: Option<u64>) -> Self {
+        TradingDataBuffer {
+            values: VecDeque::new(),
+            weights: Column::new(1.0),
+            volumes: Column::new(0.0),
+            timestamps: VecDeque::new(),
+            capacity,
+            max_age_ns,
+            latest_ns: 0,
+            prices: RollingSeries::new(),
+            returns: RollingSeries::new(),
+            log_returns: RollingSeries::new(),
+            gains: CompensatedSum::new(),
+            losses: CompensatedSum::new(),
+            index_weighted_sum: CompensatedSum::new(),
+            positive_means: PositiveMeans::new(),
+            weighted: WeightedMoments::new(),
+            notional: CompensatedSum::new(),
+            volume: CompensatedSum::new(),
+            fixed: None,
+            volume_extrema: SlidingMinMax::new(),
+            evictions_since_resync: 0,
+            quantiles: WindowQuantiles::new(&QuantileConfig::default(), capacity),
+            quantile_config: QuantileConfig::default(),
+            return_quantiles: SlidingQuantiles::new(capacity.saturating_sub(1).max(1)),
+            median: (capacity < 10usize.pow(QuantileConfig::default().from_k as u32)).then(SlidingMedian::new),
+            drawdown: SlidingDrawdown::new(capacity),
+            ewma_alpha: default_ewma_alpha(capacity),
+            ewma: 0.0,
+            ew_var: 0.0,
+            seen_any: false,
+            last_update: 0,
+            id: next_version(),
+            evicted: 0,
+            retain_from: None,
+            retained: VecDeque::new(),
+        }
+    }
+
+    /// Overrides the EWMA decay factor. `None` restores the default of
+    /// `2 / (capacity + 1)`, which gives each window an EWMA span matching its size.
+    pub fn set_ewma_alpha(&mut self, alpha: Option<f64>) {
+        self.ewma_alpha = alpha.unwrap_or(default_ewma_alpha(self.capacity));
+    }
+
+    /// Switches how the window answers quantile queries, rebuilding the
+    /// sketch from the current window contents if the config changed.
+    pub fn set_quantile_config(&mut self, config: &QuantileConfig) {
+        if *config == self.quantile_config {
+            return;
+        }
+        self.quantile_config = *config;
+        self.quantiles = match self.max_age_ns {
+            Some(_) => WindowQuantiles::evicting_by_age(config, self.capacity),
+            None => WindowQuantiles::new(config, self.capacity),
+        };
+        self.median = self.quantiles.is_exact().then(SlidingMedian::new);
+        for value in self.values.iter().map(|v| v.to_f64()) {
+            self.quantiles.insert(value);
+            if let Some(median) = &mut self.median {
+                median.insert(value);
+            }
+        }
+    }
+
+    /// Median of the window: exact while its quantiles are, otherwise
+    /// estimated by its quantile sketch.
+    pub fn median(&self) -> f64 {
+        match &self.median {
+            Some(median) => median.median().unwrap_or(0.0),
+            None if self.values.is_empty() => 0.0,
+            None => self.quantiles.quantiles(&[0.5], std::iter::empty())[0],
+        }
+    }
+
+    /// Keeps the window's sums exactly in fixed point with the given number
+    /// of decimal places, or in floating point with `None`. Values already
+    /// in the window are summed as if rounded; new ones are rounded.
+    pub fn set_fixed_point(&mut self, decimals: Option<u32>) {
+        if self.fixed.map(|f| f.decimals()) == decimals {
+            return;
+        }
+        self.fixed = decimals.map(|decimals| {
+            let mut sums = FixedPointSums::new(decimals);
+            for (i, value) in self.values.iter().enumerate() {
+                sums.add(value.to_f64(), self.volumes.get(i).to_f64());
+            }
+            sums
+        });
+    }
+
+    /// Empties the window, keeping its capacity, age limit and settings.
+    pub fn clear(&mut self) {
+        let mut fresh = match self.max_age_ns {
+            Some(max_age_ns) => Self::with_max_age(max_age_ns, self.capacity),
+            None => Self::with_capacity(self.capacity),
+        };
+        fresh.ewma_alpha = self.ewma_alpha;
+        fresh.set_quantile_config(&self.quantile_config);
+        fresh.set_fixed_point(self.fixed.map(|f| f.decimals()));
+        *self = fresh;
+    }
+
+    /// Age limit of a time window, in nanoseconds; `None` for count windows.
+    pub fn max_age_ns(&self) -> Option<u64> {
+        self.max_age_ns
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Approximate memory held by the window: the stored points and the
+    /// per-point order statistics. Sketches are bounded in size and counted
+    /// inline only.
+    pub fn memory_bytes(&self) -> usize {
+        let element = std::mem::size_of::<T>();
+        let points = (self.values.capacity() + self.weights.capacity() + self.volumes.capacity()) * element
+            + self.timestamps.capacity() * std::mem::size_of::<u64>()
+            + self.retained.capacity() * std::mem::size_of::<(T, T, T, u64)>();
+        let series = [&self.prices, &self.returns, &self.log_returns].iter().map(|s| s.heap_bytes()).sum::<usize>();
+        std::mem::size_of::<Self>()
+            + points
+            + series
+            + self.volume_extrema.heap_bytes()
+            + self.median.as_ref().map_or(0, |median| median.heap_bytes())
+            + self.drawdown.heap_bytes()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Mean of the values in the window.
+    pub fn mean(&self) -> f64 {
+        self.prices.mean()
+    }
+
+    /// Population standard deviation of the values in the window.
+    pub fn std_dev(&self) -> f64 {
+        self.prices.variance().sqrt()
+    }
+
+    /// Total upward and downward price moves between successive values in
+    /// the window, both non-negative.
+    pub fn gains_losses(&self) -> (f64, f64) {
+        (self.gains.value(), self.losses.value())
+    }
+
+    /// Estimated quantiles of the simple returns between successive values
+    /// in the window, from the window's return sketch.
+    pub fn return_quantiles(&self, qs: &[f64]) -> Vec<f64> {
+        self.return_quantiles.quantiles(qs)
+    }
+
+    /// Oldest value in the window.
+    pub fn open(&self) -> Option<f64> {
+        self.values.front().map(|v| v.to_f64())
+    }
+
+    /// `(last - open) / open` over the window, or `None` when the window is
+    /// empty or opens at zero.
+    pub fn pct_change(&self) -> Option<f64> {
+        let open = self.open()?;
+        (open != 0.0).then(|| (self.prices.last() - open) / open)
+    }
+
+    /// Values in the window, oldest first.
+    pub fn values(&self) -> impl DoubleEndedIterator<Item = f64> + ExactSizeIterator + '_ {
+        self.values.iter().map(|v| v.to_f64())
+    }
+
+    /// Event times of the oldest and newest values in the window, in
+    /// nanoseconds since the Unix epoch.
+    pub fn time_span(&self) -> Option<(u64, u64)> {
+        Some((*self.timestamps.front()?, *self.timestamps.back()?))
+    }
+
+    /// The last `n` points of the window with their weights, volumes and
+    /// timestamps, oldest first.
+    pub fn tail(&self, n: usize) -> Batch {
+        let skip = self.values.len().saturating_sub(n);
+        Batch {
+            values: self.values.iter().skip(skip).map(|v| v.to_f64()).collect(),
+            weights: Some((skip..self.values.len()).map(|i| self.weights.get(i).to_f64()).collect()),
+            volumes: Some((skip..self.values.len()).map(|i| self.volumes.get(i).to_f64()).collect()),
+            timestamps: Some(self.timestamps.iter().skip(skip).copied().collect()),
+            ..Batch::default()
+        }
+    }
+
+    /// Identifies the window's contents; it changes when the window is
+    /// cleared, so scans started before can tell.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Positions of the oldest point held and just past the newest, counting
+    /// every point added since the window was created or cleared.
+    pub fn positions(&self) -> (u64, u64) {
+        (self.evicted, self.evicted + self.values.len() as u64)
+    }
+
+    /// Keeps the points evicted from `position` on, for a scan that hasn't
+    /// read them yet, dropping those kept before it; `None` drops them all.
+    pub fn retain(&mut self, position: Option<u64>) {
+        match position {
+            Some(position) if self.retain_from.is_some() || position >= self.evicted => {
+                let oldest = self.evicted - self.retained.len() as u64;
+                let drop = position.saturating_sub(oldest).min(self.retained.len() as u64);
+                self.retained.drain(..drop as usize);
+                self.retain_from = Some(position);
+            }
+            // Points the scan needs are already gone; it will fail.
+            Some(_) | None => {
+                self.retained = VecDeque::new();
+                self.retain_from = None;
+            }
+        }
+    }
+
+    /// Up to `limit` points from `position` on, oldest first, including
+    /// evicted ones still retained; `None` if some are gone.
+    pub fn points_from(&self, position: u64, limit: usize) -> Option<Vec<Point>> {
+        let oldest = self.evicted - self.retained.len() as u64;
+        if position < oldest {
+            return None;
+        }
+        let point = |&(value, weight, volume, timestamp): &(T, T, T, u64)| Point {
+            value: value.to_f64(),
+            weight: Some(weight.to_f64()),
+            volume: Some(volume.to_f64()),
+            timestamp: Some(timestamp),
+            sequence: None,
+            id: None,
+        };
+        let held = (0..self.values.len()).map(|i| (self.values[i], self.weights.get(i), self.volumes.get(i), self.timestamps[i]));
+        let points = self.retained.iter().copied().chain(held);
+        Some(points.skip((position - oldest) as usize).take(limit).map(|p| point(&p)).collect())
+    }
+
+    /// Up to `limit` points, skipping the `offset` newest, oldest first.
+    pub fn points(&self, offset: usize, limit: usize) -> Vec<Point> {
+        let end = self.values.len().saturating_sub(offset);
+        let start = end.saturating_sub(limit);
+        (start..end)
+            .map(|i| Point {
+                value: self.values[i].to_f64(),
+                weight: Some(self.weights.get(i).to_f64()),
+                volume: Some(self.volumes.get(i).to_f64()),
+                timestamp: Some(self.timestamps[i]),
+                sequence: None,
+                id: None,
+            })
+            .collect()
+    }
+
+    /// The points whose timestamps are within `max_age_ns` of the newest
+    /// one, oldest first.
+    fn tail_within(&self, max_age_ns: u64) -> Batch {
+        let Some(&newest) = self.timestamps.back() else {
+            return Batch::default();
+        };
+        let cutoff = newest.saturating_sub(max_age_ns);
+        let n = self.timestamps.iter().rev().take_while(|&&t| t >= cutoff).count();
+        self.tail(n)
+    }
+
+    pub fn add_batch(&mut self, new_values: &[f64]) {
+        self.add_points(&Batch::new(new_values.to_vec()));
+    }
+
+    /// Adds every value of a validated batch with its optional fields.
+    /// Values without a timestamp are stamped with the current time.
+    pub fn add_points(&mut self, batch: &Batch) {
+        if batch.values.is_empty() {
+            return;
+        }
+        let now_ns = now_nanos();
+        if self.max_age_ns.is_none() && batch.values.len() >= MIN_MERGED_BATCH && self.capacity >= MIN_MERGED_BATCH {
+            self.add_merged(batch, now_ns);
+        } else {
+            for (i, &value) in batch.values.iter().enumerate() {
+                self.add(value, batch.weight(i), batch.volume(i), batch.timestamp(i).unwrap_or(now_ns));
+            }
+        }
+        self.last_update = now_ns / 1_000_000;
+    }
+
+    /// Adds a large batch to a count window in runs of at most `capacity`
+    /// values. Each run first evicts what it will push out with
+    /// [`Self::evict_many`], which leaves the window as adding one value at
+    /// a time would, then is merged in with [`Self::extend`].
+    fn add_merged(&mut self, batch: &Batch, now_ns: u64) {
+        let run = batch.values.len().min(self.capacity);
+        let (mut prices, mut weights, mut volumes) = (Vec::with_capacity(run), Vec::with_capacity(run), Vec::with_capacity(run));
+        let mut start = 0;
+        while start < batch.values.len() {
+            let end = (start + self.capacity).min(batch.values.len());
+            let newest = self.values.back().map(|v| v.to_f64());
+            self.evict_many((self.values.len() + end - start).saturating_sub(self.capacity));
+            prices.clear();
+            weights.clear();
+            volumes.clear();
+            for i in start..end {
+                let (value, weight, volume) = self.stored(batch.values[i], batch.weight(i), batch.volume(i));
+                prices.push(value);
+                weights.push(weight);
+                volumes.push(volume);
+            }
+            self.timestamps.extend((start..end).map(|i| batch.timestamp(i).unwrap_or(now_ns)));
+            self.extend(&prices, &weights, &volumes, newest);
+            if self.evictions_since_resync >= self.capacity {
+                self.resync_accumulators();
+            }
+            start = end;
+        }
+    }
+
+    /// Appends a run of stored points, whose timestamps are already in, to
+    /// a window with room for them. Every aggregate is worked out over the
+    /// run alone, mostly in lane-wise passes, and merged in at once; only
+    /// the EWMA, a recurrence on its previous value, and the sketches'
+    /// per-value buckets still take the values one by one. `newest` is the
+    /// newest value before the run's evictions: the return sketch, which
+    /// forgets by count, takes the step from it even if it's gone.
+    fn extend(&mut self, prices: &[f64], weights: &[f64], volumes: &[f64], newest: Option<f64>) {
+        let held = self.values.len();
+        let previous = self.values.back().map(|v| v.to_f64());
+        if let (None, Some(newest)) = (previous, newest) {
+            self.return_quantiles.insert(SeriesKind::Returns.step(newest, prices[0]));
+        }
+        // Steps from the window's newest value through the run.
+        let steps = || previous.into_iter().chain(prices.iter().copied()).zip(prices.iter().copied().skip(usize::from(previous.is_none())));
+        let returns: Vec<f64> = steps().map(|(a, b)| SeriesKind::Returns.step(a, b)).collect();
+        let log_returns: Vec<f64> = steps().map(|(a, b)| SeriesKind::LogReturns.step(a, b)).collect();
+        let changes: Vec<f64> = steps().map(|(a, b)| b - a).collect();
+        self.returns.extend(&returns);
+        self.log_returns.extend(&log_returns);
+        self.return_quantiles.extend(&returns);
+        let [gains, losses] = lane_sums(changes.len(), |i| [changes[i].max(0.0), (-changes[i]).max(0.0)]);
+        self.gains.add(gains);
+        self.losses.add(losses);
+
+        let [index_weighted_sum, notional, volume] =
+            lane_sums(prices.len(), |i| [(held + i) as f64 * prices[i], prices[i] * volumes[i], volumes[i]]);
+        self.index_weighted_sum.add(index_weighted_sum);
+        self.notional.add(notional);
+        self.volume.add(volume);
+        self.values.extend(prices.iter().map(|&value| T::from_f64(value)));
+        self.weights.extend(weights, held);
+        self.volumes.extend(volumes, held);
+        self.weighted.merge(&WeightedMoments::of(prices, weights));
+        let summary = BatchSummary::of(volumes);
+        self.volume_extrema.extend(volumes, summary.min, summary.max);
+        self.positive_means.extend(prices);
+        self.quantiles.extend(prices);
+        if let Some(median) = &mut self.median {
+            median.extend(prices);
+        }
+        self.drawdown.extend(prices);
+        prices.iter().for_each(|&value| self.update_ewma(value));
+        self.prices.extend(prices);
+    }
+
+    fn add(&mut self, value: f64, weight: f64, volume: f64, timestamp_ns: u64) {
+        if self.values.len() >= self.capacity {
+            self.evict_oldest();
+        }
+        let value = self.append(value, weight, volume, timestamp_ns);
+        self.prices.push(value);
+
+        if let Some(max_age_ns) = self.max_age_ns {
+            self.latest_ns = self.latest_ns.max(timestamp_ns);
+            let cutoff = self.latest_ns.saturating_sub(max_age_ns);
+            while self.timestamps.front().is_some_and(|&t| t < cutoff) {
+                self.evict_oldest();
+            }
+        }
+        if self.evictions_since_resync >= self.capacity {
+            self.resync_accumulators();
+        }
+    }
+
+    /// Appends a value to a window with room for it, updating every
+    /// aggregate but the price series, and returns the value as stored.
+    fn append(&mut self, value: f64, weight: f64, volume: f64, timestamp_ns: u64) -> f64 {
+        let (value, weight, volume) = self.stored(value, weight, volume);
+
+        if let Some(prev) = self.values.back().map(|v| v.to_f64()) {
+            self.returns.push(SeriesKind::Returns.step(prev, value));
+            self.log_returns.push(SeriesKind::LogReturns.step(prev, value));
+            self.return_quantiles.insert(SeriesKind::Returns.step(prev, value));
+            let change = value - prev;
+            self.gains.add(change.max(0.0));
+            self.losses.add((-change).max(0.0));
+        }
+        self.index_weighted_sum.add(self.values.len() as f64 * value);
+        self.values.push_back(T::from_f64(value));
+        self.weights.push(weight, self.values.len() - 1);
+        self.weighted.add(value, weight);
+        self.volumes.push(volume, self.values.len() - 1);
+        self.timestamps.push_back(timestamp_ns);
+        self.notional.add(value * volume);
+        self.volume.add(volume);
+        self.volume_extrema.push(volume);
+        self.positive_means.add(value);
+        self.quantiles.insert(value);
+        if let Some(median) = &mut self.median {
+            median.insert(value);
+        }
+        self.drawdown.push(value);
+        self.update_ewma(value);
+        value
+    }
+
+    /// A point's value, weight and volume as the window stores them,
+    /// counted in its fixed-point sums if it keeps them. The aggregates are
+    /// of the stored values, so evicting them undoes them exactly.
+    fn stored(&mut self, value: f64, weight: f64, volume: f64) -> (f64, f64, f64) {
+        let stored = |x: f64| T::from_f64(x).to_f64();
+        let (value, weight, volume) = (stored(value), stored(weight), stored(volume));
+        match &mut self.fixed {
+            Some(fixed) => {
+                let (value, volume) = (fixed.quantize(value), fixed.quantize(volume));
+                fixed.add(value, volume);
+                (value, weight, volume)
+            }
+            None => (value, weight, volume),
+        }
+    }
+
+    fn evict_oldest(&mut self) {
+        let (old_value, old_weight, old_volume) =
+            (self.values.pop_front().unwrap(), self.weights.pop_front(), self.volumes.pop_front());
+        let old_timestamp = self.timestamps.pop_front().unwrap();
+        if self.retain_from.is_some_and(|from| self.evicted >= from) {
+            // A scan too far behind to keep up with lets go of the points
+            // rather than double the window's memory; it then fails.
+            if self.retained.len() < self.capacity {
+                self.retained.push_back((old_value, old_weight, old_volume, old_timestamp));
+            } else {
+                self.retain(None);
+            }
+        }
+        self.evicted += 1;
+        let (old_value, old_weight, old_volume) = (old_value.to_f64(), old_weight.to_f64(), old_volume.to_f64());
+        self.weighted.remove(old_value, old_weight);
+        self.notional.sub(old_value * old_volume);
+        self.volume.sub(old_volume);
+        if let Some(fixed) = &mut self.fixed {
+            fixed.remove(old_value, old_volume);
+        }
+        self.volume_extrema.pop();
+        self.prices.pop(old_value);
+        // Every remaining value moves down one position.
+        self.index_weighted_sum.sub(self.prices.sum());
+        if let Some(next) = self.values.front().map(|v| v.to_f64()) {
+            self.returns.pop(SeriesKind::Returns.step(old_value, next));
+            self.log_returns.pop(SeriesKind::LogReturns.step(old_value, next));
+            let change = next - old_value;
+            self.gains.sub(change.max(0.0));
+            self.losses.sub((-change).max(0.0));
+        }
+        self.positive_means.remove(old_value);
+        self.quantiles.remove(old_value);
+        if let Some(median) = &mut self.median {
+            median.remove(old_value);
+        }
+        self.drawdown.pop();
+        self.evictions_since_resync += 1;
+    }
+
+    /// Evicts the `n` oldest points at once, taking their sums and moments
+    /// out of the window's aggregates in lane-wise passes; only the exact
+    /// median and the DDSketch still forget them one by one. Few points,
+    /// or points a scan still needs, are evicted one at a time instead.
+    fn evict_many(&mut self, n: usize) {
+        if n < MIN_MERGED_BATCH || self.retain_from.is_some() {
+            (0..n).for_each(|_| self.evict_oldest());
+            return;
+        }
+        let old: Vec<f64> = self.values.drain(..n).map(|v| v.to_f64()).collect();
+        let (weights, volumes) = (self.weights.drain_front(n), self.volumes.drain_front(n));
+        self.timestamps.drain(..n);
+        self.evicted += n as u64;
+        self.evictions_since_resync += n;
+
+        // Steps from the evicted values through the oldest one kept.
+        let next = self.values.front().map(|v| v.to_f64());
+        let steps = || old.iter().copied().zip(old.iter().copied().skip(1).chain(next));
+        let returns: Vec<f64> = steps().map(|(a, b)| SeriesKind::Returns.step(a, b)).collect();
+        let log_returns: Vec<f64> = steps().map(|(a, b)| SeriesKind::LogReturns.step(a, b)).collect();
+        let changes: Vec<f64> = steps().map(|(a, b)| b - a).collect();
+        self.returns.pop_many(&returns);
+        self.log_returns.pop_many(&log_returns);
+        let [gains, losses] = lane_sums(changes.len(), |i| [changes[i].max(0.0), (-changes[i]).max(0.0)]);
+        self.gains.sub(gains);
+        self.losses.sub(losses);
+
+        self.prices.pop_many(&old);
+        // Every remaining value moves down `n` positions.
+        let [index_weighted_sum, notional, volume] = lane_sums(n, |i| [i as f64 * old[i], old[i] * volumes[i], volumes[i]]);
+        self.index_weighted_sum.sub(index_weighted_sum + n as f64 * self.prices.sum());
+        self.notional.sub(notional);
+        self.volume.sub(volume);
+        if let Some(fixed) = &mut self.fixed {
+            old.iter().zip(&volumes).for_each(|(&value, &volume)| fixed.remove(value, volume));
+        }
+        self.weighted.unmerge(&WeightedMoments::of(&old, &weights));
+        self.volume_extrema.pop_many(n);
+        self.positive_means.remove_many(&old);
+        self.quantiles.remove_many(&old);
+        if let Some(median) = &mut self.median {
+            median.remove_many(&old);
+        }
+        self.drawdown.pop_many(n);
+    }
+
+    fn update_ewma(&mut self, value: f64) {
+        if !self.seen_any {
+            self.ewma = value;
+            self.seen_any = true;
+            return;
+        }
+        let delta = value - self.ewma;
+        self.ewma += self.ewma_alpha * delta;
+        self.ew_var = (1.0 - self.ewma_alpha) * (self.ew_var + self.ewma_alpha * delta * delta);
+    }
+
+    /// Rebuilds the running sums and moments from the window contents. Done
+    /// once per `capacity` evictions, so the cost is amortized O(1) per value
+    /// and rounding error from add/evict cycles can't accumulate indefinitely.
+    fn resync_accumulators(&mut self) {
+        let values = || self.values.iter().map(|v| v.to_f64());
+        let steps = || values().zip(values().skip(1));
+        self.prices.resync(values());
+        self.returns.resync(steps().map(|(a, b)| SeriesKind::Returns.step(a, b)));
+        self.log_returns.resync(steps().map(|(a, b)| SeriesKind::LogReturns.step(a, b)));
+        self.gains = CompensatedSum::new();
+        self.losses = CompensatedSum::new();
+        for (a, b) in steps() {
+            self.gains.add((b - a).max(0.0));
+            self.losses.add((a - b).max(0.0));
+        }
+        self.index_weighted_sum = CompensatedSum::new();
+        for (i, value) in values().enumerate() {
+            self.index_weighted_sum.add(i as f64 * value);
+        }
+        self.positive_means = PositiveMeans::new();
+        self.weighted = WeightedMoments::new();
+        for (i, value) in values().enumerate() {
+            let weight = self.weights.get(i).to_f64();
+            self.positive_means.add(value);
+            self.weighted.add(value, weight);
+        }
+        self.notional = CompensatedSum::new();
+        self.volume = CompensatedSum::new();
+        for (i, value) in values().enumerate() {
+            let volume = self.volumes.get(i).to_f64();
+            self.notional.add(value * volume);
+            self.volume.add(volume);
+        }
+        self.evictions_since_resync = 0;
+    }
+
+    /// Square root of the summed squared log returns in the window. With
+    /// `periods_per_year` (the number of steps per year at this tick rate)
+    /// it is annualized as `sqrt(periods_per_year * mean(r^2))`.
+    fn realized_vol(&self, periods_per_year: Option<f64>) -> f64 {
+        let returns = &self.log_returns;
+        let realized_var = returns.sum_of_squares();
+        match periods_per_year {
+            Some(periods) if !returns.is_empty() => {
+                (realized_var / returns.len() as f64 * periods).sqrt()
+            }
+            _ => realized_var.sqrt(),
+        }
+    }
+
+    /// Largest one-step simple return in the window in magnitude, and that
+    /// return in standard deviations of the window's returns (0.0 when the
+    /// returns are flat). Read from the return series' sliding min/max.
+    fn jump(&self) -> (f64, f64) {
+        let returns = &self.returns;
+        if returns.is_empty() {
+            return (0.0, 0.0);
+        }
+        let max_abs = returns.max().abs().max(returns.min().abs());
+        let std_dev = returns.variance().sqrt();
+        let score = if std_dev > 0.0 { max_abs / std_dev } else { 0.0 };
+        (max_abs, score)
+    }
+
+    /// Least-squares fit of value against position in the window (0 for the
+    /// oldest value), from the running Σx, Σi·x and closed forms for Σi, Σi².
+    fn trend(&self) -> Trend {
+        let n = self.values.len() as f64;
+        if n < 2.0 {
+            return Trend::default();
+        }
+        let mean_i = (n - 1.0) / 2.0;
+        let var_i = (n * n - 1.0) / 12.0;
+        let mean_x = self.prices.mean();
+        let cov = self.index_weighted_sum.value() / n - mean_i * mean_x;
+        let slope = cov / var_i;
+        let var_x = self.prices.variance();
+        let r_squared = if var_x > 0.0 {
+            (slope * slope * var_i / var_x).min(1.0)
+        } else {
+            0.0
+        };
+        Trend {
+            slope,
+            intercept: mean_x - slope * mean_i,
+            r_squared,
+        }
+    }
+
+    /// Volume-weighted average price of the window, or `None` when no volume
+    /// was traded in it.
+    pub fn vwap(&self) -> Option<f64> {
+        let volume = self.volume.value();
+        (volume > 0.0).then(|| self.notional.value() / volume)
+    }
+
+    /// Running aggregates of the prices or returns in the window.
+    pub fn series(&self, kind: SeriesKind) -> &RollingSeries {
+        match kind {
+            SeriesKind::Prices => &self.prices,
+            SeriesKind::Returns => &self.returns,
+            SeriesKind::LogReturns => &self.log_returns,
+        }
+    }
+
+    pub fn get_stats(&self) -> StatsResponse {
+        self.get_stats_with(&StatsOptions::default())
+    }
+
+    pub fn get_stats_with(&self, options: &StatsOptions) -> StatsResponse {
+        let series = self.series(options.on);
+        if series.is_empty() {
+            return StatsResponse::default();
+        }
+        let variance = series.variance();
+        let std_dev = variance.sqrt();
+        let zscore_last = if std_dev > 0.0 {
+            (series.last() - series.mean()) / std_dev
+        } else {
+            0.0
+        };
+        let mut stats = StatsResponse {
+            min: series.min(),
+            max: series.max(),
+            last: series.last(),
+            avg: series.mean(),
+            var: variance,
+            std_dev,
+            zscore_last,
+            skewness: series.skewness(),
+            kurtosis: series.kurtosis(),
+            realized_vol: self.realized_vol(options.periods_per_year),
+            count: series.len(),
+            first_timestamp: self.timestamps.front().copied().unwrap_or(0),
+            last_timestamp: self.timestamps.back().copied().unwrap_or(0),
+            last_update: self.last_update,
+            ..StatsResponse::default()
+        };
+        if options.on != SeriesKind::Prices {
+            return stats;
+        }
+
+        stats.ohlc = Ohlc {
+            open: self.open().unwrap(),
+            high: stats.max,
+            low: stats.min,
+            close: stats.last,
+        };
+        stats.pct_change = self.pct_change().unwrap_or(0.0);
+        (stats.max_abs_return, stats.jump_score) = self.jump();
+        stats.trend = self.trend();
+        let drawdown = self.drawdown.drawdown(self.values());
+        stats.max_drawdown = drawdown.absolute;
+        stats.max_drawdown_pct = drawdown.relative;
+        stats.vwap = self.vwap();
+        stats.volume_sum = self.volume.value().max(0.0);
+        if let Some(fixed) = &self.fixed {
+            stats.avg = fixed.sum() / self.values.len() as f64;
+            stats.vwap = fixed.vwap();
+            stats.volume_sum = fixed.volume();
+        }
+        stats.volume_avg = stats.volume_sum / self.values.len() as f64;
+        stats.volume_max = self.volume_extrema.max().unwrap_or(0.0);
+        stats.weighted_mean = self.weighted.mean();
+        stats.weighted_var = self.weighted.variance();
+        stats.geometric_mean = self.positive_means.geometric();
+        stats.harmonic_mean = self.positive_means.harmonic();
+        stats.ewma = self.ewma;
+        stats.ew_var = self.ew_var;
+        stats.median = self.median();
+        stats.quantiles = options
+                .quantiles
+                .iter()
+                .zip(self.quantiles.quantiles(&options.quantiles, self.values()))
+                .map(|(&q, value)| Quantile { q, value })
+                .collect();
+        stats
+    }
+}
+
+/// A single value with its optional fields, the array-of-objects form of
+/// a batch.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Point {
+    pub value: f64,
+    #[serde(default)]
+    pub weight: Option<f64>,
+    #[serde(default)]
+    pub volume: Option<f64>,
+    #[serde(default)]
+    pub timestamp: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sequence: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+}
+
+/// A batch of values for one symbol, with optional per-value fields that
+/// run parallel to `values`.
+#[derive(Debug, Clone, Default)]
+pub struct Batch {
+    pub values: Vec<f64>,
+    /// Per-value weights (e.g. trade sizes) for the weighted statistics.
+    /// Every value weighs 1.0 when absent.
+    pub weights: Option<Vec<f64>>,
+    /// Per-value traded volumes for the VWAP. Values carry no volume when absent.
+    pub volumes: Option<Vec<f64>>,
+    /// Per-value exchange/event times in nanoseconds since the Unix epoch.
+    /// Values are stamped with their receive time when absent.
+    pub timestamps: Option<Vec<u64>>,
+    /// Per-value feed sequence numbers, checked for gaps and duplicates.
+    pub sequences: Option<Vec<u64>>,
+    /// Per-value unique IDs (e.g. trade IDs). Values whose ID is among the
+    /// symbol's recent ones are dropped as replays.
+    pub ids: Option<Vec<String>>,
+    /// Values of the symbol's extra named fields, one per value. Fields the
+    /// batch leaves out don't advance.
+    pub fields: HashMap<String, Vec<f64>>,
+    /// Venue the batch was traded on. Tagged batches also feed that venue's
+    /// own windows alongside the symbol's consolidated ones.
+    pub venue: Option<String>,
+    /// Client-chosen ID of the whole batch. A batch whose ID is among the
+    /// namespace's recently added ones is skipped, so retries don't add it
+    /// twice.
+    pub batch_id: Option<String>,
+}
+
+impl Batch {
+    pub fn new(values: Vec<f64>) -> Self {
+        Batch { values, ..Batch::default() }
+    }
+
+    /// Builds a batch from points. A point without a weight weighs 1.0 and
+    /// one without a volume carries none; timestamps, sequence numbers and
+    /// IDs must each be given on every point or on none.
+    pub fn from_points(points: &[Point]) -> Result<Self, String> {
+        let timestamps: Option<Vec<u64>> = points.iter().map(|p| p.timestamp).collect();
+        if timestamps.is_none() && points.iter().any(|p| p.timestamp.is_some()) {
+            return Err("Invalid points input. Give a timestamp on every point or on none.".to_string());
+        }
+        let sequences: Option<Vec<u64>> = points.iter().map(|p| p.sequence).collect();
+        if sequences.is_none() && points.iter().any(|p| p.sequence.is_some()) {
+            return Err("Invalid points input. Give a sequence on every point or on none.".to_string());
+        }
+        let ids: Option<Vec<String>> = points.iter().map(|p| p.id.clone()).collect();
+        if ids.is_none() && points.iter().any(|p| p.id.is_some()) {
+            return Err("Invalid points input. Give an id on every point or on none.".to_string());
+        }
+        let any = |field: fn(&Point) -> Option<f64>| points.iter().any(|p| field(p).is_some());
+        Ok(Batch {
+            values: points.iter().map(|p| p.value).collect(),
+            weights: any(|p| p.weight).then(|| points.iter().map(|p| p.weight.unwrap_or(1.0)).collect()),
+            volumes: any(|p| p.volume).then(|| points.iter().map(|p| p.volume.unwrap_or(0.0)).collect()),
+            timestamps: timestamps.filter(|t| !t.is_empty()),
+            sequences: sequences.filter(|s| !s.is_empty()),
+            ids: ids.filter(|i| !i.is_empty()),
+            ..Batch::default()
+        })
+    }
+
+    fn weight(&self, i: usize) -> f64 {
+        self.weights.as_ref().map_or(1.0, |w| w[i])
+    }
+
+    fn volume(&self, i: usize) -> f64 {
+        self.volumes.as_ref().map_or(0.0, |v| v[i])
+    }
+
+    fn timestamp(&self, i: usize) -> Option<u64> {
+        self.timestamps.as_ref().map(|t| t[i])
+    }
+
+    fn validate(&self, max_size: usize) -> Result<(), ServiceError> {
+        if self.values.len() > max_size {
+            return Err(ServiceError::BatchTooLarge(format!("Batch size exceeds maximum limit of {}", max_size)));
+        }
+        if let Some(weights) = &self.weights {
+            if weights.len() != self.values.len() {
+                return Err("Invalid weights input. Expected one weight per value.".to_string().into());
+            }
+            if weights.iter().any(|w| !w.is_finite() || *w <= 0.0) {
+                return Err("Invalid weights input. Only positive values are accepted.".to_string().into());
+            }
+        }
+        if let Some(volumes) = &self.volumes {
+            if volumes.len() != self.values.len() {
+                return Err("Invalid volumes input. Expected one volume per value.".to_string().into());
+            }
+            if volumes.iter().any(|v| !v.is_finite() || *v < 0.0) {
+                return Err("Invalid volumes input. Only non-negative values are accepted.".to_string().into());
+            }
+        }
+        if self.timestamps.as_ref().is_some_and(|t| t.len() != self.values.len()) {
+            return Err("Invalid timestamps input. Expected one timestamp per value.".to_string().into());
+        }
+        if self.sequences.as_ref().is_some_and(|s| s.len() != self.values.len()) {
+            return Err("Invalid sequences input. Expected one sequence number per value.".to_string().into());
+        }
+        if self.ids.as_ref().is_some_and(|i| i.len() != self.values.len()) {
+            return Err("Invalid ids input. Expected one id per value.".to_string().into());
+        }
+        if let Some((name, _)) = self.fields.iter().find(|(_, v)| v.len() != self.values.len()) {
+            return Err(format!("Invalid fields input. Expected one {} value per value.", name).into());
+        }
+        if self.venue.as_ref().is_some_and(|v| v.is_empty() || v.len() > MAX_VENUE_LEN) {
+            return Err(format!("Invalid venue input. Expected 1 to {} characters.", MAX_VENUE_LEN).into());
+        }
+        if self.batch_id.as_ref().is_some_and(|id| id.is_empty() || id.len() > MAX_BATCH_ID_LEN) {
+            return Err(format!("Invalid batch_id input. Expected 1 to {} characters.", MAX_BATCH_ID_LEN).into());
+        }
+        Ok(())
+    }
+
+    /// Whether each value, and its extra fields, is finite.
+    fn finite_mask(&self) -> Vec<bool> {
+        (0..self.values.len())
+            .map(|i| self.values[i].is_finite() && self.fields.values().all(|field| field[i].is_finite()))
+            .collect()
+    }
+
+    /// Keeps the values (and their parallel fields) for which `keep` holds.
+    fn retain(&mut self, mut keep: impl FnMut(f64) -> bool) {
+        let mask: Vec<bool> = self.values.iter().map(|&v| keep(v)).collect();
+        self.retain_mask(&mask);
+    }
+
+    /// Keeps the values (and their parallel fields) whose mask entry is set.
+    fn retain_mask(&mut self, mask: &[bool]) {
+        retain_by_mask(&mut self.values, mask);
+        for field in [&mut self.weights, &mut self.volumes].into_iter().flatten() {
+            retain_by_mask(field, mask);
+        }
+        for field in [&mut self.timestamps, &mut self.sequences].into_iter().flatten() {
+            retain_by_mask(field, mask);
+        }
+        if let Some(ids) = &mut self.ids {
+            retain_by_mask(ids, mask);
+        }
+        for field in self.fields.values_mut() {
+            retain_by_mask(field, mask);
+        }
+    }
+
+    /// The `i`th point as a batch of its own, with its fields and venue.
+    fn point(&self, i: usize) -> Batch {
+        Batch {
+            values: vec![self.values[i]],
+            weights: self.weights.as_ref().map(|w| vec![w[i]]),
+            volumes: self.volumes.as_ref().map(|v| vec![v[i]]),
+            timestamps: self.timestamps.as_ref().map(|t| vec![t[i]]),
+            sequences: self.sequences.as_ref().map(|s| vec![s[i]]),
+            ids: self.ids.as_ref().map(|ids| vec![ids[i].clone()]),
+            fields: self.fields.iter().map(|(name, v)| (name.clone(), vec![v[i]])).collect(),
+            venue: self.venue.clone(),
+            batch_id: None,
+        }
+    }
+
+    /// The values of one extra field as a batch of their own, sharing this
+    /// batch's timestamps.
+    fn field(&self, name: &str) -> Option<Batch> {
+        Some(Batch {
+            values: self.fields.get(name)?.clone(),
+            timestamps: self.timestamps.clone(),
+            ..Batch::default()
+        })
+    }
+}
+
+fn retain_by_mask<T>(field: &mut Vec<T>, mask: &[bool]) {
+    let mut flags = mask.iter();
+    field.retain(|_| *flags.next().unwrap());
+}
+
+/// What adding a batch would do, found by
+/// [`TradingDataService::check_batch`] without adding it.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+pub struct BatchCheck {
+    /// The symbol the batch would go to, after aliases.
+    pub symbol: String,
+    /// Whether the symbol would be created.
+    pub new_symbol: bool,
+    /// Whether the batch would be skipped as a retry of an added `batch_id`.
+    pub already_added: bool,
+    /// Values that would be ingested. Outlier rejection and reordering can
+    /// still drop some, as they depend on the data around them on arrival.
+    pub accepted: usize,
+    /// Non-finite values the symbol skips.
+    pub non_finite_dropped: usize,
+    /// Values whose `ids` were seen recently or earlier in the batch.
+    pub duplicates_dropped: usize,
+}
+
+/// Optional extras computed on top of the default window statistics.
+#[derive(Debug, Clone, Default)]
+pub struct StatsOptions {
+    /// Quantiles (0.0..=1.0) to estimate from the window's sketch.
+    pub quantiles: Vec<f64>,
+    /// Series the min/max/avg/var family is computed over. The EWMA, median
+    /// and quantiles are only kept for prices and are left at their defaults
+    /// for the return series.
+    pub on: SeriesKind,
+    /// Annualizes `realized_vol` when set.
+    pub periods_per_year: Option<f64>,
+    /// Extra field whose windows the stats are computed over, instead of the
+    /// price. Not available for time windows.
+    pub field: Option<String>,
+    /// Venue whose own price windows the stats are computed over, instead of
+    /// the consolidated ones. Not available for time windows or fields.
+    pub venue: Option<String>,
+    /// Computes the stats over the symbol's extended-hours windows instead
+    /// of its in-session ones. Price windows only.
+    pub extended_hours: bool,
+    /// Computes the stats over the symbol's prices converted into this
+    /// currency. Consolidated price windows only.
+    pub currency: Option<String>,
+}
+
+fn now_nanos() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0)
+}
+
+fn default_ewma_alpha(capacity: usize) -> f64 {
+    2.0 / (capacity as f64 + 1.0)
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, async_graphql::SimpleObject)]
+#[graphql(name = "Stats")]
+pub struct StatsResponse {
+    pub min: f64,
+    pub max: f64,
+    pub last: f64,
+    pub avg: f64,
+    pub var: f64,
+    pub std_dev: f64,
+    /// `(last - avg) / std_dev`, or 0.0 for a flat window.
+    pub zscore_last: f64,
+    pub skewness: f64,
+    /// Excess kurtosis (0.0 for a normal distribution).
+    pub kurtosis: f64,
+    /// Realized volatility from squared log returns, annualized on request.
+    pub realized_vol: f64,
+    /// Total traded volume in the window.
+    pub volume_sum: f64,
+    /// Mean traded volume per value in the window.
+    pub volume_avg: f64,
+    /// Largest single traded volume in the window.
+    pub volume_max: f64,
+    /// Volume-weighted average price, present once the window holds volume.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vwap: Option<f64>,
+    /// Mean of the window weighted by each value's batch weight (equal to
+    /// `avg` when no weights were sent).
+    pub weighted_mean: f64,
+    /// Weighted population variance around `weighted_mean`.
+    pub weighted_var: f64,
+    /// Geometric mean of the window, present while every value is positive.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub geometric_mean: Option<f64>,
+    /// Harmonic mean of the window, present while every value is positive.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub harmonic_mean: Option<f64>,
+    /// Exponentially weighted moving average. Unlike the other fields it
+    /// weighs every value seen, not only the window.
+    pub ewma: f64,
+    /// Exponentially weighted variance around `ewma`.
+    pub ew_var: f64,
+    pub median: f64,
+    /// Open/high/low/close of the window, from its oldest to its newest value.
+    pub ohlc: Ohlc,
+    /// `(close - open) / open` of the window, or 0.0 when it opens at zero.
+    pub pct_change: f64,
+    /// Largest absolute one-step simple return in the window.
+    pub max_abs_return: f64,
+    /// `max_abs_return` in standard deviations of the window's returns, or
+    /// 0.0 when they are flat. Large values flag a jump.
+    pub jump_score: f64,
+    /// Linear regression of value against position in the window.
+    pub trend: Trend,
+    /// Largest peak-to-trough decline within the window, in price units.
+    pub max_drawdown: f64,
+    /// Largest peak-to-trough decline relative to its peak (0.25 = 25%).
+    pub max_drawdown_pct: f64,
+    /// Number of values currently in the window.
+    pub count: usize,
+    /// Values the symbol's outlier filter has flagged since the symbol was created.
+    pub outliers_flagged: u64,
+    /// Values the symbol's outlier filter has rejected since the symbol was created.
+    pub outliers_rejected: u64,
+    /// Times the symbol's sequence numbers skipped ahead since the symbol was created.
+    pub sequence_gaps: u64,
+    /// Sequence numbers skipped over by those gaps.
+    pub sequences_missing: u64,
+    /// Values whose sequence number was at or below the highest one already seen.
+    pub sequence_duplicates: u64,
+    /// Values dropped since the symbol was created because their ID had
+    /// already been seen.
+    pub duplicates_dropped: u64,
+    /// Values dropped since the symbol was created because they arrived after
+    /// a newer value had already left the reorder buffer.
+    pub late_dropped: u64,
+    /// Values timestamped outside the symbol's trading session since the
+    /// symbol was created.
+    pub out_of_session: u64,
+    /// Points dropped since the symbol was created because a value or field
+    /// value wasn't finite.
+    pub non_finite_dropped: u64,
+    /// Time since the symbol's latest batch arrived, in milliseconds; `None`
+    /// before its first.
+    pub age_ms: Option<u64>,
+    /// Whether the symbol's feed has gone quiet for longer than its
+    /// `stale_after_ms`. Never set without one.
+    pub is_stale: bool,
+    /// Event time of the oldest value in the window, in nanoseconds since the Unix epoch.
+    pub first_timestamp: u64,
+    /// Event time of the newest value in the window, in nanoseconds since the Unix epoch.
+    pub last_timestamp: u64,
+    /// Server receive time of the latest batch, in milliseconds since the Unix epoch.
+    pub last_update: u64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub quantiles: Vec<Quantile>,
+}
+
+/// Stats a condition can read, or symbols can be ranked by, by name.
+pub const CONDITION_STATS: [&str; 30] = [
+    "min", "max", "last", "avg", "var", "std_dev", "zscore_last", "skewness", "kurtosis", "realized_vol",
+    "volume_sum", "volume_avg", "volume_max", "vwap", "weighted_mean", "weighted_var", "geometric_mean",
+    "harmonic_mean", "ewma", "ew_var", "median", "open", "high", "low", "pct_change", "max_abs_return",
+    "jump_score", "slope", "max_drawdown", "count",
+];
+
+impl StatsResponse {
+    /// One of the [`CONDITION_STATS`] by name, `None` when the window
+    /// doesn't have it (e.g. `vwap` without volume).
+    pub fn stat(&self, name: &str) -> Option<f64> {
+        Some(match name {
+            "min" => self.min,
+            "max" => self.max,
+            "last" => self.last,
+            "avg" => self.avg,
+            "var" => self.var,
+            "std_dev" => self.std_dev,
+            "zscore_last" => self.zscore_last,
+            "skewness" => self.skewness,
+            "kurtosis" => self.kurtosis,
+            "realized_vol" => self.realized_vol,
+            "volume_sum" => self.volume_sum,
+            "volume_avg" => self.volume_avg,
+            "volume_max" => self.volume_max,
+            "vwap" => self.vwap?,
+            "weighted_mean" => self.weighted_mean,
+            "weighted_var" => self.weighted_var,
+            "geometric_mean" => self.geometric_mean?,
+            "harmonic_mean" => self.harmonic_mean?,
+            "ewma" => self.ewma,
+            "ew_var" => self.ew_var,
+            "median" => self.median,
+            "open" => self.ohlc.open,
+            "high" => self.ohlc.high,
+            "low" => self.ohlc.low,
+            "pct_change" => self.pct_change,
+            "max_abs_return" => self.max_abs_return,
+            "jump_score" => self.jump_score,
+            "slope" => self.trend.slope,
+            "max_drawdown" => self.max_drawdown,
+            "count" => self.count as f64,
+            _ => return None,
+        })
+    }
+}
+
+/// Fields of a [`StatsResponse`] a client can select, in response order.
+pub const STATS_FIELDS: [&str; 44] = [
+    "min", "max", "last", "avg", "var", "std_dev", "zscore_last", "skewness", "kurtosis", "realized_vol",
+    "volume_sum", "volume_avg", "volume_max", "vwap", "weighted_mean", "weighted_var", "geometric_mean",
+    "harmonic_mean", "ewma", "ew_var", "median", "ohlc", "pct_change", "max_abs_return", "jump_score", "trend",
+    "max_drawdown", "max_drawdown_pct", "count", "outliers_flagged", "outliers_rejected", "sequence_gaps",
+    "sequences_missing", "sequence_duplicates", "duplicates_dropped", "late_dropped", "out_of_session",
+    "non_finite_dropped", "age_ms", "is_stale", "first_timestamp", "last_timestamp", "last_update", "quantiles",
+];
+
+/// The fields of [`StatsResponse`]s a client asked for, in response order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldSelection(Vec<&'static str>);
+
+impl FieldSelection {
+    /// Parses a comma-separated list of [`STATS_FIELDS`].
+    pub fn parse(list: &str) -> Result<FieldSelection, String> {
+        let names: Vec<&str> = list.split(',').map(str::trim).collect();
+        if let Some(name) = names.iter().find(|name| !STATS_FIELDS.contains(name)) {
+            return Err(format!("Invalid fields input. {:?} isn't a stats field.", name));
+        }
+        Ok(FieldSelection(STATS_FIELDS.into_iter().filter(|field| names.contains(field)).collect()))
+    }
+
+    pub fn contains(&self, field: &str) -> bool {
+        self.0.contains(&field)
+    }
+
+    /// The stats with only the selected fields, for serializing.
+    pub fn apply<'a>(&'a self, stats: &'a StatsResponse) -> SelectedStats<'a> {
+        SelectedStats { fields: &self.0, stats }
+    }
+}
+
+/// A [`StatsResponse`] serialized with only some of its fields, the rest
+/// never serialized at all.
+pub struct SelectedStats<'a> {
+    fields: &'a [&'static str],
+    stats: &'a StatsResponse,
+}
+
+impl serde::Serialize for SelectedStats<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+
+        let stats = self.stats;
+        // Optional fields are left out when absent, as in full responses.
+        let present = |field: &&&str| match **field {
+            "vwap" => stats.vwap.is_some(),
+            "geometric_mean" => stats.geometric_mean.is_some(),
+            "harmonic_mean" => stats.harmonic_mean.is_some(),
+            "quantiles" => !stats.quantiles.is_empty(),
+            _ => true,
+        };
+        let mut map = serializer.serialize_map(Some(self.fields.iter().filter(present).count()))?;
+        for &field in self.fields.iter().filter(present) {
+            match field {
+                "min" => map.serialize_entry(field, &stats.min)?,
+                "max" => map.serialize_entry(field, &stats.max)?,
+                "last" => map.serialize_entry(field, &stats.last)?,
+                "avg" => map.serialize_entry(field, &stats.avg)?,
+                "var" => map.serialize_entry(field, &stats.var)?,
+                "std_dev" => map.serialize_entry(field, &stats.std_dev)?,
+                "zscore_last" => map.serialize_entry(field, &stats.zscore_last)?,
+                "skewness" => map.serialize_entry(field, &stats.skewness)?,
+                "kurtosis" => map.serialize_entry(field, &stats.kurtosis)?,
+                "realized_vol" => map.serialize_entry(field, &stats.realized_vol)?,
+                "volume_sum" => map.serialize_entry(field, &stats.volume_sum)?,
+                "volume_avg" => map.serialize_entry(field, &stats.volume_avg)?,
+                "volume_max" => map.serialize_entry(field, &stats.volume_max)?,
+                "vwap" => map.serialize_entry(field, &stats.vwap)?,
+                "weighted_mean" => map.serialize_entry(field, &stats.weighted_mean)?,
+                "weighted_var" => map.serialize_entry(field, &stats.weighted_var)?,
+                "geometric_mean" => map.serialize_entry(field, &stats.geometric_mean)?,
+                "harmonic_mean" => map.serialize_entry(field, &stats.harmonic_mean)?,
+                "ewma" => map.serialize_entry(field, &stats.ewma)?,
+                "ew_var" => map.serialize_entry(field, &stats.ew_var)?,
+                "median" => map.serialize_entry(field, &stats.median)?,
+                "ohlc" => map.serialize_entry(field, &stats.ohlc)?,
+                "pct_change" => map.serialize_entry(field, &stats.pct_change)?,
+                "max_abs_return" => map.serialize_entry(field, &stats.max_abs_return)?,
+                "jump_score" => map.serialize_entry(field, &stats.jump_score)?,
+                "trend" => map.serialize_entry(field, &stats.trend)?,
+                "max_drawdown" => map.serialize_entry(field, &stats.max_drawdown)?,
+                "max_drawdown_pct" => map.serialize_entry(field, &stats.max_drawdown_pct)?,
+                "count" => map.serialize_entry(field, &stats.count)?,
+                "outliers_flagged" => map.serialize_entry(field, &stats.outliers_flagged)?,
+                "outliers_rejected" => map.serialize_entry(field, &stats.outliers_rejected)?,
+                "sequence_gaps" => map.serialize_entry(field, &stats.sequence_gaps)?,
+                "sequences_missing" => map.serialize_entry(field, &stats.sequences_missing)?,
+                "sequence_duplicates" => map.serialize_entry(field, &stats.sequence_duplicates)?,
+                "duplicates_dropped" => map.serialize_entry(field, &stats.duplicates_dropped)?,
+                "late_dropped" => map.serialize_entry(field, &stats.late_dropped)?,
+                "out_of_session" => map.serialize_entry(field, &stats.out_of_session)?,
+                "non_finite_dropped" => map.serialize_entry(field, &stats.non_finite_dropped)?,
+                "age_ms" => map.serialize_entry(field, &stats.age_ms)?,
+                "is_stale" => map.serialize_entry(field, &stats.is_stale)?,
+                "first_timestamp" => map.serialize_entry(field, &stats.first_timestamp)?,
+                "last_timestamp" => map.serialize_entry(field, &stats.last_timestamp)?,
+                "last_update" => map.serialize_entry(field, &stats.last_update)?,
+                "quantiles" => map.serialize_entry(field, &stats.quantiles)?,
+                _ => {}
+            }
+        }
+        map.end()
+    }
+}
+
+/// Stats of one of the symbols a pattern matched.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct SymbolStats {
+    pub symbol: String,
+    pub stats: StatsResponse,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, async_graphql::SimpleObject)]
+#[graphql(name = "Correlation")]
+pub struct CorrelationResponse {
+    /// Pearson correlation, or `None` when it is undefined (fewer than two
+    /// aligned points, or a flat window).
+    pub correlation: Option<f64>,
+    /// Number of aligned points the correlation was computed over.
+    pub count: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SymbolInfo {
+    pub symbol: String,
+    /// Points in the symbol's largest window.
+    pub count: usize,
+    /// Server receive time of the latest batch, in milliseconds since the
+    /// Unix epoch; 0 before the first.
+    pub last_update: u64,
+    /// Whether the symbol is a spread, ratio or basket of other symbols.
+    pub synthetic: bool,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct SymbolPage {
+    /// Number of symbols matching the filter, across all pages.
+    pub total: usize,
+    pub symbols: Vec<SymbolInfo>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct ValuesPage {
+    /// Number of points in the window, across all pages.
+    pub total: usize,
+    /// The page's points, oldest first.
+    pub points: Vec<Point>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct ScanPage {
+    /// Number of points in the window when the scan started, across all
+    /// pages.
+    pub total: usize,
+    /// The page's points, oldest first.
+    pub points: Vec<Point>,
+    /// Cursor for the next page, absent after the last.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<String>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct CovarianceResponse {
+    /// Row/column order of `matrix`.
+    pub symbols: Vec<String>,
+    /// Population covariances; `matrix[i][j]` pairs `symbols[i]` and `symbols[j]`.
+    pub matrix: Vec<Vec<f64>>,
+    /// Number of aligned points the matrix was computed over.
+    pub count: usize,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct WindowComparison {
+    /// Stats of the `k1` window.
+    pub first: StatsResponse,
+    /// Stats of the `k2` window.
+    pub second: StatsResponse,
+    /// `first.avg - second.avg`.
+    pub avg_drift: f64,
+    /// `avg_drift` relative to `second.avg`, or `None` when that is 0.
+    pub avg_drift_pct: Option<f64>,
+    /// `first.std_dev / second.std_dev`, or `None` when the second window is flat.
+    pub vol_ratio: Option<f64>,
+}
+
+impl WindowComparison {
+    fn new(first: StatsResponse, second: StatsResponse) -> Self {
+        let avg_drift = first.avg - second.avg;
+        WindowComparison {
+            avg_drift,
+            avg_drift_pct: (second.avg != 0.0).then(|| avg_drift / second.avg),
+            vol_ratio: (second.std_dev > 0.0).then(|| first.std_dev / second.std_dev),
+            first,
+            second,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, serde::Serialize, serde::Deserialize, async_graphql::SimpleObject)]
+pub struct Ohlc {
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+}
+
+#[derive(Debug, Clone, Copy, Default, serde::Serialize, serde::Deserialize, async_graphql::SimpleObject)]
+pub struct Trend {
+    /// Change in value per point.
+    pub slope: f64,
+    /// Fitted value at the oldest point of the window.
+    pub intercept: f64,
+    /// Share of the window's variance explained by the fit (0 for a flat window).
+    pub r_squared: f64,
+}
+
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize, async_graphql::SimpleObject)]
+pub struct Quantile {
+    pub q: f64,
+    pub value: f64,
+}
+
+impl Default for StatsResponse {
+    fn default() -> Self {
+        StatsResponse {
+            min: 0.0,
+            max: 0.0,
+            last: 0.0,
+            avg: 0.0,
+            var: 0.0,
+            std_dev: 0.0,
+            zscore_last: 0.0,
+            skewness: 0.0,
+            kurtosis: 0.0,
+            realized_vol: 0.0,
+            volume_sum: 0.0,
+            volume_avg: 0.0,
+            volume_max: 0.0,
+            vwap: None,
+            weighted_mean: 0.0,
+            weighted_var: 0.0,
+            geometric_mean: None,
+            harmonic_mean: None,
+            ewma: 0.0,
+            ew_var: 0.0,
+            median: 0.0,
+            ohlc: Ohlc::default(),
+            pct_change: 0.0,
+            max_abs_return: 0.0,
+            jump_score: 0.0,
+            trend: Trend::default(),
+            max_drawdown: 0.0,
+            max_drawdown_pct: 0.0,
+            count: 0,
+            outliers_flagged: 0,
+            outliers_rejected: 0,
+            sequence_gaps: 0,
+            sequences_missing: 0,
+            sequence_duplicates: 0,
+            duplicates_dropped: 0,
+            late_dropped: 0,
+            out_of_session: 0,
+            non_finite_dropped: 0,
+            age_ms: None,
+            is_stale: false,
+            first_timestamp: 0,
+            last_timestamp: 0,
+            last_update: 0,
+            quantiles: Vec::new(),
+        }
+    }
+}
+
+/// Per-symbol settings, applied to every window of the symbol.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct SymbolConfig {
+    /// EWMA decay factor in (0, 1]. `None` uses each window's span default.
+    #[serde(default)]
+    pub ewma_alpha: Option<f64>,
+    /// Bar intervals to aggregate, in milliseconds.
+    #[serde(default = "default_bar_intervals")]
+    pub bar_intervals_ms: Vec<u64>,
+    /// MACD EMA periods. Changing them restarts the MACD from the next value.
+    #[serde(default)]
+    pub macd: MacdConfig,
+    /// Optional MAD-based outlier screening applied on ingestion.
+    #[serde(default)]
+    pub outlier_filter: Option<OutlierFilter>,
+    /// Exact quantiles for small windows and the sketch used for larger ones.
+    #[serde(default)]
+    pub quantiles: QuantileConfig,
+    /// Durations of the time-based windows to keep, in milliseconds.
+    #[serde(default)]
+    pub time_windows_ms: Vec<u64>,
+    /// Extra named numeric fields (e.g. `size`, `imbalance`) sent alongside
+    /// the price, each with its own 10^k windows.
+    #[serde(default)]
+    pub fields: Vec<String>,
+    /// How many recent value IDs to remember for dropping replays; 0 turns
+    /// deduplication off.
+    #[serde(default = "default_dedup_window")]
+    pub dedup_window: usize,
+    /// How long timestamped values are held back so late ones can be put in
+    /// order, in milliseconds; 0 turns reordering off.
+    #[serde(default)]
+    pub reorder_slack_ms: u64,
+    /// Decimal places to keep values and volumes in, with the window sums
+    /// (average, VWAP, volume) accumulated exactly as scaled integers.
+    /// `None` keeps them in floating point.
+    #[serde(default)]
+    pub fixed_point_decimals: Option<u32>,
+    /// Trading session; values timestamped outside it are excluded from the
+    /// windows or kept in separate extended-hours windows.
+    #[serde(default)]
+    pub session: Option<SessionConfig>,
+    /// Flags the symbol's stats as stale when no batch has arrived for
+    /// longer than this, in milliseconds.
+    #[serde(default)]
+    pub stale_after_ms: Option<u64>,
+    /// Series computed from every ingested point, e.g. `mid = (bid + ask) / 2`,
+    /// each with its own 10^k windows queried like an extra field.
+    #[serde(default)]
+    pub formulas: Vec<FormulaConfig>,
+    /// What happens to batches with NaN or infinite values or field values.
+    #[serde(default)]
+    pub non_finite: NonFiniteAction,
+}
+
+impl SymbolConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(alpha) = self.ewma_alpha {
+            if !(alpha > 0.0 && alpha <= 1.0) {
+                return Err("Invalid ewma_alpha input. Only values in (0, 1] are accepted.".to_string());
+            }
+        }
+        if self.bar_intervals_ms.contains(&0) {
+            return Err("Invalid bar_intervals_ms input. Intervals must be positive.".to_string());
+        }
+        for (i, name) in self.fields.iter().enumerate() {
+            if name.is_empty() || name == "price" || self.fields[..i].contains(name) {
+                return Err(format!("Invalid fields input. '{}' is empty, reserved or repeated.", name));
+            }
+        }
+        if let Some(session) = &self.session {
+            Session::new(session)?;
+        }
+        Formulas::new(&self.formulas, &self.fields)?;
+        if self.stale_after_ms == Some(0) {
+            return Err("Invalid stale_after_ms input. Only positive values are accepted.".to_string());
+        }
+        if self.fixed_point_decimals.is_some_and(|d| d > MAX_FIXED_POINT_DECIMALS) {
+            return Err(format!(
+                "Invalid fixed_point_decimals input. Only 0-{} decimal places are accepted.",
+                MAX_FIXED_POINT_DECIMALS
+            ));
+        }
+        if self.reorder_slack_ms > MAX_REORDER_SLACK_MS {
+            return Err(format!(
+                "Invalid reorder_slack_ms input. Only slacks up to {} are accepted.",
+                MAX_REORDER_SLACK_MS
+            ));
+        }
+        if self.dedup_window > MAX_DEDUP_WINDOW {
+            return Err(format!("Invalid dedup_window input. Only sizes up to {} are accepted.", MAX_DEDUP_WINDOW));
+        }
+        if self.time_windows_ms.iter().any(|&ms| ms == 0 || ms > MAX_TIME_WINDOW_MS) {
+            return Err(format!(
+                "Invalid time_windows_ms input. Only durations 1-{} are accepted.",
+                MAX_TIME_WINDOW_MS
+            ));
+        }
+        if let Some(filter) = &self.outlier_filter {
+            filter.validate()?;
+        }
+        self.quantiles.validate()?;
+        let macd = self.macd;
+        if macd.fast == 0 || macd.signal == 0 || macd.fast >= macd.slow {
+            return Err("Invalid macd input. Periods must be positive with fast < slow.".to_string());
+        }
+        Ok(())
+    }
+}
+
+impl Default for SymbolConfig {
+    fn default() -> Self {
+        SymbolConfig {
+            ewma_alpha: None,
+            bar_intervals_ms: default_bar_intervals(),
+            macd: MacdConfig::default(),
+            outlier_filter: None,
+            quantiles: QuantileConfig::default(),
+            time_windows_ms: Vec::new(),
+            fields: Vec::new(),
+            dedup_window: default_dedup_window(),
+            reorder_slack_ms: 0,
+            fixed_point_decimals: None,
+            session: None,
+            stale_after_ms: None,
+            formulas: Vec::new(),
+            non_finite: NonFiniteAction::default(),
+        }
+    }
+}
+
+/// Longest time window accepted, one day.
+pub const MAX_TIME_WINDOW_MS: u64 = 86_400_000;
+
+/// Most values a time window holds; older values are dropped beyond it
+/// even if they are still within the window's duration.
+pub const MAX_TIME_WINDOW_POINTS: usize = 1_000_000;
+
+/// Longest batch ID accepted.
+pub const MAX_BATCH_ID_LEN: usize = 128;
+
+/// Batch IDs a namespace remembers for skipping retried batches by default.
+pub const MAX_BATCH_IDS: usize = 100_000;
+
+/// Update notices a slow subscriber can fall behind by before it misses
+/// some.
+pub const UPDATE_CHANNEL_CAPACITY: usize = 4096;
+
+/// Most venues a symbol keeps windows for.
+pub const MAX_VENUES: usize = 64;
+
+/// Longest venue identifier accepted.
+pub const MAX_VENUE_LEN: usize = 32;
+
+/// Longest reorder slack accepted, one second.
+pub const MAX_REORDER_SLACK_MS: u64 = 1_000;
+
+/// Most windows one batch stats request reads.
+pub const MAX_STATS_BATCH: usize = 10000;
+
+/// Most symbols one multi-symbol `add_batches` call takes.
+pub const MAX_BATCH_ENTRIES: usize = 1000;
+
+/// Most raw points returned per page.
+pub const MAX_VALUES_PAGE: usize = 10000;
+
+/// Most symbols listed per page.
+pub const MAX_SYMBOLS_PAGE: usize = 1000;
+
+/// Most value IDs a symbol can be configured to remember.
+pub const MAX_DEDUP_WINDOW: usize = 1_000_000;
+
+fn default_dedup_window() -> usize {
+    10_000
+}
+
+fn default_bar_intervals() -> Vec<u64> {
+    vec![1_000, 60_000, 300_000]
+}
+
+struct SymbolState {
+    config: SymbolConfig,
+    meta: SymbolMeta,
+    buffers: Vec<TradingDataBuffer>,
+    time_windows: Vec<TradingDataBuffer>,
+    /// 10^k quote windows, empty until the symbol's first quote.
+    quotes: Vec<QuoteBuffer>,
+    /// 10^k windows of each configured extra field and formula.
+    fields: HashMap<String, Vec<TradingDataBuffer>>,
+    formulas: Formulas,
+    /// 10^k price windows of each venue seen in tagged batches.
+    venues: HashMap<String, Vec<TradingDataBuffer>>,
+    session: Option<Session>,
+    /// 10^k windows of out-of-session values, empty unless the session
+    /// keeps them.
+    extended: Vec<TradingDataBuffer>,
+    bars: Vec<BarSeries>,
+    indicators: IndicatorState,
+    recent_ids: RecentIds,
+    reorder: ReorderBuffer,
+    counters: FeedCounters,
+    /// How the symbol is computed from other symbols, if it's synthetic.
+    derivation: Option<Derivation>,
+    /// Conversion of the symbol's prices into another currency, if any.
+    fx: Option<FxConversion>,
+    /// 10^k windows of the converted prices, empty without a conversion.
+    converted: Vec<TradingDataBuffer>,
+    /// Symbols with this symbol as a leg or FX rate.
+    dependents: Vec<String>,
+    /// Changes whenever the symbol's windows do.
+    version: u64,
+}
+
+/// Source of symbol versions, shared by every namespace so that no two
+/// symbols, nor a symbol dropped and tracked again, share a version.
+static NEXT_VERSION: AtomicU64 = AtomicU64::new(1);
+
+fn next_version() -> u64 {
+    NEXT_VERSION.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Symbol-level ingestion counters, reported with every window's stats.
+#[derive(Debug, Clone, Copy, Default)]
+struct FeedCounters {
+    outliers_flagged: u64,
+    outliers_rejected: u64,
+    sequences: SequenceTracker,
+    duplicates_dropped: u64,
+    late_dropped: u64,
+    out_of_session: u64,
+    non_finite_dropped: u64,
+    /// Server time of the symbol's latest batch in milliseconds since the
+    /// Unix epoch, `None` before its first.
+    last_ingest_ms: Option<u64>,
+}
+
+impl FeedCounters {
+    fn age_ms(&self) -> Option<u64> {
+        self.last_ingest_ms.map(|last| (now_nanos() / 1_000_000).saturating_sub(last))
+    }
+
+    fn is_stale(&self, config: &SymbolConfig) -> bool {
+        config.stale_after_ms.is_some_and(|limit| self.age_ms().is_none_or(|age| age > limit))
+    }
+
+    fn report(&self, stats: &mut StatsResponse, config: &SymbolConfig) {
+        stats.age_ms = self.age_ms();
+        stats.is_stale = self.is_stale(config);
+        stats.outliers_flagged = self.outliers_flagged;
+        stats.outliers_rejected = self.outliers_rejected;
+        stats.sequence_gaps = self.sequences.gaps;
+        stats.sequences_missing = self.sequences.missing;
+        stats.sequence_duplicates = self.sequences.duplicates;
+        stats.duplicates_dropped = self.duplicates_dropped;
+        stats.late_dropped = self.late_dropped;
+        stats.out_of_session = self.out_of_session;
+        stats.non_finite_dropped = self.non_finite_dropped;
+    }
+}
+
+/// A fresh set of 10^k windows, k = 1..=8.
+fn count_windows() -> Vec<TradingDataBuffer> {
+    (1..=8).map(|k| TradingDataBuffer::new(10usize.pow(k))).collect()
+}
+
+impl SymbolState {
+    /// Creates the venue's windows with the symbol's window settings unless
+    /// it already has them.
+    fn open_venue(&mut self, venue: &str) -> Result<(), ServiceError> {
+        if self.venues.contains_key(venue) {
+            return Ok(());
+        }
+        if self.venues.len() >= MAX_VENUES {
+            return Err(ServiceError::LimitReached(format!("Symbol already has the maximum of {} venues", MAX_VENUES)));
+        }
+        let windows = self.configured_windows();
+        self.venues.insert(venue.to_string(), windows);
+        Ok(())
+    }
+
+    /// A fresh set of 10^k windows with the symbol's window settings.
+    fn configured_windows(&self) -> Vec<TradingDataBuffer> {
+        let mut windows = count_windows();
+        for buffer in windows.iter_mut() {
+            buffer.set_ewma_alpha(self.config.ewma_alpha);
+            buffer.set_quantile_config(&self.config.quantiles);
+            buffer.set_fixed_point(self.config.fixed_point_decimals);
+        }
+        windows
+    }
+
+    /// Approximate memory held by the symbol's windows, bars and ID history.
+    fn memory_bytes(&self) -> usize {
+        let keyed = self.fields.values().chain(self.venues.values()).flatten();
+        let windows = self.buffers.iter()
+            .chain(&self.time_windows)
+            .chain(keyed)
+            .chain(&self.extended)
+            .chain(&self.converted);
+        std::mem::size_of::<Self>()
+            + windows.map(|w| w.memory_bytes()).sum::<usize>()
+            + self.quotes.iter().map(|q| q.memory_bytes()).sum::<usize>()
+            + self.bars.iter().map(|b| std::mem::size_of::<BarSeries>() + b.heap_bytes()).sum::<usize>()
+            + self.recent_ids.heap_bytes()
+    }
+
+    /// Empties the symbol's 10^k windows of every kind, or with no `k` every
+    /// window, bar and indicator along with the values held for reordering.
+    /// The config, metadata, feed counters and remembered IDs are kept.
+    fn reset(&mut self, k: Option<usize>) {
+        let keyed = self.fields.values_mut().chain(self.venues.values_mut());
+        let window_sets = keyed.chain([&mut self.buffers, &mut self.extended, &mut self.converted]);
+        for windows in window_sets {
+            match k {
+                Some(k) => windows.iter_mut().skip(k - 1).take(1).for_each(|w| w.clear()),
+                None => windows.iter_mut().for_each(|w| w.clear()),
+            }
+        }
+        match k {
+            Some(k) => {
+                if let Some(quotes) = self.quotes.get_mut(k - 1) {
+                    *quotes = QuoteBuffer::new(10usize.pow(k as u32));
+                }
+            }
+            None => {
+                self.time_windows.iter_mut().for_each(|w| w.clear());
+                self.quotes.clear();
+                self.bars = self.config.bar_intervals_ms.iter().map(|&interval| BarSeries::new(interval)).collect();
+                self.indicators = IndicatorState::new(self.config.macd);
+                self.reorder = ReorderBuffer::new(self.reorder.slack_ns());
+            }
+        }
+    }
+
+    /// Whether the symbol is computed from or converted with `other`.
+    fn depends_on(&self, other: &str) -> bool {
+        self.derivation.as_ref().is_some_and(|d| d.definition().legs().contains(&other))
+            || self.fx.as_ref().is_some_and(|fx| fx.config().rate_symbol == other)
+    }
+
+    fn new(config: SymbolConfig) -> Self {
+        let mut state = SymbolState {
+            config: SymbolConfig::default(),
+            meta: SymbolMeta::default(),
+            buffers: count_windows(),
+            time_windows: Vec::new(),
+            quotes: Vec::new(),
+            fields: HashMap::new(),
+            formulas: Formulas::default(),
+            venues: HashMap::new(),
+            session: None,
+            extended: Vec::new(),
+            bars: Vec::new(),
+            indicators: IndicatorState::new(config.macd),
+            recent_ids: RecentIds::default(),
+            reorder: ReorderBuffer::default(),
+            counters: FeedCounters::default(),
+            derivation: None,
+            fx: None,
+            converted: Vec::new(),
+            dependents: Vec::new(),
+            version: next_version(),
+        };
+        state.apply_config(config);
+        state
+    }
+
+    /// Applies the config and returns the batches it released from the
+    /// reorder buffer, as ingested.
+    fn apply_config(&mut self, config: SymbolConfig) -> Vec<Batch> {
+        // Time windows that are still configured keep their contents; new
+        // ones are backfilled from the largest count window.
+        let mut old_windows = std::mem::take(&mut self.time_windows);
+        let largest = &self.buffers[self.buffers.len() - 1];
+        self.time_windows = config
+            .time_windows_ms
+            .iter()
+            .map(|&ms| {
+                let max_age_ns = ms * 1_000_000;
+                match old_windows.iter().position(|w| w.max_age_ns() == Some(max_age_ns)) {
+                    Some(i) => old_windows.swap_remove(i),
+                    None => {
+                        let mut window = TradingDataBuffer::with_max_age(max_age_ns, MAX_TIME_WINDOW_POINTS);
+                        window.add_points(&largest.tail_within(max_age_ns));
+                        window.last_update = largest.last_update;
+                        window
+                    }
+                }
+            })
+            .collect();
+        let mut formulas = Formulas::new(&config.formulas, &config.fields).unwrap_or_default();
+        formulas.carry_over(&self.formulas);
+        self.formulas = formulas;
+        let series: Vec<&String> = config.fields.iter().chain(self.formulas.names()).collect();
+        self.fields.retain(|name, _| series.contains(&name));
+        for name in series {
+            self.fields.entry(name.clone()).or_insert_with(count_windows);
+        }
+        self.session = config.session.as_ref().and_then(|session| Session::new(session).ok());
+        let keeps_extended = config.session.as_ref().is_some_and(|s| s.outside == SessionAction::Extended);
+        if !keeps_extended {
+            self.extended.clear();
+        } else if self.extended.is_empty() {
+            self.extended = count_windows();
+        }
+        let keyed_windows = self.fields.values_mut().chain(self.venues.values_mut()).flatten();
+        let keyed_windows = keyed_windows.chain(self.extended.iter_mut()).chain(self.converted.iter_mut());
+        for buffer in self.buffers.iter_mut().chain(self.time_windows.iter_mut()).chain(keyed_windows) {
+            buffer.set_ewma_alpha(config.ewma_alpha);
+            buffer.set_quantile_config(&config.quantiles);
+            buffer.set_fixed_point(config.fixed_point_decimals);
+        }
+
+        // Keep the history of intervals that are still configured.
+        let mut old_bars = std::mem::take(&mut self.bars);
+        self.bars = config
+            .bar_intervals_ms
+            .iter()
+            .map(|&interval| match old_bars.iter().position(|b| b.interval_ms() == interval) {
+                Some(i) => old_bars.swap_remove(i),
+                None => BarSeries::new(interval),
+            })
+            .collect();
+        if self.indicators.macd.config() != config.macd {
+            self.indicators = IndicatorState::new(config.macd);
+        }
+        self.recent_ids.set_capacity(config.dedup_window);
+        let ready = self.reorder.set_slack(config.reorder_slack_ms * 1_000_000);
+        self.config = config;
+        ready.into_iter().map(|batch| self.ingest(batch)).collect()
+    }
+
+    /// Screens the batch for outliers and feeds the rest to every window,
+    /// bar series and indicator of the symbol. Returns what was fed, stamped.
+    fn ingest(&mut self, mut batch: Batch) -> Batch {
+        // Stamp once so every window and bar agrees on the receive time.
+        let now_ns = now_nanos();
+        batch.timestamps.get_or_insert_with(|| vec![now_ns; batch.values.len()]);
+        if let Some(session) = self.session {
+            self.split_off_session(&session, &mut batch);
+        }
+        if let Some(filter) = self.config.outlier_filter {
+            self.screen_outliers(&filter, &mut batch);
+        }
+
+        let timestamps = batch.timestamps.as_ref().unwrap();
+        for bars in self.bars.iter_mut() {
+            for (&value, &timestamp_ns) in batch.values.iter().zip(timestamps.iter()) {
+                bars.add(timestamp_ns / 1_000_000, value);
+            }
+        }
+        for buffer in self.buffers.iter_mut().chain(self.time_windows.iter_mut()) {
+            buffer.add_points(&batch);
+        }
+        for (name, windows) in self.fields.iter_mut() {
+            let Some(field) = batch.field(name) else { continue };
+            for buffer in windows.iter_mut() {
+                buffer.add_points(&field);
+            }
+        }
+        if !self.formulas.is_empty() {
+            self.evaluate_formulas(&batch);
+        }
+        if let Some(windows) = batch.venue.as_ref().and_then(|v| self.venues.get_mut(v)) {
+            for buffer in windows.iter_mut() {
+                buffer.add_points(&batch);
+            }
+        }
+        if let Some(fx) = &self.fx {
+            let mut converted = Batch { fields: HashMap::new(), ..batch.clone() };
+            let mask: Vec<bool> = converted.values.iter().map(|&v| fx.convert(v).is_some()).collect();
+            converted.retain_mask(&mask);
+            for value in converted.values.iter_mut() {
+                *value = fx.convert(*value).unwrap();
+            }
+            for buffer in self.converted.iter_mut() {
+                buffer.add_points(&converted);
+            }
+        }
+        self.indicators.update(&batch.values);
+        batch
+    }
+
+    /// Feeds each formula's windows with its values over the batch's points,
+    /// skipping the points it yields no value for.
+    fn evaluate_formulas(&mut self, batch: &Batch) {
+        let timestamps = batch.timestamps.as_ref().unwrap();
+        let empty = Batch { timestamps: Some(Vec::new()), ..Batch::default() };
+        let mut series = vec![empty; self.formulas.names().len()];
+        for (i, &timestamp_ns) in timestamps.iter().enumerate() {
+            let values = self.formulas.evaluate(|name| match name {
+                "price" => Some(batch.values[i]),
+                "volume" => Some(batch.volume(i)),
+                _ => batch.fields.get(name).map(|field| field[i]),
+            });
+            for (series, value) in series.iter_mut().zip(values) {
+                let Some(value) = value else { continue };
+                series.values.push(value);
+                series.timestamps.as_mut().unwrap().push(timestamp_ns);
+            }
+        }
+        for (name, series) in self.formulas.names().iter().zip(series) {
+            let Some(windows) = self.fields.get_mut(name) else { continue };
+            for buffer in windows.iter_mut() {
+                buffer.add_points(&series);
+            }
+        }
+    }
+
+    /// Takes the values timestamped outside the session out of the batch,
+    /// into the extended-hours windows if the symbol keeps them.
+    fn split_off_session(&mut self, session: &Session, batch: &mut Batch) {
+        let Some(timestamps) = &batch.timestamps else { return };
+        let in_session: Vec<bool> = timestamps.iter().map(|&t| session.contains(t)).collect();
+        if !in_session.contains(&false) {
+            return;
+        }
+        let mut outside = batch.clone();
+        outside.retain_mask(&in_session.iter().map(|&inside| !inside).collect::<Vec<_>>());
+        batch.retain_mask(&in_session);
+        self.counters.out_of_session += outside.values.len() as u64;
+        for buffer in self.extended.iter_mut() {
+            buffer.add_points(&outside);
+        }
+    }
+
+    /// The checks a batch of values alone must pass to take the symbol's
+    /// fast path.
+    fn screen(&self) -> Screen {
+        Screen { non_finite: self.config.non_finite, meta: self.meta.clone() }
+    }
+
+    /// Fails the batch on a NaN or infinite value or field value, or drops
+    /// and counts those points when the symbol skips them.
+    fn screen_non_finite(&mut self, batch: &mut Batch) -> Result<(), ServiceError> {
+        let mask = batch.finite_mask();
+        let Some(i) = mask.iter().position(|&finite| !finite) else {
+            return Ok(());
+        };
+        match self.config.non_finite {
+            NonFiniteAction::Reject => Err(format!("Invalid value at index {}. Only finite values are accepted.", i).into()),
+            NonFiniteAction::Skip => {
+                let before = batch.values.len();
+                batch.retain_mask(&mask);
+                self.counters.non_finite_dropped += (before - batch.values.len()) as u64;
+                Ok(())
+            }
+        }
+    }
+
+    /// Drops values whose ID is among the recent ones, including repeats
+    /// within the batch, and remembers the IDs of the rest.
+    fn drop_duplicates(&mut self, batch: &mut Batch) {
+        let Some(ids) = &batch.ids else { return };
+        let mask: Vec<bool> = ids.iter().map(|id| self.recent_ids.insert(id)).collect();
+        let before = batch.values.len();
+        batch.retain_mask(&mask);
+        self.counters.duplicates_dropped += (before - batch.values.len()) as u64;
+    }
+
+    /// Counts outliers in the batch against the reference window as it stands
+    /// before the batch, dropping them when the filter rejects.
+    fn screen_outliers(&mut self, filter: &OutlierFilter, batch: &mut Batch) {
+        let reference = &self.buffers[filter.window_k - 1];
+        let Some(detector) = OutlierDetector::new(filter, reference.values()) else {
+            return;
+        };
+        match filter.action {
+            OutlierAction::Flag => {
+                self.counters.outliers_flagged += batch.values.iter().filter(|&&v| detector.is_outlier(v)).count() as u64;
+            }
+            OutlierAction::Reject => {
+                let before = batch.values.len();
+                batch.retain(|v| !detector.is_outlier(v));
+                self.counters.outliers_rejected += (before - batch.values.len()) as u64;
+            }
+        }
+    }
+}
+
+/// Every tracked symbol's state, each behind its own lock so unrelated
+/// symbols ingest and query concurrently. The map's lock is held for reading
+/// while a symbol's is, and only for writing to add or drop symbols or to
+/// change several at once, e.g. linking a synthetic symbol to its legs.
+type Symbols = HashMap<String, RwLock<SymbolState>>;
+
+/// Read locks on several symbols, held together.
+type Snapshot<'a> = HashMap<&'a str, RwLockReadGuard<'a, SymbolState>>;
+
+/// The state the service shares with its symbols' ingestion tasks.
+struct Shared {
+    buffers: RwLock<Symbols>,
+    /// Most symbols tracked at once, `usize::MAX` for no limit.
+    max_symbols: AtomicUsize,
+    /// IDs of the most recently added batches, oldest forgotten first.
+    batch_ids: std::sync::Mutex<RecentIds>,
+    /// Names of symbols whose windows just changed, for push subscribers.
+    updates: broadcast::Sender<String>,
+    /// Runtime settings, shared by the deployment's namespaces.
+    settings: Arc<std::sync::RwLock<Settings>>,
+}
+
+pub struct TradingDataService {
+    shared: Arc<Shared>,
+    /// Alternative spellings of symbols (e.g. `BRK-B` for `BRK.B`), mapped
+    /// to the symbol whose windows they share.
+    aliases: std::sync::RwLock<HashMap<String, String>>,
+    /// Batches queued for or being added by their symbols' tasks.
+    pending_writes: AtomicUsize,
+    /// The ingestion task of each symbol that has taken data; see [`ingest`].
+    ingestors: Ingestors,
+    /// Rings of the hot symbols whose plain batches skip their ingestion
+    /// tasks; see [`fast_path`].
+    fast_paths: FastPaths,
+    /// Open scans of raw window points.
+    scans: std::sync::Mutex<Scans>,
+}
+
+impl Shared {
+    /// A symbol created by data, with the configured new-symbol config.
+    fn new_symbol(&self) -> SymbolState {
+        SymbolState::new(self.settings.read().unwrap().new_symbol_config.clone())
+    }
+
+    /// Fails if `symbol` is new and the service already tracks its maximum.
+    fn check_room(&self, buffers: &Symbols, symbol: &str) -> Result<(), ServiceError> {
+        let max_symbols = self.max_symbols.load(Ordering::Relaxed);
+        if !buffers.contains_key(symbol) && buffers.len() >= max_symbols {
+            return Err(ServiceError::LimitReached(format!("Maximum of {} symbols reached", max_symbols)));
+        }
+        Ok(())
+    }
+
+    /// The symbol map, read-locked with each of `symbols` tracked: those
+    /// that aren't yet are created first under a brief write lock, as far
+    /// as the symbol limit allows. [`Self::check_room`] then fails for the
+    /// ones left out.
+    async fn tracking<'a>(&self, symbols: impl IntoIterator<Item = &'a str>) -> RwLockReadGuard<'_, Symbols> {
+        let symbols: Vec<&str> = symbols.into_iter().collect();
+        let buffers = self.buffers.read().await;
+        if symbols.iter().all(|symbol| buffers.contains_key(*symbol)) {
+            return buffers;
+        }
+        drop(buffers);
+        let mut buffers = self.buffers.write().await;
+        for symbol in symbols {
+            if self.check_room(&buffers, symbol).is_ok() {
+                buffers.entry(symbol.to_string()).or_insert_with(|| RwLock::new(self.new_symbol()));
+            }
+        }
+        buffers.downgrade()
+    }
+
+    /// Marks the symbol's windows changed, giving them a new version and
+    /// telling subscribers. Sending only fails while nobody is subscribed.
+    fn changed(&self, state: &mut SymbolState, symbol: String) {
+        state.version = next_version();
+        let _ = self.updates.send(symbol);
+    }
+
+    /// Passes `leg`'s newly ingested batches on to its dependents: the
+    /// latest price becomes their FX rate, and synthetic symbols are
+    /// recomputed. Each dependent is locked in turn once the leg's lock is
+    /// released, so a writer never holds two symbol locks at once.
+    async fn propagate(&self, buffers: &Symbols, leg: &str, ingested: &[Batch], dependents: Vec<String>) {
+        for symbol in dependents {
+            let Some(state) = buffers.get(&symbol) else { continue };
+            self.propagate_to(&mut *state.write().await, symbol, leg, ingested);
+        }
+    }
+
+    /// [`Self::propagate`] for callers off the runtime, e.g. the fast-path
+    /// consumers.
+    fn propagate_blocking(&self, buffers: &Symbols, leg: &str, ingested: &[Batch], dependents: Vec<String>) {
+        for symbol in dependents {
+            let Some(state) = buffers.get(&symbol) else { continue };
+            self.propagate_to(&mut state.blocking_write(), symbol, leg, ingested);
+        }
+    }
+
+    /// Updates a dependent, locked by the caller, with the leg's batches.
+    fn propagate_to(&self, state: &mut SymbolState, symbol: String, leg: &str, ingested: &[Batch]) {
+        let latest = ingested.iter().rev().find_map(|batch| batch.values.last().copied());
+        if let (Some(fx), Some(rate)) = (state.fx.as_mut(), latest) {
+            if fx.config().rate_symbol == leg {
+                fx.set_rate(rate);
+            }
+        }
+        let Some(values) = state.derivation.as_mut().map(|derivation| derive(derivation, leg, ingested)) else { return };
+        if values.values.is_empty() {
+            return;
+        }
+        state.counters.last_ingest_ms = Some(now_nanos() / 1_000_000);
+        state.ingest(values);
+        self.changed(state, symbol);
+    }
+
+    /// Adds a batch to the symbol, locked by the caller, returning the
+    /// batches it made ready for the symbol's dependents. The caller marks
+    /// the symbol changed and propagates once it's done with the lock.
+    fn add_locked(&self, state: &mut SymbolState, symbol: &str, mut batch: Batch) -> Result<Vec<Batch>, ServiceError> {
+        // The ID is only remembered once the batch is added, so a retry of
+        // a batch that failed goes through. It's checked under the symbol's
+        // lock so concurrent retries add the batch once.
+        if batch.batch_id.as_ref().is_some_and(|id| self.batch_ids.lock().unwrap().contains(id)) {
+            return Ok(Vec::new());
+        }
+        if state.derivation.is_some() {
+            return Err(ServiceError::Conflict(format!("Symbol {} is derived from other symbols and can't take data directly", symbol)));
+        }
+        if let Some(name) = batch.fields.keys().find(|name| !state.config.fields.contains(*name)) {
+            return Err(format!("Field {} is not configured for this symbol", name).into());
+        }
+        state.screen_non_finite(&mut batch)?;
+        if let Some(price) = state.meta.first_off_tick(&batch.values) {
+            return Err(format!("Value {} is not a multiple of the tick size", price).into());
+        }
+        if let Some(venue) = &batch.venue {
+            state.open_venue(venue)?;
+        }
+        for &sequence in batch.sequences.iter().flatten() {
+            state.counters.sequences.observe(sequence);
+        }
+        state.drop_duplicates(&mut batch);
+        state.counters.last_ingest_ms = Some(now_nanos() / 1_000_000);
+        if let Some(id) = &batch.batch_id {
+            self.batch_ids.lock().unwrap().insert(id);
+        }
+
+        // Only batches with event times can be put in order; the rest are
+        // stamped with the receive time and go straight through.
+        if state.reorder.slack_ns() == 0 || batch.timestamps.is_none() {
+            Ok(vec![state.ingest(batch)])
+        } else {
+            let (ready, late) = state.reorder.push(&batch);
+            state.counters.late_dropped += late;
+            Ok(ready.into_iter().map(|batch| state.ingest(batch)).collect())
+        }
+    }
+
+    /// A symbol's ingestion task, adding its queued batches in order. All
+    /// the batches queued since it last looked are added under one lock of
+    /// the symbol, then passed on to its dependents together. The symbol
+    /// was created when the batches were queued; once it's removed, what's
+    /// left is failed rather than creating it again.
+    async fn ingest_queued(self: Arc<Self>, symbol: String, mut jobs: Jobs) {
+        while let Some(queued) = jobs.next().await {
+            let buffers = self.buffers.read().await;
+            let lock = match tracked(&buffers, &symbol) {
+                Ok(lock) if !jobs.removed() => lock,
+                _ => {
+                    ingest::fail(queued, || ServiceError::UnknownSymbol);
+                    continue;
+                }
+            };
+            let mut state = lock.write().await;
+            let mut ingested = Vec::new();
+            let mut replies = Vec::with_capacity(queued.len());
+            for job in queued {
+                let result = self.add_locked(&mut state, &symbol, job.batch).map(|batches| ingested.extend(batches));
+                replies.push((job.reply, result));
+            }
+            // Batches the reorder buffer holds or drops reach no window but
+            // still change the feed counters the stats report.
+            if replies.iter().any(|(_, result)| result.is_ok()) {
+                let dependents = state.dependents.clone();
+                self.changed(&mut state, symbol.clone());
+                drop(state);
+                self.propagate(&buffers, &symbol, &ingested, dependents).await;
+            }
+            for (reply, result) in replies {
+                let _ = reply.send(result);
+            }
+        }
+    }
+
+    /// Adds values from the symbol's fast-path ring, on its consumer thread
+    /// rather than the runtime; batches that fail are only counted and
+    /// logged there, as their writers were answered when they were queued.
+    fn fast_path_apply(self: Arc<Self>, symbol: String) -> Apply {
+        Box::new(move |values, cancelled| {
+            let buffers = self.buffers.blocking_read();
+            // Checked under the map's lock, which removal holds while it
+            // cancels, so a removed symbol isn't created anew.
+            if cancelled.load(Ordering::Acquire) {
+                return Err(ServiceError::UnknownSymbol);
+            }
+            let mut state = tracked(&buffers, &symbol)?.blocking_write();
+            let ingested = self.add_locked(&mut state, &symbol, Batch::new(values))?;
+            let dependents = state.dependents.clone();
+            self.changed(&mut state, symbol.clone());
+            drop(state);
+            self.propagate_blocking(&buffers, &symbol, &ingested, dependents);
+            Ok(())
+        })
+    }
+}
+
+/// Counts a write as pending for as long as it's alive.
+struct PendingWrite<'a>(&'a AtomicUsize);
+
+impl<'a> PendingWrite<'a> {
+    fn new(counter: &'a AtomicUsize) -> Self {
+        counter.fetch_add(1, Ordering::Relaxed);
+        PendingWrite(counter)
+    }
+}
+
+impl Drop for PendingWrite<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Default for TradingDataService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TradingDataService {
+    pub fn new() -> Self {
+        Self::with_settings(Arc::default())
+    }
+
+    /// A service following settings that may change while it runs, such as
+    /// a namespace's following those of [`Namespaces`](crate::namespaces::Namespaces).
+    pub fn with_settings(settings: Arc<std::sync::RwLock<Settings>>) -> Self {
+        let batch_id_memory = settings.read().unwrap().batch_id_memory;
+        TradingDataService {
+            shared: Arc::new(Shared {
+                buffers: RwLock::new(HashMap::new()),
+                max_symbols: AtomicUsize::new(usize::MAX),
+                batch_ids: std::sync::Mutex::new(RecentIds::new(batch_id_memory)),
+                updates: broadcast::channel(UPDATE_CHANNEL_CAPACITY).0,
+                settings,
+            }),
+            pending_writes: AtomicUsize::new(0),
+            aliases: std::sync::RwLock::new(HashMap::new()),
+            ingestors: Ingestors::default(),
+            fast_paths: FastPaths::default(),
+            scans: std::sync::Mutex::default(),
+        }
+    }
+
+    pub fn settings(&self) -> Settings {
+        self.shared.settings.read().unwrap().clone()
+    }
+
+    /// Resizes the batch ID memory to changed settings, evicting the oldest
+    /// IDs when it shrinks. Batch size limits and the config of new symbols
+    /// are read as they're needed.
+    pub fn apply_settings(&self) {
+        let batch_id_memory = self.shared.settings.read().unwrap().batch_id_memory;
+        self.shared.batch_ids.lock().unwrap().set_capacity(batch_id_memory);
+    }
+
+    fn max_batch_size(&self) -> usize {
+        self.shared.settings.read().unwrap().max_batch_size
+    }
+
+    /// The symbol an alias stands for, or the symbol itself.
+    pub fn canonical(&self, symbol: String) -> String {
+        let aliases = self.aliases.read().unwrap();
+        aliases.get(&symbol).cloned().unwrap_or(symbol)
+    }
+
+    fn canonical_all(&self, symbols: Vec<String>) -> Vec<String> {
+        let aliases = self.aliases.read().unwrap();
+        symbols.into_iter().map(|s| aliases.get(&s).cloned().unwrap_or(s)).collect()
+    }
+
+    /// Makes `alias` another name for `symbol` (or for what `symbol` itself
+    /// aliases) at ingestion and query time. The alias can't already have
+    /// windows of its own or have aliases pointing at it.
+    pub async fn set_alias(&self, alias: String, symbol: String) -> Result<(), ServiceError> {
+        let symbol = self.canonical(symbol);
+        if alias.is_empty() || alias == symbol {
+            return Err("Invalid alias input. An alias must differ from its symbol.".to_string().into());
+        }
+        if self.shared.buffers.read().await.contains_key(&alias) {
+            return Err(ServiceError::Conflict(format!("Symbol {} already has data and can't become an alias", alias)));
+        }
+        let mut aliases = self.aliases.write().unwrap();
+        if aliases.values().any(|target| *target == alias) {
+            return Err(ServiceError::Conflict(format!("Symbol {} has aliases of its own and can't become an alias", alias)));
+        }
+        aliases.insert(alias, symbol);
+        Ok(())
+    }
+
+    pub fn remove_alias(&self, alias: &str) -> Result<(), ServiceError> {
+        let mut aliases = self.aliases.write().unwrap();
+        aliases.remove(alias).map(|_| ()).ok_or_else(|| ServiceError::NotFound(format!("Alias {} not found", alias)))
+    }
+
+    /// Every alias with the symbol it stands for.
+    pub fn aliases(&self) -> HashMap<String, String> {
+        self.aliases.read().unwrap().clone()
+    }
+
+    /// Makes `symbol` a synthetic symbol computed from its two legs, creating
+    /// the legs if they aren't tracked yet. It's recomputed whenever either
+    /// leg ticks, starting from the legs' latest prices, and takes no data of
+    /// its own. The symbol can't already be tracked, and legs can't be
+    /// synthetic themselves.
+    pub async fn set_derived(&self, symbol: String, mut config: DerivedConfig) -> Result<(), ServiceError> {
+        for leg in config.legs.iter_mut() {
+            *leg = self.canonical(std::mem::take(leg));
+        }
+        if config.legs[0] == config.legs[1] {
+            return Err("Invalid legs input. Two different symbols are expected.".to_string().into());
+        }
+        self.define(symbol, Definition::Pair(config)).await
+    }
+
+    /// Makes `symbol` a weighted basket of its constituents, recomputed on
+    /// every constituent tick, with the same rules as [`Self::set_derived`].
+    pub async fn set_basket(&self, symbol: String, mut config: BasketConfig) -> Result<(), ServiceError> {
+        for constituent in config.constituents.iter_mut() {
+            constituent.symbol = self.canonical(std::mem::take(&mut constituent.symbol));
+        }
+        config.validate()?;
+        self.define(symbol, Definition::Basket(config)).await
+    }
+
+    async fn define(&self, symbol: String, definition: Definition) -> Result<(), ServiceError> {
+        let symbol = self.canonical(symbol);
+        let legs: Vec<String> = definition.legs().into_iter().map(str::to_string).collect();
+        if legs.contains(&symbol) {
+            return Err(format!("Symbol {} can't be computed from itself", symbol).into());
+        }
+
+        let mut buffers = self.shared.buffers.write().await;
+        if buffers.contains_key(&symbol) {
+            return Err(ServiceError::Conflict(format!("Symbol {} is already tracked and can't become synthetic", symbol)));
+        }
+        if let Some(leg) = legs.iter().find(|leg| state_mut(&mut buffers, leg).is_some_and(|s| s.derivation.is_some())) {
+            return Err(format!("Symbol {} is synthetic and can't be a leg", leg).into());
+        }
+        let new_symbols = 1 + legs.iter().filter(|leg| !buffers.contains_key(*leg)).count();
+        let max_symbols = self.shared.max_symbols.load(Ordering::Relaxed);
+        if buffers.len().saturating_add(new_symbols) > max_symbols {
+            return Err(ServiceError::LimitReached(format!("Maximum of {} symbols reached", max_symbols)));
+        }
+
+        let mut derivation = Derivation::new(definition);
+        let mut state = self.shared.new_symbol();
+        for leg in &legs {
+            let leg_state = buffers.entry(leg.clone()).or_insert_with(|| RwLock::new(self.shared.new_symbol())).get_mut();
+            if !leg_state.dependents.contains(&symbol) {
+                leg_state.dependents.push(symbol.clone());
+            }
+            if let Some(price) = leg_state.buffers[0].values().last() {
+                derivation.update(leg, price);
+            }
+        }
+        // Seeding only primes the legs' prices; the windows fill from the
+        // next leg tick.
+        state.derivation = Some(derivation);
+        buffers.insert(symbol, RwLock::new(state));
+        Ok(())
+    }
+
+    pub async fn get_derived(&self, symbol: String) -> Result<DerivedConfig, ServiceError> {
+        match self.definition(symbol).await? {
+            Definition::Pair(config) => Ok(config),
+            Definition::Basket(_) => Err("Symbol is a basket, not a spread or ratio".to_string().into()),
+        }
+    }
+
+    pub async fn get_basket(&self, symbol: String) -> Result<BasketConfig, ServiceError> {
+        match self.definition(symbol).await? {
+            Definition::Basket(config) => Ok(config),
+            Definition::Pair(_) => Err("Symbol is a spread or ratio, not a basket".to_string().into()),
+        }
+    }
+
+    async fn definition(&self, symbol: String) -> Result<Definition, ServiceError> {
+        let symbol = self.canonical(symbol);
+        let buffers = self.shared.buffers.read().await;
+        let state = tracked(&buffers, &symbol)?.read().await;
+        state.derivation.as_ref()
+            .map(|derivation| derivation.definition().clone())
+            .ok_or_else(|| ServiceError::NotFound(format!("Symbol {} is not synthetic", symbol)))
+    }
+
+    /// Every synthetic spread and ratio with its definition.
+    pub async fn derived(&self) -> HashMap<String, DerivedConfig> {
+        let buffers = self.shared.buffers.read().await;
+        let mut derived = HashMap::new();
+        for (symbol, state) in buffers.iter() {
+            if let Some(Definition::Pair(config)) = state.read().await.derivation.as_ref().map(Derivation::definition) {
+                derived.insert(symbol.clone(), config.clone());
+            }
+        }
+        derived
+    }
+
+    /// Every basket with its definition.
+    pub async fn baskets(&self) -> HashMap<String, BasketConfig> {
+        let buffers = self.shared.buffers.read().await;
+        let mut baskets = HashMap::new();
+        for (symbol, state) in buffers.iter() {
+            if let Some(Definition::Basket(config)) = state.read().await.derivation.as_ref().map(Derivation::definition) {
+                baskets.insert(symbol.clone(), config.clone());
+            }
+        }
+        baskets
+    }
+
+    /// Stops recomputing a synthetic symbol, spread, ratio or basket alike.
+    /// It keeps its windows and takes data like any other symbol from then on.
+    pub async fn remove_derived(&self, symbol: String) -> Result<(), ServiceError> {
+        let symbol = self.canonical(symbol);
+        let mut buffers = self.shared.buffers.write().await;
+        let state = state_mut(&mut buffers, &symbol).ok_or(ServiceError::UnknownSymbol)?;
+        let derivation = state.derivation.take().ok_or_else(|| ServiceError::NotFound(format!("Symbol {} is not synthetic", symbol)))?;
+        for leg in derivation.definition().legs() {
+            release_dependent(&mut buffers, leg, &symbol);
+        }
+        Ok(())
+    }
+
+    /// Converts the symbol's prices into another currency at the rate
+    /// symbol's latest price, into windows queried with
+    /// [`StatsOptions::currency`]. Creates both symbols if they aren't
+    /// tracked yet. The windows start over when the rate symbol or currency
+    /// changes. The rate symbol can't be synthetic.
+    pub async fn set_fx(&self, symbol: String, mut config: FxConfig) -> Result<(), ServiceError> {
+        let symbol = self.canonical(symbol);
+        config.rate_symbol = self.canonical(config.rate_symbol);
+        config.validate()?;
+        if config.rate_symbol == symbol {
+            return Err(format!("Symbol {} can't be converted with itself", symbol).into());
+        }
+
+        let mut buffers = self.shared.buffers.write().await;
+        if state_mut(&mut buffers, &config.rate_symbol).is_some_and(|s| s.derivation.is_some()) {
+            return Err(format!("Symbol {} is synthetic and can't be an FX rate", config.rate_symbol).into());
+        }
+        let new_symbols = [&symbol, &config.rate_symbol].iter().filter(|s| !buffers.contains_key(**s)).count();
+        let max_symbols = self.shared.max_symbols.load(Ordering::Relaxed);
+        if buffers.len().saturating_add(new_symbols) > max_symbols {
+            return Err(ServiceError::LimitReached(format!("Maximum of {} symbols reached", max_symbols)));
+        }
+
+        let rate_state = buffers
+            .entry(config.rate_symbol.clone())
+            .or_insert_with(|| RwLock::new(self.shared.new_symbol()))
+            .get_mut();
+        if !rate_state.dependents.contains(&symbol) {
+            rate_state.dependents.push(symbol.clone());
+        }
+        let rate = rate_state.buffers[0].values().last();
+        let state = buffers.entry(symbol.clone()).or_insert_with(|| RwLock::new(self.shared.new_symbol())).get_mut();
+        let old = state.fx.take().map(|fx| fx.config().clone());
+        if old.as_ref().is_none_or(|old| old.rate_symbol != config.rate_symbol || old.currency != config.currency) {
+            state.converted = state.configured_windows();
+        }
+        let mut fx = FxConversion::new(config);
+        if let Some(rate) = rate {
+            fx.set_rate(rate);
+        }
+        state.fx = Some(fx);
+        state.version = next_version();
+        if let Some(old) = old {
+            release_dependent(&mut buffers, &old.rate_symbol, &symbol);
+        }
+        Ok(())
+    }
+
+    pub async fn get_fx(&self, symbol: String) -> Result<FxConfig, ServiceError> {
+        let symbol = self.canonical(symbol);
+        let buffers = self.shared.buffers.read().await;
+        let state = tracked(&buffers, &symbol)?.read().await;
+        state.fx.as_ref()
+            .map(|fx| fx.config().clone())
+            .ok_or_else(|| ServiceError::NotFound(format!("Symbol {} has no FX conversion", symbol)))
+    }
+
+    /// Stops converting the symbol's prices and drops the converted windows.
+    pub async fn remove_fx(&self, symbol: String) -> Result<(), ServiceError> {
+        let symbol = self.canonical(symbol);
+        let mut buffers = self.shared.buffers.write().await;
+        let state = state_mut(&mut buffers, &symbol).ok_or(ServiceError::UnknownSymbol)?;
+        let fx = state.fx.take().ok_or_else(|| ServiceError::NotFound(format!("Symbol {} has no FX conversion", symbol)))?;
+        state.converted.clear();
+        state.version = next_version();
+        release_dependent(&mut buffers, &fx.config().rate_symbol, &symbol);
+        Ok(())
+    }
+
+    /// A page of the tracked symbols by name, optionally only those starting
+    /// with `prefix`.
+    pub async fn list_symbols(&self, prefix: Option<&str>, offset: usize, limit: usize) -> Result<SymbolPage, ServiceError> {
+        if limit == 0 || limit > MAX_SYMBOLS_PAGE {
+            return Err(format!("Invalid limit input. Only values 1-{} are accepted.", MAX_SYMBOLS_PAGE).into());
+        }
+        let buffers = self.shared.buffers.read().await;
+        let mut matching: Vec<(&String, &RwLock<SymbolState>)> = buffers.iter()
+            .filter(|(symbol, _)| prefix.is_none_or(|p| symbol.starts_with(p)))
+            .collect();
+        matching.sort_by(|a, b| a.0.cmp(b.0));
+        let mut symbols = Vec::with_capacity(limit.min(matching.len()));
+        for (symbol, state) in matching.iter().skip(offset).take(limit) {
+            let state = state.read().await;
+            let largest = &state.buffers[state.buffers.len() - 1];
+            symbols.push(SymbolInfo {
+                symbol: symbol.to_string(),
+                count: largest.len(),
+                last_update: largest.last_update,
+                synthetic: state.derivation.is_some(),
+            });
+        }
+        Ok(SymbolPage { total: matching.len(), symbols })
+    }
+
+    /// Empties the symbol's data after a bad-data incident while keeping
+    /// the symbol and its settings: only its 10^k windows with `k`, or all
+    /// of its windows, bars and indicators without.
+    pub async fn reset_symbol(&self, symbol: String, k: Option<usize>) -> Result<(), ServiceError> {
+        let symbol = self.canonical(symbol);
+        if let Some(k) = k {
+            validate_k(k)?;
+        }
+        let buffers = self.shared.buffers.read().await;
+        let mut state = tracked(&buffers, &symbol)?.write().await;
+        state.reset(k);
+        self.shared.changed(&mut state, symbol);
+        Ok(())
+    }
+
+    /// Notices naming each symbol whose windows change from now on, with
+    /// the synthetic symbols a tick recomputes.
+    pub fn subscribe(&self) -> broadcast::Receiver<String> {
+        self.shared.updates.subscribe()
+    }
+
+    /// Stops tracking the symbol, dropping all its windows at once, and
+    /// returns roughly how many bytes that freed. Symbols still computed
+    /// from or converted with it must drop it first; aliases of it are kept.
+    pub async fn remove_symbol(&self, symbol: String) -> Result<usize, ServiceError> {
+        let symbol = self.canonical(symbol);
+        let mut buffers = self.shared.buffers.write().await;
+        let state = state_mut(&mut buffers, &symbol).ok_or(ServiceError::UnknownSymbol)?;
+        if let Some(dependent) = state.dependents.first() {
+            return Err(ServiceError::Conflict(format!("Symbol {} is still used by {}", symbol, dependent)));
+        }
+        let state = buffers.remove(&symbol).unwrap().into_inner();
+        self.ingestors.remove(&symbol);
+        self.fast_paths.cancel(&symbol);
+        let freed = state.memory_bytes();
+        let legs = state.derivation.as_ref().map(|d| d.definition().legs()).unwrap_or_default();
+        let rate = state.fx.as_ref().map(|fx| fx.config().rate_symbol.as_str());
+        for leg in legs.into_iter().chain(rate) {
+            if let Some(leg_state) = state_mut(&mut buffers, leg) {
+                leg_state.dependents.retain(|d| *d != symbol);
+            }
+        }
+        // Freeing a large symbol's windows can take a while; it's done off
+        // the map's lock and the runtime so other symbols carry on.
+        drop(buffers);
+        tokio::task::spawn_blocking(move || drop(state));
+        Ok(freed)
+    }
+
+    /// Limits how many symbols the service tracks; `None` lifts the limit.
+    /// Symbols already tracked are kept either way.
+    pub fn set_max_symbols(&self, max_symbols: Option<usize>) {
+        self.shared.max_symbols.store(max_symbols.unwrap_or(usize::MAX), Ordering::Relaxed);
+    }
+
+    pub fn max_symbols(&self) -> Option<usize> {
+        Some(self.shared.max_symbols.load(Ordering::Relaxed)).filter(|&max| max != usize::MAX)
+    }
+
+    /// Batch writes currently queued for or being added by their symbols'
+    /// tasks or fast paths; a growing number means ingestion can't keep up.
+    pub fn pending_writes(&self) -> usize {
+        self.pending_writes.load(Ordering::Relaxed) + self.fast_paths.pending()
+    }
+
+    pub async fn symbol_count(&self) -> usize {
+        self.shared.buffers.read().await.len()
+    }
+
+    pub async fn add_batch_values(&self, symbol: String, values: Vec<f64>) -> Result<(), ServiceError> {
+        self.add_batch(symbol, Batch::new(values)).await
+    }
+
+    /// Adds a batch with its optional per-value fields to every window of
+    /// the symbol, creating the symbol if it isn't tracked yet.
+    pub async fn add_batch(&self, symbol: String, batch: Batch) -> Result<(), ServiceError> {
+        let symbol = self.canonical(symbol);
+        batch.validate(self.max_batch_size())?;
+        let batch = match self.fast_paths.publish(&symbol, batch)? {
+            Published::Queued => return Ok(()),
+            Published::Declined(batch) => *batch,
+        };
+
+        let _pending = PendingWrite::new(&self.pending_writes);
+        ingest::submit(&self.queue(&symbol).await?, batch).await
+    }
+
+    /// Gives a tracked symbol a fast path: from then on its batches of
+    /// values alone are checked, queued on a lock-free ring and acknowledged,
+    /// and added by a thread of their own pinned to `core`, or to the next
+    /// available core if `None`; see [`fast_path`].
+    pub async fn enable_fast_path(&self, symbol: String, core: Option<usize>) -> Result<(), ServiceError> {
+        let symbol = self.canonical(symbol);
+        let buffers = self.shared.buffers.read().await;
+        let state = tracked(&buffers, &symbol)?.read().await;
+        if state.derivation.is_some() {
+            return Err(ServiceError::Conflict(format!("Symbol {} is derived from other symbols and can't take data directly", symbol)));
+        }
+        // Under the symbol's lock, so the screen can't miss a config change.
+        let apply = self.shared.clone().fast_path_apply(symbol.clone());
+        self.fast_paths.enable(&symbol, core, state.screen(), apply)
+    }
+
+    /// Sends the symbol's batches back through its ingestion task, once the
+    /// values already on its ring are added.
+    pub async fn disable_fast_path(&self, symbol: String) -> Result<(), ServiceError> {
+        let symbol = self.canonical(symbol);
+        let consumer = self.fast_paths.disable(&symbol).ok_or_else(|| ServiceError::NotFound(format!("Symbol {} has no fast path", symbol)))?;
+        tokio::task::spawn_blocking(move || consumer.join())
+            .await
+            .map_err(|e| ServiceError::Internal(format!("Fast path thread failed: {}", e)))?
+            .map_err(|_| ServiceError::Internal("Fast path thread panicked".to_string()))
+    }
+
+    pub fn fast_path_stats(&self, symbol: String) -> Result<FastPathStats, ServiceError> {
+        let symbol = self.canonical(symbol);
+        self.fast_paths.stats(&symbol).ok_or_else(|| ServiceError::NotFound(format!("Symbol {} has no fast path", symbol)))
+    }
+
+    /// Adds batches for many symbols as [`Self::add_batch`] would one by
+    /// one, queueing them all before waiting on any so the symbols' tasks
+    /// add them concurrently. Each batch succeeds or fails on its own.
+    pub async fn add_batches(&self, batches: Vec<(String, Batch)>) -> Result<Vec<Result<(), ServiceError>>, ServiceError> {
+        if batches.len() > MAX_BATCH_ENTRIES {
+            return Err(ServiceError::BatchTooLarge(format!(
+                "Batch size exceeds maximum limit of {} symbols",
+                MAX_BATCH_ENTRIES
+            )));
+        }
+        let max_size = self.max_batch_size();
+        let batches: Vec<(String, Result<Batch, ServiceError>)> = batches
+            .into_iter()
+            .map(|(symbol, batch)| (self.canonical(symbol), batch.validate(max_size).map(|()| batch)))
+            .collect();
+
+        let _pending = PendingWrite::new(&self.pending_writes);
+        let mut queued = Vec::with_capacity(batches.len());
+        for (symbol, batch) in batches {
+            // Batches taken by a fast path are done once queued.
+            queued.push(match batch.and_then(|batch| self.fast_paths.publish(&symbol, batch)) {
+                Ok(Published::Queued) => Ok(None),
+                Ok(Published::Declined(batch)) => match self.queue(&symbol).await {
+                    Ok(queue) => ingest::enqueue(&queue, *batch).await.map(Some),
+                    Err(e) => Err(e),
+                },
+                Err(e) => Err(e),
+            });
+        }
+        let mut results = Vec::with_capacity(queued.len());
+        for queued in queued {
+            results.push(match queued {
+                Ok(Some(queued)) => queued.result().await,
+                Ok(None) => Ok(()),
+                Err(e) => Err(e),
+            });
+        }
+        Ok(results)
+    }
+
+    /// The symbol's ingestion queue, creating the symbol and starting its
+    /// task if need be. Symbols the symbol limit keeps out get no task.
+    async fn queue(&self, symbol: &str) -> Result<mpsc::Sender<Job>, ServiceError> {
+        // Held while the queue is looked up, so it can't be that of a
+        // symbol removed meanwhile.
+        let buffers = self.shared.tracking([symbol]).await;
+        self.shared.check_room(&buffers, symbol)?;
+        let shared = self.shared.clone();
+        let owned = symbol.to_string();
+        Ok(self.ingestors.queue(symbol, move |jobs| shared.ingest_queued(owned, jobs)))
+    }
+
+    /// Runs the checks [`Self::add_batch`] would on the batch, reporting
+    /// what adding it would do without changing anything.
+    pub async fn check_batch(&self, symbol: String, batch: Batch) -> Result<BatchCheck, ServiceError> {
+        let symbol = self.canonical(symbol);
+        batch.validate(self.max_batch_size())?;
+        let buffers = self.shared.buffers.read().await;
+        self.check_validated_batch(&buffers, symbol, &batch).await
+    }
+
+    /// Checks batches for many symbols as [`Self::add_batches`] would add
+    /// them, each against the current state rather than after the ones
+    /// before it.
+    pub async fn check_batches(&self, batches: Vec<(String, Batch)>) -> Result<Vec<Result<BatchCheck, ServiceError>>, ServiceError> {
+        if batches.len() > MAX_BATCH_ENTRIES {
+            return Err(ServiceError::BatchTooLarge(format!(
+                "Batch size exceeds maximum limit of {} symbols",
+                MAX_BATCH_ENTRIES
+            )));
+        }
+        let max_size = self.max_batch_size();
+        let buffers = self.shared.buffers.read().await;
+        let mut results = Vec::with_capacity(batches.len());
+        for (symbol, batch) in batches {
+            results.push(match batch.validate(max_size) {
+                Ok(()) => self.check_validated_batch(&buffers, self.canonical(symbol), &batch).await,
+                Err(e) => Err(e),
+            });
+        }
+        Ok(results)
+    }
+
+    /// The checks of [`Shared::add_locked`], in the same order.
+    async fn check_validated_batch(&self, buffers: &Symbols, symbol: String, batch: &Batch) -> Result<BatchCheck, ServiceError> {
+        if batch.batch_id.as_ref().is_some_and(|id| self.shared.batch_ids.lock().unwrap().contains(id)) {
+            return Ok(BatchCheck { symbol, already_added: true, ..BatchCheck::default() });
+        }
+        self.shared.check_room(buffers, &symbol)?;
+        let guard = match buffers.get(&symbol) {
+            Some(state) => Some(state.read().await),
+            None => None,
+        };
+        let state = guard.as_deref();
+        let new_config;
+        let config = match state {
+            Some(state) => &state.config,
+            None => {
+                new_config = self.shared.settings.read().unwrap().new_symbol_config.clone();
+                &new_config
+            }
+        };
+        if state.is_some_and(|state| state.derivation.is_some()) {
+            return Err(ServiceError::Conflict(format!("Symbol {} is derived from other symbols and can't take data directly", symbol)));
+        }
+        if let Some(name) = batch.fields.keys().find(|name| !config.fields.contains(*name)) {
+            return Err(format!("Field {} is not configured for this symbol", name).into());
+        }
+        let mut kept = batch.finite_mask();
+        let non_finite_dropped = kept.iter().filter(|&&finite| !finite).count();
+        if let (Some(i), NonFiniteAction::Reject) = (kept.iter().position(|&finite| !finite), config.non_finite) {
+            return Err(format!("Invalid value at index {}. Only finite values are accepted.", i).into());
+        }
+        let finite: Vec<f64> = batch.values.iter().zip(&kept).filter(|(_, &keep)| keep).map(|(&v, _)| v).collect();
+        if let Some(price) = state.and_then(|state| state.meta.first_off_tick(&finite)) {
+            return Err(format!("Value {} is not a multiple of the tick size", price).into());
+        }
+        if let (Some(venue), Some(state)) = (&batch.venue, state) {
+            if !state.venues.contains_key(venue) && state.venues.len() >= MAX_VENUES {
+                return Err(ServiceError::LimitReached(format!("Symbol already has the maximum of {} venues", MAX_VENUES)));
+            }
+        }
+        let mut duplicates_dropped = 0;
+        if let (Some(ids), true) = (&batch.ids, config.dedup_window > 0) {
+            let mut seen = HashSet::new();
+            for (id, keep) in ids.iter().zip(kept.iter_mut()) {
+                let recent = state.is_some_and(|state| state.recent_ids.contains(id));
+                if *keep && (recent || !seen.insert(id)) {
+                    *keep = false;
+                    duplicates_dropped += 1;
+                }
+            }
+        }
+        Ok(BatchCheck {
+            symbol,
+            new_symbol: state.is_none(),
+            already_added: false,
+            accepted: kept.iter().filter(|&&keep| keep).count(),
+            non_finite_dropped,
+            duplicates_dropped,
+        })
+    }
+
+    /// Adds bid/ask quotes to the symbol's quote windows, creating the
+    /// symbol if it isn't tracked yet. Quotes don't touch the price windows.
+    pub async fn add_quotes(&self, symbol: String, quotes: Vec<Quote>) -> Result<(), ServiceError> {
+        let symbol = self.canonical(symbol);
+        let max_size = self.max_batch_size();
+        if quotes.len() > max_size {
+            return Err(ServiceError::BatchTooLarge(format!("Batch size exceeds maximum limit of {}", max_size)));
+        }
+        for quote in &quotes {
+            quote.validate()?;
+        }
+
+        let buffers = self.shared.tracking([symbol.as_str()]).await;
+        self.shared.check_room(&buffers, &symbol)?;
+        let mut state = tracked(&buffers, &symbol)?.write().await;
+        if state.quotes.is_empty() {
+            state.quotes = (1..=8).map(|k| QuoteBuffer::new(10usize.pow(k))).collect();
+        }
+        for window in state.quotes.iter_mut() {
+            for &quote in &quotes {
+                window.add(quote);
+            }
+        }
+        Ok(())
+    }
+
+    /// Spread and mid-price stats over the symbol's last 10^k quotes.
+    pub async fn get_quote_stats(&self, symbol: String, k: usize) -> Result<QuoteStats, ServiceError> {
+        let symbol = self.canonical(symbol);
+        validate_k(k)?;
+
+        let buffers = self.shared.buffers.read().await;
+        let state = tracked(&buffers, &symbol)?.read().await;
+        state.quotes
+            .get(k - 1)
+            .map(QuoteBuffer::stats)
+            .ok_or_else(|| ServiceError::NotFound(format!("No quotes received for {}", symbol)))
+    }
+
+    /// Replaces the symbol's configuration, creating the symbol if it isn't
+    /// tracked yet. Existing window contents are kept.
+    pub async fn set_symbol_config(&self, symbol: String, config: SymbolConfig) -> Result<(), ServiceError> {
+        let symbol = self.canonical(symbol);
+        config.validate()?;
+
+        let buffers = self.shared.tracking([symbol.as_str()]).await;
+        self.shared.check_room(&buffers, &symbol)?;
+        let mut state = tracked(&buffers, &symbol)?.write().await;
+        let released = state.apply_config(config);
+        self.fast_paths.rescreen(&symbol, state.screen());
+        let dependents = state.dependents.clone();
+        self.shared.changed(&mut state, symbol.clone());
+        drop(state);
+        self.shared.propagate(&buffers, &symbol, &released, dependents).await;
+        Ok(())
+    }
+
+    pub async fn get_symbol_config(&self, symbol: String) -> Result<SymbolConfig, ServiceError> {
+        let symbol = self.canonical(symbol);
+        let buffers = self.shared.buffers.read().await;
+        let config = tracked(&buffers, &symbol)?.read().await.config.clone();
+        Ok(config)
+    }
+
+    /// Sets the symbol's reference data, creating the symbol if it isn't
+    /// tracked yet.
+    pub async fn set_symbol_meta(&self, symbol: String, meta: SymbolMeta) -> Result<(), ServiceError> {
+        let symbol = self.canonical(symbol);
+        meta.validate()?;
+
+        let buffers = self.shared.tracking([symbol.as_str()]).await;
+        self.shared.check_room(&buffers, &symbol)?;
+        let mut state = tracked(&buffers, &symbol)?.write().await;
+        state.meta = meta;
+        self.fast_paths.rescreen(&symbol, state.screen());
+        Ok(())
+    }
+
+    pub async fn get_symbol_meta(&self, symbol: String) -> Result<SymbolMeta, ServiceError> {
+        let symbol = self.canonical(symbol);
+        let buffers = self.shared.buffers.read().await;
+        let meta = tracked(&buffers, &symbol)?.read().await.meta.clone();
+        Ok(meta)
+    }
+
+    /// Replaces the symbol's tags, keeping the rest of its reference data.
+    pub async fn set_symbol_tags(&self, symbol: String, tags: BTreeMap<String, String>) -> Result<(), ServiceError> {
+        let symbol = self.canonical(symbol);
+        validate_tags(&tags)?;
+
+        let buffers = self.shared.tracking([symbol.as_str()]).await;
+        self.shared.check_room(&buffers, &symbol)?;
+        tracked(&buffers, &symbol)?.write().await.meta.tags = tags;
+        Ok(())
+    }
+
+    /// Clears the symbol's reference data, keeping its windows.
+    pub async fn delete_symbol_meta(&self, symbol: String) -> Result<(), ServiceError> {
+        let symbol = self.canonical(symbol);
+        let buffers = self.shared.buffers.read().await;
+        let mut state = tracked(&buffers, &symbol)?.write().await;
+        state.meta = SymbolMeta::default();
+        self.fast_paths.rescreen(&symbol, state.screen());
+        Ok(())
+    }
+
+    /// A tag that changes whenever the symbol's stats may have: with its
+    /// windows, and with its staleness, which turns with time alone.
+    pub async fn stats_tag(&self, symbol: String) -> Result<String, ServiceError> {
+        let symbol = self.canonical(symbol);
+        let buffers = self.shared.buffers.read().await;
+        let state = tracked(&buffers, &symbol)?.read().await;
+        let stale = if state.counters.is_stale(&state.config) { "-stale" } else { "" };
+        Ok(format!("{}{}", state.version, stale))
+    }
+
+    pub async fn get_stats(&self, symbol: String, k: usize) -> Result<StatsResponse, ServiceError> {
+        self.get_stats_with(symbol, k, &StatsOptions::default()).await
+    }
+
+    pub async fn get_stats_with(
+        &self,
+        symbol: String,
+        k: usize,
+        options: &StatsOptions,
+    ) -> Result<StatsResponse, ServiceError> {
+        let symbol = self.canonical(symbol);
+        validate_k(k)?;
+        validate_stats_options(options)?;
+
+        let buffers = self.shared.buffers.read().await;
+        let state = tracked(&buffers, &symbol)?.read().await;
+        symbol_stats(&state, &symbol, k, options)
+    }
+
+    /// Stats of every 10^k window of the symbol, k = 1..=8 in order, from one
+    /// consistent snapshot.
+    pub async fn get_all_stats(&self, symbol: String, options: &StatsOptions) -> Result<Vec<StatsResponse>, ServiceError> {
+        let symbol = self.canonical(symbol);
+        validate_stats_options(options)?;
+
+        let buffers = self.shared.buffers.read().await;
+        let state = tracked(&buffers, &symbol)?.read().await;
+        (1..=8).map(|k| symbol_stats(&state, &symbol, k, options)).collect()
+    }
+
+    /// Checks a condition against the symbol's current stats, read from one
+    /// consistent snapshot; see [`Condition::check`].
+    pub async fn check_condition(&self, symbol: String, condition: &mut Condition) -> Result<bool, ServiceError> {
+        let symbol = self.canonical(symbol);
+        let stats = {
+            let buffers = self.shared.buffers.read().await;
+            let state = tracked(&buffers, &symbol)?.read().await;
+            condition
+                .windows()
+                .into_iter()
+                .map(|k| Ok((k, symbol_stats(&state, &symbol, k, &StatsOptions::default())?)))
+                .collect::<Result<Vec<_>, ServiceError>>()?
+        };
+        Ok(condition.check(|name, k| stats.iter().find(|(window, _)| *window == k)?.1.stat(name)))
+    }
+
+    /// Stats of the 10^k window of every symbol matching a pattern in which
+    /// `*` stands for any run of characters and `?` for any one, e.g. `ES*`
+    /// for a futures chain, by symbol and from one consistent snapshot.
+    /// Symbols the options don't apply to, e.g. without the field asked
+    /// for, are left out.
+    pub async fn get_stats_matching(
+        &self,
+        pattern: &str,
+        k: usize,
+        options: &StatsOptions,
+    ) -> Result<Vec<SymbolStats>, ServiceError> {
+        validate_k(k)?;
+        validate_stats_options(options)?;
+
+        let buffers = self.shared.buffers.read().await;
+        let mut symbols: Vec<&str> = buffers.keys().map(String::as_str).filter(|symbol| glob_match(pattern, symbol)).collect();
+        if symbols.len() > MAX_SYMBOLS_PAGE {
+            return Err(format!("Pattern {} matches more than {} symbols", pattern, MAX_SYMBOLS_PAGE).into());
+        }
+        symbols.sort();
+        let states = read_all(&buffers, symbols.iter().copied()).await;
+        Ok(symbols
+            .into_iter()
+            .filter_map(|symbol| {
+                let stats = symbol_stats(&states[symbol], symbol, k, options).ok()?;
+                Some(SymbolStats { symbol: symbol.to_string(), stats })
+            })
+            .collect())
+    }
+
+    /// Stats of many (symbol, k) windows read under locks held together, so
+    /// they reflect the same point in time. Each entry fails on its own.
+    pub async fn get_stats_batch(
+        &self,
+        requests: Vec<(String, usize)>,
+    ) -> Result<Vec<Result<StatsResponse, ServiceError>>, ServiceError> {
+        if requests.len() > MAX_STATS_BATCH {
+            return Err(ServiceError::BatchTooLarge(format!("Batch size exceeds maximum limit of {}", MAX_STATS_BATCH)));
+        }
+        let requests: Vec<(String, usize)> = {
+            let aliases = self.aliases.read().unwrap();
+            requests.into_iter().map(|(s, k)| (aliases.get(&s).cloned().unwrap_or(s), k)).collect()
+        };
+        let options = StatsOptions::default();
+
+        let buffers = self.shared.buffers.read().await;
+        let states = read_all(&buffers, requests.iter().map(|(symbol, _)| symbol.as_str())).await;
+        Ok(requests
+            .iter()
+            .map(|(symbol, k)| {
+                validate_k(*k)?;
+                let state = states.get(symbol.as_str()).ok_or(ServiceError::UnknownSymbol)?;
+                symbol_stats(state, symbol, *k, &options)
+            })
+            .collect())
+    }
+
+    /// Stats of one of the symbol's configured time windows, covering the
+    /// values within `duration_ms` of the newest event timestamp.
+    pub async fn get_stats_over(
+        &self,
+        symbol: String,
+        duration_ms: u64,
+        options: &StatsOptions,
+    ) -> Result<StatsResponse, ServiceError> {
+        let symbol = self.canonical(symbol);
+        validate_stats_options(options)?;
+        if options.field.as_deref().is_some_and(|f| f != "price") {
+            return Err("Fields are not kept for time windows".to_string().into());
+        }
+        if options.venue.is_some() || options.extended_hours || options.currency.is_some() {
+            return Err("Venues, extended hours and converted prices are not kept for time windows".to_string().into());
+        }
+
+        let buffers = self.shared.buffers.read().await;
+        let state = tracked(&buffers, &symbol)?.read().await;
+        let window = state
+            .time_windows
+            .iter()
+            .find(|w| w.max_age_ns() == Some(duration_ms * 1_000_000))
+            .ok_or_else(|| ServiceError::NotFound(format!("Time window {}ms is not configured for {}", duration_ms, symbol)))?;
+        let mut stats = window.get_stats_with(options);
+        state.counters.report(&mut stats, &state.config);
+        Ok(stats)
+    }
+
+    /// Stats of two of the symbol's windows, typically a short and a long
+    /// one, read under a single lock acquisition, with the drift of the
+    /// first window's mean from the second's and their volatility ratio.
+    pub async fn compare_windows(
+        &self,
+        symbol: String,
+        k1: usize,
+        k2: usize,
+    ) -> Result<WindowComparison, ServiceError> {
+        let symbol = self.canonical(symbol);
+        validate_k(k1)?;
+        validate_k(k2)?;
+
+        let buffers = self.shared.buffers.read().await;
+        let state = tracked(&buffers, &symbol)?.read().await;
+        let options = StatsOptions::default();
+        let first = symbol_stats(&state, &symbol, k1, &options)?;
+        let second = symbol_stats(&state, &symbol, k2, &options)?;
+        Ok(WindowComparison::new(first, second))
+    }
+
+    /// Stats over the symbol's last `n` values (up to 10^8), for window sizes
+    /// other than the powers of ten. The points are copied out of the
+    /// smallest window that covers `n` and rebuilt into a window of exactly
+    /// `n` on the blocking pool, so EWMA fields only reflect those points.
+    pub async fn get_stats_for_last(
+        &self,
+        symbol: String,
+        n: usize,
+        options: &StatsOptions,
+    ) -> Result<StatsResponse, ServiceError> {
+        let symbol = self.canonical(symbol);
+        let k = covering_k(n)?;
+        if 10usize.pow(k as u32) == n {
+            return self.get_stats_with(symbol, k, options).await;
+        }
+        validate_stats_options(options)?;
+
+        let (points, config, last_update, counters) = {
+            let buffers = self.shared.buffers.read().await;
+            let state = tracked(&buffers, &symbol)?.read().await;
+            let source = stats_window(&state, &symbol, k, options)?;
+            (
+                source.tail(n),
+                state.config.clone(),
+                source.last_update,
+                state.counters,
+            )
+        };
+        partial_stats(points, n, config, last_update, counters, options).await
+    }
+
+    /// Stats of the symbol's last `n` values as of `as_of`, in nanoseconds
+    /// since the Unix epoch: the window as it was once every value
+    /// timestamped up to then had arrived. It's rebuilt from the symbol's
+    /// largest window, so fails once the values it needs have left that.
+    /// The feed counters are the current ones.
+    pub async fn get_stats_as_of(
+        &self,
+        symbol: String,
+        n: usize,
+        as_of: u64,
+        options: &StatsOptions,
+    ) -> Result<StatsResponse, ServiceError> {
+        let symbol = self.canonical(symbol);
+        covering_k(n)?;
+        validate_stats_options(options)?;
+
+        let (points, config, counters) = {
+            let buffers = self.shared.buffers.read().await;
+            let state = tracked(&buffers, &symbol)?.read().await;
+            let source = stats_window(&state, &symbol, 8, options)?;
+            let newer = source.timestamps.iter().rev().take_while(|&&t| t > as_of).count();
+            let end = source.len() - newer;
+            // A full largest window may have evicted values the window held.
+            if end == 0 || (end < n && source.len() == source.capacity) {
+                return Err(ServiceError::NotFound(format!("Values as of {} are no longer retained", as_of)));
+            }
+            (Batch::from_points(&source.points(newer, n))?, state.config.clone(), state.counters)
+        };
+        partial_stats(points, n, config, as_of / 1_000_000, counters, options).await
+    }
+
+    /// The most recent `limit` bars of one of the symbol's configured intervals,
+    /// oldest first.
+    pub async fn get_bars(&self, symbol: String, interval_ms: u64, limit: usize) -> Result<Vec<Bar>, ServiceError> {
+        let symbol = self.canonical(symbol);
+        let buffers = self.shared.buffers.read().await;
+        let state = tracked(&buffers, &symbol)?.read().await;
+        state.bars
+            .iter()
+            .find(|b| b.interval_ms() == interval_ms)
+            .map(|b| b.recent(limit))
+            .ok_or_else(|| ServiceError::NotFound(format!("Bar interval {}ms is not configured for {}", interval_ms, symbol)))
+    }
+
+    /// Bollinger Bands around the mean of the symbol's 10^k window, `width`
+    /// standard deviations wide on each side.
+    pub async fn get_bollinger(&self, symbol: String, k: usize, width: f64) -> Result<BollingerBands, ServiceError> {
+        let symbol = self.canonical(symbol);
+        validate_k(k)?;
+        if !width.is_finite() || width < 0.0 {
+            return Err("Invalid width input. Only non-negative values are accepted.".to_string().into());
+        }
+
+        let buffers = self.shared.buffers.read().await;
+        let state = tracked(&buffers, &symbol)?.read().await;
+        Ok(indicators::bollinger(window(&state, k)?, width))
+    }
+
+    /// Relative strength index over the symbol's 10^k window.
+    pub async fn get_rsi(&self, symbol: String, k: usize) -> Result<Rsi, ServiceError> {
+        let symbol = self.canonical(symbol);
+        validate_k(k)?;
+
+        let buffers = self.shared.buffers.read().await;
+        let state = tracked(&buffers, &symbol)?.read().await;
+        Ok(indicators::rsi(window(&state, k)?))
+    }
+
+    /// Current MACD of the symbol, over every value ingested since the
+    /// MACD periods were last changed.
+    pub async fn get_macd(&self, symbol: String) -> Result<Macd, ServiceError> {
+        let symbol = self.canonical(symbol);
+        let buffers = self.shared.buffers.read().await;
+        let state = tracked(&buffers, &symbol)?.read().await;
+        Ok(state.indicators.macd.current().unwrap_or_default())
+    }
+
+    /// Average true range over the retained bars of one of the symbol's bar
+    /// intervals, Wilder-smoothed over `period` bars.
+    pub async fn get_atr(&self, symbol: String, interval_ms: u64, period: usize) -> Result<Atr, ServiceError> {
+        let symbol = self.canonical(symbol);
+        if period == 0 {
+            return Err("Invalid period input. Only positive values are accepted.".to_string().into());
+        }
+        let bars = self.get_bars(symbol, interval_ms, usize::MAX).await?;
+        indicators::atr(&bars, period)
+            .ok_or_else(|| ServiceError::InvalidValue(format!("Not enough bars for period {} (have {})", period, bars.len())))
+    }
+
+    /// Moving average over the symbol's last `lookback` values (up to 10^8),
+    /// read from the smallest window that holds them.
+    pub async fn get_moving_average(
+        &self,
+        symbol: String,
+        lookback: usize,
+        kind: MovingAverageKind,
+    ) -> Result<MovingAverage, ServiceError> {
+        let symbol = self.canonical(symbol);
+        let k = covering_k(lookback)?;
+
+        let buffers = self.shared.buffers.read().await;
+        let state = tracked(&buffers, &symbol)?.read().await;
+        Ok(indicators::moving_average(window(&state, k)?, lookback, kind))
+    }
+
+    /// Historical-simulation VaR at each confidence level over the returns
+    /// in the symbol's 10^k window.
+    pub async fn get_value_at_risk(
+        &self,
+        symbol: String,
+        k: usize,
+        confidences: &[f64],
+    ) -> Result<ValueAtRisk, ServiceError> {
+        let symbol = self.canonical(symbol);
+        validate_k(k)?;
+        if confidences.iter().any(|c| !(*c > 0.0 && *c < 1.0)) {
+            return Err("Invalid confidence input. Only values in (0, 1) are accepted.".to_string().into());
+        }
+
+        let buffers = self.shared.buffers.read().await;
+        let state = tracked(&buffers, &symbol)?.read().await;
+        Ok(risk::value_at_risk(window(&state, k)?, confidences))
+    }
+
+    /// Annualized Sharpe ratio of the simple returns in the symbol's 10^k
+    /// window, against an annual risk-free rate.
+    pub async fn get_sharpe(
+        &self,
+        symbol: String,
+        k: usize,
+        risk_free_rate: f64,
+        periods_per_year: f64,
+    ) -> Result<Sharpe, ServiceError> {
+        let symbol = self.canonical(symbol);
+        validate_k(k)?;
+        if !risk_free_rate.is_finite() {
+            return Err("Invalid rf input. Only finite values are accepted.".to_string().into());
+        }
+        if !periods_per_year.is_finite() || periods_per_year <= 0.0 {
+            return Err("Invalid periods_per_year input. Only positive values are accepted.".to_string().into());
+        }
+
+        let buffers = self.shared.buffers.read().await;
+        let state = tracked(&buffers, &symbol)?.read().await;
+        Ok(risk::sharpe(window(&state, k)?, risk_free_rate, periods_per_year))
+    }
+
+    /// Hurst exponent of the log returns in the symbol's 10^k window. The
+    /// window is copied under the read lock and analysed on the blocking
+    /// pool, so ingestion isn't held up by the computation.
+    pub async fn get_hurst(&self, symbol: String, k: usize) -> Result<Hurst, ServiceError> {
+        let symbol = self.canonical(symbol);
+        let values = self.snapshot_values(&symbol, k).await?;
+        tokio::task::spawn_blocking(move || analytics::hurst(&values))
+            .await
+            .map_err(|e| ServiceError::Internal(format!("Hurst computation failed: {}", e)))
+    }
+
+    /// Autocorrelation of the log returns in the symbol's 10^k window at
+    /// lags 1 through `lags`, computed on the blocking pool like the Hurst
+    /// exponent.
+    pub async fn get_autocorrelation(
+        &self,
+        symbol: String,
+        k: usize,
+        lags: usize,
+    ) -> Result<Autocorrelation, ServiceError> {
+        let symbol = self.canonical(symbol);
+        if !(1..=MAX_ACF_LAGS).contains(&lags) {
+            return Err(format!("Invalid lags input. Only values 1-{} are accepted.", MAX_ACF_LAGS).into());
+        }
+        let values = self.snapshot_values(&symbol, k).await?;
+        tokio::task::spawn_blocking(move || analytics::autocorrelation(&values, lags))
+            .await
+            .map_err(|e| ServiceError::Internal(format!("Autocorrelation computation failed: {}", e)))
+    }
+
+    /// Shannon entropy of the values in the symbol's 10^k window over `bins`
+    /// equal-width bins, computed on the blocking pool.
+    pub async fn get_entropy(&self, symbol: String, k: usize, bins: usize) -> Result<Entropy, ServiceError> {
+        let symbol = self.canonical(symbol);
+        if !(2..=MAX_ENTROPY_BINS).contains(&bins) {
+            return Err(format!("Invalid bins input. Only values 2-{} are accepted.", MAX_ENTROPY_BINS).into());
+        }
+        let values = self.snapshot_values(&symbol, k).await?;
+        tokio::task::spawn_blocking(move || analytics::entropy(&values, bins))
+            .await
+            .map_err(|e| ServiceError::Internal(format!("Entropy computation failed: {}", e)))
+    }
+
+    /// A page of the raw points in the symbol's 10^k window, counting
+    /// `offset` back from the newest point.
+    pub async fn get_values(&self, symbol: String, k: usize, offset: usize, limit: usize) -> Result<ValuesPage, ServiceError> {
+        let symbol = self.canonical(symbol);
+        validate_k(k)?;
+        validate_values_limit(limit)?;
+        let buffers = self.shared.buffers.read().await;
+        let state = tracked(&buffers, &symbol)?.read().await;
+        let window = window(&state, k)?;
+        Ok(ValuesPage { total: window.len(), points: window.points(offset, limit) })
+    }
+
+    /// Starts a scan of the raw points of the symbol's 10^k window as they
+    /// are now, oldest first, returning the first page and a cursor for the
+    /// next; see [`scans`]. Takes the symbol's write lock, as scans hold on
+    /// to the points evicted before they've read them.
+    pub async fn scan_values(&self, symbol: String, k: usize, limit: usize) -> Result<ScanPage, ServiceError> {
+        let symbol = self.canonical(symbol);
+        validate_k(k)?;
+        validate_values_limit(limit)?;
+        let buffers = self.shared.buffers.read().await;
+        self.expire_scans(&buffers).await;
+        let mut state = tracked(&buffers, &symbol)?.write().await;
+        let window = window(&state, k)?;
+        let (start, end) = window.positions();
+        let scan = Scan::new(symbol, k, window.id(), start, end);
+        self.next_scan_page(&mut state, None, scan, limit)
+    }
+
+    /// The next page of the scan the cursor names.
+    pub async fn next_scan_values(&self, cursor: &str, limit: usize) -> Result<ScanPage, ServiceError> {
+        validate_values_limit(limit)?;
+        let buffers = self.shared.buffers.read().await;
+        self.expire_scans(&buffers).await;
+        let unknown = || ServiceError::NotFound("Unknown or expired cursor".to_string());
+        let symbol = self.scans.lock().unwrap().get(cursor).ok_or_else(unknown)?.symbol.clone();
+        let Some(state) = buffers.get(&symbol) else {
+            self.scans.lock().unwrap().take(cursor);
+            return Err(scan_ended());
+        };
+        let mut state = state.write().await;
+        // Another request may have read or expired the scan meanwhile.
+        let (id, scan) = self.scans.lock().unwrap().take(cursor).ok_or_else(unknown)?;
+        self.next_scan_page(&mut state, Some(id), scan, limit)
+    }
+
+    /// Reads the scan's next page from its symbol's state, putting it back
+    /// among the open scans, under `id` if it had one, unless it's done or
+    /// failed.
+    fn next_scan_page(&self, state: &mut SymbolState, id: Option<u64>, mut scan: Scan, limit: usize) -> Result<ScanPage, ServiceError> {
+        let mut scans = self.scans.lock().unwrap();
+        let page = match state.buffers.get(scan.k - 1) {
+            Some(window) if window.id() == scan.window_id => {
+                let limit = limit.min((scan.end - scan.next) as usize);
+                window.points_from(scan.next, limit).ok_or_else(|| {
+                    ServiceError::Conflict("The scan fell too far behind; points it hadn't read were evicted".to_string())
+                })
+            }
+            _ => Err(scan_ended()),
+        };
+        let cursor = match &page {
+            Ok(points) if scan.next + (points.len() as u64) < scan.end => {
+                scan.next += points.len() as u64;
+                let cursor = match id {
+                    Some(id) => Ok(scans.reopen(id, scan.clone())),
+                    None => scans.open(scan.clone()),
+                };
+                Some(cursor)
+            }
+            _ => None,
+        };
+        repin(state, &scans, &scan);
+        Ok(ScanPage { total: (scan.end - scan.start) as usize, points: page?, cursor: cursor.transpose()? })
+    }
+
+    /// Drops the scans gone idle, letting go of the points they held on to.
+    async fn expire_scans(&self, buffers: &Symbols) {
+        let expired = self.scans.lock().unwrap().expire();
+        for scan in expired {
+            let Some(state) = buffers.get(&scan.symbol) else { continue };
+            let mut state = state.write().await;
+            repin(&mut state, &self.scans.lock().unwrap(), &scan);
+        }
+    }
+
+    /// Every point of the symbol's 10^k window with its weight, volume and
+    /// timestamp, oldest first, copied under one read lock.
+    pub async fn get_window(&self, symbol: String, k: usize) -> Result<Batch, ServiceError> {
+        let symbol = self.canonical(symbol);
+        validate_k(k)?;
+        let buffers = self.shared.buffers.read().await;
+        let state = tracked(&buffers, &symbol)?.read().await;
+        let window = window(&state, k)?;
+        Ok(window.tail(window.len()))
+    }
+
+    async fn snapshot_values(&self, symbol: &str, k: usize) -> Result<Vec<f64>, ServiceError> {
+        validate_k(k)?;
+        let buffers = self.shared.buffers.read().await;
+        let state = tracked(&buffers, symbol)?.read().await;
+        Ok(window(&state, k)?.values().collect())
+    }
+
+    /// The `limit` symbols whose 10^k window has the largest `metric` in
+    /// magnitude, largest first. The symbol list is read once and each
+    /// symbol is then scored under its own short read lock, so a scan over
+    /// many symbols holds up neither ingestion nor new symbols.
+    pub async fn get_top_movers(&self, metric: RankMetric, k: usize, limit: usize) -> Result<Vec<Mover>, ServiceError> {
+        validate_k(k)?;
+        let symbols: Vec<String> = self.shared.buffers.read().await.keys().cloned().collect();
+
+        let mut movers = Vec::with_capacity(symbols.len());
+        for symbol in symbols {
+            let buffers = self.shared.buffers.read().await;
+            // The symbol may have been dropped since the list was taken.
+            let Some(state) = buffers.get(&symbol) else { continue };
+            let state = state.read().await;
+            let Ok(window) = window(&state, k) else { continue };
+            if let Some(value) = metric.value(window) {
+                movers.push(Mover { symbol, value });
+            }
+        }
+        Ok(ranking::top(movers, limit))
+    }
+
+    /// The `limit` symbols first in `order` by one of the
+    /// [`CONDITION_STATS`] of their 10^k window, for screening the universe
+    /// without reading every symbol's stats. Symbols without data or the
+    /// stat are left out. Like [`Self::get_top_movers`], each symbol is
+    /// read under its own short lock.
+    pub async fn get_ranking(&self, stat: &str, k: usize, order: Order, limit: usize) -> Result<Vec<Mover>, ServiceError> {
+        validate_k(k)?;
+        if !CONDITION_STATS.contains(&stat) {
+            return Err(format!("Invalid metric input. {:?} isn't a stat symbols can be ranked by.", stat).into());
+        }
+        let symbols: Vec<String> = self.shared.buffers.read().await.keys().cloned().collect();
+
+        let mut ranked = Vec::with_capacity(symbols.len());
+        for symbol in symbols {
+            let buffers = self.shared.buffers.read().await;
+            // The symbol may have been dropped since the list was taken.
+            let Some(state) = buffers.get(&symbol) else { continue };
+            let state = state.read().await;
+            let Ok(stats) = symbol_stats(&state, &symbol, k, &StatsOptions::default()) else { continue };
+            if let Some(value) = stats.stat(stat).filter(|value| stats.count > 0 && value.is_finite()) {
+                ranked.push(Mover { symbol, value });
+            }
+        }
+        Ok(ranking::rank(ranked, order, limit))
+    }
+
+    /// `metric` of the 10^k window aggregated over each group of symbols
+    /// the tag filter covers, from one consistent snapshot. Symbols whose
+    /// metric is undefined, e.g. with an empty window, are left out.
+    pub async fn get_group_stats(&self, filter: &TagFilter, metric: RankMetric, k: usize) -> Result<Vec<GroupStats>, ServiceError> {
+        validate_k(k)?;
+        let buffers = self.shared.buffers.read().await;
+        let states = read_all(&buffers, buffers.keys().map(String::as_str)).await;
+        let mut groups: BTreeMap<&str, Vec<f64>> = BTreeMap::new();
+        for state in states.values() {
+            let Some(group) = filter.group(&state.meta.tags) else { continue };
+            let values = groups.entry(group).or_default();
+            if let Some(value) = window(state, k).ok().and_then(|window| metric.value(window)) {
+                values.push(value);
+            }
+        }
+        Ok(groups::aggregate(&filter.name, groups))
+    }
+
+    /// Pearson correlation between two symbols' 10^k windows, aligned on
+    /// their most recent values.
+    pub async fn get_correlation(
+        &self,
+        symbol_a: String,
+        symbol_b: String,
+        k: usize,
+    ) -> Result<CorrelationResponse, ServiceError> {
+        validate_k(k)?;
+
+        let symbols = self.canonical_all(vec![symbol_a, symbol_b]);
+        let buffers = self.shared.buffers.read().await;
+        let states = read_all(&buffers, symbols.iter().map(String::as_str)).await;
+        let windows = aligned_windows(&states, &symbols, k)?;
+        let (count, correlation) = cross::correlation(windows[0], windows[1]);
+        Ok(CorrelationResponse { correlation, count })
+    }
+
+    /// Pairwise covariance matrix of the symbols' 10^k windows, aligned on
+    /// their most recent values and read under locks held together.
+    pub async fn get_covariance(&self, symbols: Vec<String>, k: usize) -> Result<CovarianceResponse, ServiceError> {
+        let symbols = self.canonical_all(symbols);
+        validate_k(k)?;
+        if symbols.is_empty() {
+            return Err("At least one symbol is required".to_string().into());
+        }
+
+        let buffers = self.shared.buffers.read().await;
+        let states = read_all(&buffers, symbols.iter().map(String::as_str)).await;
+        let windows = aligned_windows(&states, &symbols, k)?;
+        let (count, matrix) = cross::covariance_matrix(&windows);
+        Ok(CovarianceResponse { symbols, matrix, count })
+    }
+}
+
+/// Upper bound on the lags accepted by `get_autocorrelation`.
+const MAX_ACF_LAGS: usize = 1000;
+
+/// Upper bound on the bins accepted by `get_entropy`.
+const MAX_ENTROPY_BINS: usize = 10_000;
+
+/// Stops `leg` feeding `dependent` unless the dependent still uses it.
+fn release_dependent(buffers: &mut Symbols, leg: &str, dependent: &str) {
+    if state_mut(buffers, dependent).is_some_and(|state| state.depends_on(leg)) {
+        return;
+    }
+    if let Some(leg_state) = state_mut(buffers, leg) {
+        leg_state.dependents.retain(|d| d != dependent);
+    }
+}
+
+/// The values a synthetic symbol takes from `leg`'s newly ingested batches.
+fn derive(derivation: &mut Derivation, leg: &str, ingested: &[Batch]) -> Batch {
+    let mut values = Vec::new();
+    let mut timestamps = Vec::new();
+    for batch in ingested {
+        for (&price, &timestamp_ns) in batch.values.iter().zip(batch.timestamps.iter().flatten()) {
+            if let Some(value) = derivation.update(leg, price) {
+                values.push(value);
+                timestamps.push(timestamp_ns);
+            }
+        }
+    }
+    Batch { values, timestamps: Some(timestamps), ..Batch::default() }
+}
+
+/// Whether the name matches the pattern, where `*` matches any run of
+/// characters and `?` any single one.
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let (pattern, name): (Vec<char>, Vec<char>) = (pattern.chars().collect(), name.chars().collect());
+    let (mut p, mut n) = (0, 0);
+    // Where the last `*` was and the name position it's matched up to.
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = star {
+            // Let the last `*` swallow one more character and retry.
+            p = star_p + 1;
+            n = star_n + 1;
+            star = Some((star_p, star_n + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Smallest k whose 10^k window holds the last `n` values.
+fn covering_k(n: usize) -> Result<usize, ServiceError> {
+    (1..=8)
+        .find(|&k| n >= 1 && n <= 10usize.pow(k as u32))
+        .ok_or_else(|| "Invalid window input. Only values 1-100000000 are accepted.".to_string().into())
+}
+
+fn tracked<'a>(buffers: &'a Symbols, symbol: &str) -> Result<&'a RwLock<SymbolState>, ServiceError> {
+    buffers.get(symbol).ok_or(ServiceError::UnknownSymbol)
+}
+
+/// A symbol's state under the map's write lock, which no symbol lock
+/// outlives, so it's reached without locking.
+fn state_mut<'a>(buffers: &'a mut Symbols, symbol: &str) -> Option<&'a mut SymbolState> {
+    buffers.get_mut(symbol).map(RwLock::get_mut)
+}
+
+/// Read locks on the tracked ones of `symbols`, taken in name order so
+/// readers of several symbols can't deadlock, and held together so they
+/// read one consistent snapshot.
+async fn read_all<'a, 'b>(buffers: &'a Symbols, symbols: impl IntoIterator<Item = &'b str>) -> Snapshot<'a> {
+    let symbols: BTreeSet<&str> = symbols.into_iter().collect();
+    let mut states = HashMap::with_capacity(symbols.len());
+    for symbol in symbols {
+        if let Some((symbol, state)) = buffers.get_key_value(symbol) {
+            states.insert(symbol.as_str(), state.read().await);
+        }
+    }
+    states
+}
+
+fn window(state: &SymbolState, k: usize) -> Result<&TradingDataBuffer, ServiceError> {
+    state.buffers.get(k - 1).ok_or(ServiceError::UnknownSymbol)
+}
+
+fn validate_values_limit(limit: usize) -> Result<(), ServiceError> {
+    if limit == 0 || limit > MAX_VALUES_PAGE {
+        return Err(format!("Invalid limit input. Only values 1-{} are accepted.", MAX_VALUES_PAGE).into());
+    }
+    Ok(())
+}
+
+fn scan_ended() -> ServiceError {
+    ServiceError::Conflict("The window was reset or removed since the scan started".to_string())
+}
+
+/// Has the scan's window keep the points evicted from the oldest position
+/// its open scans still need, or none once they're done.
+fn repin(state: &mut SymbolState, scans: &Scans, scan: &Scan) {
+    if let Some(window) = state.buffers.get_mut(scan.k - 1) {
+        window.retain(scans.oldest_needed(&scan.symbol, scan.k, window.id()));
+    }
+}
+
+/// The symbol's consolidated 10^k price window, or the one of the venue,
+/// extra field or extended hours the options select.
+fn stats_window<'a>(
+    state: &'a SymbolState,
+    symbol: &str,
+    k: usize,
+    options: &StatsOptions,
+) -> Result<&'a TradingDataBuffer, ServiceError> {
+    let field = options.field.as_deref().filter(|&f| f != "price");
+    if let Some(currency) = &options.currency {
+        if field.is_some() || options.venue.is_some() || options.extended_hours {
+            return Err("Converted prices are kept for the consolidated price only".to_string().into());
+        }
+        return match &state.fx {
+            Some(fx) if fx.config().currency == *currency => Ok(&state.converted[k - 1]),
+            _ => Err(format!("Prices of {} are not converted into {}", symbol, currency).into()),
+        };
+    }
+    if options.extended_hours {
+        if field.is_some() || options.venue.is_some() {
+            return Err("Extended hours are kept for the consolidated price only".to_string().into());
+        }
+        return state.extended.get(k - 1)
+            .ok_or_else(|| ServiceError::NotFound(format!("Extended hours are not kept for {}", symbol)));
+    }
+    let (keyed, key) = match (field, &options.venue) {
+        (None, None) => return window(state, k),
+        (Some(_), Some(_)) => return Err("Fields are not kept per venue".to_string().into()),
+        (Some(field), None) => (false, field),
+        (None, Some(venue)) => (true, venue.as_str()),
+    };
+    let windows = if keyed { state.venues.get(key) } else { state.fields.get(key) };
+    windows.and_then(|windows| windows.get(k - 1)).ok_or_else(|| {
+        let kind = if keyed { "Venue" } else { "Field" };
+        ServiceError::NotFound(format!("{} {} not found for {}", kind, key, symbol))
+    })
+}
+
+/// Stats of a window of `n` values rebuilt from its points, computed off
+/// the async runtime.
+async fn partial_stats(
+    points: Batch,
+    n: usize,
+    config: SymbolConfig,
+    last_update: u64,
+    counters: FeedCounters,
+    options: &StatsOptions,
+) -> Result<StatsResponse, ServiceError> {
+    let options = options.clone();
+    tokio::task::spawn_blocking(move || {
+        let mut partial = TradingDataBuffer::new(n);
+        partial.set_ewma_alpha(config.ewma_alpha);
+        partial.set_quantile_config(&config.quantiles);
+        partial.set_fixed_point(config.fixed_point_decimals);
+        partial.add_points(&points);
+        partial.last_update = last_update;
+        let mut stats = partial.get_stats_with(&options);
+        counters.report(&mut stats, &config);
+        stats
+    })
+    .await
+    .map_err(|e| ServiceError::Internal(format!("Stats computation failed: {}", e)))
+}
+
+/// Stats of the symbol's 10^k window, with the symbol-level outlier counts.
+fn symbol_stats(
+    state: &SymbolState,
+    symbol: &str,
+    k: usize,
+    options: &StatsOptions,
+) -> Result<StatsResponse, ServiceError> {
+    let mut stats = stats_window(state, symbol, k, options)?.get_stats_with(options);
+    state.counters.report(&mut stats, &state.config);
+    Ok(stats)
+}
+
+/// Looks up the 10^k window of every symbol, failing on the first unknown one.
+fn aligned_windows<'a>(
+    states: &'a Snapshot,
+    symbols: &[String],
+    k: usize,
+) -> Result<Vec<&'a TradingDataBuffer>, ServiceError> {
+    symbols
+        .iter()
+        .map(|symbol| window(states.get(symbol.as_str()).ok_or(ServiceError::UnknownSymbol)?, k))
+        .collect()
+}
+
+fn validate_stats_options(options: &StatsOptions) -> Result<(), ServiceError> {
+    if options.quantiles.iter().any(|q| !(0.0..=1.0).contains(q)) {
+        return Err("Invalid quantiles input. Only values 0.0-1.0 are accepted.".to_string().into());
+    }
+    if options.periods_per_year.is_some_and(|p| !p.is_finite() || p <= 0.0) {
+        return Err("Invalid periods_per_year input. Only positive values are accepted.".to_string().into());
+    }
+    Ok(())
+}
+
+fn validate_k(k: usize) -> Result<(), ServiceError> {
+    if !(1..=8).contains(&k) {
+        return Err(ServiceError::InvalidK);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::derived::{Constituent, DerivedOp};
+    use crate::expr::FormulaConfig;
+    use crate::fx::FxConfig;
+    use crate::groups::TagFilter;
+    use crate::ranking::Order;
+    use crate::sketch::QuantileSketch;
+
+    const DELTA: f64 = 1e-6;
+
+    fn assert_float_eq(a: f64, b: f64) {
+        assert!((a - b).abs() < DELTA, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn test_add_batch_and_get_stats() {
+        let mut buffer = TradingDataBuffer::new(5);
+        buffer.add_batch(&[1.0, 2.0, 3.0]);
+
+        let stats = buffer.get_stats();
+        assert_float_eq(1.0, stats.min);
+        assert_float_eq(3.0, stats.max);
+        assert_float_eq(3.0, stats.last);
+        assert_float_eq(2.0, stats.avg);
+        assert_float_eq(0.6666667, stats.var);
+        assert_float_eq(2.0, stats.median);
+    }
+
+    #[test]
+    fn test_window_memory_follows_points() {
+        let mut buffer = TradingDataBuffer::new(100_000_000);
+        assert!(buffer.memory_bytes() < 1_000_000, "{}", buffer.memory_bytes());
+
+        buffer.add_batch(&[1.0; 1000]);
+        assert_eq!((0, 0), (buffer.weights.capacity(), buffer.volumes.capacity()));
+        buffer.add_points(&Batch { volumes: Some(vec![5.0]), ..Batch::new(vec![2.0]) });
+        assert_eq!(0, buffer.weights.capacity());
+        assert_eq!((0.0, 5.0), (buffer.volumes.get(0), buffer.volumes.get(1000)));
+        assert_eq!(5.0, buffer.volume.value());
+    }
+
+    #[test]
+    fn test_median_of_sketched_windows() {
+        let mut buffer = TradingDataBuffer::new(10_000);
+        let values: Vec<f64> = (0..20_000).map(|i| 100.0 + (i % 1000) as f64 * 0.01).collect();
+        buffer.add_batch(&values);
+        assert!(buffer.median.is_none());
+        assert!((buffer.median() - 105.0).abs() < 0.1, "{}", buffer.median());
+
+        // Exact quantiles bring back the exact median.
+        buffer.set_quantile_config(&QuantileConfig { from_k: 5, ..QuantileConfig::default() });
+        assert_eq!(104.995, buffer.median());
+    }
+
+    #[test]
+    fn test_buffer_overflow() {
+        let mut buffer = TradingDataBuffer::new(5);
+        buffer.add_batch(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+
+        let stats = buffer.get_stats();
+        assert_float_eq(2.0, stats.min);
+        assert_float_eq(6.0, stats.max);
+        assert_float_eq(6.0, stats.last);
+        assert_float_eq(4.0, stats.avg);
+        assert_float_eq(4.0, stats.median);
+    }
+
+    #[test]
+    fn test_empty_buffer() {
+        let buffer = TradingDataBuffer::new(5);
+        let stats = buffer.get_stats();
+        assert_float_eq(0.0, stats.min);
+        assert_float_eq(0.0, stats.max);
+        assert_float_eq(0.0, stats.last);
+        assert_float_eq(0.0, stats.avg);
+        assert_float_eq(0.0, stats.var);
+        assert_eq!(0, stats.count);
+        assert_eq!(0, stats.last_update);
+    }
+
+    #[test]
+    fn test_single_element() {
+        let mut buffer = TradingDataBuffer::new(5);
+        buffer.add_batch(&[5.0]);
+
+        let stats = buffer.get_stats();
+        assert_float_eq(5.0, stats.min);
+        assert_float_eq(5.0, stats.max);
+        assert_float_eq(5.0, stats.last);
+        assert_float_eq(5.0, stats.avg);
+        assert_float_eq(0.0, stats.var);
+        assert_eq!(1, stats.count);
+        assert!(stats.last_update > 0);
+    }
+
+    #[test]
+    fn test_variance_calculation() {
+        let mut buffer = TradingDataBuffer::new(5);
+        buffer.add_batch(&[2.0, 4.0, 6.0]);
+
+        let stats = buffer.get_stats();
+        assert_float_eq(4.0, stats.avg);
+        assert_float_eq(2.6666667, stats.var);
+        assert_float_eq(1.6329932, stats.std_dev);
+        assert_float_eq(1.2247449, stats.zscore_last);
+    }
+
+    #[test]
+    fn test_skewness_and_kurtosis() {
+        let mut buffer = TradingDataBuffer::new(10);
+        buffer.add_batch(&[2.0, 4.0, 6.0]);
+        let stats = buffer.get_stats();
+        assert_float_eq(0.0, stats.skewness);
+        assert_float_eq(-1.5, stats.kurtosis);
+
+        buffer.add_batch(&[1.0, 1.0, 1.0, 10.0]);
+        let stats = buffer.get_stats();
+        assert!(stats.skewness > 0.0);
+    }
+
+    #[test]
+    fn test_variance_of_large_prices() {
+        let mut buffer = TradingDataBuffer::new(1000);
+        let values: Vec<f64> = (0..5000).map(|i| 50_000.0 + (i % 2) as f64 * 0.01).collect();
+        buffer.add_batch(&values);
+
+        let stats = buffer.get_stats();
+        assert!((stats.var - 0.000025).abs() < 1e-10, "{}", stats.var);
+    }
+
+    #[test]
+    fn test_merged_batches_match_single_values() {
+        // Exact quantiles and median, then a t-digest.
+        for capacity in [100, 10_000] {
+            let mut single = TradingDataBuffer::new(capacity);
+            let mut merged = TradingDataBuffer::new(capacity);
+            let n = capacity * 3 + 30;
+            let values: Vec<f64> = (0..n).map(|i| 100.0 + ((i * 53) % 97) as f64 * 0.25).collect();
+            let weights: Vec<f64> = (0..n).map(|i| 1.0 + (i % 3) as f64).collect();
+            let volumes: Vec<f64> = (0..n).map(|i| ((i * 7) % 5) as f64).collect();
+            let points = |range: std::ops::Range<usize>| Batch {
+                weights: Some(weights[range.clone()].to_vec()),
+                volumes: Some(volumes[range.clone()].to_vec()),
+                ..Batch::new(values[range].to_vec())
+            };
+            for range in [0..30, 30..n] {
+                for i in range.clone() {
+                    single.add_points(&points(i..i + 1));
+                }
+                merged.add_points(&points(range));
+            }
+
+            for on in [SeriesKind::Prices, SeriesKind::Returns] {
+                let options = StatsOptions { quantiles: vec![0.05, 0.5, 0.95], on, ..StatsOptions::default() };
+                let (single, merged) = (single.get_stats_with(&options), merged.get_stats_with(&options));
+                assert_eq!(single.count, merged.count);
+                assert_eq!((single.min, single.max, single.last), (merged.min, merged.max, merged.last));
+                assert_float_eq(single.avg, merged.avg);
+                assert_float_eq(single.var, merged.var);
+                assert_float_eq(single.skewness, merged.skewness);
+                assert_float_eq(single.kurtosis, merged.kurtosis);
+                assert_float_eq(single.realized_vol, merged.realized_vol);
+                if on == SeriesKind::Returns {
+                    continue;
+                }
+                for (single, merged) in single.quantiles.iter().zip(&merged.quantiles) {
+                    assert_float_eq(single.value, merged.value);
+                }
+                assert_float_eq(single.median, merged.median);
+                assert_float_eq(single.vwap.unwrap(), merged.vwap.unwrap());
+                assert_float_eq(single.volume_sum, merged.volume_sum);
+                assert_eq!(single.volume_max, merged.volume_max);
+                assert_float_eq(single.weighted_mean, merged.weighted_mean);
+                assert_float_eq(single.weighted_var, merged.weighted_var);
+                assert_float_eq(single.geometric_mean.unwrap(), merged.geometric_mean.unwrap());
+                assert_float_eq(single.harmonic_mean.unwrap(), merged.harmonic_mean.unwrap());
+                assert_float_eq(single.ewma, merged.ewma);
+                assert_float_eq(single.ew_var, merged.ew_var);
+                assert_eq!((single.max_abs_return, single.max_drawdown), (merged.max_abs_return, merged.max_drawdown));
+                assert_float_eq(single.max_drawdown_pct, merged.max_drawdown_pct);
+                assert_float_eq(single.trend.slope, merged.trend.slope);
+            }
+            assert_eq!(single.return_quantiles(&[0.1, 0.9]), merged.return_quantiles(&[0.1, 0.9]));
+            assert_float_eq(single.gains_losses().0, merged.gains_losses().0);
+            assert_float_eq(single.gains_losses().1, merged.gains_losses().1);
+        }
+    }
+
+    #[test]
+    fn test_avg_after_many_evictions() {
+        let mut buffer = TradingDataBuffer::new(10);
+        for i in 0..100_000 {
+            buffer.add_batch(&[1e9 + (i % 7) as f64 * 0.1, 0.3]);
+        }
+        buffer.add_batch(&[1.0; 10]);
+
+        let stats = buffer.get_stats();
+        assert_eq!(1.0, stats.avg);
+        assert_float_eq(0.0, stats.var);
+    }
+
+    #[test]
+    fn test_max_drawdown() {
+        let mut buffer = TradingDataBuffer::new(4);
+        buffer.add_batch(&[100.0, 50.0, 10.0, 8.0, 12.0, 9.0]);
+
+        let stats = buffer.get_stats();
+        assert_float_eq(3.0, stats.max_drawdown);
+        assert_float_eq(0.25, stats.max_drawdown_pct);
+    }
+
+    #[test]
+    fn test_jump_score() {
+        let mut buffer = TradingDataBuffer::new(5);
+        buffer.add_batch(&[100.0, 100.0, 100.0, 100.0, 80.0]);
+
+        let stats = buffer.get_stats();
+        assert_float_eq(0.2, stats.max_abs_return);
+        // Returns 0, 0, 0, -0.2: std 0.2 * sqrt(3) / 4.
+        assert_float_eq(4.0 / 3.0f64.sqrt(), stats.jump_score);
+
+        buffer.add_batch(&[80.0; 5]);
+        assert_float_eq(0.0, buffer.get_stats().jump_score);
+    }
+
+    #[test]
+    fn test_trend_follows_window() {
+        let mut buffer = TradingDataBuffer::new(4);
+        buffer.add_batch(&[50.0, 40.0, 1.0, 3.0, 5.0, 7.0]);
+
+        let trend = buffer.get_stats().trend;
+        assert_float_eq(2.0, trend.slope);
+        assert_float_eq(1.0, trend.intercept);
+        assert_float_eq(1.0, trend.r_squared);
+
+        buffer.add_batch(&[5.0]);
+        let trend = buffer.get_stats().trend;
+        assert_float_eq(0.8, trend.slope);
+        assert_float_eq(3.8, trend.intercept);
+        assert!(trend.r_squared < 1.0);
+    }
+
+    #[test]
+    fn test_returns_stats() {
+        let mut buffer = TradingDataBuffer::new(3);
+        buffer.add_batch(&[100.0, 110.0, 99.0, 99.0]);
+
+        let options = StatsOptions { on: SeriesKind::Returns, ..StatsOptions::default() };
+        let stats = buffer.get_stats_with(&options);
+        assert_eq!(2, stats.count);
+        assert_float_eq(-0.1, stats.min);
+        assert_float_eq(0.0, stats.max);
+        assert_float_eq(0.0, stats.last);
+        assert_float_eq(-0.05, stats.avg);
+        assert_float_eq(0.0025, stats.var);
+
+        let options = StatsOptions { on: SeriesKind::LogReturns, ..StatsOptions::default() };
+        let stats = buffer.get_stats_with(&options);
+        assert_float_eq((99.0f64 / 110.0).ln(), stats.min);
+    }
+
+    #[test]
+    fn test_realized_vol() {
+        let mut buffer = TradingDataBuffer::new(10);
+        let e = std::f64::consts::E;
+        buffer.add_batch(&[1.0, e, 1.0]);
+
+        let stats = buffer.get_stats();
+        assert_float_eq(2.0f64.sqrt(), stats.realized_vol);
+
+        let options = StatsOptions { periods_per_year: Some(252.0), ..StatsOptions::default() };
+        let stats = buffer.get_stats_with(&options);
+        assert_float_eq(252.0f64.sqrt(), stats.realized_vol);
+    }
+
+    #[test]
+    fn test_weighted_stats() {
+        let mut buffer = TradingDataBuffer::new(3);
+        let batch = Batch {
+            values: vec![100.0, 1.0, 2.0, 4.0],
+            weights: Some(vec![9.0, 1.0, 3.0, 2.0]),
+            ..Batch::default()
+        };
+        buffer.add_points(&batch);
+
+        let stats = buffer.get_stats();
+        assert_float_eq(2.5, stats.weighted_mean);
+        assert_float_eq(1.25, stats.weighted_var);
+        assert_float_eq(7.0 / 3.0, stats.avg);
+    }
+
+    #[tokio::test]
+    async fn test_add_batch_validates_weights() {
+        let service = TradingDataService::new();
+        let batch = Batch { weights: Some(vec![1.0]), ..Batch::new(vec![1.0, 2.0]) };
+        assert!(service.add_batch("AAPL".to_string(), batch).await.is_err());
+        let batch = Batch { weights: Some(vec![1.0, -1.0]), ..Batch::new(vec![1.0, 2.0]) };
+        assert!(service.add_batch("AAPL".to_string(), batch).await.is_err());
+    }
+
+    #[test]
+    fn test_timestamps_follow_window() {
+        let mut buffer = TradingDataBuffer::new(2);
+        let batch = Batch { timestamps: Some(vec![10, 20, 30]), ..Batch::new(vec![1.0, 2.0, 3.0]) };
+        buffer.add_points(&batch);
+
+        let stats = buffer.get_stats();
+        assert_eq!(20, stats.first_timestamp);
+        assert_eq!(30, stats.last_timestamp);
+        assert_eq!(Some((20, 30)), buffer.time_span());
+    }
+
+    #[test]
+    fn test_time_window_evicts_by_age() {
+        let mut buffer: TradingDataBuffer = TradingDataBuffer::with_max_age(100, 1000);
+        let batch = Batch { timestamps: Some(vec![0, 50, 100, 160]), ..Batch::new(vec![1.0, 2.0, 3.0, 4.0]) };
+        buffer.add_points(&batch);
+
+        let stats = buffer.get_stats();
+        assert_eq!(2, stats.count);
+        assert_float_eq(3.0, stats.min);
+        assert_eq!(100, stats.first_timestamp);
+    }
+
+    #[tokio::test]
+    async fn test_service_time_windows() {
+        let service = TradingDataService::new();
+        let batch = Batch { timestamps: Some(vec![0, 5_000_000_000]), ..Batch::new(vec![1.0, 2.0]) };
+        service.add_batch("AAPL".to_string(), batch).await.unwrap();
+        let config = SymbolConfig { time_windows_ms: vec![1_000], ..SymbolConfig::default() };
+        service.set_symbol_config("AAPL".to_string(), config).await.unwrap();
+
+        let options = StatsOptions::default();
+        let stats = service.get_stats_over("AAPL".to_string(), 1_000, &options).await.unwrap();
+        assert_eq!(1, stats.count);
+
+        let batch = Batch { timestamps: Some(vec![5_500_000_000]), ..Batch::new(vec![3.0]) };
+        service.add_batch("AAPL".to_string(), batch).await.unwrap();
+        let stats = service.get_stats_over("AAPL".to_string(), 1_000, &options).await.unwrap();
+        assert_eq!(2, stats.count);
+        assert_float_eq(2.5, stats.avg);
+        assert!(service.get_stats_over("AAPL".to_string(), 60_000, &options).await.is_err());
+    }
+
+    #[test]
+    fn test_batch_from_points() {
+        let points = [
+            Point { value: 1.0, weight: None, volume: Some(5.0), timestamp: None, sequence: None, id: None },
+            Point { value: 2.0, weight: None, volume: None, timestamp: None, sequence: None, id: None },
+        ];
+        let batch = Batch::from_points(&points).unwrap();
+        assert_eq!(vec![1.0, 2.0], batch.values);
+        assert_eq!(Some(vec![5.0, 0.0]), batch.volumes);
+        assert_eq!(None, batch.weights);
+        assert_eq!(None, batch.timestamps);
+
+        let mixed = [Point { timestamp: Some(1), ..points[0].clone() }, points[1].clone()];
+        assert!(Batch::from_points(&mixed).is_err());
+    }
+
+    #[test]
+    fn test_element_types() {
+        let mut single = TradingDataBuffer::<f32>::with_capacity(3);
+        single.add_batch(&[0.1, 0.2, 0.3, 0.4]);
+        let stats = single.get_stats();
+        assert_eq!(0.4f32 as f64, stats.last);
+        assert!((stats.avg - 0.3).abs() < 1e-7);
+
+        // Integer windows round what they store, e.g. prices in ticks.
+        let mut ticks = TradingDataBuffer::<i64>::with_capacity(10);
+        ticks.add_batch(&[101.0, 102.4, 102.6]);
+        assert_eq!(vec![101.0, 102.0, 103.0], ticks.values().collect::<Vec<_>>());
+        assert_float_eq(2.0, ticks.get_stats().max - ticks.get_stats().min);
+    }
+
+    #[test]
+    fn test_vwap() {
+        let mut buffer = TradingDataBuffer::new(3);
+        buffer.add_batch(&[10.0]);
+        assert_eq!(None, buffer.get_stats().vwap);
 
-use tokio::sync::RwLock;
+        let batch = Batch { volumes: Some(vec![100.0, 300.0, 0.0]), ..Batch::new(vec![20.0, 12.0, 99.0]) };
+        buffer.add_points(&batch);
+        assert_float_eq(14.0, buffer.get_stats().vwap.unwrap());
 
-pub struct TradingDataBuffer {
-    values: VecDeque<f64>,
-    capacity: usize,
-    min: f64,
-    max: f64,
-    sum: f64,
-    sum_squares: f64,
-}
+        buffer.add_points(&Batch { volumes: Some(vec![600.0]), ..Batch::new(vec![11.0]) });
+        let stats = buffer.get_stats();
+        assert_float_eq(10_200.0 / 900.0, stats.vwap.unwrap());
+        assert_float_eq(900.0, stats.volume_sum);
+        assert_float_eq(300.0, stats.volume_avg);
+        assert_float_eq(600.0, stats.volume_max);
+    }
 
-impl TradingDataBuffer {
-    pub fn new(capacity: usize) -> Self {
-        TradingDataBuffer {
-            values: VecDeque::with_capacity(capacity),
-            capacity,
-            min: f64::MAX,
-            max: f64::MIN,
-            sum: 0.0,
-            sum_squares: 0.0,
-        }
+    #[test]
+    fn test_geometric_and_harmonic_means() {
+        let mut buffer = TradingDataBuffer::new(3);
+        buffer.add_batch(&[-1.0, 1.0, 2.0]);
+        assert_eq!(None, buffer.get_stats().geometric_mean);
+
+        buffer.add_batch(&[4.0]);
+        let stats = buffer.get_stats();
+        assert_float_eq(2.0, stats.geometric_mean.unwrap());
+        assert_float_eq(12.0 / 7.0, stats.harmonic_mean.unwrap());
     }
 
-    pub fn add_batch(&mut self, new_values: &[f64]) {
-        for &value in new_values {
-            self.add(value);
-        }
+    #[test]
+    fn test_ewma() {
+        let mut buffer = TradingDataBuffer::new(3);
+        buffer.set_ewma_alpha(Some(0.5));
+        buffer.add_batch(&[10.0, 20.0]);
+
+        let stats = buffer.get_stats();
+        assert_float_eq(15.0, stats.ewma);
+        assert_float_eq(25.0, stats.ew_var);
     }
 
-    fn add(&mut self, value: f64) {
-        if self.values.len() >= self.capacity {
-            let old_value = self.values.pop_front().unwrap();
-            self.sum -= old_value;
-            self.sum_squares -= old_value * old_value;
-            if old_value == self.min || old_value == self.max {
-                self.recalculate_min_max();
-            }
-        }
+    #[tokio::test]
+    async fn test_symbol_config_sets_ewma_alpha() {
+        let service = TradingDataService::new();
+        let config = SymbolConfig { ewma_alpha: Some(1.0), ..SymbolConfig::default() };
+        service.set_symbol_config("AAPL".to_string(), config).await.unwrap();
+        service.add_batch_values("AAPL".to_string(), vec![1.0, 5.0]).await.unwrap();
 
-        self.values.push_back(value);
-        self.sum += value;
-        self.sum_squares += value * value;
-        self.min = self.min.min(value);
-        self.max = self.max.max(value);
+        let stats = service.get_stats("AAPL".to_string(), 8).await.unwrap();
+        assert_float_eq(5.0, stats.ewma);
+
+        let invalid = SymbolConfig { ewma_alpha: Some(1.5), ..SymbolConfig::default() };
+        assert!(service.set_symbol_config("AAPL".to_string(), invalid).await.is_err());
     }
 
-    fn recalculate_min_max(&mut self) {
-        let (min, max) = self.values.iter().fold((f64::MAX, f64::MIN), |(min, max), &v| {
-            (min.min(v), max.max(v))
-        });
-        self.min = min;
-        self.max = max;
+    #[tokio::test]
+    async fn test_service_correlation() {
+        let service = TradingDataService::new();
+        service.add_batch_values("A".to_string(), vec![1.0, 2.0, 3.0]).await.unwrap();
+        service.add_batch_values("B".to_string(), vec![2.0, 4.0, 6.0]).await.unwrap();
+
+        let result = service.get_correlation("A".to_string(), "B".to_string(), 1).await.unwrap();
+        assert_eq!(3, result.count);
+        assert_float_eq(1.0, result.correlation.unwrap());
+        assert!(service.get_correlation("A".to_string(), "C".to_string(), 1).await.is_err());
+
+        let symbols = vec!["A".to_string(), "B".to_string()];
+        let result = service.get_covariance(symbols, 1).await.unwrap();
+        assert_eq!(3, result.count);
+        assert_float_eq(4.0 / 3.0, result.matrix[0][1]);
     }
 
-    pub fn get_stats(&self) -> StatsResponse {
-        if self.values.is_empty() {
-            return StatsResponse::default();
-        }
-        let avg = self.sum / self.values.len() as f64;
-        let variance = (self.sum_squares / self.values.len() as f64) - (avg * avg);
-        let last = *self.values.back().unwrap();
-        StatsResponse {
-            min: self.min,
-            max: self.max,
-            last,
-            avg,
-            var: variance,
+    #[tokio::test]
+    async fn test_service_bars() {
+        let service = TradingDataService::new();
+        service.add_batch_values("AAPL".to_string(), vec![3.0, 5.0, 1.0, 2.0]).await.unwrap();
+
+        let bars = service.get_bars("AAPL".to_string(), 60_000, 10).await.unwrap();
+        assert_eq!(1, bars.len());
+        assert_eq!((3.0, 5.0, 1.0, 2.0, 4), (bars[0].open, bars[0].high, bars[0].low, bars[0].close, bars[0].ticks));
+        assert!(service.get_bars("AAPL".to_string(), 7_000, 10).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_service_macd_config() {
+        let service = TradingDataService::new();
+        let config = SymbolConfig {
+            macd: MacdConfig { fast: 1, slow: 3, signal: 1 },
+            ..SymbolConfig::default()
+        };
+        service.set_symbol_config("AAPL".to_string(), config).await.unwrap();
+        service.add_batch_values("AAPL".to_string(), vec![10.0, 20.0]).await.unwrap();
+
+        let macd = service.get_macd("AAPL".to_string()).await.unwrap();
+        assert_float_eq(5.0, macd.macd);
+
+        let invalid = SymbolConfig {
+            macd: MacdConfig { fast: 26, slow: 12, signal: 9 },
+            ..SymbolConfig::default()
+        };
+        assert!(service.set_symbol_config("AAPL".to_string(), invalid).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_service_fields() {
+        let service = TradingDataService::new();
+        let config = SymbolConfig { fields: vec!["size".to_string()], ..SymbolConfig::default() };
+        service.set_symbol_config("AAPL".to_string(), config).await.unwrap();
+
+        let mut batch = Batch::new(vec![100.0, 101.0]);
+        batch.fields.insert("size".to_string(), vec![300.0, 500.0]);
+        service.add_batch("AAPL".to_string(), batch).await.unwrap();
+
+        let options = StatsOptions { field: Some("size".to_string()), ..StatsOptions::default() };
+        let stats = service.get_stats_with("AAPL".to_string(), 1, &options).await.unwrap();
+        assert_float_eq(400.0, stats.avg);
+        assert_float_eq(100.5, service.get_stats("AAPL".to_string(), 1).await.unwrap().avg);
+
+        let mut unknown = Batch::new(vec![102.0]);
+        unknown.fields.insert("imbalance".to_string(), vec![0.1]);
+        assert!(service.add_batch("AAPL".to_string(), unknown).await.is_err());
+        let options = StatsOptions { field: Some("imbalance".to_string()), ..StatsOptions::default() };
+        assert!(service.get_stats_with("AAPL".to_string(), 1, &options).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_service_sequence_gaps() {
+        let service = TradingDataService::new();
+        let sequenced = |sequences: Vec<u64>| Batch {
+            sequences: Some(sequences.clone()),
+            ..Batch::new(vec![100.0; sequences.len()])
+        };
+        service.add_batch("AAPL".to_string(), sequenced(vec![1, 2, 3])).await.unwrap();
+        service.add_batch("AAPL".to_string(), sequenced(vec![3, 6])).await.unwrap();
+
+        let stats = service.get_stats("AAPL".to_string(), 1).await.unwrap();
+        assert_eq!(1, stats.sequence_gaps);
+        assert_eq!(2, stats.sequences_missing);
+        assert_eq!(1, stats.sequence_duplicates);
+        assert_eq!(5, stats.count);
+    }
+
+    #[tokio::test]
+    async fn test_service_drops_duplicate_ids() {
+        let service = TradingDataService::new();
+        let with_ids = |ids: &[&str]| Batch {
+            ids: Some(ids.iter().map(|id| id.to_string()).collect()),
+            ..Batch::new((0..ids.len()).map(|i| 100.0 + i as f64).collect())
+        };
+        service.add_batch("AAPL".to_string(), with_ids(&["t1", "t2", "t2"])).await.unwrap();
+        service.add_batch("AAPL".to_string(), with_ids(&["t1", "t3"])).await.unwrap();
+
+        let stats = service.get_stats("AAPL".to_string(), 1).await.unwrap();
+        assert_eq!(3, stats.count);
+        assert_eq!(2, stats.duplicates_dropped);
+        assert_float_eq(101.0, stats.last);
+
+        let config = SymbolConfig { dedup_window: 0, ..SymbolConfig::default() };
+        service.set_symbol_config("AAPL".to_string(), config).await.unwrap();
+        service.add_batch("AAPL".to_string(), with_ids(&["t1"])).await.unwrap();
+        assert_eq!(4, service.get_stats("AAPL".to_string(), 1).await.unwrap().count);
+    }
+
+    #[tokio::test]
+    async fn test_service_reorders_late_ticks() {
+        let service = TradingDataService::new();
+        let config = SymbolConfig { reorder_slack_ms: 50, ..SymbolConfig::default() };
+        service.set_symbol_config("AAPL".to_string(), config).await.unwrap();
+        let ms = 1_000_000;
+        let timed = |values: Vec<f64>, timestamps: Vec<u64>| Batch { timestamps: Some(timestamps), ..Batch::new(values) };
+        service.add_batch("AAPL".to_string(), timed(vec![100.0, 102.0], vec![0, 20 * ms])).await.unwrap();
+        service.add_batch("AAPL".to_string(), timed(vec![101.0, 103.0], vec![10 * ms, 100 * ms])).await.unwrap();
+
+        // Everything up to t=50ms has been released, in timestamp order.
+        let stats = service.get_stats("AAPL".to_string(), 1).await.unwrap();
+        assert_eq!(3, stats.count);
+        assert_float_eq(102.0, stats.last);
+        assert_float_eq(1.0, stats.trend.slope);
+
+        service.add_batch("AAPL".to_string(), timed(vec![99.0], vec![5 * ms])).await.unwrap();
+        let stats = service.get_stats("AAPL".to_string(), 1).await.unwrap();
+        assert_eq!(1, stats.late_dropped);
+
+        let config = SymbolConfig::default();
+        service.set_symbol_config("AAPL".to_string(), config).await.unwrap();
+        assert_float_eq(103.0, service.get_stats("AAPL".to_string(), 1).await.unwrap().last);
+    }
+
+    #[tokio::test]
+    async fn test_service_fixed_point() {
+        let service = TradingDataService::new();
+        let config = SymbolConfig { fixed_point_decimals: Some(8), ..SymbolConfig::default() };
+        service.set_symbol_config("EURUSD".to_string(), config).await.unwrap();
+        let batch = Batch { volumes: Some(vec![3.0, 1.0]), ..Batch::new(vec![0.1, 0.2]) };
+        service.add_batch("EURUSD".to_string(), batch).await.unwrap();
+        service.add_batch_values("EURUSD".to_string(), vec![0.123456789]).await.unwrap();
+
+        let stats = service.get_stats("EURUSD".to_string(), 1).await.unwrap();
+        assert_eq!(0.12345679, stats.last);
+        assert_eq!(0.42345679 / 3.0, stats.avg);
+        assert_eq!(Some(0.125), stats.vwap);
+        assert_eq!(4.0, stats.volume_sum);
+    }
+
+    #[tokio::test]
+    async fn test_service_symbol_meta() {
+        let service = TradingDataService::new();
+        assert!(service.get_symbol_meta("AAPL".to_string()).await.is_err());
+        let meta = SymbolMeta {
+            tick_size: Some(0.01),
+            currency: Some("USD".to_string()),
+            reject_off_tick: true,
+            ..SymbolMeta::default()
+        };
+        service.set_symbol_meta("AAPL".to_string(), meta.clone()).await.unwrap();
+        assert_eq!(meta, service.get_symbol_meta("AAPL".to_string()).await.unwrap());
+
+        service.add_batch_values("AAPL".to_string(), vec![100.01, 100.02]).await.unwrap();
+        assert!(service.add_batch_values("AAPL".to_string(), vec![100.03, 100.035]).await.is_err());
+        assert_eq!(2, service.get_stats("AAPL".to_string(), 1).await.unwrap().count);
+
+        service.delete_symbol_meta("AAPL".to_string()).await.unwrap();
+        service.add_batch_values("AAPL".to_string(), vec![100.035]).await.unwrap();
+        assert_eq!(None, service.get_symbol_meta("AAPL".to_string()).await.unwrap().tick_size);
+    }
+
+    #[tokio::test]
+    async fn test_ranking() {
+        let service = TradingDataService::new();
+        service.add_batch_values("A".to_string(), vec![1.0, 5.0]).await.unwrap();
+        service.add_batch_values("B".to_string(), vec![1.0, 2.0]).await.unwrap();
+        service.add_batch_values("C".to_string(), vec![1.0, 9.0]).await.unwrap();
+        service.set_symbol_meta("D".to_string(), SymbolMeta::default()).await.unwrap();
+
+        let ranked = service.get_ranking("var", 1, Order::Desc, 2).await.unwrap();
+        assert_eq!(vec!["C", "A"], ranked.iter().map(|m| m.symbol.as_str()).collect::<Vec<_>>());
+        assert_float_eq(16.0, ranked[0].value);
+        let ranked = service.get_ranking("max", 1, Order::Asc, 10).await.unwrap();
+        assert_eq!(vec!["B", "A", "C"], ranked.iter().map(|m| m.symbol.as_str()).collect::<Vec<_>>());
+
+        // vwap is undefined without volumes.
+        assert!(service.get_ranking("vwap", 1, Order::Desc, 10).await.unwrap().is_empty());
+        assert!(service.get_ranking("quantiles", 1, Order::Desc, 10).await.is_err());
+        assert_eq!(ServiceError::InvalidK, service.get_ranking("var", 9, Order::Desc, 10).await.unwrap_err());
+    }
+
+    #[tokio::test]
+    async fn test_group_stats() {
+        let service = TradingDataService::new();
+        let tag = |sector: &str| BTreeMap::from([("sector".to_string(), sector.to_string())]);
+        for (symbol, sector, values) in [("A", "tech", [1.0, 2.0]), ("B", "tech", [1.0, 1.5]), ("C", "energy", [10.0, 5.0])] {
+            service.add_batch_values(symbol.to_string(), values.to_vec()).await.unwrap();
+            service.set_symbol_tags(symbol.to_string(), tag(sector)).await.unwrap();
         }
+        service.set_symbol_tags("D".to_string(), tag("tech")).await.unwrap();
+        service.add_batch_values("E".to_string(), vec![1.0]).await.unwrap();
+
+        let filter = TagFilter::parse("sector").unwrap();
+        let groups = service.get_group_stats(&filter, RankMetric::PctChange, 1).await.unwrap();
+        let tags: Vec<(&str, usize)> = groups.iter().map(|group| (group.tag.as_str(), group.count)).collect();
+        assert_eq!(vec![("sector=energy", 1), ("sector=tech", 2)], tags);
+        assert_float_eq(0.75, groups[1].mean);
+        assert_float_eq(-0.5, groups[0].min);
+
+        let filter = TagFilter::parse("sector=tech").unwrap();
+        assert_eq!(1, service.get_group_stats(&filter, RankMetric::Range, 1).await.unwrap().len());
+
+        // Replacing the meta replaces its tags too.
+        let meta = SymbolMeta { tick_size: Some(0.5), ..SymbolMeta::default() };
+        service.set_symbol_meta("A".to_string(), meta).await.unwrap();
+        assert!(service.get_symbol_meta("A".to_string()).await.unwrap().tags.is_empty());
+        let long = BTreeMap::from([("sector".to_string(), "x".repeat(65))]);
+        assert!(service.set_symbol_tags("A".to_string(), long).await.is_err());
     }
-}
 
-#[derive(Debug, serde::Serialize, serde::Deserialize)]
-pub struct StatsResponse {
-    pub min: f64,
-    pub max: f64,
-    pub last: f64,
-    pub avg: f64,
-    pub var: f64,
-}
+    #[tokio::test]
+    async fn test_service_aliases() {
+        let service = TradingDataService::new();
+        service.set_alias("BRK-B".to_string(), "BRK.B".to_string()).await.unwrap();
+        service.set_alias("BRKB".to_string(), "BRK-B".to_string()).await.unwrap();
+        assert_eq!(Some(&"BRK.B".to_string()), service.aliases().get("BRKB"));
 
-impl Default for StatsResponse {
-    fn default() -> Self {
-        StatsResponse {
-            min: 0.0,
-            max: 0.0,
-            last: 0.0,
-            avg: 0.0,
-            var: 0.0,
+        service.add_batch_values("BRK-B".to_string(), vec![400.0]).await.unwrap();
+        service.add_batch_values("BRKB".to_string(), vec![402.0]).await.unwrap();
+        let stats = service.get_stats("BRK.B".to_string(), 1).await.unwrap();
+        assert_eq!(2, stats.count);
+        assert_float_eq(401.0, service.get_stats("BRKB".to_string(), 1).await.unwrap().avg);
+
+        // A symbol with windows or aliases of its own can't become an alias.
+        assert!(service.set_alias("BRK.B".to_string(), "BRKB".to_string()).await.is_err());
+        service.add_batch_values("AAPL".to_string(), vec![1.0]).await.unwrap();
+        assert!(service.set_alias("AAPL".to_string(), "BRK.B".to_string()).await.is_err());
+
+        service.remove_alias("BRKB").unwrap();
+        assert!(service.get_stats("BRKB".to_string(), 1).await.is_err());
+        assert!(service.remove_alias("BRKB").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_service_trading_session() {
+        use crate::calendar::{TimeZone, Weekday};
+
+        let service = TradingDataService::new();
+        let session = SessionConfig {
+            start: "09:30".to_string(),
+            end: "16:00".to_string(),
+            time_zone: TimeZone::NewYork,
+            days: vec![Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri],
+            outside: SessionAction::Extended,
+        };
+        let config = SymbolConfig { session: Some(session), ..SymbolConfig::default() };
+        service.set_symbol_config("AAPL".to_string(), config).await.unwrap();
+
+        // Tuesday 2024-10-15 at 08:00, 10:00 and 17:00 New York time (UTC-4).
+        let at = |hour: u64| (1_728_950_400 + hour * 3600) * 1_000_000_000;
+        let batch = Batch { timestamps: Some(vec![at(12), at(14), at(21)]), ..Batch::new(vec![99.0, 100.0, 101.0]) };
+        service.add_batch("AAPL".to_string(), batch).await.unwrap();
+
+        let stats = service.get_stats("AAPL".to_string(), 1).await.unwrap();
+        assert_eq!(1, stats.count);
+        assert_float_eq(100.0, stats.last);
+        assert_eq!(2, stats.out_of_session);
+        let extended = StatsOptions { extended_hours: true, ..StatsOptions::default() };
+        let stats = service.get_stats_with("AAPL".to_string(), 1, &extended).await.unwrap();
+        assert_eq!(2, stats.count);
+        assert_float_eq(100.0, stats.avg);
+    }
+
+    #[tokio::test]
+    async fn test_service_staleness() {
+        let service = TradingDataService::new();
+        let config = SymbolConfig { stale_after_ms: Some(20), ..SymbolConfig::default() };
+        service.set_symbol_config("AAPL".to_string(), config).await.unwrap();
+        let stats = service.get_stats("AAPL".to_string(), 1).await.unwrap();
+        assert!(stats.is_stale);
+        assert_eq!(None, stats.age_ms);
+
+        service.add_batch_values("AAPL".to_string(), vec![100.0]).await.unwrap();
+        let stats = service.get_stats("AAPL".to_string(), 1).await.unwrap();
+        assert!(!stats.is_stale);
+        assert!(stats.age_ms.unwrap() < 20);
+
+        tokio::time::sleep(std::time::Duration::from_millis(30)).await;
+        assert!(service.get_stats("AAPL".to_string(), 1).await.unwrap().is_stale);
+    }
+
+    #[tokio::test]
+    async fn test_service_derived_symbols() {
+        let service = TradingDataService::new();
+        service.add_batch_values("A".to_string(), vec![10.0]).await.unwrap();
+        let config = DerivedConfig { op: DerivedOp::Spread, legs: ["A".to_string(), "B".to_string()] };
+        service.set_derived("A-B".to_string(), config.clone()).await.unwrap();
+        assert_eq!(0, service.get_stats("A-B".to_string(), 1).await.unwrap().count);
+
+        service.add_batch_values("B".to_string(), vec![4.0, 3.0]).await.unwrap();
+        service.add_batch_values("A".to_string(), vec![12.0]).await.unwrap();
+        let stats = service.get_stats("A-B".to_string(), 1).await.unwrap();
+        assert_eq!(3, stats.count);
+        assert_eq!(9.0, stats.last);
+        assert_eq!(6.0, stats.min);
+
+        assert!(service.add_batch_values("A-B".to_string(), vec![1.0]).await.is_err());
+        let chained = DerivedConfig { op: DerivedOp::Ratio, legs: ["A-B".to_string(), "A".to_string()] };
+        assert!(service.set_derived("X".to_string(), chained).await.is_err());
+        assert_eq!(config, service.get_derived("A-B".to_string()).await.unwrap());
+
+        service.remove_derived("A-B".to_string()).await.unwrap();
+        service.add_batch_values("A".to_string(), vec![20.0]).await.unwrap();
+        assert_eq!(9.0, service.get_stats("A-B".to_string(), 1).await.unwrap().last);
+        assert!(service.add_batch_values("A-B".to_string(), vec![1.0]).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_service_baskets() {
+        let service = TradingDataService::new();
+        let constituent = |symbol: &str, weight| Constituent { symbol: symbol.to_string(), weight };
+        let config = BasketConfig {
+            constituents: vec![constituent("A", 1.0), constituent("B", 2.0), constituent("C", 0.5)],
+            divisor: 2.0,
+        };
+        service.set_basket("IDX".to_string(), config.clone()).await.unwrap();
+        service.add_batch_values("A".to_string(), vec![10.0]).await.unwrap();
+        service.add_batch_values("B".to_string(), vec![20.0]).await.unwrap();
+        assert_eq!(0, service.get_stats("IDX".to_string(), 1).await.unwrap().count);
+
+        service.add_batch_values("C".to_string(), vec![40.0]).await.unwrap();
+        service.add_batch_values("A".to_string(), vec![14.0]).await.unwrap();
+        let stats = service.get_stats("IDX".to_string(), 1).await.unwrap();
+        assert_eq!(2, stats.count);
+        assert_eq!(37.0, stats.last);
+        assert_eq!(config, service.get_basket("IDX".to_string()).await.unwrap());
+        assert!(service.get_derived("IDX".to_string()).await.is_err());
+        assert_eq!(1, service.baskets().await.len());
+    }
+
+    #[tokio::test]
+    async fn test_service_formulas() {
+        let service = TradingDataService::new();
+        let config = SymbolConfig {
+            fields: vec!["bid".to_string(), "ask".to_string()],
+            formulas: vec![
+                FormulaConfig { name: "mid".to_string(), expression: "(bid + ask) / 2".to_string() },
+                FormulaConfig { name: "change".to_string(), expression: "price - prev(price)".to_string() },
+            ],
+            ..SymbolConfig::default()
+        };
+        service.set_symbol_config("AAPL".to_string(), config).await.unwrap();
+        let batch = Batch {
+            values: vec![100.0, 101.5, 101.0],
+            fields: HashMap::from([
+                ("bid".to_string(), vec![99.0, 101.0, 100.5]),
+                ("ask".to_string(), vec![101.0, 102.0, 101.5]),
+            ]),
+            ..Batch::default()
+        };
+        service.add_batch("AAPL".to_string(), batch).await.unwrap();
+
+        let options = |field: &str| StatsOptions { field: Some(field.to_string()), ..StatsOptions::default() };
+        let mid = service.get_stats_with("AAPL".to_string(), 1, &options("mid")).await.unwrap();
+        assert_eq!(3, mid.count);
+        assert_eq!(101.0, mid.last);
+        let change = service.get_stats_with("AAPL".to_string(), 1, &options("change")).await.unwrap();
+        assert_eq!(2, change.count);
+        assert_eq!(-0.5, change.last);
+
+        let mid_batch = Batch { fields: HashMap::from([("mid".to_string(), vec![1.0])]), ..Batch::new(vec![1.0]) };
+        assert!(service.add_batch("AAPL".to_string(), mid_batch).await.is_err());
+        let bad = SymbolConfig {
+            formulas: vec![FormulaConfig { name: "x".to_string(), expression: "ln(".to_string() }],
+            ..SymbolConfig::default()
+        };
+        assert!(service.set_symbol_config("AAPL".to_string(), bad).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_service_fx_conversion() {
+        let service = TradingDataService::new();
+        service.add_batch_values("SAP".to_string(), vec![100.0]).await.unwrap();
+        let config = FxConfig { rate_symbol: "EURUSD".to_string(), currency: "USD".to_string(), invert: false };
+        service.set_fx("SAP".to_string(), config.clone()).await.unwrap();
+        service.add_batch_values("SAP".to_string(), vec![101.0]).await.unwrap();
+        service.add_batch_values("EURUSD".to_string(), vec![1.1, 1.2]).await.unwrap();
+        service.add_batch_values("SAP".to_string(), vec![100.0, 110.0]).await.unwrap();
+
+        let usd = StatsOptions { currency: Some("USD".to_string()), ..StatsOptions::default() };
+        let stats = service.get_stats_with("SAP".to_string(), 1, &usd).await.unwrap();
+        assert_eq!(2, stats.count);
+        assert_float_eq(132.0, stats.last);
+        assert_float_eq(120.0, stats.min);
+        assert_eq!(4, service.get_stats("SAP".to_string(), 1).await.unwrap().count);
+        let gbp = StatsOptions { currency: Some("GBP".to_string()), ..StatsOptions::default() };
+        assert!(service.get_stats_with("SAP".to_string(), 1, &gbp).await.is_err());
+        assert_eq!(config, service.get_fx("SAP".to_string()).await.unwrap());
+
+        service.remove_fx("SAP".to_string()).await.unwrap();
+        assert!(service.get_stats_with("SAP".to_string(), 1, &usd).await.is_err());
+        assert!(service.set_fx("EURUSD".to_string(), FxConfig { rate_symbol: "EURUSD".to_string(), ..config }).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_service_remove_symbol() {
+        let service = TradingDataService::new();
+        service.add_batch_values("AAPL".to_string(), (0..1000).map(|i| i as f64).collect()).await.unwrap();
+        let freed = service.remove_symbol("AAPL".to_string()).await.unwrap();
+        assert!(freed > 1000 * std::mem::size_of::<f64>());
+        assert_eq!(0, service.symbol_count().await);
+        assert!(service.get_stats("AAPL".to_string(), 1).await.is_err());
+        assert!(service.remove_symbol("AAPL".to_string()).await.is_err());
+
+        let config = DerivedConfig { op: DerivedOp::Spread, legs: ["A".to_string(), "B".to_string()] };
+        service.set_derived("A-B".to_string(), config).await.unwrap();
+        assert!(service.remove_symbol("A".to_string()).await.is_err());
+        service.remove_symbol("A-B".to_string()).await.unwrap();
+        service.remove_symbol("A".to_string()).await.unwrap();
+        service.add_batch_values("B".to_string(), vec![1.0]).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_service_list_symbols() {
+        let service = TradingDataService::new();
+        for symbol in ["MSFT", "AAPL", "AMZN", "GOOG"] {
+            service.add_batch_values(symbol.to_string(), vec![1.0, 2.0]).await.unwrap();
         }
+        let page = service.list_symbols(None, 1, 2).await.unwrap();
+        assert_eq!(4, page.total);
+        let names: Vec<&str> = page.symbols.iter().map(|s| s.symbol.as_str()).collect();
+        assert_eq!(vec!["AMZN", "GOOG"], names);
+        assert_eq!(2, page.symbols[0].count);
+        assert!(page.symbols[0].last_update > 0);
+
+        let page = service.list_symbols(Some("A"), 0, 10).await.unwrap();
+        assert_eq!(2, page.total);
+        assert_eq!("AAPL", page.symbols[0].symbol);
+        assert!(service.list_symbols(None, 0, 0).await.is_err());
     }
-}
 
-pub struct TradingDataService {
-    buffers: Arc<RwLock<std::collections::HashMap<String, Vec<TradingDataBuffer>>>>,
-}
+    #[tokio::test]
+    async fn test_service_all_stats() {
+        let service = TradingDataService::new();
+        service.add_batch_values("AAPL".to_string(), (1..=20).map(|i| i as f64).collect()).await.unwrap();
+        let all = service.get_all_stats("AAPL".to_string(), &StatsOptions::default()).await.unwrap();
+        let counts: Vec<usize> = all.iter().map(|s| s.count).collect();
+        assert_eq!(vec![10, 20, 20, 20, 20, 20, 20, 20], counts);
+        assert_eq!(15.5, all[0].avg);
+        assert_eq!(10.5, all[7].avg);
+        assert!(service.get_all_stats("MSFT".to_string(), &StatsOptions::default()).await.is_err());
+    }
 
-impl TradingDataService {
-    pub fn new() -> Self {
-        TradingDataService {
-            buffers: Arc::new(RwLock::new(std::collections::HashMap::new())),
+    #[tokio::test]
+    async fn test_service_values_page() {
+        let service = TradingDataService::new();
+        let batch = Batch { timestamps: Some((1..=5).collect()), ..Batch::new(vec![1.0, 2.0, 3.0, 4.0, 5.0]) };
+        service.add_batch("AAPL".to_string(), batch).await.unwrap();
+        let page = service.get_values("AAPL".to_string(), 1, 1, 2).await.unwrap();
+        assert_eq!(5, page.total);
+        let values: Vec<f64> = page.points.iter().map(|p| p.value).collect();
+        assert_eq!(vec![3.0, 4.0], values);
+        assert_eq!(Some(4), page.points[1].timestamp);
+        assert!(service.get_values("AAPL".to_string(), 1, 10, 2).await.unwrap().points.is_empty());
+        assert!(service.get_values("AAPL".to_string(), 1, 0, 0).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_scan_values() {
+        let service = TradingDataService::new();
+        let initial: Vec<f64> = (1..=10).map(f64::from).collect();
+        service.add_batch_values("AAPL".to_string(), initial.clone()).await.unwrap();
+
+        // Points evicted mid-scan are still read; points added aren't.
+        let mut page = service.scan_values("AAPL".to_string(), 1, 4).await.unwrap();
+        let mut scanned: Vec<f64> = page.points.iter().map(|p| p.value).collect();
+        service.add_batch_values("AAPL".to_string(), vec![11.0, 12.0, 13.0, 14.0, 15.0, 16.0]).await.unwrap();
+        while let Some(cursor) = page.cursor {
+            page = service.next_scan_values(&cursor, 4).await.unwrap();
+            assert_eq!(10, page.total);
+            scanned.extend(page.points.iter().map(|p| p.value));
         }
+        assert_eq!(initial, scanned);
+        let buffers = service.shared.buffers.read().await;
+        assert!(buffers["AAPL"].read().await.buffers[0].retained.is_empty());
+        drop(buffers);
+
+        // A reset ends the scan.
+        let page = service.scan_values("AAPL".to_string(), 1, 4).await.unwrap();
+        service.reset_symbol("AAPL".to_string(), Some(1)).await.unwrap();
+        let cursor = page.cursor.unwrap();
+        assert!(matches!(service.next_scan_values(&cursor, 4).await, Err(ServiceError::Conflict(_))));
+        assert!(matches!(service.next_scan_values(&cursor, 4).await, Err(ServiceError::NotFound(_))));
+        assert!(service.scan_values("AAPL".to_string(), 1, 0).await.is_err());
     }
 
-    pub async fn add_batch_values(&self, symbol: String, values: Vec<f64>) -> Result<(), String> {
-        if values.len() > 10000 {
-            return Err("Batch size exceeds maximum limit of 10000".to_string());
+    #[tokio::test]
+    async fn test_service_add_batches() {
+        let service = TradingDataService::new();
+        let batches = vec![
+            ("AAPL".to_string(), Batch::new(vec![1.0, 2.0])),
+            ("MSFT".to_string(), Batch { weights: Some(vec![1.0]), ..Batch::new(vec![1.0, 2.0]) }),
+            ("GOOG".to_string(), Batch::new(vec![3.0])),
+        ];
+        let results = service.add_batches(batches).await.unwrap();
+        assert!(results[0].is_ok() && results[1].is_err() && results[2].is_ok());
+        assert_eq!(2.0, service.get_stats("AAPL".to_string(), 1).await.unwrap().last);
+        assert!(service.get_stats("MSFT".to_string(), 1).await.is_err());
+        assert_eq!(3.0, service.get_stats("GOOG".to_string(), 1).await.unwrap().last);
+    }
+
+    #[tokio::test]
+    async fn test_service_pending_writes() {
+        let service = Arc::new(TradingDataService::new());
+        let guard = service.shared.buffers.read().await;
+        let writer = {
+            let service = service.clone();
+            tokio::spawn(async move { service.add_batch_values("AAPL".to_string(), vec![1.0]).await })
+        };
+        while service.pending_writes() == 0 {
+            tokio::task::yield_now().await;
         }
+        drop(guard);
+        writer.await.unwrap().unwrap();
+        assert_eq!(0, service.pending_writes());
+    }
 
-        let mut buffers = self.buffers.write().await;
-        let symbol_buffers = buffers.entry(symbol).or_insert_with(|| {
-            (1..=8).map(|k| TradingDataBuffer::new(10usize.pow(k))).collect()
-        });
+    #[tokio::test]
+    async fn test_service_ingestion_tasks() {
+        let service = TradingDataService::new();
+        service.set_max_symbols(Some(1));
+        service.add_batch_values("AAPL".to_string(), vec![1.0]).await.unwrap();
+        let result = service.add_batch_values("MSFT".to_string(), vec![1.0]).await;
+        assert!(matches!(result, Err(ServiceError::LimitReached(_))));
+
+        // A removed symbol's task is replaced when it takes data again.
+        service.remove_symbol("AAPL".to_string()).await.unwrap();
+        service.add_batch_values("AAPL".to_string(), vec![2.0, 3.0]).await.unwrap();
+        let stats = service.get_stats("AAPL".to_string(), 1).await.unwrap();
+        assert_eq!((2, 3.0), (stats.count, stats.last));
+    }
+
+    #[tokio::test]
+    async fn test_service_fast_path() {
+        let service = TradingDataService::new();
+        assert_eq!(Err(ServiceError::UnknownSymbol), service.enable_fast_path("AAPL".to_string(), None).await);
+        service.add_batch_values("AAPL".to_string(), vec![1.0]).await.unwrap();
+        service.enable_fast_path("AAPL".to_string(), None).await.unwrap();
+        assert!(matches!(service.enable_fast_path("AAPL".to_string(), None).await, Err(ServiceError::Conflict(_))));
 
-        for buffer in symbol_buffers.iter_mut() {
-            buffer.add_batch(&values);
+        // Queued batches count as pending until the consumer gets the lock.
+        let held = service.shared.buffers.read().await;
+        let state = held["AAPL"].write().await;
+        service.add_batch_values("AAPL".to_string(), vec![2.0, 3.0]).await.unwrap();
+        assert_eq!(1, service.pending_writes());
+        drop(state);
+        drop(held);
+        while service.pending_writes() > 0 {
+            tokio::time::sleep(std::time::Duration::from_millis(1)).await;
         }
+        let stats = service.get_stats("AAPL".to_string(), 1).await.unwrap();
+        assert_eq!((3, 3.0), (stats.count, stats.last));
 
-        Ok(())
+        // Batches are checked before they're queued.
+        let nan = service.add_batch_values("AAPL".to_string(), vec![4.0, f64::NAN]).await;
+        assert!(matches!(nan, Err(ServiceError::InvalidValue(_))));
+        let meta = SymbolMeta { tick_size: Some(0.5), reject_off_tick: true, ..SymbolMeta::default() };
+        service.set_symbol_meta("AAPL".to_string(), meta).await.unwrap();
+        let off_tick = service.add_batch_values("AAPL".to_string(), vec![4.2]).await;
+        assert!(matches!(off_tick, Err(ServiceError::InvalidValue(_))));
+
+        let results = service.add_batches(vec![("AAPL".to_string(), Batch::new(vec![4.5])), ("MSFT".to_string(), Batch::new(vec![5.0]))]).await.unwrap();
+        assert_eq!(vec![Ok(()), Ok(())], results);
+        service.disable_fast_path("AAPL".to_string()).await.unwrap();
+        let stats = service.fast_path_stats("AAPL".to_string());
+        assert!(matches!(stats, Err(ServiceError::NotFound(_))));
+        assert_eq!(4.5, service.get_stats("AAPL".to_string(), 1).await.unwrap().last);
+
+        service.enable_fast_path("AAPL".to_string(), None).await.unwrap();
+        service.remove_symbol("AAPL".to_string()).await.unwrap();
+        assert!(matches!(service.disable_fast_path("AAPL".to_string()).await, Err(ServiceError::NotFound(_))));
+        assert_eq!(0, service.pending_writes());
     }
 
-    pub async fn get_stats(&self, symbol: String, k: usize) -> Result<StatsResponse, String> {
-        if k < 1 || k > 8 {
-            return Err("Invalid k input. Only values 1-8 are accepted.".to_string());
+    #[tokio::test]
+    async fn test_service_removal_cancels_queued_batches() {
+        let service = Arc::new(TradingDataService::new());
+        service.add_batch_values("AAPL".to_string(), vec![1.0]).await.unwrap();
+        let buffers = service.shared.buffers.read().await;
+        let held = buffers["AAPL"].write().await;
+
+        // One batch waits on the symbol's lock, the other in its queue.
+        let add = |value: f64| {
+            let service = service.clone();
+            tokio::spawn(async move { service.add_batch_values("AAPL".to_string(), vec![value]).await })
+        };
+        let first = add(2.0);
+        while service.pending_writes() < 1 {
+            tokio::task::yield_now().await;
         }
+        for _ in 0..3 {
+            tokio::task::yield_now().await;
+        }
+        let second = add(3.0);
+        while service.pending_writes() < 2 {
+            tokio::task::yield_now().await;
+        }
+        let removing = service.clone();
+        let removed = tokio::spawn(async move { removing.remove_symbol("AAPL".to_string()).await });
+        tokio::task::yield_now().await;
+        drop(held);
+        drop(buffers);
 
-        let buffers = self.buffers.read().await;
-        buffers.get(&symbol)
-            .and_then(|b| b.get(k - 1))
-            .map(|b| b.get_stats())
-            .ok_or_else(|| "Symbol not found".to_string())
+        assert!(removed.await.unwrap().is_ok());
+        assert!(first.await.unwrap().is_ok());
+        assert_eq!(Err(ServiceError::UnknownSymbol), second.await.unwrap());
+        assert_eq!(0, service.symbol_count().await);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[tokio::test]
+    async fn test_service_symbol_locks() {
+        let service = TradingDataService::new();
+        service.add_batch_values("AAPL".to_string(), vec![1.0]).await.unwrap();
+        service.add_batch_values("MSFT".to_string(), vec![1.0]).await.unwrap();
 
-    const DELTA: f64 = 1e-6;
+        // A symbol held for writing holds up neither another symbol nor
+        // readers of several symbols that leave it out.
+        let buffers = service.shared.buffers.read().await;
+        let held = buffers["AAPL"].write().await;
+        let timeout = std::time::Duration::from_secs(5);
+        tokio::time::timeout(timeout, service.add_batch_values("MSFT".to_string(), vec![2.0])).await.unwrap().unwrap();
+        let stats = tokio::time::timeout(timeout, service.get_stats_batch(vec![("MSFT".to_string(), 1)])).await.unwrap().unwrap();
+        assert_eq!(2.0, stats[0].as_ref().unwrap().last);
+        assert!(tokio::time::timeout(std::time::Duration::from_millis(50), service.get_stats("AAPL".to_string(), 1)).await.is_err());
+        drop(held);
+        drop(buffers);
+        assert_eq!(1.0, service.get_stats("AAPL".to_string(), 1).await.unwrap().last);
+    }
 
-    fn assert_float_eq(a: f64, b: f64) {
-        assert!((a - b).abs() < DELTA, "{} != {}", a, b);
+    #[tokio::test]
+    async fn test_service_reset_symbol() {
+        let service = TradingDataService::new();
+        let config = SymbolConfig { ewma_alpha: Some(0.5), ..SymbolConfig::default() };
+        service.set_symbol_config("AAPL".to_string(), config).await.unwrap();
+        service.add_batch_values("AAPL".to_string(), (1..=20).map(|i| i as f64).collect()).await.unwrap();
+
+        service.reset_symbol("AAPL".to_string(), Some(1)).await.unwrap();
+        assert_eq!(0, service.get_stats("AAPL".to_string(), 1).await.unwrap().count);
+        assert_eq!(20, service.get_stats("AAPL".to_string(), 2).await.unwrap().count);
+
+        service.reset_symbol("AAPL".to_string(), None).await.unwrap();
+        assert_eq!(0, service.get_stats("AAPL".to_string(), 2).await.unwrap().count);
+        service.add_batch_values("AAPL".to_string(), vec![2.0, 4.0]).await.unwrap();
+        let stats = service.get_stats("AAPL".to_string(), 1).await.unwrap();
+        assert_eq!(3.0, stats.avg);
+        assert_eq!(Some(0.5), service.get_symbol_config("AAPL".to_string()).await.unwrap().ewma_alpha);
+        assert!(service.reset_symbol("AAPL".to_string(), Some(9)).await.is_err());
+        assert!(service.reset_symbol("MSFT".to_string(), None).await.is_err());
     }
 
-    #[test]
-    fn test_add_batch_and_get_stats() {
-        let mut buffer = TradingDataBuffer::new(5);
-        buffer.add_batch(&[1.0, 2.0, 3.0]);
+    #[tokio::test]
+    async fn test_stats_tag() {
+        let service = TradingDataService::new();
+        assert_eq!(ServiceError::UnknownSymbol, service.stats_tag("AAPL".to_string()).await.unwrap_err());
+        service.add_batch_values("AAPL".to_string(), vec![1.0]).await.unwrap();
+        let tag = service.stats_tag("AAPL".to_string()).await.unwrap();
+        service.add_batch_values("MSFT".to_string(), vec![1.0]).await.unwrap();
+        assert_eq!(tag, service.stats_tag("AAPL".to_string()).await.unwrap());
 
-        let stats = buffer.get_stats();
-        assert_float_eq(1.0, stats.min);
-        assert_float_eq(3.0, stats.max);
-        assert_float_eq(3.0, stats.last);
-        assert_float_eq(2.0, stats.avg);
-        assert_float_eq(0.6666667, stats.var);
+        service.add_batch_values("AAPL".to_string(), vec![2.0]).await.unwrap();
+        let added = service.stats_tag("AAPL".to_string()).await.unwrap();
+        assert_ne!(tag, added);
+        service.reset_symbol("AAPL".to_string(), None).await.unwrap();
+        assert_ne!(added, service.stats_tag("AAPL".to_string()).await.unwrap());
+
+        // A batch held for reordering changes the counters, if no window.
+        let config = SymbolConfig { reorder_slack_ms: 1000, ..SymbolConfig::default() };
+        service.set_symbol_config("AAPL".to_string(), config).await.unwrap();
+        let reordering = service.stats_tag("AAPL".to_string()).await.unwrap();
+        let held = Batch { timestamps: Some(vec![now_nanos()]), ..Batch::new(vec![3.0]) };
+        service.add_batch("AAPL".to_string(), held).await.unwrap();
+        assert_eq!(0, service.get_stats("AAPL".to_string(), 1).await.unwrap().count);
+        assert_ne!(reordering, service.stats_tag("AAPL".to_string()).await.unwrap());
+
+        // A symbol tracked again never repeats an old tag.
+        let dropped = service.stats_tag("MSFT".to_string()).await.unwrap();
+        service.remove_symbol("MSFT".to_string()).await.unwrap();
+        service.add_batch_values("MSFT".to_string(), vec![1.0]).await.unwrap();
+        assert_ne!(dropped, service.stats_tag("MSFT".to_string()).await.unwrap());
+
+        let config = SymbolConfig { stale_after_ms: Some(1), ..SymbolConfig::default() };
+        service.set_symbol_config("MSFT".to_string(), config).await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        assert!(service.stats_tag("MSFT".to_string()).await.unwrap().ends_with("-stale"));
     }
 
-    #[test]
-    fn test_buffer_overflow() {
-        let mut buffer = TradingDataBuffer::new(5);
-        buffer.add_batch(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+    #[tokio::test]
+    async fn test_field_selection() {
+        let service = TradingDataService::new();
+        service.add_batch_values("AAPL".to_string(), vec![1.0, 2.0, 3.0]).await.unwrap();
+        let options = StatsOptions { quantiles: vec![0.5], ..StatsOptions::default() };
+        let stats = service.get_stats_with("AAPL".to_string(), 1, &options).await.unwrap();
 
-        let stats = buffer.get_stats();
-        assert_float_eq(2.0, stats.min);
-        assert_float_eq(6.0, stats.max);
-        assert_float_eq(6.0, stats.last);
-        assert_float_eq(4.0, stats.avg);
+        // Fields come back in response order, whatever order they're asked in.
+        let selection = FieldSelection::parse("last, min,vwap").unwrap();
+        assert_eq!(r#"{"min":1.0,"last":3.0}"#, serde_json::to_string(&selection.apply(&stats)).unwrap());
+
+        let selection = FieldSelection::parse(&STATS_FIELDS.join(",")).unwrap();
+        assert_eq!(serde_json::to_value(&stats).unwrap(), serde_json::to_value(selection.apply(&stats)).unwrap());
+
+        assert!(FieldSelection::parse("min,mean").is_err());
+        assert!(FieldSelection::parse("").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_check_batch() {
+        let service = TradingDataService::new();
+        let check = service.check_batch("AAPL".to_string(), Batch::new(vec![1.0, 2.0])).await.unwrap();
+        assert_eq!(BatchCheck { symbol: "AAPL".to_string(), new_symbol: true, accepted: 2, ..BatchCheck::default() }, check);
+        assert_eq!(ServiceError::UnknownSymbol, service.get_stats("AAPL".to_string(), 1).await.unwrap_err());
+
+        let config = SymbolConfig { non_finite: NonFiniteAction::Skip, dedup_window: 10, ..SymbolConfig::default() };
+        service.set_symbol_config("AAPL".to_string(), config).await.unwrap();
+        let batch = Batch { batch_id: Some("b1".to_string()), ..Batch::new(vec![1.0]) };
+        service.add_batch("AAPL".to_string(), batch.clone()).await.unwrap();
+        assert!(service.check_batch("AAPL".to_string(), batch).await.unwrap().already_added);
+
+        let batch = Batch {
+            ids: Some(vec!["a".to_string(), "b".to_string(), "a".to_string(), "c".to_string()]),
+            ..Batch::new(vec![1.0, f64::NAN, 2.0, 3.0])
+        };
+        let check = service.check_batch("AAPL".to_string(), batch.clone()).await.unwrap();
+        assert_eq!((false, 2, 1, 1), (check.new_symbol, check.accepted, check.non_finite_dropped, check.duplicates_dropped));
+        let counters = service.get_stats("AAPL".to_string(), 1).await.unwrap();
+        assert_eq!((1, 0), (counters.count, counters.non_finite_dropped));
+
+        // The same checks fail as when adding, and nothing is added either way.
+        service.set_symbol_config("AAPL".to_string(), SymbolConfig::default()).await.unwrap();
+        let checked = service.check_batch("AAPL".to_string(), batch.clone()).await.unwrap_err();
+        assert_eq!(service.add_batch("AAPL".to_string(), batch).await.unwrap_err(), checked);
+        let batch = Batch { weights: Some(vec![1.0]), ..Batch::new(vec![1.0, 2.0]) };
+        assert!(service.check_batch("AAPL".to_string(), batch).await.is_err());
+        let checks = service.check_batches(vec![("MSFT".to_string(), Batch::new(vec![1.0]))]).await.unwrap();
+        assert!(checks[0].as_ref().unwrap().new_symbol);
+        assert_eq!(1, service.symbol_count().await);
+    }
+
+    #[tokio::test]
+    async fn test_service_error_kinds() {
+        let service = TradingDataService::new();
+        assert_eq!(ServiceError::UnknownSymbol, service.get_stats("AAPL".to_string(), 1).await.unwrap_err());
+        service.add_batch_values("AAPL".to_string(), vec![1.0]).await.unwrap();
+        assert_eq!(ServiceError::InvalidK, service.get_stats("AAPL".to_string(), 9).await.unwrap_err());
+        let error = service.add_batch_values("AAPL".to_string(), vec![1.0; 10001]).await.unwrap_err();
+        assert_eq!("batch_too_large", error.code());
+        let error = service.get_stats_for_last("AAPL".to_string(), 0, &StatsOptions::default()).await.unwrap_err();
+        assert_eq!("invalid_value", error.code());
+    }
+
+    #[tokio::test]
+    async fn test_service_stats_batch() {
+        let service = TradingDataService::new();
+        service.add_batch_values("AAPL".to_string(), vec![1.0, 2.0, 3.0]).await.unwrap();
+        service.add_batch_values("MSFT".to_string(), vec![10.0]).await.unwrap();
+        let requests = vec![("AAPL".to_string(), 1), ("MSFT".to_string(), 2), ("GOOG".to_string(), 1), ("AAPL".to_string(), 9)];
+        let results = service.get_stats_batch(requests).await.unwrap();
+        assert_eq!(3.0, results[0].as_ref().unwrap().last);
+        assert_eq!(10.0, results[1].as_ref().unwrap().last);
+        assert!(results[2].is_err());
+        assert!(results[3].is_err());
+    }
+
+    #[tokio::test]
+    async fn test_service_venues() {
+        let service = TradingDataService::new();
+        let tagged = |venue: &str, values: Vec<f64>| Batch { venue: Some(venue.to_string()), ..Batch::new(values) };
+        service.add_batch("AAPL".to_string(), tagged("NASDAQ", vec![100.0, 102.0])).await.unwrap();
+        service.add_batch("AAPL".to_string(), tagged("ARCA", vec![101.0])).await.unwrap();
+        service.add_batch_values("AAPL".to_string(), vec![103.0]).await.unwrap();
+
+        let venue = |v: &str| StatsOptions { venue: Some(v.to_string()), ..StatsOptions::default() };
+        let nasdaq = service.get_stats_with("AAPL".to_string(), 1, &venue("NASDAQ")).await.unwrap();
+        assert_float_eq(101.0, nasdaq.avg);
+        assert_eq!(2, nasdaq.count);
+        let arca = service.get_stats_with("AAPL".to_string(), 1, &venue("ARCA")).await.unwrap();
+        assert_float_eq(101.0, arca.last);
+        let consolidated = service.get_stats("AAPL".to_string(), 1).await.unwrap();
+        assert_eq!(4, consolidated.count);
+        assert!(service.get_stats_with("AAPL".to_string(), 1, &venue("BATS")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_service_quotes() {
+        let service = TradingDataService::new();
+        service.add_batch_values("AAPL".to_string(), vec![100.0]).await.unwrap();
+        assert!(service.get_quote_stats("AAPL".to_string(), 1).await.is_err());
+
+        let quotes = vec![Quote { bid: 99.0, ask: 101.0 }, Quote { bid: 99.5, ask: 100.5 }];
+        service.add_quotes("AAPL".to_string(), quotes).await.unwrap();
+        let stats = service.get_quote_stats("AAPL".to_string(), 1).await.unwrap();
+        assert_eq!(2, stats.count);
+        assert_float_eq(1.5, stats.spread.avg);
+        assert_eq!(1, service.get_stats("AAPL".to_string(), 1).await.unwrap().count);
+
+        let crossed = vec![Quote { bid: 101.0, ask: 99.0 }];
+        assert!(service.add_quotes("AAPL".to_string(), crossed).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_top_movers() {
+        let service = TradingDataService::new();
+        service.add_batch_values("A".to_string(), vec![100.0, 101.0]).await.unwrap();
+        service.add_batch_values("B".to_string(), vec![100.0, 95.0]).await.unwrap();
+        service.add_batch_values("C".to_string(), vec![100.0, 103.0]).await.unwrap();
+
+        let movers = service.get_top_movers(RankMetric::PctChange, 1, 2).await.unwrap();
+        assert_eq!(2, movers.len());
+        assert_eq!("B", movers[0].symbol);
+        assert_float_eq(-0.05, movers[0].value);
+        assert_eq!("C", movers[1].symbol);
+    }
+
+    #[tokio::test]
+    async fn test_compare_windows() {
+        let service = TradingDataService::new();
+        let values: Vec<f64> = (0..100).map(|i| if i < 90 { (i % 2) as f64 * 10.0 } else { 20.0 + (i % 2) as f64 }).collect();
+        service.add_batch_values("AAPL".to_string(), values).await.unwrap();
+
+        let comparison = service.compare_windows("AAPL".to_string(), 1, 2).await.unwrap();
+        assert_eq!(10, comparison.first.count);
+        assert_eq!(100, comparison.second.count);
+        assert_float_eq(20.5 - 6.55, comparison.avg_drift);
+        assert!(comparison.vol_ratio.unwrap() < 0.2);
+        assert!(service.compare_windows("AAPL".to_string(), 1, 9).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_stats_for_last_n() {
+        let service = TradingDataService::new();
+        let values: Vec<f64> = (1..=300).map(|i| i as f64).collect();
+        service.add_batch_values("AAPL".to_string(), values).await.unwrap();
+
+        let options = StatsOptions::default();
+        let stats = service.get_stats_for_last("AAPL".to_string(), 25, &options).await.unwrap();
+        assert_eq!(25, stats.count);
+        assert_float_eq(276.0, stats.min);
+        assert_float_eq(288.0, stats.avg);
+        assert!(stats.last_update > 0);
+
+        let stats = service.get_stats_for_last("AAPL".to_string(), 100, &options).await.unwrap();
+        assert_float_eq(201.0, stats.min);
+        assert!(service.get_stats_for_last("AAPL".to_string(), 0, &options).await.is_err());
     }
 
     #[test]
-    fn test_empty_buffer() {
-        let buffer = TradingDataBuffer::new(5);
-        let stats = buffer.get_stats();
-        assert_float_eq(0.0, stats.min);
-        assert_float_eq(0.0, stats.max);
-        assert_float_eq(0.0, stats.last);
-        assert_float_eq(0.0, stats.avg);
-        assert_float_eq(0.0, stats.var);
+    fn test_covering_k() {
+        assert_eq!(Ok(1), covering_k(1));
+        assert_eq!(Ok(1), covering_k(10));
+        assert_eq!(Ok(3), covering_k(250));
+        assert_eq!(Ok(8), covering_k(100_000_000));
+        assert!(covering_k(0).is_err());
+        assert!(covering_k(100_000_001).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_outlier_filter_rejects_bad_prints() {
+        let service = TradingDataService::new();
+        let filter = OutlierFilter { threshold: 5.0, action: OutlierAction::Reject, window_k: 1 };
+        let config = SymbolConfig { outlier_filter: Some(filter), ..SymbolConfig::default() };
+        service.set_symbol_config("AAPL".to_string(), config).await.unwrap();
+
+        let values: Vec<f64> = (0..20).map(|i| 100.0 + (i % 4) as f64).collect();
+        service.add_batch_values("AAPL".to_string(), values).await.unwrap();
+        service.add_batch_values("AAPL".to_string(), vec![101.0, 0.01, 102.0]).await.unwrap();
+
+        let stats = service.get_stats("AAPL".to_string(), 2).await.unwrap();
+        assert_float_eq(100.0, stats.min);
+        assert_eq!(22, stats.count);
+        assert_eq!(1, stats.outliers_rejected);
+        assert_eq!(0, stats.outliers_flagged);
+    }
+
+    #[tokio::test]
+    async fn test_retried_batch_is_added_once() {
+        let service = TradingDataService::new();
+        let batch = |values: Vec<f64>| Batch { batch_id: Some("b-1".to_string()), ..Batch::new(values) };
+        assert!(service.add_batch("AAPL".to_string(), batch(vec![f64::NAN])).await.is_err());
+        service.add_batch("AAPL".to_string(), batch(vec![1.0, 2.0])).await.unwrap();
+        service.add_batch("AAPL".to_string(), batch(vec![1.0, 2.0])).await.unwrap();
+        service.add_batch("AAPL".to_string(), Batch::new(vec![3.0])).await.unwrap();
+        assert_eq!(3, service.get_stats("AAPL".to_string(), 1).await.unwrap().count);
+    }
+
+    #[tokio::test]
+    async fn test_updates_name_changed_symbols() {
+        let service = TradingDataService::new();
+        let config = DerivedConfig { op: DerivedOp::Spread, legs: ["A".to_string(), "B".to_string()] };
+        service.set_derived("A-B".to_string(), config).await.unwrap();
+        let mut updates = service.subscribe();
+        service.add_batch_values("B".to_string(), vec![1.0]).await.unwrap();
+        service.add_batch_values("A".to_string(), vec![3.0]).await.unwrap();
+        assert!(service.add_batch_values("A".to_string(), vec![f64::NAN]).await.is_err());
+        service.reset_symbol("B".to_string(), None).await.unwrap();
+        let received: Vec<String> = std::iter::from_fn(|| updates.try_recv().ok()).collect();
+        assert_eq!(vec!["B", "A", "A-B", "B"], received);
+    }
+
+    #[tokio::test]
+    async fn test_stats_as_of() {
+        let service = TradingDataService::new();
+        let batch = Batch { timestamps: Some((1..=30).collect()), ..Batch::new((1..=30).map(f64::from).collect()) };
+        service.add_batch("AAPL".to_string(), batch).await.unwrap();
+
+        let stats = service.get_stats_as_of("AAPL".to_string(), 10, 15, &StatsOptions::default()).await.unwrap();
+        assert_eq!((6.0, 15.0, 10), (stats.min, stats.last, stats.count));
+        // Fewer values had arrived than the window holds.
+        let stats = service.get_stats_as_of("AAPL".to_string(), 10, 4, &StatsOptions::default()).await.unwrap();
+        assert_eq!((1.0, 4.0, 4), (stats.min, stats.last, stats.count));
+        let stats = service.get_stats_as_of("AAPL".to_string(), 100, u64::MAX, &StatsOptions::default()).await.unwrap();
+        assert_eq!(30, stats.count);
+        let e = service.get_stats_as_of("AAPL".to_string(), 10, 0, &StatsOptions::default()).await.unwrap_err();
+        assert_eq!("not_found", e.code());
     }
 
     #[test]
-    fn test_single_element() {
-        let mut buffer = TradingDataBuffer::new(5);
-        buffer.add_batch(&[5.0]);
+    fn test_glob_match() {
+        assert!(glob_match("ES*", "ESZ4"));
+        assert!(glob_match("ES*", "ES"));
+        assert!(!glob_match("ES*", "NQZ4"));
+        assert!(glob_match("SPX*C*", "SPX241220C05000"));
+        assert!(glob_match("ES?4", "ESZ4"));
+        assert!(!glob_match("ES?4", "ESZ24"));
+        assert!(glob_match("AAPL", "AAPL"));
+    }
 
-        let stats = buffer.get_stats();
-        assert_float_eq(5.0, stats.min);
-        assert_float_eq(5.0, stats.max);
-        assert_float_eq(5.0, stats.last);
-        assert_float_eq(5.0, stats.avg);
-        assert_float_eq(0.0, stats.var);
+    #[tokio::test]
+    async fn test_stats_matching() {
+        let service = TradingDataService::new();
+        for symbol in ["ESZ4", "ESH5", "NQZ4"] {
+            service.add_batch_values(symbol.to_string(), vec![1.0, 2.0]).await.unwrap();
+        }
+        let matched = service.get_stats_matching("ES*", 1, &StatsOptions::default()).await.unwrap();
+        let symbols: Vec<&str> = matched.iter().map(|m| m.symbol.as_str()).collect();
+        assert_eq!(vec!["ESH5", "ESZ4"], symbols);
+        assert_eq!(2.0, matched[0].stats.last);
+        assert!(service.get_stats_matching("CL*", 1, &StatsOptions::default()).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_non_finite_values() {
+        let service = TradingDataService::new();
+        assert!(service.add_batch_values("AAPL".to_string(), vec![1.0, f64::NAN]).await.is_err());
+        assert!(service.add_batch_values("AAPL".to_string(), vec![f64::INFINITY]).await.is_err());
+
+        let config = SymbolConfig { non_finite: NonFiniteAction::Skip, ..SymbolConfig::default() };
+        service.set_symbol_config("AAPL".to_string(), config).await.unwrap();
+        service.add_batch_values("AAPL".to_string(), vec![1.0, f64::NAN, 3.0, f64::NEG_INFINITY]).await.unwrap();
+        let stats = service.get_stats("AAPL".to_string(), 1).await.unwrap();
+        assert_eq!(2, stats.count);
+        assert_float_eq(2.0, stats.avg);
+        assert_eq!(2, stats.non_finite_dropped);
     }
 
     #[test]
-    fn test_variance_calculation() {
+    fn test_std_dev_of_flat_window() {
         let mut buffer = TradingDataBuffer::new(5);
-        buffer.add_batch(&[2.0, 4.0, 6.0]);
+        buffer.add_batch(&[0.1, 0.1, 0.1, 0.1, 0.1, 0.1, 0.1]);
 
         let stats = buffer.get_stats();
-        assert_float_eq(4.0, stats.avg);
-        assert_float_eq(2.6666667, stats.var);
+        assert!(stats.var >= 0.0);
+        assert_float_eq(0.0, stats.std_dev);
+        assert_float_eq(0.0, stats.zscore_last);
     }
 
     #[test]
@@ -214,6 +5238,45 @@ mod tests {
         assert_float_eq(6.0, stats.max);
     }
 
+    #[test]
+    fn test_ohlc() {
+        let mut buffer = TradingDataBuffer::new(4);
+        buffer.add_batch(&[9.0, 3.0, 1.0, 5.0, 2.0, 4.0]);
+
+        let stats = buffer.get_stats();
+        let ohlc = stats.ohlc;
+        assert_float_eq(3.0, stats.pct_change);
+        assert_float_eq(1.0, ohlc.open);
+        assert_float_eq(5.0, ohlc.high);
+        assert_float_eq(1.0, ohlc.low);
+        assert_float_eq(4.0, ohlc.close);
+    }
+
+    #[tokio::test]
+    async fn test_symbol_config_selects_ddsketch() {
+        let service = TradingDataService::new();
+        let values: Vec<f64> = (1..=1000).map(|i| i as f64).collect();
+        service.add_batch_values("AAPL".to_string(), values).await.unwrap();
+
+        let quantiles = QuantileConfig {
+            sketch: QuantileSketch::DdSketch,
+            relative_accuracy: 0.001,
+            from_k: 3,
+        };
+        let config = SymbolConfig { quantiles, ..SymbolConfig::default() };
+        service.set_symbol_config("AAPL".to_string(), config).await.unwrap();
+
+        let options = StatsOptions { quantiles: vec![0.5], ..StatsOptions::default() };
+        let stats = service.get_stats_with("AAPL".to_string(), 3, &options).await.unwrap();
+        assert!((stats.quantiles[0].value - 500.0).abs() <= 0.5, "{}", stats.quantiles[0].value);
+
+        let invalid = SymbolConfig {
+            quantiles: QuantileConfig { relative_accuracy: 1.0, ..QuantileConfig::default() },
+            ..SymbolConfig::default()
+        };
+        assert!(service.set_symbol_config("AAPL".to_string(), invalid).await.is_err());
+    }
+
     #[test]
     fn test_large_number_of_additions() {
         let mut buffer = TradingDataBuffer::new(1000);
@@ -240,4 +5303,19 @@ mod tests {
         assert_float_eq(5.0, stats.last);
         assert_float_eq(3.0, stats.avg);
     }
+
+    #[test]
+    fn test_quantiles() {
+        let mut buffer = TradingDataBuffer::new(1000);
+        let values: Vec<f64> = (1..=1000).map(|i| i as f64).collect();
+        buffer.add_batch(&values);
+
+        let options = StatsOptions { quantiles: vec![0.5, 0.99], ..StatsOptions::default() };
+        let stats = buffer.get_stats_with(&options);
+        assert_eq!(2, stats.quantiles.len());
+        assert_float_eq(0.5, stats.quantiles[0].q);
+        assert!((stats.quantiles[0].value - 500.0).abs() < 5.0);
+        assert!((stats.quantiles[1].value - 990.0).abs() < 2.0);
+        assert!(buffer.get_stats().quantiles.is_empty());
+    }
 }
@@ -1,56 +1,1655 @@
-use actix_web::{App, HttpResponse, HttpServer, Responder, web};
+mod admin;
+mod body;
+mod cors;
+mod export;
+mod flight;
+mod graphql;
+mod grpc;
+mod request_id;
+mod upload;
+mod ws;
+
+use std::collections::{BTreeMap, HashMap};
+use std::future::{ready, Ready};
+use std::ops::Deref;
+use std::sync::Arc;
+
+use actix_cors::Cors;
+use actix_web::dev::{Decompress, Payload};
+use actix_web::error::InternalError;
+use actix_web::http::Method;
+use actix_web::{guard, middleware, App, FromRequest, HttpRequest, HttpResponse, HttpServer, Responder, web};
+use futures::StreamExt;
 use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+use tracing_subscriber::filter::LevelFilter;
 
-use trading_service::TradingDataService;
+use crate::admin::AdminToken;
+use crate::body::{check_encoding, respond, respond_tagged, Body, Proto, MAX_BODY_BYTES};
+use crate::cors::CorsConfig;
+
+use trading_service::bars::parse_interval;
+use trading_service::expr::Condition;
+use trading_service::derived::{BasketConfig, DerivedConfig};
+use trading_service::fx::FxConfig;
+use trading_service::groups::TagFilter;
+use trading_service::indicators::MovingAverageKind;
+use trading_service::quotes::Quote;
+use trading_service::ranking::{Order, RankMetric};
+use trading_service::meta::SymbolMeta;
+use trading_service::namespaces::{Namespaces, DEFAULT_NAMESPACE};
+use trading_service::{
+    Batch, BatchCheck, FieldSelection, Point, SelectedStats, SeriesKind, ServiceError, StatsOptions, StatsResponse, SymbolConfig, TradingDataService,
+    CONDITION_STATS, MAX_BATCH_ENTRIES,
+};
 
 #[derive(Debug, Deserialize)]
 struct AddBatchRequest {
     symbol: String,
+    #[serde(default)]
     values: Vec<f64>,
+    /// Optional per-value weights, e.g. trade sizes.
+    weights: Option<Vec<f64>>,
+    /// Optional per-value traded volumes.
+    volumes: Option<Vec<f64>>,
+    /// Optional per-value event times, in nanoseconds since the Unix epoch.
+    timestamps: Option<Vec<u64>>,
+    /// Optional per-value feed sequence numbers.
+    sequences: Option<Vec<u64>>,
+    /// Optional sequence number of the first value, the rest numbered
+    /// consecutively after it.
+    sequence: Option<u64>,
+    /// Optional per-value unique IDs, e.g. trade IDs.
+    ids: Option<Vec<String>>,
+    /// Array-of-objects alternative to `values` and the parallel arrays.
+    points: Option<Vec<Point>>,
+    /// Values of the symbol's configured extra fields, keyed by field name.
+    #[serde(default)]
+    fields: HashMap<String, Vec<f64>>,
+    /// Optional venue the batch was traded on, e.g. `NASDAQ`.
+    venue: Option<String>,
+    /// Optional ID of the batch; a retried batch with the same ID is skipped.
+    batch_id: Option<String>,
+}
+
+/// One batch, or an array of batches for several symbols.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum AddBatchPayload {
+    One(Box<AddBatchRequest>),
+    Many(Vec<AddBatchRequest>),
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct AddBatchQuery {
+    /// Runs the checks and reports what adding would do, without adding.
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct AddBatchResult {
+    symbol: String,
+    /// What adding the batch would do, in a dry run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    check: Option<BatchCheck>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<&'static str>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AddQuotesRequest {
+    symbol: String,
+    quotes: Vec<Quote>,
 }
 
 #[derive(Debug, Deserialize)]
 struct GetStatsQuery {
+    /// A symbol, or a pattern with `*` and `?` wildcards matching many.
     symbol: String,
+    /// Window exponent; exactly one of `k`, `n` and `window` is required.
+    k: Option<u8>,
+    /// Arbitrary window size, 1 to 10^8.
+    n: Option<usize>,
+    /// Configured time window, e.g. `1s` or `5m`.
+    window: Option<String>,
+    /// Comma-separated quantiles, e.g. `0.5,0.99`.
+    quantiles: Option<String>,
+    /// `prices` (default), `returns` or `log_returns`.
+    #[serde(default)]
+    on: SeriesKind,
+    /// Annualizes `realized_vol`.
+    periods_per_year: Option<f64>,
+    /// Extra field to compute the stats over, default `price`.
+    field: Option<String>,
+    /// Venue to compute the stats over instead of the consolidated windows.
+    venue: Option<String>,
+    /// Computes the stats over the extended-hours windows.
+    #[serde(default)]
+    extended_hours: bool,
+    /// Computes the stats over the prices converted into this currency.
+    currency: Option<String>,
+    /// Rebuilds the `k` or `n` window as it was at this time, in nanoseconds
+    /// since the Unix epoch.
+    as_of: Option<u64>,
+    /// Comma-separated fields to return, e.g. `min,max,last`.
+    fields: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CompareQuery {
+    symbol: String,
+    k1: u8,
+    k2: u8,
+}
+
+#[derive(Debug, Deserialize)]
+struct TopQuery {
+    /// `pct_change` (default), `volatility` or `range`.
+    #[serde(default)]
+    metric: RankMetric,
+    k: u8,
+    /// Number of symbols to return, default 20.
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RankQuery {
+    /// Stat to rank by, e.g. `var` or `pct_change`.
+    metric: String,
     k: u8,
+    /// `desc` (default) or `asc`.
+    #[serde(default)]
+    order: Order,
+    /// Number of symbols to return, default 20.
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GroupStatsQuery {
+    /// A tag name, grouping by each of its values, or `name=value`.
+    tag: String,
+    /// `pct_change` (default), `volatility` or `range`.
+    #[serde(default)]
+    metric: RankMetric,
+    k: u8,
+}
+
+#[derive(Debug, Deserialize)]
+struct CorrelationQuery {
+    symbol1: String,
+    symbol2: String,
+    k: u8,
+}
+
+#[derive(Debug, Deserialize)]
+struct CovarianceQuery {
+    /// Comma-separated symbols, e.g. `A,B,C`.
+    symbols: String,
+    k: u8,
+}
+
+#[derive(Debug, Deserialize)]
+struct BarsQuery {
+    symbol: String,
+    /// Bar interval, e.g. `1s`, `1m` or `5m`.
+    interval: String,
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SymbolQuery {
+    symbol: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct FastPathQuery {
+    /// CPU core to pin the consumer thread to; the next available one if
+    /// absent.
+    core: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WindowQuery {
+    symbol: String,
+    k: u8,
+}
+
+#[derive(Debug, Deserialize)]
+struct BollingerQuery {
+    symbol: String,
+    k: u8,
+    /// Band width in standard deviations, default 2.
+    width: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AtrQuery {
+    symbol: String,
+    /// Bar interval the true ranges are taken from, e.g. `1m`.
+    interval: String,
+    /// Smoothing period in bars, default 14.
+    period: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MovingAverageQuery {
+    symbol: String,
+    /// Number of most recent values to average.
+    window: usize,
+    /// `sma` (default) or `ema`.
+    #[serde(default, rename = "type")]
+    kind: MovingAverageKind,
+}
+
+#[derive(Debug, Deserialize)]
+struct VarQuery {
+    symbol: String,
+    k: u8,
+    /// Comma-separated confidence levels, default `0.95,0.99`.
+    confidence: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SharpeQuery {
+    symbol: String,
+    k: u8,
+    /// Annual risk-free rate, default 0.
+    rf: Option<f64>,
+    /// Return periods per year used for annualization, default 252.
+    periods_per_year: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AcfQuery {
+    symbol: String,
+    k: u8,
+    /// Highest lag to report, default 20.
+    lags: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EntropyQuery {
+    symbol: String,
+    k: u8,
+    /// Number of equal-width bins, default 20.
+    bins: Option<usize>,
 }
 
 #[derive(Debug, Serialize)]
 struct ErrorResponse {
     error: String,
+    /// Stable machine-readable kind of the error, e.g. `unknown_symbol`.
+    code: &'static str,
+}
+
+impl From<&ServiceError> for ErrorResponse {
+    fn from(e: &ServiceError) -> Self {
+        ErrorResponse { error: e.to_string(), code: e.code() }
+    }
+}
+
+/// The error's response, with the status its kind maps to.
+fn error_response(e: impl Into<ServiceError>) -> HttpResponse {
+    let e = e.into();
+    let mut response = match e {
+        ServiceError::UnknownSymbol | ServiceError::NotFound(_) => HttpResponse::NotFound(),
+        ServiceError::InvalidK | ServiceError::InvalidValue(_) => HttpResponse::BadRequest(),
+        ServiceError::BatchTooLarge(_) => HttpResponse::PayloadTooLarge(),
+        ServiceError::Conflict(_) => HttpResponse::Conflict(),
+        ServiceError::LimitReached(_) => HttpResponse::TooManyRequests(),
+        ServiceError::Unauthorized(_) => HttpResponse::Unauthorized(),
+        ServiceError::Internal(_) => HttpResponse::InternalServerError(),
+    };
+    response.json(ErrorResponse::from(&e))
+}
+
+#[derive(Debug, Deserialize)]
+struct SymbolPath {
+    symbol: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct AliasPath {
+    alias: String,
+}
+
+/// Header naming the request's namespace when the path doesn't.
+const NAMESPACE_HEADER: &str = "X-Namespace";
+
+/// Header carrying a single batch's ID, as an alternative to `batch_id`.
+const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+/// The data service of the request's namespace: the `/ns/{ns}` path prefix,
+/// else the `X-Namespace` header, else the default namespace. Reads need an
+/// existing namespace; other requests create it.
+struct Tenant(Arc<TradingDataService>);
+
+impl Deref for Tenant {
+    type Target = TradingDataService;
+
+    fn deref(&self) -> &TradingDataService {
+        &self.0
+    }
+}
+
+impl Tenant {
+    /// The request's namespace, created if `create` is set.
+    fn resolve(req: &HttpRequest, create: bool) -> Result<Tenant, ServiceError> {
+        let namespaces = req.app_data::<web::Data<Namespaces>>().expect("namespaces are registered");
+        let name = match req.match_info().get("ns") {
+            Some(name) => name,
+            None => match req.headers().get(NAMESPACE_HEADER) {
+                Some(value) => value.to_str().map_err(|_| ServiceError::from("Invalid namespace header".to_string()))?,
+                None => DEFAULT_NAMESPACE,
+            },
+        };
+        let service = if create { namespaces.get_or_create(name) } else { namespaces.get(name) };
+        service.map(Tenant)
+    }
+}
+
+impl FromRequest for Tenant {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let service = Tenant::resolve(req, *req.method() != Method::GET);
+        ready(service.map_err(|e| {
+            let response = error_response(e.clone());
+            InternalError::from_response(e, response).into()
+        }))
+    }
+}
+
+/// Splits a request into its symbol and batch, moving its arrays into the
+/// batch rather than copying them.
+fn to_batch(req: AddBatchRequest) -> (String, Result<Batch, String>) {
+    let AddBatchRequest { symbol, values, weights, volumes, timestamps, sequences, sequence, ids, points, fields, venue, batch_id } = req;
+    let batch = match points {
+        None => Ok(Batch { values, weights, volumes, timestamps, sequences, ids, fields, venue, batch_id }),
+        Some(_) if !values.is_empty() => Err("Send either values or points, not both".to_string()),
+        Some(points) => Batch::from_points(&points).map(|batch| Batch { fields, venue, batch_id, ..batch }),
+    };
+    let batch = batch.and_then(|batch| match sequence {
+        None => Ok(batch),
+        Some(_) if batch.sequences.is_some() => Err("Send either sequence or sequences, not both".to_string()),
+        Some(first) => Ok(Batch { sequences: Some((first..).take(batch.values.len()).collect()), ..batch }),
+    });
+    (symbol, batch)
 }
 
 async fn add_batch(
-    service: web::Data<TradingDataService>,
-    req: web::Json<AddBatchRequest>,
+    service: Tenant,
+    http_req: HttpRequest,
+    query: web::Query<AddBatchQuery>,
+    req: Body<AddBatchPayload>,
+) -> impl Responder {
+    let key = match http_req.headers().get(IDEMPOTENCY_KEY_HEADER).map(|value| value.to_str()) {
+        None => None,
+        Some(Ok(key)) => Some(key.to_string()),
+        Some(Err(_)) => return error_response("Invalid Idempotency-Key header".to_string()),
+    };
+    let requests = match req.into_inner() {
+        AddBatchPayload::One(mut req) => {
+            if key.is_some() {
+                if req.batch_id.is_some() {
+                    return error_response("Send either the Idempotency-Key header or batch_id, not both".to_string());
+                }
+                req.batch_id = key;
+            }
+            let (symbol, batch) = to_batch(*req);
+            if query.dry_run {
+                let result = match batch {
+                    Ok(batch) => service.check_batch(symbol, batch).await,
+                    Err(e) => Err(e.into()),
+                };
+                return match result {
+                    Ok(check) => respond(&http_req, &check),
+                    Err(e) => error_response(e),
+                };
+            }
+            let result = match batch {
+                Ok(batch) => service.add_batch(symbol, batch).await,
+                Err(e) => Err(e.into()),
+            };
+            return match result {
+                Ok(_) => HttpResponse::Ok().body("Batch data added successfully"),
+                Err(e) => error_response(e),
+            };
+        }
+        AddBatchPayload::Many(_) if key.is_some() => {
+            return error_response("Idempotency-Key applies to a single batch; give each entry a batch_id".to_string());
+        }
+        AddBatchPayload::Many(requests) => requests,
+    };
+
+    // Entries that don't convert are reported without reaching the service.
+    let mut symbols = Vec::with_capacity(requests.len());
+    let mut failed = Vec::with_capacity(requests.len());
+    let mut batches = Vec::with_capacity(requests.len());
+    for (symbol, batch) in requests.into_iter().map(to_batch) {
+        match batch {
+            Ok(batch) => {
+                batches.push((symbol.clone(), batch));
+                failed.push(None);
+            }
+            Err(e) => failed.push(Some(e)),
+        }
+        symbols.push(symbol);
+    }
+    let outcomes: Result<Vec<Result<Option<BatchCheck>, ServiceError>>, ServiceError> = if query.dry_run {
+        service.check_batches(batches).await.map(|checks| checks.into_iter().map(|check| check.map(Some)).collect())
+    } else {
+        service.add_batches(batches).await.map(|added| added.into_iter().map(|added| added.map(|_| None)).collect())
+    };
+    let mut outcomes = match outcomes {
+        Ok(outcomes) => outcomes.into_iter(),
+        Err(e) => return error_response(e),
+    };
+    let results: Vec<AddBatchResult> = symbols
+        .into_iter()
+        .zip(failed)
+        .map(|(symbol, failed)| {
+            let result = match failed {
+                Some(e) => Err(e.into()),
+                None => outcomes.next().unwrap(),
+            };
+            let (check, error) = match result {
+                Ok(check) => (check, None),
+                Err(e) => (None, Some(e)),
+            };
+            AddBatchResult {
+                symbol,
+                check,
+                code: error.as_ref().map(ServiceError::code),
+                error: error.map(|e| e.to_string()),
+            }
+        })
+        .collect();
+    respond(&http_req, &results)
+}
+
+/// Adds one batch sent as the `AddBatchRequest` message of
+/// `proto/trading.proto`, cheaper to encode and smaller than JSON.
+async fn add_batch_pb(
+    service: Tenant,
+    http_req: HttpRequest,
+    req: Proto<grpc::proto::AddBatchRequest>,
 ) -> impl Responder {
-    match service.add_batch_values(req.symbol.clone(), req.values.clone()).await {
+    let mut req = req.into_inner();
+    match http_req.headers().get(IDEMPOTENCY_KEY_HEADER).map(|value| value.to_str()) {
+        None => {}
+        Some(Ok(_)) if req.batch_id.is_some() => {
+            return error_response("Send either the Idempotency-Key header or batch_id, not both".to_string());
+        }
+        Some(Ok(key)) => req.batch_id = Some(key.to_string()),
+        Some(Err(_)) => return error_response("Invalid Idempotency-Key header".to_string()),
+    }
+    let symbol = std::mem::take(&mut req.symbol);
+    match service.add_batch(symbol, req.into()).await {
         Ok(_) => HttpResponse::Ok().body("Batch data added successfully"),
-        Err(e) => HttpResponse::BadRequest().json(ErrorResponse { error: e }),
+        Err(e) => error_response(e),
     }
 }
 
+/// Failed lines an `/ingest` summary lists; the rest are only counted.
+const MAX_INGEST_ERRORS: usize = 100;
+
+/// One line of an `/ingest` stream: a single tick, or a batch in the
+/// `/add_batch` form.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum IngestLine {
+    Tick {
+        symbol: String,
+        #[serde(flatten)]
+        point: Point,
+    },
+    Batch(Box<AddBatchRequest>),
+}
+
+#[derive(Debug, Serialize)]
+struct IngestError {
+    line: usize,
+    error: String,
+    code: &'static str,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct IngestSummary {
+    lines: usize,
+    added: usize,
+    failed: usize,
+    /// The first failed lines, by line number.
+    errors: Vec<IngestError>,
+    /// Why the stream was cut short, if it was.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    aborted: Option<String>,
+}
+
+impl IngestSummary {
+    fn fail(&mut self, line: usize, e: ServiceError) {
+        self.failed += 1;
+        if self.errors.len() < MAX_INGEST_ERRORS {
+            self.errors.push(IngestError { line, error: e.to_string(), code: e.code() });
+        }
+    }
+
+    /// Parses one line, leaving blank ones out.
+    fn parse(&mut self, line: usize, bytes: &[u8]) -> Option<(String, Batch)> {
+        if bytes.iter().all(u8::is_ascii_whitespace) {
+            return None;
+        }
+        self.lines += 1;
+        let parsed = serde_json::from_slice::<IngestLine>(bytes)
+            .map_err(|e| format!("Invalid line: {}", e))
+            .and_then(|parsed| match parsed {
+                IngestLine::Tick { symbol, point } => Batch::from_points(&[point]).map(|batch| (symbol, batch)),
+                // A tick that didn't parse lands here without values.
+                IngestLine::Batch(req) if req.values.is_empty() && req.points.is_none() => {
+                    Err("Invalid line. Expected a tick with a numeric value, or values or points.".to_string())
+                }
+                IngestLine::Batch(req) => {
+                    let (symbol, batch) = to_batch(*req);
+                    batch.map(|batch| (symbol, batch))
+                }
+            });
+        parsed.map_err(|e| self.fail(line, e.into())).ok()
+    }
+
+    /// Adds the parsed lines, up to `MAX_BATCH_ENTRIES` at a time.
+    async fn apply(&mut self, service: &TradingDataService, parsed: Vec<(usize, String, Batch)>) {
+        let mut parsed = parsed.into_iter().peekable();
+        while parsed.peek().is_some() {
+            let (lines, batches): (Vec<usize>, Vec<(String, Batch)>) = parsed
+                .by_ref()
+                .take(MAX_BATCH_ENTRIES)
+                .map(|(line, symbol, batch)| (line, (symbol, batch)))
+                .unzip();
+            let results = match service.add_batches(batches).await {
+                Ok(results) => results,
+                Err(e) => vec![Err(e); lines.len()],
+            };
+            for (line, result) in lines.into_iter().zip(results) {
+                match result {
+                    Ok(_) => self.added += 1,
+                    Err(e) => self.fail(line, e),
+                }
+            }
+        }
+    }
+}
+
+/// Takes a long-lived stream of newline-delimited JSON lines, adding the
+/// complete lines of each chunk as it arrives.
+async fn ingest(service: Tenant, http_req: HttpRequest, payload: web::Payload) -> impl Responder {
+    if let Err(e) = check_encoding(&http_req) {
+        return error_response(e);
+    }
+    let mut stream = Decompress::from_headers(payload.into_inner(), http_req.headers());
+    let mut summary = IngestSummary::default();
+    let mut pending: Vec<u8> = Vec::new();
+    let mut line = 0;
+    let mut status = HttpResponse::Ok();
+
+    loop {
+        let chunk = match stream.next().await {
+            Some(Ok(chunk)) => Some(chunk),
+            Some(Err(e)) => {
+                summary.aborted = Some(format!("Invalid request body: {}", e));
+                status = HttpResponse::BadRequest();
+                break;
+            }
+            None => None,
+        };
+        let last = chunk.is_none();
+        pending.extend_from_slice(&chunk.unwrap_or_default());
+
+        let mut parsed = Vec::new();
+        let mut start = 0;
+        while let Some(end) = pending[start..].iter().position(|&b| b == b'\n').map(|i| start + i) {
+            line += 1;
+            if let Some((symbol, batch)) = summary.parse(line, &pending[start..end]) {
+                parsed.push((line, symbol, batch));
+            }
+            start = end + 1;
+        }
+        pending.drain(..start);
+        if last && !pending.is_empty() {
+            line += 1;
+            if let Some((symbol, batch)) = summary.parse(line, &pending) {
+                parsed.push((line, symbol, batch));
+            }
+        }
+        summary.apply(&service, parsed).await;
+
+        if last {
+            break;
+        }
+        if pending.len() > MAX_BODY_BYTES {
+            summary.aborted = Some(format!("Line {} exceeds {} bytes", line + 1, MAX_BODY_BYTES));
+            status = HttpResponse::PayloadTooLarge();
+            break;
+        }
+    }
+    status.json(summary)
+}
+
+async fn add_quotes(
+    service: Tenant,
+    req: web::Json<AddQuotesRequest>,
+) -> impl Responder {
+    let AddQuotesRequest { symbol, quotes } = req.into_inner();
+    match service.add_quotes(symbol, quotes).await {
+        Ok(_) => HttpResponse::Ok().body("Quotes added successfully"),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn get_quote_stats(
+    service: Tenant,
+    query: web::Query<WindowQuery>,
+) -> impl Responder {
+    let query = query.into_inner();
+    match service.get_quote_stats(query.symbol, query.k as usize).await {
+        Ok(stats) => HttpResponse::Ok().json(stats),
+        Err(e) => error_response(e),
+    }
+}
+
+/// A wildcard match with only the selected fields.
+#[derive(Serialize)]
+struct SelectedSymbolStats<'a> {
+    symbol: &'a str,
+    stats: SelectedStats<'a>,
+}
+
+fn stats_options(query: &GetStatsQuery) -> Result<StatsOptions, String> {
+    Ok(StatsOptions {
+        quantiles: parse_number_list(query.quantiles.as_deref())?,
+        on: query.on,
+        periods_per_year: query.periods_per_year,
+        field: query.field.clone(),
+        venue: query.venue.clone(),
+        extended_hours: query.extended_hours,
+        currency: query.currency.clone(),
+    })
+}
+
 async fn get_stats(
-    service: web::Data<TradingDataService>,
+    service: Tenant,
+    http_req: HttpRequest,
     query: web::Query<GetStatsQuery>,
 ) -> impl Responder {
-    match service.get_stats(query.symbol.clone(), query.k as usize).await {
+    let query = query.into_inner();
+    let mut options = match stats_options(&query) {
+        Ok(options) => options,
+        Err(e) => return error_response(e),
+    };
+    let selection = match query.fields.as_deref().map(FieldSelection::parse).transpose() {
+        Ok(selection) => selection,
+        Err(e) => return error_response(e),
+    };
+    // Quantiles nobody asked for aren't worth computing.
+    if selection.as_ref().is_some_and(|selection| !selection.contains("quantiles")) {
+        options.quantiles.clear();
+    }
+
+    if query.symbol.contains(['*', '?']) {
+        let result = match (query.k, query.n, query.window.as_deref(), query.as_of) {
+            (Some(k), None, None, None) => service.get_stats_matching(&query.symbol, k as usize, &options).await,
+            _ => Err("Symbol patterns work with k only".to_string().into()),
+        };
+        return match (result, &selection) {
+            (Ok(matched), Some(selection)) => {
+                let selected: Vec<_> = matched
+                    .iter()
+                    .map(|matched| SelectedSymbolStats { symbol: &matched.symbol, stats: selection.apply(&matched.stats) })
+                    .collect();
+                respond(&http_req, &selected)
+            }
+            (Ok(matched), None) => respond(&http_req, &matched),
+            (Err(e), _) => error_response(e),
+        };
+    }
+
+    // Taken before the stats, so data arriving in between can only make
+    // the tag older than the response, never newer.
+    let tag = service.stats_tag(query.symbol.clone()).await;
+    let GetStatsQuery { symbol, k, n, window, as_of, .. } = query;
+    let result = match (k, n, window.as_deref()) {
+        (Some(k), None, None) => match as_of {
+            Some(as_of) if (1..=8).contains(&k) => {
+                service.get_stats_as_of(symbol, 10usize.pow(k as u32), as_of, &options).await
+            }
+            Some(_) => Err(ServiceError::InvalidK),
+            None => service.get_stats_with(symbol, k as usize, &options).await,
+        },
+        (None, Some(n), None) => match as_of {
+            Some(as_of) => service.get_stats_as_of(symbol, n, as_of, &options).await,
+            None => service.get_stats_for_last(symbol, n, &options).await,
+        },
+        (None, None, Some(_)) if as_of.is_some() => Err("as_of works with k or n only".to_string().into()),
+        (None, None, Some(window)) => match parse_interval(window) {
+            Ok(duration_ms) => service.get_stats_over(symbol, duration_ms, &options).await,
+            Err(e) => Err(e.into()),
+        },
+        _ => Err("Exactly one of k, n and window is required".to_string().into()),
+    };
+    match (result, tag, &selection) {
+        (Ok(stats), Ok(tag), Some(selection)) => respond_tagged(&http_req, &selection.apply(&stats), &tag),
+        (Ok(stats), Err(_), Some(selection)) => respond(&http_req, &selection.apply(&stats)),
+        (Ok(stats), Ok(tag), None) => respond_tagged(&http_req, &stats, &tag),
+        (Ok(stats), Err(_), None) => respond(&http_req, &stats),
+        (Err(e), _, _) => error_response(e),
+    }
+}
+
+/// Shortest and longest push interval of `/stats/stream`, in milliseconds.
+const MIN_STREAM_INTERVAL_MS: u64 = 10;
+const MAX_STREAM_INTERVAL_MS: u64 = 60_000;
+
+#[derive(Debug, Deserialize)]
+struct StatsStreamQuery {
+    symbol: String,
+    k: u8,
+    /// Time between snapshots, default 1000.
+    interval_ms: Option<u64>,
+    /// Condition a snapshot is only sent when it has come to hold.
+    when: Option<String>,
+}
+
+/// One Server-Sent Event carrying the value as JSON.
+fn sse_event<T: Serialize>(event: &str, value: &T) -> web::Bytes {
+    let data = serde_json::to_string(value).unwrap_or_default();
+    web::Bytes::from(format!("event: {}\ndata: {}\n\n", event, data))
+}
+
+/// Pushes a stats snapshot of the window as a Server-Sent Event every
+/// interval, or only at the intervals where a `when` condition came to
+/// hold, ending with an `error` event if the symbol goes away.
+async fn stream_stats(service: Tenant, query: web::Query<StatsStreamQuery>) -> impl Responder {
+    let interval_ms = query.interval_ms.unwrap_or(1000);
+    if !(MIN_STREAM_INTERVAL_MS..=MAX_STREAM_INTERVAL_MS).contains(&interval_ms) {
+        return error_response(format!(
+            "Invalid interval_ms input. Only values {}-{} are accepted.",
+            MIN_STREAM_INTERVAL_MS, MAX_STREAM_INTERVAL_MS
+        ));
+    }
+    let StatsStreamQuery { symbol, k, when, .. } = query.into_inner();
+    let condition = match when.map(|when| Condition::parse(&when, |stat| CONDITION_STATS.contains(&stat))) {
+        Some(Ok(condition)) => Some(condition),
+        Some(Err(e)) => return error_response(e),
+        None => None,
+    };
+    // Checked upfront so an unknown symbol or bad k fails with its status.
+    if let Err(e) = service.get_stats(symbol.clone(), k as usize).await {
+        return error_response(e);
+    }
+
+    let ticks = tokio::time::interval(std::time::Duration::from_millis(interval_ms));
+    let service = service.0;
+    let events = futures::stream::unfold(Some((ticks, condition)), move |state| {
+        let (service, symbol) = (service.clone(), symbol.clone());
+        async move {
+            let (mut ticks, mut condition) = state?;
+            let result = loop {
+                ticks.tick().await;
+                let Some(condition) = condition.as_mut() else {
+                    break service.get_stats(symbol, k as usize).await;
+                };
+                match service.check_condition(symbol.clone(), condition).await {
+                    Ok(true) => break service.get_stats(symbol, k as usize).await,
+                    Ok(false) => continue,
+                    Err(e) => break Err(e),
+                }
+            };
+            match result {
+                Ok(stats) => Some((Ok::<_, actix_web::Error>(sse_event("stats", &stats)), Some((ticks, condition)))),
+                Err(e) => Some((Ok(sse_event("error", &ErrorResponse::from(&e))), None)),
+            }
+        }
+    });
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(events)
+}
+
+/// Longest wait `/stats/wait` accepts, in milliseconds.
+const MAX_WAIT_TIMEOUT_MS: u64 = 60_000;
+
+#[derive(Debug, Deserialize)]
+struct StatsWaitQuery {
+    symbol: String,
+    k: u8,
+    /// How long to wait for new data, default 30000.
+    timeout_ms: Option<u64>,
+    /// `last_update` of the stats the client last saw; newer data that
+    /// arrived since answers right away.
+    since: Option<u64>,
+}
+
+/// Answers with the window's stats once the symbol takes new data, or with
+/// `204 No Content` if none arrives before the timeout.
+async fn wait_stats(service: Tenant, http_req: HttpRequest, query: web::Query<StatsWaitQuery>) -> impl Responder {
+    let timeout_ms = query.timeout_ms.unwrap_or(30_000);
+    if !(1..=MAX_WAIT_TIMEOUT_MS).contains(&timeout_ms) {
+        return error_response(format!("Invalid timeout_ms input. Only values 1-{} are accepted.", MAX_WAIT_TIMEOUT_MS));
+    }
+    let StatsWaitQuery { symbol, k, since, .. } = query.into_inner();
+    let symbol = service.canonical(symbol);
+    // Subscribed before the first read so no change in between is missed.
+    let mut updates = service.subscribe();
+    let stats = match service.get_stats(symbol.clone(), k as usize).await {
+        Ok(stats) => stats,
+        Err(e) => return error_response(e),
+    };
+    if since.is_some_and(|since| stats.last_update > since) {
+        return respond(&http_req, &stats);
+    }
+
+    let updated = async {
+        loop {
+            match updates.recv().await {
+                Ok(updated) if updated != symbol => continue,
+                Ok(_) | Err(RecvError::Lagged(_)) => return true,
+                Err(RecvError::Closed) => return false,
+            }
+        }
+    };
+    match tokio::time::timeout(std::time::Duration::from_millis(timeout_ms), updated).await {
+        Ok(true) => match service.get_stats(symbol, k as usize).await {
+            Ok(stats) => respond(&http_req, &stats),
+            Err(e) => error_response(e),
+        },
+        Ok(false) | Err(_) => HttpResponse::NoContent().finish(),
+    }
+}
+
+async fn get_all_stats(
+    service: Tenant,
+    http_req: HttpRequest,
+    query: web::Query<GetStatsQuery>,
+) -> impl Responder {
+    let query = query.into_inner();
+    if query.k.is_some() || query.n.is_some() || query.window.is_some() {
+        let error = "k, n and window don't apply to every window at once".to_string();
+        return error_response(error);
+    }
+    let result = match stats_options(&query) {
+        Ok(options) => service.get_all_stats(query.symbol, &options).await,
+        Err(e) => Err(e.into()),
+    };
+    match result {
+        Ok(stats) => respond(&http_req, &stats),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn compare_windows(
+    service: Tenant,
+    query: web::Query<CompareQuery>,
+) -> impl Responder {
+    let query = query.into_inner();
+    match service.compare_windows(query.symbol, query.k1 as usize, query.k2 as usize).await {
+        Ok(comparison) => HttpResponse::Ok().json(comparison),
+        Err(e) => error_response(e),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct StatsBatchEntry {
+    symbol: String,
+    k: u8,
+}
+
+#[derive(Debug, Serialize)]
+struct StatsBatchResult {
+    symbol: String,
+    k: u8,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stats: Option<StatsResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<&'static str>,
+}
+
+async fn get_stats_batch(
+    service: Tenant,
+    http_req: HttpRequest,
+    req: Body<Vec<StatsBatchEntry>>,
+) -> impl Responder {
+    let requests = req.iter().map(|e| (e.symbol.clone(), e.k as usize)).collect();
+    match service.get_stats_batch(requests).await {
+        Ok(results) => {
+            let results: Vec<StatsBatchResult> = req
+                .into_inner()
+                .into_iter()
+                .zip(results)
+                .map(|(entry, result)| {
+                    let (stats, error, code) = match result {
+                        Ok(stats) => (Some(stats), None, None),
+                        Err(e) => (None, Some(e.to_string()), Some(e.code())),
+                    };
+                    StatsBatchResult { symbol: entry.symbol, k: entry.k, stats, error, code }
+                })
+                .collect();
+            respond(&http_req, &results)
+        }
+        Err(e) => error_response(e),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ValuesQuery {
+    symbol: String,
+    k: u8,
+    /// Points to return, default 500.
+    limit: Option<usize>,
+    /// Newest points to skip, default 0.
+    #[serde(default)]
+    offset: usize,
+}
+
+async fn get_values(
+    service: Tenant,
+    query: web::Query<ValuesQuery>,
+) -> impl Responder {
+    let query = query.into_inner();
+    let limit = query.limit.unwrap_or(500);
+    match service.get_values(query.symbol, query.k as usize, query.offset, limit).await {
+        Ok(page) => HttpResponse::Ok().json(page),
+        Err(e) => error_response(e),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ScanQuery {
+    /// Continues the scan the cursor names; otherwise `symbol` and `k` start
+    /// one.
+    cursor: Option<String>,
+    symbol: Option<String>,
+    k: Option<u8>,
+    /// Points to return, default 500.
+    limit: Option<usize>,
+}
+
+async fn scan_values(
+    service: Tenant,
+    query: web::Query<ScanQuery>,
+) -> impl Responder {
+    let limit = query.limit.unwrap_or(500);
+    let result = match (&query.cursor, &query.symbol, query.k) {
+        (Some(cursor), None, None) => service.next_scan_values(cursor, limit).await,
+        (None, Some(symbol), Some(k)) => service.scan_values(symbol.clone(), k as usize, limit).await,
+        _ => Err("Either cursor, or symbol and k, are required.".to_string().into()),
+    };
+    match result {
+        Ok(page) => HttpResponse::Ok().json(page),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn get_top_movers(
+    service: Tenant,
+    query: web::Query<TopQuery>,
+) -> impl Responder {
+    let limit = query.limit.unwrap_or(20);
+    match service.get_top_movers(query.metric, query.k as usize, limit).await {
+        Ok(movers) => HttpResponse::Ok().json(movers),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn get_ranking(
+    service: Tenant,
+    query: web::Query<RankQuery>,
+) -> impl Responder {
+    let limit = query.limit.unwrap_or(20);
+    match service.get_ranking(&query.metric, query.k as usize, query.order, limit).await {
+        Ok(ranked) => HttpResponse::Ok().json(ranked),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn get_group_stats(
+    service: Tenant,
+    query: web::Query<GroupStatsQuery>,
+) -> impl Responder {
+    let filter = match TagFilter::parse(&query.tag) {
+        Ok(filter) => filter,
+        Err(e) => return error_response(e),
+    };
+    match service.get_group_stats(&filter, query.metric, query.k as usize).await {
+        Ok(groups) => HttpResponse::Ok().json(groups),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn get_bars(
+    service: Tenant,
+    query: web::Query<BarsQuery>,
+) -> impl Responder {
+    let query = query.into_inner();
+    let interval_ms = match parse_interval(&query.interval) {
+        Ok(interval_ms) => interval_ms,
+        Err(e) => return error_response(e),
+    };
+    let limit = query.limit.unwrap_or(100);
+
+    match service.get_bars(query.symbol, interval_ms, limit).await {
+        Ok(bars) => HttpResponse::Ok().json(bars),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn get_bollinger(
+    service: Tenant,
+    query: web::Query<BollingerQuery>,
+) -> impl Responder {
+    let query = query.into_inner();
+    let width = query.width.unwrap_or(2.0);
+    match service.get_bollinger(query.symbol, query.k as usize, width).await {
+        Ok(bands) => HttpResponse::Ok().json(bands),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn get_rsi(
+    service: Tenant,
+    query: web::Query<WindowQuery>,
+) -> impl Responder {
+    let query = query.into_inner();
+    match service.get_rsi(query.symbol, query.k as usize).await {
+        Ok(rsi) => HttpResponse::Ok().json(rsi),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn get_macd(
+    service: Tenant,
+    query: web::Query<SymbolQuery>,
+) -> impl Responder {
+    let query = query.into_inner();
+    match service.get_macd(query.symbol).await {
+        Ok(macd) => HttpResponse::Ok().json(macd),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn get_atr(
+    service: Tenant,
+    query: web::Query<AtrQuery>,
+) -> impl Responder {
+    let query = query.into_inner();
+    let interval_ms = match parse_interval(&query.interval) {
+        Ok(interval_ms) => interval_ms,
+        Err(e) => return error_response(e),
+    };
+    let period = query.period.unwrap_or(14);
+
+    match service.get_atr(query.symbol, interval_ms, period).await {
+        Ok(atr) => HttpResponse::Ok().json(atr),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn get_moving_average(
+    service: Tenant,
+    query: web::Query<MovingAverageQuery>,
+) -> impl Responder {
+    let query = query.into_inner();
+    match service.get_moving_average(query.symbol, query.window, query.kind).await {
+        Ok(ma) => HttpResponse::Ok().json(ma),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn get_value_at_risk(
+    service: Tenant,
+    query: web::Query<VarQuery>,
+) -> impl Responder {
+    let query = query.into_inner();
+    let confidences = match query.confidence.as_deref() {
+        None => Ok(vec![0.95, 0.99]),
+        raw => parse_number_list(raw),
+    };
+    let confidences = match confidences {
+        Ok(confidences) => confidences,
+        Err(e) => return error_response(e),
+    };
+
+    match service.get_value_at_risk(query.symbol, query.k as usize, &confidences).await {
+        Ok(var) => HttpResponse::Ok().json(var),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn get_sharpe(
+    service: Tenant,
+    query: web::Query<SharpeQuery>,
+) -> impl Responder {
+    let query = query.into_inner();
+    let rf = query.rf.unwrap_or(0.0);
+    let periods_per_year = query.periods_per_year.unwrap_or(252.0);
+    match service.get_sharpe(query.symbol, query.k as usize, rf, periods_per_year).await {
+        Ok(sharpe) => HttpResponse::Ok().json(sharpe),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn get_hurst(
+    service: Tenant,
+    query: web::Query<WindowQuery>,
+) -> impl Responder {
+    let query = query.into_inner();
+    match service.get_hurst(query.symbol, query.k as usize).await {
+        Ok(hurst) => HttpResponse::Ok().json(hurst),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn get_autocorrelation(
+    service: Tenant,
+    query: web::Query<AcfQuery>,
+) -> impl Responder {
+    let query = query.into_inner();
+    let lags = query.lags.unwrap_or(20);
+    match service.get_autocorrelation(query.symbol, query.k as usize, lags).await {
+        Ok(acf) => HttpResponse::Ok().json(acf),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn get_entropy(
+    service: Tenant,
+    query: web::Query<EntropyQuery>,
+) -> impl Responder {
+    let query = query.into_inner();
+    let bins = query.bins.unwrap_or(20);
+    match service.get_entropy(query.symbol, query.k as usize, bins).await {
+        Ok(entropy) => HttpResponse::Ok().json(entropy),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn get_correlation(
+    service: Tenant,
+    query: web::Query<CorrelationQuery>,
+) -> impl Responder {
+    let query = query.into_inner();
+    match service.get_correlation(query.symbol1, query.symbol2, query.k as usize).await {
+        Ok(result) => HttpResponse::Ok().json(result),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn get_covariance(
+    service: Tenant,
+    query: web::Query<CovarianceQuery>,
+) -> impl Responder {
+    let symbols = query.symbols.split(',').map(|s| s.trim().to_string()).collect();
+    match service.get_covariance(symbols, query.k as usize).await {
+        Ok(result) => HttpResponse::Ok().json(result),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn get_symbol_config(
+    service: Tenant,
+    path: web::Path<SymbolPath>,
+) -> impl Responder {
+    match service.get_symbol_config(path.into_inner().symbol).await {
+        Ok(config) => HttpResponse::Ok().json(config),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn set_symbol_config(
+    service: Tenant,
+    path: web::Path<SymbolPath>,
+    config: web::Json<SymbolConfig>,
+) -> impl Responder {
+    match service.set_symbol_config(path.into_inner().symbol, config.into_inner()).await {
+        Ok(_) => HttpResponse::Ok().body("Symbol config updated successfully"),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn get_symbol_meta(
+    service: Tenant,
+    path: web::Path<SymbolPath>,
+) -> impl Responder {
+    match service.get_symbol_meta(path.into_inner().symbol).await {
+        Ok(meta) => HttpResponse::Ok().json(meta),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn set_symbol_meta(
+    service: Tenant,
+    path: web::Path<SymbolPath>,
+    meta: web::Json<SymbolMeta>,
+) -> impl Responder {
+    match service.set_symbol_meta(path.into_inner().symbol, meta.into_inner()).await {
+        Ok(_) => HttpResponse::Ok().body("Symbol metadata updated successfully"),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn delete_symbol_meta(
+    service: Tenant,
+    path: web::Path<SymbolPath>,
+) -> impl Responder {
+    match service.delete_symbol_meta(path.into_inner().symbol).await {
+        Ok(_) => HttpResponse::Ok().body("Symbol metadata deleted successfully"),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn get_symbol_tags(
+    service: Tenant,
+    path: web::Path<SymbolPath>,
+) -> impl Responder {
+    match service.get_symbol_meta(path.into_inner().symbol).await {
+        Ok(meta) => HttpResponse::Ok().json(meta.tags),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn set_symbol_tags(
+    service: Tenant,
+    path: web::Path<SymbolPath>,
+    tags: web::Json<BTreeMap<String, String>>,
+) -> impl Responder {
+    match service.set_symbol_tags(path.into_inner().symbol, tags.into_inner()).await {
+        Ok(_) => HttpResponse::Ok().body("Symbol tags updated successfully"),
+        Err(e) => error_response(e),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ListSymbolsQuery {
+    /// Only symbols starting with this.
+    prefix: Option<String>,
+    /// Symbols to skip, default 0.
+    #[serde(default)]
+    offset: usize,
+    /// Symbols per page, default 100.
+    limit: Option<usize>,
+}
+
+async fn list_symbols(service: Tenant, query: web::Query<ListSymbolsQuery>) -> impl Responder {
+    let limit = query.limit.unwrap_or(100);
+    match service.list_symbols(query.prefix.as_deref(), query.offset, limit).await {
+        Ok(page) => HttpResponse::Ok().json(page),
+        Err(e) => error_response(e),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ResetQuery {
+    /// Only the 10^k windows; every window when absent.
+    k: Option<u8>,
+}
+
+async fn reset_symbol(
+    service: Tenant,
+    path: web::Path<SymbolPath>,
+    query: web::Query<ResetQuery>,
+) -> impl Responder {
+    match service.reset_symbol(path.into_inner().symbol, query.k.map(|k| k as usize)).await {
+        Ok(_) => HttpResponse::Ok().body("Symbol reset successfully"),
+        Err(e) => error_response(e),
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct RemoveSymbolResponse {
+    /// Approximate memory released, in bytes.
+    freed_bytes: usize,
+}
+
+async fn remove_symbol(service: Tenant, path: web::Path<SymbolPath>) -> impl Responder {
+    match service.remove_symbol(path.into_inner().symbol).await {
+        Ok(freed_bytes) => HttpResponse::Ok().json(RemoveSymbolResponse { freed_bytes }),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn get_fx(service: Tenant, path: web::Path<SymbolPath>) -> impl Responder {
+    match service.get_fx(path.into_inner().symbol).await {
+        Ok(config) => HttpResponse::Ok().json(config),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn set_fx(
+    service: Tenant,
+    path: web::Path<SymbolPath>,
+    config: web::Json<FxConfig>,
+) -> impl Responder {
+    match service.set_fx(path.into_inner().symbol, config.into_inner()).await {
+        Ok(_) => HttpResponse::Ok().body("FX conversion set successfully"),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn remove_fx(service: Tenant, path: web::Path<SymbolPath>) -> impl Responder {
+    match service.remove_fx(path.into_inner().symbol).await {
+        Ok(_) => HttpResponse::Ok().body("FX conversion removed successfully"),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn get_fast_path(service: Tenant, path: web::Path<SymbolPath>) -> impl Responder {
+    match service.fast_path_stats(path.into_inner().symbol) {
         Ok(stats) => HttpResponse::Ok().json(stats),
-        Err(e) => HttpResponse::BadRequest().json(ErrorResponse { error: e }),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn enable_fast_path(service: Tenant, path: web::Path<SymbolPath>, query: web::Query<FastPathQuery>) -> impl Responder {
+    match service.enable_fast_path(path.into_inner().symbol, query.core).await {
+        Ok(_) => HttpResponse::Ok().body("Fast path enabled successfully"),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn disable_fast_path(service: Tenant, path: web::Path<SymbolPath>) -> impl Responder {
+    match service.disable_fast_path(path.into_inner().symbol).await {
+        Ok(_) => HttpResponse::Ok().body("Fast path disabled successfully"),
+        Err(e) => error_response(e),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct SetAliasRequest {
+    symbol: String,
+}
+
+async fn list_aliases(service: Tenant) -> impl Responder {
+    HttpResponse::Ok().json(service.aliases())
+}
+
+async fn set_alias(
+    service: Tenant,
+    alias: web::Path<AliasPath>,
+    req: web::Json<SetAliasRequest>,
+) -> impl Responder {
+    match service.set_alias(alias.into_inner().alias, req.into_inner().symbol).await {
+        Ok(_) => HttpResponse::Ok().body("Alias set successfully"),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn remove_alias(service: Tenant, alias: web::Path<AliasPath>) -> impl Responder {
+    match service.remove_alias(&alias.alias) {
+        Ok(_) => HttpResponse::Ok().body("Alias removed successfully"),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn list_derived(service: Tenant) -> impl Responder {
+    HttpResponse::Ok().json(service.derived().await)
+}
+
+async fn get_derived(service: Tenant, path: web::Path<SymbolPath>) -> impl Responder {
+    match service.get_derived(path.into_inner().symbol).await {
+        Ok(config) => HttpResponse::Ok().json(config),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn set_derived(
+    service: Tenant,
+    path: web::Path<SymbolPath>,
+    config: web::Json<DerivedConfig>,
+) -> impl Responder {
+    match service.set_derived(path.into_inner().symbol, config.into_inner()).await {
+        Ok(_) => HttpResponse::Ok().body("Derived symbol set successfully"),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn remove_derived(service: Tenant, path: web::Path<SymbolPath>) -> impl Responder {
+    match service.remove_derived(path.into_inner().symbol).await {
+        Ok(_) => HttpResponse::Ok().body("Derived symbol removed successfully"),
+        Err(e) => error_response(e),
     }
 }
 
+async fn list_baskets(service: Tenant) -> impl Responder {
+    HttpResponse::Ok().json(service.baskets().await)
+}
+
+async fn get_basket(service: Tenant, path: web::Path<SymbolPath>) -> impl Responder {
+    match service.get_basket(path.into_inner().symbol).await {
+        Ok(config) => HttpResponse::Ok().json(config),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn set_basket(
+    service: Tenant,
+    path: web::Path<SymbolPath>,
+    config: web::Json<BasketConfig>,
+) -> impl Responder {
+    match service.set_basket(path.into_inner().symbol, config.into_inner()).await {
+        Ok(_) => HttpResponse::Ok().body("Basket set successfully"),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Pending batch writes beyond which the instance reports itself not ready.
+const MAX_PENDING_WRITES: usize = 1000;
+
+#[derive(Debug, Serialize)]
+struct HealthResponse {
+    status: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+struct ReadinessResponse {
+    ready: bool,
+    namespaces: usize,
+    pending_writes: usize,
+    max_pending_writes: usize,
+}
+
+async fn healthz() -> impl Responder {
+    HttpResponse::Ok().json(HealthResponse { status: "ok" })
+}
+
+async fn readyz(namespaces: web::Data<Namespaces>) -> impl Responder {
+    let pending_writes = namespaces.pending_writes();
+    let readiness = ReadinessResponse {
+        ready: pending_writes <= MAX_PENDING_WRITES,
+        namespaces: namespaces.count(),
+        pending_writes,
+        max_pending_writes: MAX_PENDING_WRITES,
+    };
+    if readiness.ready {
+        HttpResponse::Ok().json(readiness)
+    } else {
+        HttpResponse::ServiceUnavailable().json(readiness)
+    }
+}
+
+fn parse_number_list(raw: Option<&str>) -> Result<Vec<f64>, String> {
+    match raw {
+        None | Some("") => Ok(Vec::new()),
+        Some(raw) => raw
+            .split(',')
+            .map(|q| q.trim().parse::<f64>().map_err(|_| format!("Invalid number '{}'", q)))
+            .collect(),
+    }
+}
+
+/// The data endpoints, served both at the root and under `/ns/{ns}`.
+fn routes(cfg: &mut web::ServiceConfig) {
+    cfg
+        .route("/add_batch", web::post().guard(guard::fn_guard(upload::is_streamed)).to(upload::add_batch_stream))
+        .route("/add_batch", web::post().to(add_batch))
+        .route("/add_batch_pb", web::post().to(add_batch_pb))
+        .route("/ingest", web::post().to(ingest))
+        .route("/add_quotes", web::post().to(add_quotes))
+        .route("/quotes/stats", web::get().to(get_quote_stats))
+        .route("/stats", web::get().to(get_stats))
+        .route("/stats/compare", web::get().to(compare_windows))
+        .route("/stats/batch", web::post().to(get_stats_batch))
+        .route("/stats/all", web::get().to(get_all_stats))
+        .route("/stats/stream", web::get().to(stream_stats))
+        .route("/stats/wait", web::get().to(wait_stats))
+        .route("/stats/group", web::get().to(get_group_stats))
+        .route("/ws", web::get().to(ws::stats_ws))
+        .route("/values", web::get().to(get_values))
+        .route("/values/scan", web::get().to(scan_values))
+        .route("/export/arrow", web::get().to(export::export_arrow))
+        .route("/top", web::get().to(get_top_movers))
+        .route("/rank", web::get().to(get_ranking))
+        .route("/bars", web::get().to(get_bars))
+        .route("/indicators/bollinger", web::get().to(get_bollinger))
+        .route("/indicators/rsi", web::get().to(get_rsi))
+        .route("/indicators/macd", web::get().to(get_macd))
+        .route("/indicators/atr", web::get().to(get_atr))
+        .route("/ma", web::get().to(get_moving_average))
+        .route("/risk/var", web::get().to(get_value_at_risk))
+        .route("/risk/sharpe", web::get().to(get_sharpe))
+        .route("/analytics/hurst", web::get().to(get_hurst))
+        .route("/analytics/acf", web::get().to(get_autocorrelation))
+        .route("/analytics/entropy", web::get().to(get_entropy))
+        .route("/correlation", web::get().to(get_correlation))
+        .route("/covariance", web::get().to(get_covariance))
+        .route("/symbols", web::get().to(list_symbols))
+        .route("/symbols/{symbol}", web::delete().to(remove_symbol))
+        .route("/symbols/{symbol}/reset", web::post().to(reset_symbol))
+        .route("/symbols/{symbol}/config", web::get().to(get_symbol_config))
+        .route("/symbols/{symbol}/config", web::put().to(set_symbol_config))
+        .route("/symbols/{symbol}/meta", web::get().to(get_symbol_meta))
+        .route("/symbols/{symbol}/meta", web::put().to(set_symbol_meta))
+        .route("/symbols/{symbol}/meta", web::delete().to(delete_symbol_meta))
+        .route("/symbols/{symbol}/tags", web::get().to(get_symbol_tags))
+        .route("/symbols/{symbol}/tags", web::put().to(set_symbol_tags))
+        .route("/symbols/{symbol}/fx", web::get().to(get_fx))
+        .route("/symbols/{symbol}/fx", web::put().to(set_fx))
+        .route("/symbols/{symbol}/fx", web::delete().to(remove_fx))
+        .route("/symbols/{symbol}/fast_path", web::get().to(get_fast_path))
+        .route("/symbols/{symbol}/fast_path", web::put().to(enable_fast_path))
+        .route("/symbols/{symbol}/fast_path", web::delete().to(disable_fast_path))
+        .route("/aliases", web::get().to(list_aliases))
+        .route("/aliases/{alias}", web::put().to(set_alias))
+        .route("/aliases/{alias}", web::delete().to(remove_alias))
+        .route("/derived", web::get().to(list_derived))
+        .route("/derived/{symbol}", web::get().to(get_derived))
+        .route("/derived/{symbol}", web::put().to(set_derived))
+        .route("/derived/{symbol}", web::delete().to(remove_derived))
+        .route("/baskets", web::get().to(list_baskets))
+        .route("/baskets/{symbol}", web::get().to(get_basket))
+        .route("/baskets/{symbol}", web::put().to(set_basket))
+        .route("/baskets/{symbol}", web::delete().to(remove_derived))
+        .route("/graphql", web::get().to(graphql::graphql))
+        .route("/graphql", web::post().to(graphql::graphql));
+}
+
+/// Version of the API a response follows, set on every `/v{n}` response.
+const API_VERSION_HEADER: &str = "Api-Version";
+
+/// The v1 API, served under `/v1` and, for clients from before versioning,
+/// without a prefix.
+///
+/// A later version gets its own scope that registers the handlers whose
+/// requests or responses change (say, a reshaped `StatsResponse`) ahead of
+/// the shared routes, so it inherits every endpoint it doesn't override
+/// while v1 keeps its shapes.
+fn v1(cfg: &mut web::ServiceConfig) {
+    cfg.configure(admin::routes)
+        .service(web::scope("/ns/{ns}").configure(routes))
+        .configure(routes);
+}
+
+/// Level of the logs written to stderr, `info` by default.
+const LOG_LEVEL_VAR: &str = "LOG_LEVEL";
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    let service = web::Data::new(TradingDataService::new());
+    let level = match std::env::var(LOG_LEVEL_VAR) {
+        Ok(level) => level.parse().map_err(|_| {
+            std::io::Error::other(format!("Invalid {} input. {:?} isn't a level such as debug.", LOG_LEVEL_VAR, level))
+        })?,
+        Err(_) => LevelFilter::INFO,
+    };
+    tracing_subscriber::fmt().with_max_level(level).with_writer(std::io::stderr).init();
+    let namespaces = web::Data::new(Namespaces::new());
+    let schema = web::Data::new(graphql::schema());
+    let cors = CorsConfig::from_env().map_err(std::io::Error::other)?;
+    let admin_token = web::Data::new(AdminToken::from_env());
 
-    HttpServer::new(move || {
+    let grpc = grpc::serve(namespaces.clone().into_inner(), grpc::GRPC_ADDR.parse().unwrap());
+
+    let http = HttpServer::new(move || {
         App::new()
-            .app_data(service.clone())
-            .route("/add_batch", web::post().to(add_batch))
-            .route("/stats", web::get().to(get_stats))
+            .app_data(namespaces.clone())
+            .app_data(schema.clone())
+            .app_data(admin_token.clone())
+            .app_data(web::PayloadConfig::new(MAX_BODY_BYTES))
+            .wrap(middleware::Condition::new(
+                cors.is_some(),
+                cors.as_ref().map_or_else(Cors::default, CorsConfig::middleware),
+            ))
+            // Outermost, so CORS rejections carry the id too.
+            .wrap(middleware::from_fn(request_id::middleware))
+            .route("/healthz", web::get().to(healthz))
+            .route("/readyz", web::get().to(readyz))
+            .service(
+                web::scope("/v1")
+                    .wrap(middleware::DefaultHeaders::new().add((API_VERSION_HEADER, "1")))
+                    .configure(v1),
+            )
+            // Unversioned paths from before /v1, kept for existing clients.
+            .configure(v1)
     })
         .bind("127.0.0.1:8080")?
-        .run()
-        .await
+        .run();
+
+    // Both APIs share the namespaces; the process ends with either server.
+    tokio::select! {
+        result = http => result,
+        result = grpc => result.map_err(std::io::Error::other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test;
+    use trading_service::settings::{Settings, MAX_BATCH_SIZE_LIMIT};
+
+    use super::*;
+
+    #[actix_rt::test]
+    async fn test_batch_at_size_limit() {
+        let namespaces = web::Data::new(Namespaces::new());
+        let settings = Settings { max_batch_size: MAX_BATCH_SIZE_LIMIT, ..Settings::default() };
+        namespaces.set_settings(settings).unwrap();
+        let app = test::init_service(
+            App::new().app_data(namespaces.clone()).app_data(web::PayloadConfig::new(MAX_BODY_BYTES)).configure(v1),
+        )
+        .await;
+
+        // Full-precision prices, the longest values a client usually sends.
+        let values: Vec<f64> = (0..MAX_BATCH_SIZE_LIMIT).map(|i| 1234.5678901234 + i as f64 / 7.0).collect();
+        let req = test::TestRequest::post()
+            .uri("/add_batch")
+            .set_json(serde_json::json!({ "symbol": "AAPL", "values": values }))
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert!(response.status().is_success(), "{:?}", response.status());
+        let stats = namespaces.get(DEFAULT_NAMESPACE).unwrap().get_stats("AAPL".to_string(), 5).await.unwrap();
+        assert_eq!(MAX_BATCH_SIZE_LIMIT, stats.count);
+    }
 }
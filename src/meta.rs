@@ -0,0 +1,126 @@
+//! Descriptive reference data kept per symbol.
+
+use std::collections::BTreeMap;
+
+/// Longest currency code accepted, e.g. `USD` or `USDT`.
+pub const MAX_CURRENCY_LEN: usize = 8;
+
+/// Longest description accepted.
+pub const MAX_DESCRIPTION_LEN: usize = 256;
+
+/// Most tags a symbol can carry.
+pub const MAX_TAGS: usize = 32;
+
+/// Longest tag name and value accepted.
+pub const MAX_TAG_LEN: usize = 64;
+
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SymbolMeta {
+    /// Smallest price increment.
+    #[serde(default)]
+    pub tick_size: Option<f64>,
+    /// Smallest tradable quantity.
+    #[serde(default)]
+    pub lot_size: Option<f64>,
+    #[serde(default)]
+    pub currency: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Rejects batches holding a price that isn't a multiple of `tick_size`.
+    #[serde(default)]
+    pub reject_off_tick: bool,
+    /// Tags grouping the symbol with others, e.g. `sector: tech`.
+    #[serde(default)]
+    pub tags: BTreeMap<String, String>,
+}
+
+/// Checks tags are few enough and each name and value is 1 to 64
+/// characters, names without `=` so `name=value` filters stay unambiguous.
+pub fn validate_tags(tags: &BTreeMap<String, String>) -> Result<(), String> {
+    if tags.len() > MAX_TAGS {
+        return Err(format!("Invalid tags input. A symbol can carry up to {} tags.", MAX_TAGS));
+    }
+    let too_long = |s: &str| s.is_empty() || s.len() > MAX_TAG_LEN;
+    if let Some((name, value)) = tags.iter().find(|(name, value)| too_long(name) || too_long(value) || name.contains('=')) {
+        return Err(format!(
+            "Invalid tags input. {:?}: {:?} isn't a tag of 1 to {} characters, without = in its name.",
+            name, value, MAX_TAG_LEN
+        ));
+    }
+    Ok(())
+}
+
+impl SymbolMeta {
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, size) in [("tick_size", self.tick_size), ("lot_size", self.lot_size)] {
+            if size.is_some_and(|s| !(s.is_finite() && s > 0.0)) {
+                return Err(format!("Invalid {} input. Only positive values are accepted.", name));
+            }
+        }
+        if self.currency.as_ref().is_some_and(|c| c.is_empty() || c.len() > MAX_CURRENCY_LEN) {
+            return Err(format!("Invalid currency input. Expected 1 to {} characters.", MAX_CURRENCY_LEN));
+        }
+        if self.description.as_ref().is_some_and(|d| d.len() > MAX_DESCRIPTION_LEN) {
+            return Err(format!("Invalid description input. Expected up to {} characters.", MAX_DESCRIPTION_LEN));
+        }
+        if self.reject_off_tick && self.tick_size.is_none() {
+            return Err("Invalid reject_off_tick input. It needs a tick_size.".to_string());
+        }
+        validate_tags(&self.tags)
+    }
+
+    /// Whether `price` is a whole number of ticks, allowing for the rounding
+    /// of decimal ticks in binary floating point. Always true without a tick size.
+    pub fn is_on_tick(&self, price: f64) -> bool {
+        let Some(tick) = self.tick_size else {
+            return true;
+        };
+        let ticks = price / tick;
+        (ticks - ticks.round()).abs() <= 1e-9 * ticks.abs().max(1.0)
+    }
+
+    /// The first price that is off-tick when the meta rejects those.
+    pub fn first_off_tick(&self, prices: &[f64]) -> Option<f64> {
+        if !self.reject_off_tick {
+            return None;
+        }
+        prices.iter().copied().find(|&p| !self.is_on_tick(p))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_on_tick() {
+        let meta = SymbolMeta { tick_size: Some(0.01), reject_off_tick: true, ..SymbolMeta::default() };
+        assert!(meta.is_on_tick(100.07));
+        assert!(meta.is_on_tick(0.3));
+        assert!(!meta.is_on_tick(100.075));
+        assert_eq!(Some(1.001), meta.first_off_tick(&[1.0, 1.001, 1.002]));
+
+        let untiled = SymbolMeta::default();
+        assert!(untiled.is_on_tick(1.23456));
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(SymbolMeta { tick_size: Some(0.0), ..SymbolMeta::default() }.validate().is_err());
+        assert!(SymbolMeta { reject_off_tick: true, ..SymbolMeta::default() }.validate().is_err());
+        assert!(SymbolMeta { currency: Some(String::new()), ..SymbolMeta::default() }.validate().is_err());
+        let meta = SymbolMeta {
+            tick_size: Some(0.01),
+            lot_size: Some(100.0),
+            currency: Some("USD".to_string()),
+            description: Some("Apple Inc.".to_string()),
+            reject_off_tick: true,
+            tags: BTreeMap::from([("sector".to_string(), "tech".to_string())]),
+        };
+        assert!(meta.validate().is_ok());
+        let tags = BTreeMap::from([("sector=tech".to_string(), "yes".to_string())]);
+        assert!(SymbolMeta { tags, ..SymbolMeta::default() }.validate().is_err());
+        let tags = BTreeMap::from([("sector".to_string(), String::new())]);
+        assert!(validate_tags(&tags).is_err());
+    }
+}
@@ -0,0 +1,712 @@
+/// Running central moments (up to the fourth) of a sliding window.
+///
+/// Values are added with Welford's update extended to higher moments
+/// (Pébay's single-point form of Chan's parallel merge) and removed by
+/// inverting that update, so the accumulators stay centred on the mean and
+/// don't cancel catastrophically when values are large relative to their spread.
+#[derive(Debug, Clone, Default)]
+pub struct Moments {
+    count: u64,
+    mean: f64,
+    m2: f64,
+    m3: f64,
+    m4: f64,
+}
+
+impl Moments {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn add(&mut self, value: f64) {
+        let n1 = self.count as f64;
+        self.count += 1;
+        let n = self.count as f64;
+        let delta = value - self.mean;
+        let delta_n = delta / n;
+        let delta_n2 = delta_n * delta_n;
+        let term1 = delta * delta_n * n1;
+
+        self.mean += delta_n;
+        self.m4 += term1 * delta_n2 * (n * n - 3.0 * n + 3.0) + 6.0 * delta_n2 * self.m2
+            - 4.0 * delta_n * self.m3;
+        self.m3 += term1 * delta_n * (n - 2.0) - 3.0 * delta_n * self.m2;
+        self.m2 += term1;
+    }
+
+    /// Moments of a batch of values, from its mean in a second pass so
+    /// large values with a small spread don't cancel.
+    pub fn of(values: &[f64], summary: &BatchSummary) -> Self {
+        let mean = summary.mean();
+        let (mut m2, mut m3, mut m4) = ([0.0; LANES], [0.0; LANES], [0.0; LANES]);
+        let chunks = values.chunks_exact(LANES);
+        let rest = chunks.remainder();
+        for chunk in chunks {
+            for lane in 0..LANES {
+                let d = chunk[lane] - mean;
+                let d2 = d * d;
+                m2[lane] += d2;
+                m3[lane] += d2 * d;
+                m4[lane] += d2 * d2;
+            }
+        }
+        for (lane, &value) in rest.iter().enumerate() {
+            let d = value - mean;
+            let d2 = d * d;
+            m2[lane] += d2;
+            m3[lane] += d2 * d;
+            m4[lane] += d2 * d2;
+        }
+        Moments {
+            count: values.len() as u64,
+            mean,
+            m2: m2.iter().sum(),
+            m3: m3.iter().sum(),
+            m4: m4.iter().sum(),
+        }
+    }
+
+    /// Adds every value `other` was built from, by Chan's parallel merge.
+    pub fn merge(&mut self, other: &Moments) {
+        if other.count == 0 {
+            return;
+        }
+        if self.count == 0 {
+            *self = other.clone();
+            return;
+        }
+        let (na, nb) = (self.count as f64, other.count as f64);
+        let n = na + nb;
+        let delta = other.mean - self.mean;
+        let delta_n = delta / n;
+        let delta_n2 = delta_n * delta_n;
+        let term1 = delta * delta_n * na * nb;
+
+        self.m4 += other.m4
+            + term1 * delta_n2 * (na * na - na * nb + nb * nb)
+            + 6.0 * delta_n2 * (na * na * other.m2 + nb * nb * self.m2)
+            + 4.0 * delta_n * (na * other.m3 - nb * self.m3);
+        self.m3 += other.m3 + term1 * delta_n * (na - nb) + 3.0 * delta_n * (na * other.m2 - nb * self.m2);
+        self.m2 += other.m2 + term1;
+        self.mean += delta_n * nb;
+        self.count += other.count;
+    }
+
+    /// Removes every value `other` was built from, all previously added,
+    /// by inverting [`Self::merge`].
+    pub fn unmerge(&mut self, other: &Moments) {
+        if other.count == 0 {
+            return;
+        }
+        if self.count <= other.count {
+            *self = Self::default();
+            return;
+        }
+        let (n, nb) = (self.count as f64, other.count as f64);
+        let na = n - nb;
+        let mean = (n * self.mean - nb * other.mean) / na;
+        let delta = other.mean - mean;
+        let delta_n = delta / n;
+        let delta_n2 = delta_n * delta_n;
+        let term1 = delta * delta_n * na * nb;
+
+        let m2 = self.m2 - other.m2 - term1;
+        let m3 = self.m3 - other.m3 - term1 * delta_n * (na - nb) - 3.0 * delta_n * (na * other.m2 - nb * m2);
+        let m4 = self.m4
+            - other.m4
+            - term1 * delta_n2 * (na * na - na * nb + nb * nb)
+            - 6.0 * delta_n2 * (na * na * other.m2 + nb * nb * m2)
+            - 4.0 * delta_n * (na * other.m3 - nb * m3);
+
+        self.count -= other.count;
+        self.mean = mean;
+        self.m2 = m2;
+        self.m3 = m3;
+        self.m4 = m4;
+    }
+
+    /// Removes a value that was previously added.
+    pub fn remove(&mut self, value: f64) {
+        if self.count <= 1 {
+            *self = Self::default();
+            return;
+        }
+        let n = self.count as f64;
+        let n1 = n - 1.0;
+        let mean = (n * self.mean - value) / n1;
+        let delta = value - mean;
+        let delta_n = delta / n;
+        let delta_n2 = delta_n * delta_n;
+        let term1 = delta * delta_n * n1;
+
+        let m2 = self.m2 - term1;
+        let m3 = self.m3 - term1 * delta_n * (n - 2.0) + 3.0 * delta_n * m2;
+        let m4 = self.m4 - term1 * delta_n2 * (n * n - 3.0 * n + 3.0) - 6.0 * delta_n2 * m2
+            + 4.0 * delta_n * m3;
+
+        self.count -= 1;
+        self.mean = mean;
+        self.m2 = m2;
+        self.m3 = m3;
+        self.m4 = m4;
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.mean
+    }
+
+    /// Population variance, clamped at zero against rounding.
+    pub fn variance(&self) -> f64 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        (self.m2 / self.count as f64).max(0.0)
+    }
+
+    pub fn skewness(&self) -> f64 {
+        if self.m2 <= 0.0 {
+            return 0.0;
+        }
+        (self.count as f64).sqrt() * self.m3 / self.m2.powf(1.5)
+    }
+
+    /// Excess kurtosis (0.0 for a normal distribution).
+    pub fn kurtosis(&self) -> f64 {
+        if self.m2 <= 0.0 {
+            return 0.0;
+        }
+        self.count as f64 * self.m4 / (self.m2 * self.m2) - 3.0
+    }
+}
+
+/// Lanes the batch kernels accumulate side by side. Independent lanes let
+/// the compiler keep them in SIMD registers instead of chaining every
+/// addition on the one before.
+const LANES: usize = 4;
+
+/// Sum, min and max of a batch of values, accumulated over [`LANES`] lanes
+/// at once before being merged into a window.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BatchSummary {
+    pub count: usize,
+    pub sum: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl BatchSummary {
+    pub fn of(values: &[f64]) -> Self {
+        let (mut sum, mut min, mut max) = ([0.0; LANES], [f64::INFINITY; LANES], [f64::NEG_INFINITY; LANES]);
+        let chunks = values.chunks_exact(LANES);
+        let rest = chunks.remainder();
+        for chunk in chunks {
+            for lane in 0..LANES {
+                let value = chunk[lane];
+                sum[lane] += value;
+                // Comparisons rather than f64::min, whose NaN handling
+                // keeps it from vectorizing.
+                min[lane] = if value < min[lane] { value } else { min[lane] };
+                max[lane] = if value > max[lane] { value } else { max[lane] };
+            }
+        }
+        for (lane, &value) in rest.iter().enumerate() {
+            sum[lane] += value;
+            min[lane] = if value < min[lane] { value } else { min[lane] };
+            max[lane] = if value > max[lane] { value } else { max[lane] };
+        }
+        BatchSummary {
+            count: values.len(),
+            sum: sum.iter().sum(),
+            min: min.iter().copied().fold(f64::INFINITY, f64::min),
+            max: max.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+        }
+    }
+
+    pub fn mean(&self) -> f64 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        self.sum / self.count as f64
+    }
+}
+
+/// Sums the `N` terms `term(i)` gives for each of the `len` points of a
+/// batch, over [`LANES`] lanes at once like [`BatchSummary::of`], for the
+/// batch kernels that need more than the values' own sum.
+pub fn lane_sums<const N: usize>(len: usize, term: impl Fn(usize) -> [f64; N]) -> [f64; N] {
+    let mut lanes = [[0.0; N]; LANES];
+    for start in (0..len).step_by(LANES) {
+        for (lane, sums) in lanes.iter_mut().enumerate().take(len - start) {
+            for (sum, t) in sums.iter_mut().zip(term(start + lane)) {
+                *sum += t;
+            }
+        }
+    }
+    let mut total = [0.0; N];
+    for sums in lanes {
+        for (total, sum) in total.iter_mut().zip(sums) {
+            *total += sum;
+        }
+    }
+    total
+}
+
+/// Running weighted mean and variance of a sliding window, by West's
+/// weighted form of Welford's update. Removal inverts the update, so a
+/// window can evict its oldest value with the weight it was added with.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WeightedMoments {
+    total_weight: f64,
+    mean: f64,
+    m2: f64,
+}
+
+impl WeightedMoments {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, value: f64, weight: f64) {
+        self.total_weight += weight;
+        let delta = value - self.mean;
+        self.mean += delta * weight / self.total_weight;
+        self.m2 += weight * delta * (value - self.mean);
+    }
+
+    /// Weighted moments of a batch of values, from its weighted mean in a
+    /// second pass.
+    pub fn of(values: &[f64], weights: &[f64]) -> Self {
+        let [total_weight, weighted_sum] = lane_sums(values.len(), |i| [weights[i], weights[i] * values[i]]);
+        if total_weight <= 0.0 {
+            return Self::default();
+        }
+        let mean = weighted_sum / total_weight;
+        let [m2] = lane_sums(values.len(), |i| {
+            let d = values[i] - mean;
+            [weights[i] * d * d]
+        });
+        WeightedMoments { total_weight, mean, m2 }
+    }
+
+    /// Adds every value `other` was built from, by the weighted form of
+    /// Chan's parallel merge.
+    pub fn merge(&mut self, other: &WeightedMoments) {
+        if other.total_weight <= 0.0 {
+            return;
+        }
+        if self.total_weight <= 0.0 {
+            *self = *other;
+            return;
+        }
+        let total_weight = self.total_weight + other.total_weight;
+        let delta = other.mean - self.mean;
+        self.mean += delta * other.total_weight / total_weight;
+        self.m2 += other.m2 + delta * delta * self.total_weight * other.total_weight / total_weight;
+        self.total_weight = total_weight;
+    }
+
+    /// Removes every value `other` was built from, all previously added
+    /// with the same weights, by inverting [`Self::merge`].
+    pub fn unmerge(&mut self, other: &WeightedMoments) {
+        if other.total_weight <= 0.0 {
+            return;
+        }
+        let remaining = self.total_weight - other.total_weight;
+        if remaining <= 0.0 {
+            *self = Self::default();
+            return;
+        }
+        let mean = (self.total_weight * self.mean - other.total_weight * other.mean) / remaining;
+        let delta = other.mean - mean;
+        self.m2 -= other.m2 + delta * delta * remaining * other.total_weight / self.total_weight;
+        self.mean = mean;
+        self.total_weight = remaining;
+    }
+
+    /// Removes a value that was previously added with `weight`.
+    pub fn remove(&mut self, value: f64, weight: f64) {
+        let remaining = self.total_weight - weight;
+        if remaining <= 0.0 {
+            *self = Self::default();
+            return;
+        }
+        let mean = (self.total_weight * self.mean - weight * value) / remaining;
+        self.m2 -= weight * (value - mean) * (value - self.mean);
+        self.mean = mean;
+        self.total_weight = remaining;
+    }
+
+    pub fn total_weight(&self) -> f64 {
+        self.total_weight
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.mean
+    }
+
+    /// Weighted population variance, clamped at zero against rounding.
+    pub fn variance(&self) -> f64 {
+        if self.total_weight <= 0.0 {
+            return 0.0;
+        }
+        (self.m2 / self.total_weight).max(0.0)
+    }
+}
+
+/// Neumaier-compensated running sum. The compensation term carries the
+/// low-order bits lost by each addition, so add/subtract cycles don't drift.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CompensatedSum {
+    sum: f64,
+    compensation: f64,
+}
+
+impl CompensatedSum {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, value: f64) {
+        let t = self.sum + value;
+        if self.sum.abs() >= value.abs() {
+            self.compensation += (self.sum - t) + value;
+        } else {
+            self.compensation += (value - t) + self.sum;
+        }
+        self.sum = t;
+    }
+
+    pub fn sub(&mut self, value: f64) {
+        self.add(-value);
+    }
+
+    pub fn value(&self) -> f64 {
+        self.sum + self.compensation
+    }
+}
+
+/// Running log-sum and reciprocal-sum of a window, for its geometric and
+/// harmonic means. Both means are only defined while every value in the
+/// window is positive, so non-positive values are counted instead of summed.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PositiveMeans {
+    log_sum: CompensatedSum,
+    reciprocal_sum: CompensatedSum,
+    count: usize,
+    non_positive: usize,
+}
+
+impl PositiveMeans {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, value: f64) {
+        self.count += 1;
+        if value > 0.0 {
+            self.log_sum.add(value.ln());
+            self.reciprocal_sum.add(value.recip());
+        } else {
+            self.non_positive += 1;
+        }
+    }
+
+    /// Adds a batch of values, summing their logs and reciprocals over the
+    /// batch alone first.
+    pub fn extend(&mut self, values: &[f64]) {
+        let [log_sum, reciprocal_sum, non_positive] = Self::sums(values);
+        self.count += values.len();
+        self.log_sum.add(log_sum);
+        self.reciprocal_sum.add(reciprocal_sum);
+        self.non_positive += non_positive as usize;
+    }
+
+    /// Removes a batch of values that were previously added.
+    pub fn remove_many(&mut self, values: &[f64]) {
+        let [log_sum, reciprocal_sum, non_positive] = Self::sums(values);
+        self.count -= values.len();
+        self.log_sum.sub(log_sum);
+        self.reciprocal_sum.sub(reciprocal_sum);
+        self.non_positive -= non_positive as usize;
+    }
+
+    fn sums(values: &[f64]) -> [f64; 3] {
+        lane_sums(values.len(), |i| match values[i] {
+            v if v > 0.0 => [v.ln(), v.recip(), 0.0],
+            _ => [0.0, 0.0, 1.0],
+        })
+    }
+
+    /// Removes a value that was previously added.
+    pub fn remove(&mut self, value: f64) {
+        self.count -= 1;
+        if value > 0.0 {
+            self.log_sum.sub(value.ln());
+            self.reciprocal_sum.sub(value.recip());
+        } else {
+            self.non_positive -= 1;
+        }
+    }
+
+    /// `exp(mean(ln x))`, or `None` if the window is empty or holds a
+    /// non-positive value.
+    pub fn geometric(&self) -> Option<f64> {
+        self.defined().then(|| (self.log_sum.value() / self.count as f64).exp())
+    }
+
+    /// `n / Σ(1/x)`, or `None` if the window is empty or holds a
+    /// non-positive value.
+    pub fn harmonic(&self) -> Option<f64> {
+        self.defined().then(|| self.count as f64 / self.reciprocal_sum.value())
+    }
+
+    fn defined(&self) -> bool {
+        self.count > 0 && self.non_positive == 0
+    }
+}
+
+/// Most decimal places a fixed-point window can keep.
+pub const MAX_FIXED_POINT_DECIMALS: u32 = 12;
+
+/// Exact window sums of values, volumes and notionals, held as integers
+/// scaled by 10^decimals. The sums wrap on overflow, which keeps them exact
+/// as long as the window's own totals fit in an `i128`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FixedPointSums {
+    decimals: u32,
+    sum: i128,
+    volume: i128,
+    /// Σ p·v, scaled by 10^(2·decimals).
+    notional: i128,
+}
+
+impl FixedPointSums {
+    pub fn new(decimals: u32) -> Self {
+        FixedPointSums { decimals, sum: 0, volume: 0, notional: 0 }
+    }
+
+    pub fn decimals(&self) -> u32 {
+        self.decimals
+    }
+
+    fn factor(&self) -> f64 {
+        10f64.powi(self.decimals as i32)
+    }
+
+    /// `x` as an integer count of 10^-decimals units, rounded to nearest.
+    pub fn scale(&self, x: f64) -> i128 {
+        (x * self.factor()).round() as i128
+    }
+
+    /// `x` rounded to the window's decimal places.
+    pub fn quantize(&self, x: f64) -> f64 {
+        self.scale(x) as f64 / self.factor()
+    }
+
+    pub fn add(&mut self, value: f64, volume: f64) {
+        let (value, volume) = (self.scale(value), self.scale(volume));
+        self.sum = self.sum.wrapping_add(value);
+        self.volume = self.volume.wrapping_add(volume);
+        self.notional = self.notional.wrapping_add(value.wrapping_mul(volume));
+    }
+
+    /// Removes a value that was previously added.
+    pub fn remove(&mut self, value: f64, volume: f64) {
+        let (value, volume) = (self.scale(value), self.scale(volume));
+        self.sum = self.sum.wrapping_sub(value);
+        self.volume = self.volume.wrapping_sub(volume);
+        self.notional = self.notional.wrapping_sub(value.wrapping_mul(volume));
+    }
+
+    pub fn sum(&self) -> f64 {
+        self.sum as f64 / self.factor()
+    }
+
+    pub fn volume(&self) -> f64 {
+        self.volume as f64 / self.factor()
+    }
+
+    /// Σ p·v / Σ v, or `None` while the window has no volume.
+    pub fn vwap(&self) -> Option<f64> {
+        (self.volume > 0).then(|| self.notional as f64 / self.volume as f64 / self.factor())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(expected: f64, actual: f64) {
+        assert!((expected - actual).abs() < 1e-9, "{} != {}", expected, actual);
+    }
+
+    #[test]
+    fn test_remove_inverts_add() {
+        let mut window = Moments::new();
+        for v in [3.0, 1.0, 4.0, 1.0, 5.0, 9.0, 2.0] {
+            window.add(v);
+        }
+        window.remove(3.0);
+        window.remove(1.0);
+
+        let mut expected = Moments::new();
+        for v in [4.0, 1.0, 5.0, 9.0, 2.0] {
+            expected.add(v);
+        }
+        assert_eq!(expected.count(), window.count());
+        assert_close(expected.mean(), window.mean());
+        assert_close(expected.variance(), window.variance());
+        assert_close(expected.skewness(), window.skewness());
+        assert_close(expected.kurtosis(), window.kurtosis());
+    }
+
+    #[test]
+    fn test_batch_merge_matches_adds() {
+        let values: Vec<f64> = (0..1003).map(|i| 50_000.0 + ((i * 37) % 101) as f64 * 0.01).collect();
+        let summary = BatchSummary::of(&values[3..]);
+        assert_eq!(1000, summary.count);
+        assert_eq!(50_000.0, summary.min);
+        assert_eq!(50_001.0, summary.max);
+
+        let mut window = Moments::new();
+        let mut expected = Moments::new();
+        for &v in &values[..3] {
+            window.add(v);
+        }
+        window.merge(&Moments::of(&values[3..], &summary));
+        for &v in &values {
+            expected.add(v);
+        }
+        assert_eq!(expected.count(), window.count());
+        assert_close(expected.mean(), window.mean());
+        assert_close(expected.variance(), window.variance());
+        assert_close(expected.skewness(), window.skewness());
+        assert_close(expected.kurtosis(), window.kurtosis());
+
+        // Unmerging the oldest values leaves those after them.
+        window.unmerge(&Moments::of(&values[..500], &BatchSummary::of(&values[..500])));
+        let mut expected = Moments::new();
+        for &v in &values[500..] {
+            expected.add(v);
+        }
+        assert_eq!(expected.count(), window.count());
+        assert_close(expected.mean(), window.mean());
+        assert_close(expected.variance(), window.variance());
+        assert_close(expected.skewness(), window.skewness());
+        assert_close(expected.kurtosis(), window.kurtosis());
+    }
+
+    #[test]
+    fn test_variance_of_large_values() {
+        let mut window = Moments::new();
+        for i in 0..10_000 {
+            window.add(50_000.0 + (i % 2) as f64 * 0.001);
+        }
+        for i in 0..5_000 {
+            window.remove(50_000.0 + (i % 2) as f64 * 0.001);
+        }
+        assert!((window.variance() - 0.00000025).abs() < 1e-12, "{}", window.variance());
+    }
+
+    #[test]
+    fn test_weighted_remove_inverts_add() {
+        let mut window = WeightedMoments::new();
+        for (v, w) in [(10.0, 5.0), (1.0, 1.0), (2.0, 3.0), (4.0, 2.0)] {
+            window.add(v, w);
+        }
+        window.remove(10.0, 5.0);
+
+        // Equivalent to 1, 2, 2, 2, 4, 4 unweighted.
+        assert_close(6.0, window.total_weight());
+        assert_close(2.5, window.mean());
+        assert_close(1.25, window.variance());
+    }
+
+    #[test]
+    fn test_weighted_batch_merge_matches_adds() {
+        let points: Vec<(f64, f64)> = (0..203).map(|i| (100.0 + ((i * 37) % 11) as f64, 1.0 + (i % 3) as f64)).collect();
+        let (values, weights): (Vec<f64>, Vec<f64>) = points[3..].iter().copied().unzip();
+        let mut window = WeightedMoments::new();
+        let mut expected = WeightedMoments::new();
+        for &(v, w) in &points[..3] {
+            window.add(v, w);
+        }
+        window.merge(&WeightedMoments::of(&values, &weights));
+        for &(v, w) in &points {
+            expected.add(v, w);
+        }
+        assert_close(expected.total_weight(), window.total_weight());
+        assert_close(expected.mean(), window.mean());
+        assert_close(expected.variance(), window.variance());
+
+        window.unmerge(&WeightedMoments::of(&values[..100], &weights[..100]));
+        let mut expected = WeightedMoments::new();
+        for &(v, w) in points[..3].iter().chain(&points[103..]) {
+            expected.add(v, w);
+        }
+        assert_close(expected.total_weight(), window.total_weight());
+        assert_close(expected.mean(), window.mean());
+        assert_close(expected.variance(), window.variance());
+
+        let mut means = PositiveMeans::new();
+        means.add(1.0);
+        means.extend(&[2.0, 4.0]);
+        assert_close(2.0, means.geometric().unwrap());
+        means.extend(&[0.0]);
+        assert_eq!(None, means.harmonic());
+        means.remove_many(&[1.0, 2.0]);
+        means.remove_many(&[4.0, 0.0]);
+        assert_eq!(None, means.geometric());
+    }
+
+    #[test]
+    fn test_positive_means() {
+        let mut means = PositiveMeans::new();
+        for v in [-1.0, 1.0, 2.0, 4.0] {
+            means.add(v);
+        }
+        assert_eq!(None, means.geometric());
+        assert_eq!(None, means.harmonic());
+
+        means.remove(-1.0);
+        assert_close(2.0, means.geometric().unwrap());
+        assert_close(12.0 / 7.0, means.harmonic().unwrap());
+    }
+
+    #[test]
+    fn test_fixed_point_sums_are_exact() {
+        let mut sums = FixedPointSums::new(8);
+        assert_eq!(0.12345679, sums.quantize(0.123456789));
+        for _ in 0..10 {
+            sums.add(0.1, 0.0);
+        }
+        sums.add(0.2, 3.0);
+        sums.add(0.3, 1.0);
+        sums.remove(0.1, 0.0);
+
+        assert_eq!(1.4, sums.sum());
+        assert_eq!(4.0, sums.volume());
+        assert_eq!(Some(0.225), sums.vwap());
+    }
+
+    #[test]
+    fn test_compensated_sum_does_not_drift() {
+        let mut sum = CompensatedSum::new();
+        let mut naive = 0.0;
+        sum.add(1e16);
+        naive += 1e16;
+        for _ in 0..10_000 {
+            sum.add(1.0);
+            naive += 1.0;
+        }
+        sum.sub(1e16);
+        naive -= 1e16;
+
+        assert_eq!(10_000.0, sum.value());
+        assert_ne!(10_000.0, naive);
+    }
+}
@@ -0,0 +1,201 @@
+//! Isolated data namespaces, so several desks can share one deployment.
+//!
+//! Each namespace is a [`TradingDataService`] of its own: the same symbol in
+//! two namespaces has two independent sets of windows, and limits such as
+//! the number of symbols apply per namespace.
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use crate::settings::Settings;
+use crate::{ServiceError, TradingDataService};
+
+/// Namespace used when a request doesn't name one.
+pub const DEFAULT_NAMESPACE: &str = "default";
+
+/// Most namespaces one deployment holds.
+pub const MAX_NAMESPACES: usize = 1000;
+
+/// Longest namespace name accepted.
+pub const MAX_NAMESPACE_LEN: usize = 64;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct NamespaceLimits {
+    /// Most symbols the namespace tracks; `None` for no limit.
+    #[serde(default)]
+    pub max_symbols: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct NamespaceInfo {
+    pub name: String,
+    /// Number of symbols currently tracked.
+    pub symbols: usize,
+    pub limits: NamespaceLimits,
+}
+
+pub struct Namespaces {
+    spaces: RwLock<HashMap<String, Arc<TradingDataService>>>,
+    /// Runtime settings, shared by every namespace.
+    settings: Arc<RwLock<Settings>>,
+}
+
+impl Default for Namespaces {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Namespaces {
+    /// Starts with just the default namespace.
+    pub fn new() -> Self {
+        let settings = Arc::new(RwLock::new(Settings::default()));
+        let default = TradingDataService::with_settings(settings.clone());
+        let spaces = HashMap::from([(DEFAULT_NAMESPACE.to_string(), Arc::new(default))]);
+        Namespaces { spaces: RwLock::new(spaces), settings }
+    }
+
+    pub fn get(&self, name: &str) -> Result<Arc<TradingDataService>, ServiceError> {
+        let spaces = self.spaces.read().unwrap();
+        spaces.get(name).cloned().ok_or_else(|| ServiceError::NotFound(format!("Namespace {} not found", name)))
+    }
+
+    /// The namespace's service, creating the namespace if it doesn't exist.
+    pub fn get_or_create(&self, name: &str) -> Result<Arc<TradingDataService>, ServiceError> {
+        if let Ok(service) = self.get(name) {
+            return Ok(service);
+        }
+        validate_name(name)?;
+        let mut spaces = self.spaces.write().unwrap();
+        if !spaces.contains_key(name) && spaces.len() >= MAX_NAMESPACES {
+            return Err(ServiceError::LimitReached(format!("Maximum of {} namespaces reached", MAX_NAMESPACES)));
+        }
+        let service = spaces
+            .entry(name.to_string())
+            .or_insert_with(|| Arc::new(TradingDataService::with_settings(self.settings.clone())));
+        Ok(service.clone())
+    }
+
+    pub fn settings(&self) -> Settings {
+        self.settings.read().unwrap().clone()
+    }
+
+    /// Replaces the settings of every namespace, keeping their data.
+    pub fn set_settings(&self, settings: Settings) -> Result<(), ServiceError> {
+        settings.validate()?;
+        *self.settings.write().unwrap() = settings;
+        for service in self.spaces.read().unwrap().values() {
+            service.apply_settings();
+        }
+        Ok(())
+    }
+
+    /// Sets the namespace's limits, creating the namespace if it doesn't
+    /// exist. Symbols already tracked beyond a lowered limit are kept.
+    pub fn set_limits(&self, name: &str, limits: NamespaceLimits) -> Result<(), ServiceError> {
+        if limits.max_symbols == Some(0) {
+            return Err("Invalid max_symbols input. Only positive values are accepted.".to_string().into());
+        }
+        self.get_or_create(name)?.set_max_symbols(limits.max_symbols);
+        Ok(())
+    }
+
+    pub fn count(&self) -> usize {
+        self.spaces.read().unwrap().len()
+    }
+
+    /// Batch writes pending across every namespace.
+    pub fn pending_writes(&self) -> usize {
+        self.spaces.read().unwrap().values().map(|service| service.pending_writes()).sum()
+    }
+
+    /// Every namespace with its symbol count and limits, by name.
+    pub async fn list(&self) -> Vec<NamespaceInfo> {
+        let mut spaces: Vec<(String, Arc<TradingDataService>)> = {
+            let spaces = self.spaces.read().unwrap();
+            spaces.iter().map(|(name, service)| (name.clone(), service.clone())).collect()
+        };
+        spaces.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut infos = Vec::with_capacity(spaces.len());
+        for (name, service) in spaces {
+            let limits = NamespaceLimits { max_symbols: service.max_symbols() };
+            infos.push(NamespaceInfo { name, symbols: service.symbol_count().await, limits });
+        }
+        infos
+    }
+}
+
+fn validate_name(name: &str) -> Result<(), String> {
+    let valid_chars = name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if name.is_empty() || name.len() > MAX_NAMESPACE_LEN || !valid_chars {
+        return Err(format!(
+            "Invalid namespace '{}'. Expected 1 to {} letters, digits, '-' or '_'.",
+            name, MAX_NAMESPACE_LEN
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_namespaces_are_isolated() {
+        let namespaces = Namespaces::new();
+        let desk = namespaces.get_or_create("equities-desk").unwrap();
+        desk.add_batch_values("AAPL".to_string(), vec![1.0, 2.0]).await.unwrap();
+        let default = namespaces.get(DEFAULT_NAMESPACE).unwrap();
+        default.add_batch_values("AAPL".to_string(), vec![100.0]).await.unwrap();
+
+        assert_eq!(2.0, desk.get_stats("AAPL".to_string(), 1).await.unwrap().last);
+        assert_eq!(100.0, default.get_stats("AAPL".to_string(), 1).await.unwrap().last);
+        assert!(namespaces.get("fx-desk").is_err());
+        assert!(namespaces.get_or_create("bad/name").is_err());
+
+        let names: Vec<String> = namespaces.list().await.into_iter().map(|i| i.name).collect();
+        assert_eq!(vec!["default", "equities-desk"], names);
+    }
+
+    #[tokio::test]
+    async fn test_max_symbols() {
+        let namespaces = Namespaces::new();
+        let limits = NamespaceLimits { max_symbols: Some(1) };
+        namespaces.set_limits("small", limits).unwrap();
+        let small = namespaces.get("small").unwrap();
+        small.add_batch_values("AAPL".to_string(), vec![1.0]).await.unwrap();
+        small.add_batch_values("AAPL".to_string(), vec![2.0]).await.unwrap();
+        assert!(small.add_batch_values("MSFT".to_string(), vec![1.0]).await.is_err());
+
+        let info = namespaces.list().await.into_iter().find(|i| i.name == "small").unwrap();
+        assert_eq!(1, info.symbols);
+        assert_eq!(limits, info.limits);
+    }
+
+    #[tokio::test]
+    async fn test_settings() {
+        let namespaces = Namespaces::new();
+        let default = namespaces.get(DEFAULT_NAMESPACE).unwrap();
+        default.add_batch_values("AAPL".to_string(), vec![1.0; 5]).await.unwrap();
+
+        let mut settings = Settings { max_batch_size: 4, ..Settings::default() };
+        settings.new_symbol_config.time_windows_ms = vec![1_000];
+        namespaces.set_settings(settings.clone()).unwrap();
+        let desk = namespaces.get_or_create("desk").unwrap();
+        for service in [&default, &desk] {
+            let e = service.add_batch_values("MSFT".to_string(), vec![1.0; 5]).await.unwrap_err();
+            assert_eq!("batch_too_large", e.code());
+            service.add_batch_values("MSFT".to_string(), vec![1.0; 4]).await.unwrap();
+            let config = service.get_symbol_config("MSFT".to_string()).await.unwrap();
+            assert_eq!(vec![1_000], config.time_windows_ms);
+        }
+        // Symbols already tracked keep their windows and config.
+        assert_eq!(5, default.get_stats("AAPL".to_string(), 1).await.unwrap().count);
+        let config = default.get_symbol_config("AAPL".to_string()).await.unwrap();
+        assert!(config.time_windows_ms.is_empty());
+
+        settings.max_batch_size = 0;
+        assert!(namespaces.set_settings(settings).is_err());
+        assert_eq!(4, namespaces.settings().max_batch_size);
+    }
+}
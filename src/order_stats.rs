@@ -0,0 +1,501 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, VecDeque};
+
+/// `f64` with a total order so it can key ordered collections.
+#[derive(Debug, Clone, Copy)]
+pub struct OrderedF64(pub f64);
+
+impl PartialEq for OrderedF64 {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.total_cmp(&other.0) == Ordering::Equal
+    }
+}
+
+impl Eq for OrderedF64 {}
+
+impl PartialOrd for OrderedF64 {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for OrderedF64 {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+/// Sorted multiset storing one entry per distinct value.
+#[derive(Debug, Clone, Default)]
+struct MultiSet {
+    counts: BTreeMap<OrderedF64, usize>,
+    len: usize,
+}
+
+impl MultiSet {
+    fn insert(&mut self, value: f64) {
+        *self.counts.entry(OrderedF64(value)).or_insert(0) += 1;
+        self.len += 1;
+    }
+
+    fn remove(&mut self, value: f64) -> bool {
+        let key = OrderedF64(value);
+        match self.counts.get_mut(&key) {
+            Some(count) => {
+                *count -= 1;
+                if *count == 0 {
+                    self.counts.remove(&key);
+                }
+                self.len -= 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn first(&self) -> Option<f64> {
+        self.counts.keys().next().map(|k| k.0)
+    }
+
+    fn last(&self) -> Option<f64> {
+        self.counts.keys().next_back().map(|k| k.0)
+    }
+
+    /// Approximate size of the entries, not counting tree node overhead.
+    fn heap_bytes(&self) -> usize {
+        self.counts.len() * std::mem::size_of::<(OrderedF64, usize)>()
+    }
+}
+
+/// Exact running median of a sliding window, kept as two ordered multisets
+/// (a lower and an upper half) so insertion and eviction are O(log d) where
+/// d is the number of distinct values in the window.
+#[derive(Debug, Clone, Default)]
+pub struct SlidingMedian {
+    low: MultiSet,
+    high: MultiSet,
+}
+
+impl SlidingMedian {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn heap_bytes(&self) -> usize {
+        self.low.heap_bytes() + self.high.heap_bytes()
+    }
+
+    pub fn insert(&mut self, value: f64) {
+        match self.low.last() {
+            Some(max_low) if value > max_low => self.high.insert(value),
+            _ => self.low.insert(value),
+        }
+        self.rebalance();
+    }
+
+    /// Inserts a batch of values, splitting them between the halves around
+    /// the current lower median and rebalancing once at the end.
+    pub fn extend(&mut self, values: &[f64]) {
+        match self.low.last() {
+            Some(max_low) => {
+                for &value in values {
+                    if value > max_low {
+                        self.high.insert(value);
+                    } else {
+                        self.low.insert(value);
+                    }
+                }
+            }
+            None => values.iter().for_each(|&value| self.low.insert(value)),
+        }
+        self.rebalance();
+    }
+
+    /// Removes one occurrence of `value`, which must have been inserted.
+    pub fn remove(&mut self, value: f64) {
+        if !self.low.remove(value) {
+            self.high.remove(value);
+        }
+        self.rebalance();
+    }
+
+    /// Removes one occurrence of each value, all inserted, rebalancing
+    /// once at the end.
+    pub fn remove_many(&mut self, values: &[f64]) {
+        for &value in values {
+            if !self.low.remove(value) {
+                self.high.remove(value);
+            }
+        }
+        self.rebalance();
+    }
+
+    pub fn median(&self) -> Option<f64> {
+        let max_low = self.low.last()?;
+        if self.low.len > self.high.len {
+            Some(max_low)
+        } else {
+            self.high.first().map(|min_high| (max_low + min_high) / 2.0)
+        }
+    }
+
+    fn rebalance(&mut self) {
+        while self.low.len > self.high.len + 1 {
+            let value = self.low.last().unwrap();
+            self.low.remove(value);
+            self.high.insert(value);
+        }
+        while self.high.len > self.low.len {
+            let value = self.high.first().unwrap();
+            self.high.remove(value);
+            self.low.insert(value);
+        }
+    }
+}
+
+/// Sliding-window min and max kept in two monotonic deques. Each value is
+/// pushed and popped at most once per deque, so maintenance is amortized
+/// O(1) regardless of trends in the data.
+#[derive(Debug, Clone, Default)]
+pub struct SlidingMinMax {
+    mins: VecDeque<(u64, f64)>,
+    maxs: VecDeque<(u64, f64)>,
+    pushed: u64,
+    evicted: u64,
+}
+
+impl SlidingMinMax {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn heap_bytes(&self) -> usize {
+        (self.mins.capacity() + self.maxs.capacity()) * std::mem::size_of::<(u64, f64)>()
+    }
+
+    pub fn push(&mut self, value: f64) {
+        let seq = self.pushed;
+        self.pushed += 1;
+
+        while self.mins.back().is_some_and(|&(_, v)| v >= value) {
+            self.mins.pop_back();
+        }
+        self.mins.push_back((seq, value));
+
+        while self.maxs.back().is_some_and(|&(_, v)| v <= value) {
+            self.maxs.pop_back();
+        }
+        self.maxs.push_back((seq, value));
+    }
+
+    /// Pushes a batch whose min and max are known. Of the batch, only the
+    /// values below (above) every later one would stay in the min (max)
+    /// deque; they're picked in one backward scan and appended behind the
+    /// older entries the batch's extreme doesn't displace, rather than
+    /// pushing value by value.
+    pub fn extend(&mut self, values: &[f64], min: f64, max: f64) {
+        while self.mins.back().is_some_and(|&(_, v)| v >= min) {
+            self.mins.pop_back();
+        }
+        while self.maxs.back().is_some_and(|&(_, v)| v <= max) {
+            self.maxs.pop_back();
+        }
+        let (mut mins, mut maxs) = (Vec::new(), Vec::new());
+        let (mut lowest, mut highest) = (f64::INFINITY, f64::NEG_INFINITY);
+        for (i, &value) in values.iter().enumerate().rev() {
+            let seq = self.pushed + i as u64;
+            if value < lowest {
+                lowest = value;
+                mins.push((seq, value));
+            }
+            if value > highest {
+                highest = value;
+                maxs.push((seq, value));
+            }
+        }
+        // Found newest first.
+        self.mins.extend(mins.into_iter().rev());
+        self.maxs.extend(maxs.into_iter().rev());
+        self.pushed += values.len() as u64;
+    }
+
+    /// Evicts the oldest pushed value.
+    pub fn pop(&mut self) {
+        let seq = self.evicted;
+        self.evicted += 1;
+
+        if self.mins.front().is_some_and(|&(s, _)| s == seq) {
+            self.mins.pop_front();
+        }
+        if self.maxs.front().is_some_and(|&(s, _)| s == seq) {
+            self.maxs.pop_front();
+        }
+    }
+
+    /// Evicts the `n` oldest pushed values.
+    pub fn pop_many(&mut self, n: usize) {
+        self.evicted += n as u64;
+        while self.mins.front().is_some_and(|&(s, _)| s < self.evicted) {
+            self.mins.pop_front();
+        }
+        while self.maxs.front().is_some_and(|&(s, _)| s < self.evicted) {
+            self.maxs.pop_front();
+        }
+    }
+
+    pub fn min(&self) -> Option<f64> {
+        self.mins.front().map(|&(_, v)| v)
+    }
+
+    pub fn max(&self) -> Option<f64> {
+        self.maxs.front().map(|&(_, v)| v)
+    }
+}
+
+/// Largest peak-to-trough decline within a run of values, where the peak
+/// comes before the trough.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Drawdown {
+    max: f64,
+    min: f64,
+    /// Absolute decline, in value units.
+    pub absolute: f64,
+    /// Decline relative to the peak. Only positive peaks are considered.
+    pub relative: f64,
+}
+
+impl Default for Drawdown {
+    fn default() -> Self {
+        Drawdown { max: f64::MIN, min: f64::MAX, absolute: 0.0, relative: 0.0 }
+    }
+}
+
+impl Drawdown {
+    fn push(&mut self, value: f64) {
+        self.max = self.max.max(value);
+        self.min = self.min.min(value);
+        self.absolute = self.absolute.max(self.max - value);
+        if self.max > 0.0 {
+            self.relative = self.relative.max((self.max - value) / self.max);
+        }
+    }
+
+    /// Drawdown of `self` followed by `later`.
+    fn then(self, later: Drawdown) -> Drawdown {
+        let mut relative = self.relative.max(later.relative);
+        if self.max > 0.0 {
+            relative = relative.max((self.max - later.min) / self.max);
+        }
+        Drawdown {
+            max: self.max.max(later.max),
+            min: self.min.min(later.min),
+            absolute: self.absolute.max(later.absolute).max(self.max - later.min),
+            relative,
+        }
+    }
+}
+
+/// Maximum drawdown of a sliding window, kept as per-block summaries of
+/// about sqrt(window) values each. Updates are O(1); a query combines the
+/// block summaries and rescans the partially evicted oldest block, so it
+/// costs O(sqrt(window)).
+#[derive(Debug, Clone)]
+pub struct SlidingDrawdown {
+    block_size: usize,
+    blocks: VecDeque<(usize, Drawdown)>,
+    evicted_from_front: usize,
+}
+
+impl SlidingDrawdown {
+    pub fn new(window: usize) -> Self {
+        SlidingDrawdown {
+            block_size: (window as f64).sqrt().ceil().max(1.0) as usize,
+            blocks: VecDeque::new(),
+            evicted_from_front: 0,
+        }
+    }
+
+    pub fn heap_bytes(&self) -> usize {
+        self.blocks.capacity() * std::mem::size_of::<(usize, Drawdown)>()
+    }
+
+    pub fn push(&mut self, value: f64) {
+        if self.blocks.back().is_none_or(|&(len, _)| len >= self.block_size) {
+            self.blocks.push_back((0, Drawdown::default()));
+        }
+        let (len, summary) = self.blocks.back_mut().unwrap();
+        *len += 1;
+        summary.push(value);
+    }
+
+    /// Pushes a batch of values, summarizing each block it fills in one go.
+    pub fn extend(&mut self, values: &[f64]) {
+        let mut rest = values;
+        while !rest.is_empty() {
+            if self.blocks.back().is_none_or(|&(len, _)| len >= self.block_size) {
+                self.blocks.push_back((0, Drawdown::default()));
+            }
+            let (len, summary) = self.blocks.back_mut().unwrap();
+            let (block, next) = rest.split_at((self.block_size - *len).min(rest.len()));
+            *len += block.len();
+            block.iter().for_each(|&value| summary.push(value));
+            rest = next;
+        }
+    }
+
+    /// Evicts the oldest pushed value.
+    pub fn pop(&mut self) {
+        self.evicted_from_front += 1;
+        if self.blocks.front().is_some_and(|&(len, _)| len == self.evicted_from_front) {
+            self.blocks.pop_front();
+            self.evicted_from_front = 0;
+        }
+    }
+
+    /// Evicts the `n` oldest pushed values, dropping the blocks they empty
+    /// whole.
+    pub fn pop_many(&mut self, n: usize) {
+        self.evicted_from_front += n;
+        while let Some(&(len, _)) = self.blocks.front().filter(|&&(len, _)| len <= self.evicted_from_front) {
+            self.blocks.pop_front();
+            self.evicted_from_front -= len;
+        }
+    }
+
+    /// Drawdown over the window. `values` must be the window's contents,
+    /// oldest first; only the oldest partial block is read from it.
+    pub fn drawdown(&self, values: impl Iterator<Item = f64>) -> Drawdown {
+        let mut blocks = self.blocks.iter();
+        let mut result = match blocks.next() {
+            Some(&(len, _)) if self.evicted_from_front > 0 => {
+                let mut partial = Drawdown::default();
+                values.take(len - self.evicted_from_front).for_each(|v| partial.push(v));
+                partial
+            }
+            Some(&(_, summary)) => summary,
+            None => return Drawdown::default(),
+        };
+        for &(_, summary) in blocks {
+            result = result.then(summary);
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sliding_median_matches_sorted_window() {
+        let values = [5.0, 1.0, 4.0, 4.0, 9.0, 2.0, 7.0, 3.0, 3.0, 8.0];
+        let window = 4;
+        let mut median = SlidingMedian::new();
+
+        for (i, &value) in values.iter().enumerate() {
+            median.insert(value);
+            if i >= window {
+                median.remove(values[i - window]);
+            }
+
+            let start = (i + 1).saturating_sub(window);
+            let mut sorted = values[start..=i].to_vec();
+            sorted.sort_by(f64::total_cmp);
+            let n = sorted.len();
+            let expected = if n % 2 == 1 {
+                sorted[n / 2]
+            } else {
+                (sorted[n / 2 - 1] + sorted[n / 2]) / 2.0
+            };
+            assert_eq!(Some(expected), median.median());
+        }
+    }
+
+    #[test]
+    fn test_sliding_median_empty() {
+        let mut median = SlidingMedian::new();
+        assert_eq!(None, median.median());
+        median.insert(1.0);
+        median.remove(1.0);
+        assert_eq!(None, median.median());
+    }
+
+    #[test]
+    fn test_sliding_min_max() {
+        let values = [3.0, 1.0, 5.0, 2.0, 4.0, 6.0, 3.0, 0.5];
+        let window = 3;
+        let mut extrema = SlidingMinMax::new();
+
+        for (i, &value) in values.iter().enumerate() {
+            extrema.push(value);
+            if i >= window {
+                extrema.pop();
+            }
+
+            let start = (i + 1).saturating_sub(window);
+            let slice = &values[start..=i];
+            let min = slice.iter().cloned().fold(f64::MAX, f64::min);
+            let max = slice.iter().cloned().fold(f64::MIN, f64::max);
+            assert_eq!(Some(min), extrema.min());
+            assert_eq!(Some(max), extrema.max());
+        }
+    }
+
+    #[test]
+    fn test_batches_match_pushes() {
+        let values = [3.0, 1.0, 5.0, 2.0, 2.0, 4.0, 6.0, 3.0, 0.5, 7.0, 7.0, 1.0];
+        let (mut median, mut batched_median) = (SlidingMedian::new(), SlidingMedian::new());
+        let (mut extrema, mut batched_extrema) = (SlidingMinMax::new(), SlidingMinMax::new());
+        let (mut drawdown, mut batched_drawdown) = (SlidingDrawdown::new(9), SlidingDrawdown::new(9));
+        for &value in &values {
+            median.insert(value);
+            extrema.push(value);
+            drawdown.push(value);
+        }
+        for batch in [&values[..2], &values[2..7], &values[7..]] {
+            batched_median.extend(batch);
+            let (min, max) = (batch.iter().copied().fold(f64::MAX, f64::min), batch.iter().copied().fold(f64::MIN, f64::max));
+            batched_extrema.extend(batch, min, max);
+            batched_drawdown.extend(batch);
+        }
+        for i in 0..values.len() - 1 {
+            assert_eq!(median.median(), batched_median.median());
+            assert_eq!((extrema.min(), extrema.max()), (batched_extrema.min(), batched_extrema.max()));
+            assert_eq!(drawdown.drawdown(values[i..].iter().copied()), batched_drawdown.drawdown(values[i..].iter().copied()));
+            median.remove(values[i]);
+            batched_median.remove(values[i]);
+            extrema.pop();
+            batched_extrema.pop();
+            drawdown.pop();
+            batched_drawdown.pop();
+        }
+    }
+
+    #[test]
+    fn test_sliding_drawdown_matches_brute_force() {
+        let values = [5.0, 8.0, 3.0, 6.0, 9.0, 4.0, 4.5, 10.0, 2.0, 7.0, 1.0, 6.0];
+        let window = 5;
+        let mut drawdown = SlidingDrawdown::new(window);
+
+        for (i, &value) in values.iter().enumerate() {
+            drawdown.push(value);
+            if i >= window {
+                drawdown.pop();
+            }
+
+            let start = (i + 1).saturating_sub(window);
+            let slice = &values[start..=i];
+            let mut expected = 0.0f64;
+            let mut expected_rel = 0.0f64;
+            for a in 0..slice.len() {
+                for b in a..slice.len() {
+                    expected = expected.max(slice[a] - slice[b]);
+                    expected_rel = expected_rel.max((slice[a] - slice[b]) / slice[a]);
+                }
+            }
+            let result = drawdown.drawdown(slice.iter().copied());
+            assert_eq!(expected, result.absolute, "window ending at {}", i);
+            assert!((expected_rel - result.relative).abs() < 1e-12, "window ending at {}", i);
+        }
+    }
+}
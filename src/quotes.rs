@@ -0,0 +1,158 @@
+//! Bid/ask quote windows.
+//!
+//! Quotes are kept apart from the trade price windows: each symbol gets its
+//! own count-based quote windows, created on its first quote, that track the
+//! spread and mid price of the last 10^k quotes.
+
+use std::collections::VecDeque;
+
+use crate::series::RollingSeries;
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Quote {
+    pub bid: f64,
+    pub ask: f64,
+}
+
+impl Quote {
+    pub fn spread(&self) -> f64 {
+        self.ask - self.bid
+    }
+
+    pub fn mid(&self) -> f64 {
+        (self.bid + self.ask) / 2.0
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.bid.is_finite() || !self.ask.is_finite() {
+            return Err("Invalid quote input. Only finite bid and ask are accepted.".to_string());
+        }
+        if self.ask < self.bid {
+            return Err("Invalid quote input. Crossed quotes (ask below bid) are rejected.".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Min/max/avg/std_dev/last of one quote-derived series.
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SeriesSummary {
+    pub min: f64,
+    pub max: f64,
+    pub avg: f64,
+    pub std_dev: f64,
+    pub last: f64,
+}
+
+impl SeriesSummary {
+    fn of(series: &RollingSeries) -> Self {
+        SeriesSummary {
+            min: series.min(),
+            max: series.max(),
+            avg: series.mean(),
+            std_dev: series.variance().sqrt(),
+            last: series.last(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct QuoteStats {
+    /// Ask minus bid.
+    pub spread: SeriesSummary,
+    /// Midpoint of bid and ask.
+    pub mid: SeriesSummary,
+    /// Mean spread relative to the mean mid price.
+    pub relative_spread: f64,
+    /// Number of quotes in the window.
+    pub count: usize,
+}
+
+/// Spread and mid-price aggregates of the last `capacity` quotes.
+#[derive(Debug, Clone)]
+pub struct QuoteBuffer {
+    quotes: VecDeque<Quote>,
+    capacity: usize,
+    spread: RollingSeries,
+    mid: RollingSeries,
+    evictions_since_resync: usize,
+}
+
+impl QuoteBuffer {
+    pub fn new(capacity: usize) -> Self {
+        QuoteBuffer {
+            quotes: VecDeque::new(),
+            capacity,
+            spread: RollingSeries::new(),
+            mid: RollingSeries::new(),
+            evictions_since_resync: 0,
+        }
+    }
+
+    /// Approximate memory held by the buffer, inline and on the heap.
+    pub fn memory_bytes(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.quotes.capacity() * std::mem::size_of::<Quote>()
+            + self.spread.heap_bytes()
+            + self.mid.heap_bytes()
+    }
+
+    pub fn add(&mut self, quote: Quote) {
+        if self.quotes.len() >= self.capacity {
+            let old = self.quotes.pop_front().unwrap();
+            self.spread.pop(old.spread());
+            self.mid.pop(old.mid());
+            self.evictions_since_resync += 1;
+        }
+        self.quotes.push_back(quote);
+        self.spread.push(quote.spread());
+        self.mid.push(quote.mid());
+
+        // Same amortized resync as the price windows.
+        if self.evictions_since_resync >= self.capacity {
+            self.spread.resync(self.quotes.iter().map(Quote::spread));
+            self.mid.resync(self.quotes.iter().map(Quote::mid));
+            self.evictions_since_resync = 0;
+        }
+    }
+
+    pub fn stats(&self) -> QuoteStats {
+        if self.quotes.is_empty() {
+            return QuoteStats::default();
+        }
+        let mid = self.mid.mean();
+        QuoteStats {
+            spread: SeriesSummary::of(&self.spread),
+            mid: SeriesSummary::of(&self.mid),
+            relative_spread: if mid != 0.0 { self.spread.mean() / mid } else { 0.0 },
+            count: self.quotes.len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote_window_stats() {
+        let mut buffer = QuoteBuffer::new(2);
+        for (bid, ask) in [(90.0, 110.0), (99.0, 101.0), (99.5, 100.5)] {
+            buffer.add(Quote { bid, ask });
+        }
+
+        let stats = buffer.stats();
+        assert_eq!(2, stats.count);
+        assert_eq!(1.0, stats.spread.min);
+        assert_eq!(2.0, stats.spread.max);
+        assert_eq!(1.5, stats.spread.avg);
+        assert_eq!(100.0, stats.mid.avg);
+        assert_eq!(0.015, stats.relative_spread);
+    }
+
+    #[test]
+    fn test_crossed_quote_is_rejected() {
+        assert!(Quote { bid: 101.0, ask: 100.0 }.validate().is_err());
+        assert!(Quote { bid: 100.0, ask: 100.0 }.validate().is_ok());
+    }
+}
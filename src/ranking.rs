@@ -0,0 +1,104 @@
+//! Cross-symbol rankings of a per-window metric.
+
+use crate::TradingDataBuffer;
+
+/// Metric symbols are ranked by.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RankMetric {
+    /// `(last - open) / open` over the window.
+    #[default]
+    PctChange,
+    /// Population standard deviation of the window.
+    Volatility,
+    /// `max - min` of the window.
+    Range,
+}
+
+impl RankMetric {
+    /// Value of the metric for a window, or `None` when it is undefined
+    /// (an empty window, or a zero open for `pct_change`).
+    pub fn value(self, window: &TradingDataBuffer) -> Option<f64> {
+        window.open()?;
+        let series = window.series(crate::SeriesKind::Prices);
+        match self {
+            RankMetric::PctChange => window.pct_change(),
+            RankMetric::Volatility => Some(window.std_dev()),
+            RankMetric::Range => Some(series.max() - series.min()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Mover {
+    pub symbol: String,
+    pub value: f64,
+}
+
+/// Direction of a ranking.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Order {
+    Asc,
+    /// Largest first.
+    #[default]
+    Desc,
+}
+
+/// Keeps the `limit` symbols first in `order` by value, ties by symbol.
+pub fn rank(mut ranked: Vec<Mover>, order: Order, limit: usize) -> Vec<Mover> {
+    ranked.sort_by(|a, b| {
+        let by_value = match order {
+            Order::Asc => a.value.total_cmp(&b.value),
+            Order::Desc => b.value.total_cmp(&a.value),
+        };
+        by_value.then_with(|| a.symbol.cmp(&b.symbol))
+    });
+    ranked.truncate(limit);
+    ranked
+}
+
+/// Keeps the `limit` movers with the largest magnitude, largest first.
+pub fn top(mut movers: Vec<Mover>, limit: usize) -> Vec<Mover> {
+    movers.sort_by(|a, b| b.value.abs().total_cmp(&a.value.abs()).then_with(|| a.symbol.cmp(&b.symbol)));
+    movers.truncate(limit);
+    movers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metric_values() {
+        let mut window = TradingDataBuffer::new(4);
+        window.add_batch(&[1.0, 8.0, 4.0, 2.0, 5.0]);
+        assert_eq!(Some(-0.375), RankMetric::PctChange.value(&window));
+        assert_eq!(Some(6.0), RankMetric::Range.value(&window));
+        assert_eq!(None, RankMetric::PctChange.value(&TradingDataBuffer::new(4)));
+    }
+
+    #[test]
+    fn test_top_ranks_by_magnitude() {
+        let movers = vec![
+            Mover { symbol: "A".to_string(), value: 0.1 },
+            Mover { symbol: "B".to_string(), value: -0.3 },
+            Mover { symbol: "C".to_string(), value: 0.2 },
+        ];
+        let symbols: Vec<String> = top(movers, 2).into_iter().map(|m| m.symbol).collect();
+        assert_eq!(vec!["B", "C"], symbols);
+    }
+
+    #[test]
+    fn test_rank_by_value() {
+        let ranked = vec![
+            Mover { symbol: "A".to_string(), value: 0.1 },
+            Mover { symbol: "B".to_string(), value: -0.3 },
+            Mover { symbol: "C".to_string(), value: 0.2 },
+            Mover { symbol: "D".to_string(), value: 0.2 },
+        ];
+        let symbols = |ranked: Vec<Mover>| ranked.into_iter().map(|m| m.symbol).collect::<Vec<_>>();
+        assert_eq!(vec!["C", "D", "A"], symbols(rank(ranked.clone(), Order::Desc, 3)));
+        assert_eq!(vec!["B", "A"], symbols(rank(ranked, Order::Asc, 2)));
+    }
+}
@@ -0,0 +1,118 @@
+//! Event-time reordering of late ticks.
+//!
+//! With a non-zero slack, timestamped points are held back until a point at
+//! least `slack` newer has arrived, so ticks that arrive slightly out of
+//! order still reach the windows in timestamp order. A point older than one
+//! already released can no longer be placed and is dropped as too late.
+
+use std::collections::BTreeMap;
+
+use crate::Batch;
+
+#[derive(Debug, Clone, Default)]
+pub struct ReorderBuffer {
+    slack_ns: u64,
+    /// Held points keyed by timestamp, then arrival order for ties.
+    pending: BTreeMap<(u64, u64), Batch>,
+    arrivals: u64,
+    newest_ns: u64,
+    released_ns: Option<u64>,
+}
+
+impl ReorderBuffer {
+    pub fn new(slack_ns: u64) -> Self {
+        ReorderBuffer { slack_ns, ..ReorderBuffer::default() }
+    }
+
+    pub fn slack_ns(&self) -> u64 {
+        self.slack_ns
+    }
+
+    /// Points currently held back.
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Holds the batch's timestamped points and returns the ones now ready,
+    /// one point per batch in timestamp order, along with how many of the
+    /// batch's points were dropped as too late.
+    pub fn push(&mut self, batch: &Batch) -> (Vec<Batch>, u64) {
+        let Some(timestamps) = &batch.timestamps else {
+            return (vec![batch.clone()], 0);
+        };
+        let mut late = 0;
+        for (i, &timestamp_ns) in timestamps.iter().enumerate() {
+            if self.released_ns.is_some_and(|released| timestamp_ns < released) {
+                late += 1;
+                continue;
+            }
+            self.newest_ns = self.newest_ns.max(timestamp_ns);
+            self.pending.insert((timestamp_ns, self.arrivals), batch.point(i));
+            self.arrivals += 1;
+        }
+        (self.release(), late)
+    }
+
+    /// Changes the slack, returning the points that are ready under the new
+    /// one. A zero slack releases everything held.
+    pub fn set_slack(&mut self, slack_ns: u64) -> Vec<Batch> {
+        self.slack_ns = slack_ns;
+        self.release()
+    }
+
+    fn release(&mut self) -> Vec<Batch> {
+        let held = match self.newest_ns.checked_sub(self.slack_ns) {
+            _ if self.slack_ns == 0 => BTreeMap::new(),
+            Some(cutoff) => self.pending.split_off(&(cutoff + 1, 0)),
+            None => std::mem::take(&mut self.pending),
+        };
+        let ready = std::mem::replace(&mut self.pending, held);
+        if let Some(&(timestamp_ns, _)) = ready.keys().next_back() {
+            self.released_ns = Some(timestamp_ns);
+        }
+        ready.into_values().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timed(values: &[f64], timestamps: &[u64]) -> Batch {
+        Batch { timestamps: Some(timestamps.to_vec()), ..Batch::new(values.to_vec()) }
+    }
+
+    fn values(batches: &[Batch]) -> Vec<f64> {
+        batches.iter().flat_map(|b| b.values.iter().copied()).collect()
+    }
+
+    #[test]
+    fn test_reorders_within_slack() {
+        let mut buffer = ReorderBuffer::new(50);
+        let (ready, late) = buffer.push(&timed(&[1.0, 3.0], &[100, 130]));
+        assert!(ready.is_empty());
+        assert_eq!(0, late);
+
+        // 2.0 arrives late but within the slack; 4.0 releases up to t=110.
+        let (ready, _) = buffer.push(&timed(&[2.0, 4.0], &[120, 160]));
+        assert_eq!(vec![1.0], values(&ready));
+        let (ready, late) = buffer.push(&timed(&[0.5, 5.0], &[95, 200]));
+        assert_eq!(vec![2.0, 3.0], values(&ready));
+        assert_eq!(1, late);
+
+        assert_eq!(vec![4.0, 5.0], values(&buffer.set_slack(0)));
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_untimestamped_batches_pass_through() {
+        let mut buffer = ReorderBuffer::new(50);
+        let (ready, late) = buffer.push(&Batch::new(vec![1.0, 2.0]));
+        assert_eq!(vec![1.0, 2.0], values(&ready));
+        assert_eq!(0, late);
+    }
+}
@@ -0,0 +1,112 @@
+//! `X-Request-Id`, so a client timing out can find the server's side of
+//! the request.
+//!
+//! A request keeps the id it sent, or gets one generated when it sent none
+//! or an unusable one. Its handler chain runs in a `request` tracing span
+//! carrying the id, and every response echoes it, errors included.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::InternalError;
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::Error;
+use tracing::Instrument;
+
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+/// Longest id taken from a client; longer ones are replaced.
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// The client's id, if it's 1 to 128 visible ASCII characters.
+fn client_id(value: &HeaderValue) -> Option<String> {
+    let bytes = value.as_bytes();
+    let valid = (1..=MAX_REQUEST_ID_LEN).contains(&bytes.len()) && bytes.iter().all(|byte| byte.is_ascii_graphic());
+    valid.then(|| String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// An id unique within the process and, by its start time, across
+/// restarts.
+fn generate() -> String {
+    static PREFIX: OnceLock<String> = OnceLock::new();
+    static NEXT: AtomicU64 = AtomicU64::new(1);
+    let prefix = PREFIX.get_or_init(|| {
+        let started = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        format!("{:x}", started.as_micros())
+    });
+    format!("{}-{}", prefix, NEXT.fetch_add(1, Ordering::Relaxed))
+}
+
+/// Runs the request in a span carrying its id and echoes the id on the
+/// response. Completed requests are logged at debug level, server errors
+/// at warn.
+pub async fn middleware<B: MessageBody>(req: ServiceRequest, next: Next<B>) -> Result<ServiceResponse<B>, Error> {
+    let id = req.headers().get(REQUEST_ID_HEADER).and_then(client_id).unwrap_or_else(generate);
+    let span = tracing::info_span!("request", request_id = %id, method = %req.method(), path = %req.path());
+    let started = Instant::now();
+    let result = next.call(req).instrument(span.clone()).await;
+
+    let status = match &result {
+        Ok(res) => res.status(),
+        Err(e) => e.as_response_error().status_code(),
+    };
+    let elapsed_us = started.elapsed().as_micros() as u64;
+    if status.is_server_error() {
+        tracing::warn!(parent: &span, status = status.as_u16(), elapsed_us, "request failed");
+    } else {
+        tracing::debug!(parent: &span, status = status.as_u16(), elapsed_us, "request done");
+    }
+    // Ids are checked or generated as header-safe.
+    let value = HeaderValue::from_str(&id).expect("request ids are visible ASCII");
+    match result {
+        Ok(mut res) => {
+            res.headers_mut().insert(HeaderName::from_static("x-request-id"), value);
+            Ok(res)
+        }
+        // A middleware's error, e.g. a CORS rejection, becomes a response
+        // only after this, so it's given one carrying the id now.
+        Err(e) => {
+            let mut res = e.error_response();
+            res.headers_mut().insert(HeaderName::from_static("x-request-id"), value);
+            Err(InternalError::from_response(e, res).into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{middleware, test, web, App, HttpResponse};
+
+    #[actix_rt::test]
+    async fn test_middleware() {
+        let app = test::init_service(
+            App::new()
+                .wrap(middleware::from_fn(middleware))
+                .route("/ok", web::get().to(HttpResponse::Ok))
+                .route("/fail", web::get().to(|| async { Err::<HttpResponse, _>(actix_web::error::ErrorBadRequest("no")) })),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/ok").insert_header((REQUEST_ID_HEADER, "abc-123")).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!("abc-123", res.headers().get(REQUEST_ID_HEADER).unwrap());
+
+        let req = test::TestRequest::get().uri("/fail").insert_header((REQUEST_ID_HEADER, "abc-124")).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(400, res.status().as_u16());
+        assert_eq!("abc-124", res.headers().get(REQUEST_ID_HEADER).unwrap());
+
+        // Missing and unusable ids are replaced with distinct generated ones.
+        let res = test::call_service(&app, test::TestRequest::get().uri("/missing").to_request()).await;
+        let generated = res.headers().get(REQUEST_ID_HEADER).unwrap().clone();
+        let req = test::TestRequest::get().uri("/ok").insert_header((REQUEST_ID_HEADER, "a b")).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_ne!(generated, res.headers().get(REQUEST_ID_HEADER).unwrap());
+        assert_ne!("a b", res.headers().get(REQUEST_ID_HEADER).unwrap());
+    }
+}
@@ -0,0 +1,103 @@
+//! Risk metrics derived from the windows' return distributions.
+
+use crate::{SeriesKind, TradingDataBuffer};
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct VarLevel {
+    /// Confidence level, e.g. 0.99.
+    pub confidence: f64,
+    /// Loss (as a positive fraction of price) not exceeded with the given
+    /// confidence over one step.
+    pub var: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ValueAtRisk {
+    pub levels: Vec<VarLevel>,
+    /// Number of returns in the window's distribution.
+    pub count: usize,
+}
+
+/// Historical-simulation VaR: the negated `1 - confidence` quantile of the
+/// window's simple returns, read from its return sketch.
+pub fn value_at_risk(window: &TradingDataBuffer, confidences: &[f64]) -> ValueAtRisk {
+    let count = window.len().saturating_sub(1);
+    let tails: Vec<f64> = confidences.iter().map(|c| 1.0 - c).collect();
+    let quantiles = if count > 0 {
+        window.return_quantiles(&tails)
+    } else {
+        vec![0.0; tails.len()]
+    };
+    let levels = confidences
+        .iter()
+        .zip(quantiles)
+        .map(|(&confidence, q)| VarLevel { confidence, var: (-q).max(0.0) })
+        .collect();
+    ValueAtRisk { levels, count }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Sharpe {
+    /// Annualized Sharpe ratio, or `None` when the returns are flat.
+    pub sharpe: Option<f64>,
+    /// Annualized mean return.
+    pub mean_return: f64,
+    /// Annualized volatility of returns.
+    pub volatility: f64,
+    /// Number of returns in the window.
+    pub count: usize,
+}
+
+/// Annualized Sharpe ratio of the window's simple returns. `risk_free_rate`
+/// is annual and is spread evenly over `periods_per_year` steps.
+pub fn sharpe(window: &TradingDataBuffer, risk_free_rate: f64, periods_per_year: f64) -> Sharpe {
+    let returns = window.series(SeriesKind::Returns);
+    let std_dev = returns.variance().sqrt();
+    let excess = returns.mean() - risk_free_rate / periods_per_year;
+    let sharpe = (std_dev > 0.0).then(|| excess / std_dev * periods_per_year.sqrt());
+    Sharpe {
+        sharpe,
+        mean_return: returns.mean() * periods_per_year,
+        volatility: std_dev * periods_per_year.sqrt(),
+        count: returns.len(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_value_at_risk_from_returns() {
+        let mut window = TradingDataBuffer::new(1001);
+        // 1000 returns: 1% lose 10%, the rest gain 1%.
+        let mut price = 100.0;
+        let mut prices = vec![price];
+        for i in 0..1000 {
+            price *= if i % 100 == 0 { 0.9 } else { 1.01 };
+            prices.push(price);
+        }
+        window.add_batch(&prices);
+
+        let result = value_at_risk(&window, &[0.95, 0.999]);
+        assert_eq!(1000, result.count);
+        assert_eq!(0.0, result.levels[0].var);
+        assert!((result.levels[1].var - 0.1).abs() < 1e-6, "{}", result.levels[1].var);
+    }
+
+    #[test]
+    fn test_sharpe() {
+        let mut window = TradingDataBuffer::new(10);
+        // Returns alternate between +10% and -5%.
+        window.add_batch(&[100.0, 110.0, 104.5, 114.95, 109.2025]);
+
+        let result = sharpe(&window, 0.0, 4.0);
+        assert_eq!(4, result.count);
+        // mean 0.025, std 0.075 per period; annualized over 4 periods.
+        assert!((result.sharpe.unwrap() - 0.025 / 0.075 * 2.0).abs() < 1e-9);
+        assert!((result.mean_return - 0.1).abs() < 1e-9);
+
+        let with_rf = sharpe(&window, 0.1, 4.0);
+        assert!(with_rf.sharpe.unwrap().abs() < 1e-9);
+    }
+}
@@ -0,0 +1,117 @@
+//! Cursors paging through a window's raw points as they were when the scan
+//! started, however long it takes: points added since are left out, and
+//! points evicted since are kept aside until the scan has read them.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::ServiceError;
+
+/// How long a scan may go without a page before it's dropped.
+pub const SCAN_TTL: Duration = Duration::from_secs(60);
+
+/// Most scans a service keeps open at once.
+pub const MAX_SCANS: usize = 64;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scan {
+    pub symbol: String,
+    pub k: usize,
+    /// [`crate::TradingDataBuffer::id`] of the window scanned.
+    pub window_id: u64,
+    /// Position of the oldest point when the scan started.
+    pub start: u64,
+    /// Position of the next point to read.
+    pub next: u64,
+    /// Position just past the newest point when the scan started.
+    pub end: u64,
+    last_used: Instant,
+}
+
+impl Scan {
+    /// A scan of the points from position `start` up to `end`.
+    pub fn new(symbol: String, k: usize, window_id: u64, start: u64, end: u64) -> Scan {
+        Scan { symbol, k, window_id, start, next: start, end, last_used: Instant::now() }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Scans {
+    scans: HashMap<u64, Scan>,
+}
+
+impl Scans {
+    /// Opens a scan, returning its cursor. Ids are random, so one client
+    /// can't guess the cursor of another's scan, nor one from before a
+    /// restart name a new scan.
+    pub fn open(&mut self, scan: Scan) -> Result<String, ServiceError> {
+        if self.scans.len() >= MAX_SCANS {
+            return Err(ServiceError::LimitReached(format!("Maximum of {} open scans reached", MAX_SCANS)));
+        }
+        let id = loop {
+            let id = getrandom::u64().map_err(|e| ServiceError::Internal(format!("No random scan id: {}", e)))?;
+            if !self.scans.contains_key(&id) {
+                break id;
+            }
+        };
+        self.scans.insert(id, scan);
+        Ok(format!("{:016x}", id))
+    }
+
+    /// The scan a cursor names, left open.
+    pub fn get(&self, cursor: &str) -> Option<&Scan> {
+        let id = u64::from_str_radix(cursor, 16).ok()?;
+        self.scans.get(&id)
+    }
+
+    /// Takes the scan a cursor names out, to be put back with
+    /// [`Self::reopen`] if it has more to read.
+    pub fn take(&mut self, cursor: &str) -> Option<(u64, Scan)> {
+        let id = u64::from_str_radix(cursor, 16).ok()?;
+        self.scans.remove(&id).map(|scan| (id, scan))
+    }
+
+    pub fn reopen(&mut self, id: u64, mut scan: Scan) -> String {
+        scan.last_used = Instant::now();
+        self.scans.insert(id, scan);
+        format!("{:016x}", id)
+    }
+
+    /// Drops the scans idle for longer than [`SCAN_TTL`], returning them.
+    pub fn expire(&mut self) -> Vec<Scan> {
+        let expired: Vec<u64> = self.scans.iter().filter(|(_, scan)| scan.last_used.elapsed() > SCAN_TTL).map(|(&id, _)| id).collect();
+        expired.into_iter().filter_map(|id| self.scans.remove(&id)).collect()
+    }
+
+    /// The oldest position an open scan of the window still needs.
+    pub fn oldest_needed(&self, symbol: &str, k: usize, window_id: u64) -> Option<u64> {
+        self.scans
+            .values()
+            .filter(|scan| scan.symbol == symbol && scan.k == k && scan.window_id == window_id)
+            .map(|scan| scan.next)
+            .min()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scans() {
+        let mut scans = Scans::default();
+        let cursor = scans.open(Scan::new("A".to_string(), 8, 1, 10, 100)).unwrap();
+        let other = scans.open(Scan::new("A".to_string(), 8, 1, 5, 100)).unwrap();
+        assert_ne!(cursor, other);
+        assert_eq!(Some(5), scans.oldest_needed("A", 8, 1));
+        assert_eq!(None, scans.oldest_needed("A", 8, 2));
+
+        assert_eq!(Some(10), scans.get(&cursor).map(|scan| scan.next));
+        let (id, mut scan) = scans.take(&cursor).unwrap();
+        assert!(scans.take(&cursor).is_none());
+        scan.next = 20;
+        assert_eq!(cursor, scans.reopen(id, scan));
+        assert!(scans.take("not a cursor").is_none());
+        assert!(scans.expire().is_empty());
+    }
+}
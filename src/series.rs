@@ -0,0 +1,198 @@
+use crate::moments::{BatchSummary, CompensatedSum, Moments};
+use crate::order_stats::SlidingMinMax;
+
+/// Which series a window's statistics are computed over.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SeriesKind {
+    #[default]
+    Prices,
+    /// Simple returns `x[i] / x[i-1] - 1` between successive prices.
+    Returns,
+    /// Log returns `ln(x[i] / x[i-1])` between successive prices.
+    LogReturns,
+}
+
+impl SeriesKind {
+    /// Return from `prev` to `next`. Returns are undefined for non-positive
+    /// prices; those steps count as 0.0 so the return series stays aligned
+    /// with the price window.
+    pub fn step(self, prev: f64, next: f64) -> f64 {
+        match self {
+            SeriesKind::Prices => next,
+            SeriesKind::Returns if prev > 0.0 => next / prev - 1.0,
+            SeriesKind::LogReturns if prev > 0.0 && next > 0.0 => (next / prev).ln(),
+            _ => 0.0,
+        }
+    }
+}
+
+/// The incremental aggregates kept for one series of a window: compensated
+/// sum, central moments and sliding min/max. Values must be popped in the
+/// order they were pushed.
+#[derive(Debug, Clone, Default)]
+pub struct RollingSeries {
+    sum: CompensatedSum,
+    moments: Moments,
+    extrema: SlidingMinMax,
+    last: f64,
+}
+
+impl RollingSeries {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn heap_bytes(&self) -> usize {
+        self.extrema.heap_bytes()
+    }
+
+    pub fn push(&mut self, value: f64) {
+        self.sum.add(value);
+        self.moments.add(value);
+        self.extrema.push(value);
+        self.last = value;
+    }
+
+    /// Pushes a batch of values at once: its sum, min, max and moments are
+    /// worked out over the batch alone and then merged in, rather than
+    /// updating the running aggregates one value at a time.
+    pub fn extend(&mut self, values: &[f64]) {
+        let Some(&last) = values.last() else { return };
+        let summary = BatchSummary::of(values);
+        self.sum.add(summary.sum);
+        self.moments.merge(&Moments::of(values, &summary));
+        self.extrema.extend(values, summary.min, summary.max);
+        self.last = last;
+    }
+
+    /// Evicts the oldest value, which the caller passes back in.
+    pub fn pop(&mut self, value: f64) {
+        self.sum.sub(value);
+        self.moments.remove(value);
+        self.extrema.pop();
+    }
+
+    /// Evicts the oldest values, which the caller passes back in, by taking
+    /// their sum and moments out at once.
+    pub fn pop_many(&mut self, values: &[f64]) {
+        let summary = BatchSummary::of(values);
+        self.sum.sub(summary.sum);
+        self.moments.unmerge(&Moments::of(values, &summary));
+        self.extrema.pop_many(values.len());
+    }
+
+    /// Rebuilds the sum and moments from the values currently in the window,
+    /// discarding accumulated rounding error. Min/max are exact and kept.
+    pub fn resync(&mut self, values: impl Iterator<Item = f64>) {
+        self.sum = CompensatedSum::new();
+        self.moments = Moments::new();
+        for value in values {
+            self.sum.add(value);
+            self.moments.add(value);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.moments.count() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn last(&self) -> f64 {
+        self.last
+    }
+
+    pub fn sum(&self) -> f64 {
+        self.sum.value()
+    }
+
+    pub fn mean(&self) -> f64 {
+        if self.is_empty() {
+            return 0.0;
+        }
+        self.sum.value() / self.len() as f64
+    }
+
+    /// Sum of squared values, derived from the central moments.
+    pub fn sum_of_squares(&self) -> f64 {
+        let n = self.len() as f64;
+        let mean = self.mean();
+        n * (self.variance() + mean * mean)
+    }
+
+    pub fn variance(&self) -> f64 {
+        self.moments.variance()
+    }
+
+    pub fn skewness(&self) -> f64 {
+        self.moments.skewness()
+    }
+
+    pub fn kurtosis(&self) -> f64 {
+        self.moments.kurtosis()
+    }
+
+    pub fn min(&self) -> f64 {
+        self.extrema.min().unwrap_or(0.0)
+    }
+
+    pub fn max(&self) -> f64 {
+        self.extrema.max().unwrap_or(0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rolling_series_push_pop() {
+        let mut series = RollingSeries::new();
+        for v in [1.0, 2.0, 3.0, 4.0] {
+            series.push(v);
+        }
+        series.pop(1.0);
+
+        assert_eq!(3, series.len());
+        assert_eq!(3.0, series.mean());
+        assert_eq!(2.0, series.min());
+        assert_eq!(4.0, series.max());
+        assert_eq!(4.0, series.last());
+    }
+
+    #[test]
+    fn test_rolling_series_extend() {
+        let mut pushed = RollingSeries::new();
+        let mut extended = RollingSeries::new();
+        for v in [5.0, 3.0] {
+            pushed.push(v);
+            extended.push(v);
+        }
+        let batch = [4.0, 1.0, 6.0, 2.0, 2.0, 7.0, 3.0];
+        for v in batch {
+            pushed.push(v);
+        }
+        extended.extend(&batch);
+        for v in [5.0, 3.0, 4.0, 1.0, 6.0] {
+            pushed.pop(v);
+        }
+        extended.pop(5.0);
+        extended.pop_many(&[3.0, 4.0, 1.0, 6.0]);
+
+        assert_eq!(pushed.len(), extended.len());
+        assert_eq!((2.0, 7.0, 3.0), (extended.min(), extended.max(), extended.last()));
+        assert!((3.5 - extended.mean()).abs() < 1e-12);
+        assert!((pushed.variance() - extended.variance()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_series_kind_step() {
+        assert_eq!(0.5, SeriesKind::Returns.step(2.0, 3.0));
+        assert_eq!(0.0, SeriesKind::LogReturns.step(2.0, 2.0));
+        assert_eq!(0.0, SeriesKind::Returns.step(0.0, 3.0));
+        assert_eq!(0.0, SeriesKind::LogReturns.step(2.0, -1.0));
+    }
+}
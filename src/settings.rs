@@ -0,0 +1,61 @@
+//! Service-wide settings an operator can change at runtime, without a
+//! restart losing the in-memory windows.
+
+use crate::{SymbolConfig, MAX_BATCH_IDS};
+
+/// Default of the most values one batch may hold.
+pub const DEFAULT_MAX_BATCH_SIZE: usize = 10_000;
+
+/// Highest `max_batch_size` accepted, as many values as fit the 2 MiB
+/// request body at about 20 bytes each, a full-precision JSON number and
+/// its comma.
+pub const MAX_BATCH_SIZE_LIMIT: usize = 100_000;
+
+/// Highest `batch_id_memory` accepted.
+pub const MAX_BATCH_ID_MEMORY: usize = 10_000_000;
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Settings {
+    /// Most values, or quotes, one batch may hold.
+    #[serde(default = "default_max_batch_size")]
+    pub max_batch_size: usize,
+    /// Config of symbols created by data rather than by a config of their
+    /// own, e.g. by their first batch or as another symbol's leg. Symbols
+    /// already tracked keep theirs.
+    #[serde(default)]
+    pub new_symbol_config: SymbolConfig,
+    /// How many recent batch IDs each namespace remembers to skip retried
+    /// batches, the oldest evicted first; 0 remembers none.
+    #[serde(default = "default_batch_id_memory")]
+    pub batch_id_memory: usize,
+}
+
+fn default_max_batch_size() -> usize {
+    DEFAULT_MAX_BATCH_SIZE
+}
+
+fn default_batch_id_memory() -> usize {
+    MAX_BATCH_IDS
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            max_batch_size: DEFAULT_MAX_BATCH_SIZE,
+            new_symbol_config: SymbolConfig::default(),
+            batch_id_memory: MAX_BATCH_IDS,
+        }
+    }
+}
+
+impl Settings {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_batch_size == 0 || self.max_batch_size > MAX_BATCH_SIZE_LIMIT {
+            return Err(format!("Invalid max_batch_size input. Only values 1-{} are accepted.", MAX_BATCH_SIZE_LIMIT));
+        }
+        if self.batch_id_memory > MAX_BATCH_ID_MEMORY {
+            return Err(format!("Invalid batch_id_memory input. Only values up to {} are accepted.", MAX_BATCH_ID_MEMORY));
+        }
+        self.new_symbol_config.validate()
+    }
+}
@@ -0,0 +1,564 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::moments::BatchSummary;
+
+/// Compression used for every digest in a sliding window. Higher values keep
+/// more centroids (better accuracy, more memory).
+const COMPRESSION: f64 = 100.0;
+
+/// Number of digest blocks a sliding window is split into.
+const BLOCKS_PER_WINDOW: usize = 8;
+
+#[derive(Debug, Clone, Copy)]
+struct Centroid {
+    mean: f64,
+    weight: f64,
+}
+
+/// Merging t-digest (Dunning) using the k1 (arcsine) scale function.
+#[derive(Debug, Clone)]
+pub struct TDigest {
+    compression: f64,
+    centroids: Vec<Centroid>,
+    unmerged: Vec<Centroid>,
+    count: u64,
+    min: f64,
+    max: f64,
+}
+
+impl TDigest {
+    pub fn new(compression: f64) -> Self {
+        TDigest {
+            compression,
+            centroids: Vec::new(),
+            unmerged: Vec::new(),
+            count: 0,
+            min: f64::MAX,
+            max: f64::MIN,
+        }
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn insert(&mut self, value: f64) {
+        self.push(Centroid { mean: value, weight: 1.0 }, 1);
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    /// Inserts a batch of values, buffering as many at a time as fit before
+    /// the next compression.
+    pub fn extend(&mut self, values: &[f64]) {
+        let threshold = (self.compression * 5.0).ceil() as usize;
+        let mut rest = values;
+        while !rest.is_empty() {
+            let (chunk, next) = rest.split_at((threshold - self.unmerged.len()).min(rest.len()));
+            self.unmerged.extend(chunk.iter().map(|&mean| Centroid { mean, weight: 1.0 }));
+            self.count += chunk.len() as u64;
+            let summary = BatchSummary::of(chunk);
+            self.min = self.min.min(summary.min);
+            self.max = self.max.max(summary.max);
+            if self.unmerged.len() >= threshold {
+                self.compress();
+            }
+            rest = next;
+        }
+    }
+
+    /// Folds another digest into this one. Both digests keep their accuracy
+    /// guarantees, which is what makes per-block digests usable for windows.
+    pub fn merge(&mut self, other: &TDigest) {
+        for &c in other.centroids.iter().chain(other.unmerged.iter()) {
+            self.push(c, 0);
+        }
+        self.count += other.count;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+
+    fn push(&mut self, centroid: Centroid, count: u64) {
+        self.unmerged.push(centroid);
+        self.count += count;
+        if self.unmerged.len() as f64 >= self.compression * 5.0 {
+            self.compress();
+        }
+    }
+
+    pub fn compress(&mut self) {
+        if self.unmerged.is_empty() {
+            return;
+        }
+        let mut all = std::mem::take(&mut self.centroids);
+        all.append(&mut self.unmerged);
+        all.sort_by(|a, b| a.mean.total_cmp(&b.mean));
+
+        let total: f64 = all.iter().map(|c| c.weight).sum();
+        let mut merged = Vec::with_capacity(self.compression as usize * 2);
+        let mut current = all[0];
+        let mut weight_so_far = 0.0;
+        let mut limit = total * self.q_limit(0.0);
+
+        for &c in &all[1..] {
+            if weight_so_far + current.weight + c.weight <= limit {
+                let weight = current.weight + c.weight;
+                current.mean += (c.mean - current.mean) * c.weight / weight;
+                current.weight = weight;
+            } else {
+                weight_so_far += current.weight;
+                limit = total * self.q_limit(weight_so_far / total);
+                merged.push(current);
+                current = c;
+            }
+        }
+        merged.push(current);
+        self.centroids = merged;
+    }
+
+    /// Largest cumulative quantile the centroid starting at `q` may grow to.
+    fn q_limit(&self, q: f64) -> f64 {
+        let k = self.compression / (2.0 * std::f64::consts::PI) * (2.0 * q - 1.0).asin() + 1.0;
+        let q = ((k * 2.0 * std::f64::consts::PI / self.compression).min(std::f64::consts::FRAC_PI_2).sin() + 1.0) / 2.0;
+        q.min(1.0)
+    }
+
+    /// Estimated value at quantile `q` (0.0..=1.0). The digest must be
+    /// compressed; returns 0.0 when empty.
+    pub fn quantile(&self, q: f64) -> f64 {
+        let centroids = &self.centroids;
+        if centroids.is_empty() {
+            return 0.0;
+        }
+        if centroids.len() == 1 {
+            return centroids[0].mean;
+        }
+
+        let total: f64 = centroids.iter().map(|c| c.weight).sum();
+        let target = q.clamp(0.0, 1.0) * total;
+
+        let first = centroids[0];
+        if target < first.weight / 2.0 {
+            return self.min + (first.mean - self.min) * target / (first.weight / 2.0);
+        }
+
+        let mut cumulative = 0.0;
+        for pair in centroids.windows(2) {
+            let left = cumulative + pair[0].weight / 2.0;
+            let right = cumulative + pair[0].weight + pair[1].weight / 2.0;
+            if target <= right {
+                let t = (target - left) / (right - left);
+                return pair[0].mean + t * (pair[1].mean - pair[0].mean);
+            }
+            cumulative += pair[0].weight;
+        }
+
+        let last = centroids[centroids.len() - 1];
+        let left = total - last.weight / 2.0;
+        let t = ((target - left) / (last.weight / 2.0)).min(1.0);
+        last.mean + t * (self.max - last.mean)
+    }
+}
+
+/// Quantile sketch over the last `window` values.
+///
+/// The window is split into fixed-size blocks, each with its own t-digest.
+/// A block is dropped once the newer blocks alone cover the window, so the
+/// sketch always spans between `window` and `window + block_size - 1` of the
+/// most recent values. Queries merge the live blocks.
+#[derive(Debug, Clone)]
+pub struct SlidingQuantiles {
+    window: usize,
+    block_size: usize,
+    blocks: VecDeque<TDigest>,
+    count: usize,
+}
+
+impl SlidingQuantiles {
+    pub fn new(window: usize) -> Self {
+        SlidingQuantiles {
+            window,
+            block_size: (window / BLOCKS_PER_WINDOW).max(1),
+            blocks: VecDeque::new(),
+            count: 0,
+        }
+    }
+
+    pub fn insert(&mut self, value: f64) {
+        let needs_block = self
+            .blocks
+            .back()
+            .is_none_or(|b| b.count() as usize >= self.block_size);
+        if needs_block {
+            self.blocks.push_back(TDigest::new(COMPRESSION));
+        }
+        self.blocks.back_mut().unwrap().insert(value);
+        self.count += 1;
+        self.drop_covered();
+    }
+
+    /// Drops the oldest blocks the newer ones cover the window without.
+    fn drop_covered(&mut self) {
+        while let Some(front) = self.blocks.front() {
+            let front_count = front.count() as usize;
+            if self.count - front_count < self.window {
+                break;
+            }
+            self.count -= front_count;
+            self.blocks.pop_front();
+        }
+    }
+
+    /// Inserts a batch of values a block's worth at a time.
+    pub fn extend(&mut self, values: &[f64]) {
+        let mut rest = values;
+        while !rest.is_empty() {
+            let room = match self.blocks.back() {
+                Some(block) if (block.count() as usize) < self.block_size => self.block_size - block.count() as usize,
+                _ => {
+                    self.blocks.push_back(TDigest::new(COMPRESSION));
+                    self.block_size
+                }
+            };
+            let (block, next) = rest.split_at(room.min(rest.len()));
+            self.blocks.back_mut().unwrap().extend(block);
+            self.count += block.len();
+            self.drop_covered();
+            rest = next;
+        }
+    }
+
+    pub fn quantiles(&self, qs: &[f64]) -> Vec<f64> {
+        let mut digest = TDigest::new(COMPRESSION);
+        for block in &self.blocks {
+            digest.merge(block);
+        }
+        digest.compress();
+        qs.iter().map(|&q| digest.quantile(q)).collect()
+    }
+}
+
+/// Magnitudes below this are counted as zero by `DdSketch`.
+const DD_MIN_INDEXABLE: f64 = 1e-9;
+
+/// DDSketch (Masson et al.): log-spaced buckets that answer every quantile
+/// within `relative_accuracy` of the true value. Bucket counts can be
+/// decremented, so a window evicts values exactly rather than by block.
+#[derive(Debug, Clone)]
+pub struct DdSketch {
+    gamma: f64,
+    ln_gamma: f64,
+    positive: BTreeMap<i32, u64>,
+    /// Buckets of `-x` for negative values.
+    negative: BTreeMap<i32, u64>,
+    zeros: u64,
+    count: u64,
+}
+
+impl DdSketch {
+    pub fn new(relative_accuracy: f64) -> Self {
+        let gamma = (1.0 + relative_accuracy) / (1.0 - relative_accuracy);
+        DdSketch {
+            gamma,
+            ln_gamma: gamma.ln(),
+            positive: BTreeMap::new(),
+            negative: BTreeMap::new(),
+            zeros: 0,
+            count: 0,
+        }
+    }
+
+    pub fn insert(&mut self, value: f64) {
+        match self.bucket(value) {
+            Some((buckets, index)) => *buckets.entry(index).or_insert(0) += 1,
+            None => self.zeros += 1,
+        }
+        self.count += 1;
+    }
+
+    pub fn extend(&mut self, values: &[f64]) {
+        values.iter().for_each(|&value| self.insert(value));
+    }
+
+    /// Removes a value that was previously inserted.
+    pub fn remove(&mut self, value: f64) {
+        match self.bucket(value) {
+            Some((buckets, index)) => {
+                if let Some(count) = buckets.get_mut(&index) {
+                    *count -= 1;
+                    if *count == 0 {
+                        buckets.remove(&index);
+                    }
+                }
+            }
+            None => self.zeros = self.zeros.saturating_sub(1),
+        }
+        self.count = self.count.saturating_sub(1);
+    }
+
+    fn bucket(&mut self, value: f64) -> Option<(&mut BTreeMap<i32, u64>, i32)> {
+        if value.abs() < DD_MIN_INDEXABLE {
+            return None;
+        }
+        let index = (value.abs().ln() / self.ln_gamma).ceil() as i32;
+        let buckets = if value > 0.0 { &mut self.positive } else { &mut self.negative };
+        Some((buckets, index))
+    }
+
+    /// Representative value of bucket `index`, within the relative accuracy
+    /// of everything in it.
+    fn value_of(&self, index: i32) -> f64 {
+        2.0 * self.gamma.powi(index) / (self.gamma + 1.0)
+    }
+
+    /// Estimated value at quantile `q` (0.0..=1.0); 0.0 when empty.
+    pub fn quantile(&self, q: f64) -> f64 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        let rank = (q.clamp(0.0, 1.0) * (self.count - 1) as f64).floor() as u64;
+        let mut seen = 0;
+        for (&index, &count) in self.negative.iter().rev() {
+            seen += count;
+            if seen > rank {
+                return -self.value_of(index);
+            }
+        }
+        seen += self.zeros;
+        if seen > rank {
+            return 0.0;
+        }
+        for (&index, &count) in &self.positive {
+            seen += count;
+            if seen > rank {
+                return self.value_of(index);
+            }
+        }
+        self.positive.keys().next_back().map_or(0.0, |&index| self.value_of(index))
+    }
+}
+
+/// Sketch used for the window quantiles of windows at or above `from_k`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QuantileSketch {
+    /// Block-based sliding t-digest: accurate in the tails, bounded memory.
+    #[default]
+    TDigest,
+    /// DDSketch with a relative-error guarantee and exact eviction.
+    DdSketch,
+}
+
+/// How a symbol's windows answer quantile queries. Windows smaller than
+/// 10^`from_k` sort their values for exact quantiles; larger ones use `sketch`.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct QuantileConfig {
+    #[serde(default)]
+    pub sketch: QuantileSketch,
+    /// Relative accuracy of the DDSketch, in (0, 1).
+    #[serde(default = "default_relative_accuracy")]
+    pub relative_accuracy: f64,
+    /// Smallest window exponent that uses a sketch, 1 to 8.
+    #[serde(default = "default_sketch_from_k")]
+    pub from_k: usize,
+}
+
+fn default_relative_accuracy() -> f64 {
+    0.01
+}
+
+fn default_sketch_from_k() -> usize {
+    4
+}
+
+impl Default for QuantileConfig {
+    fn default() -> Self {
+        QuantileConfig {
+            sketch: QuantileSketch::default(),
+            relative_accuracy: default_relative_accuracy(),
+            from_k: default_sketch_from_k(),
+        }
+    }
+}
+
+impl QuantileConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.relative_accuracy > 0.0 && self.relative_accuracy < 1.0) {
+            return Err("Invalid relative_accuracy input. Only values in (0, 1) are accepted.".to_string());
+        }
+        if !(1..=8).contains(&self.from_k) {
+            return Err("Invalid quantiles from_k input. Only values 1-8 are accepted.".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Quantile state of one window, chosen by its capacity and the symbol's
+/// `QuantileConfig`.
+#[derive(Debug, Clone)]
+pub enum WindowQuantiles {
+    /// No state; quantiles are read from a sorted copy of the window.
+    Exact,
+    TDigest(SlidingQuantiles),
+    DdSketch(DdSketch),
+}
+
+impl WindowQuantiles {
+    pub fn new(config: &QuantileConfig, capacity: usize) -> Self {
+        Self::with_sketch(config, capacity, config.sketch)
+    }
+
+    /// For windows that also evict by age. The block-based t-digest can
+    /// only forget values by count, so those windows use a DDSketch instead.
+    pub fn evicting_by_age(config: &QuantileConfig, capacity: usize) -> Self {
+        Self::with_sketch(config, capacity, QuantileSketch::DdSketch)
+    }
+
+    fn with_sketch(config: &QuantileConfig, capacity: usize, sketch: QuantileSketch) -> Self {
+        if capacity < 10usize.pow(config.from_k as u32) {
+            return WindowQuantiles::Exact;
+        }
+        match sketch {
+            QuantileSketch::TDigest => WindowQuantiles::TDigest(SlidingQuantiles::new(capacity)),
+            QuantileSketch::DdSketch => WindowQuantiles::DdSketch(DdSketch::new(config.relative_accuracy)),
+        }
+    }
+
+    /// Whether quantiles are read from the window's values rather than a
+    /// sketch.
+    pub fn is_exact(&self) -> bool {
+        matches!(self, WindowQuantiles::Exact)
+    }
+
+    pub fn insert(&mut self, value: f64) {
+        match self {
+            WindowQuantiles::Exact => {}
+            WindowQuantiles::TDigest(sketch) => sketch.insert(value),
+            WindowQuantiles::DdSketch(sketch) => sketch.insert(value),
+        }
+    }
+
+    pub fn extend(&mut self, values: &[f64]) {
+        match self {
+            WindowQuantiles::Exact => {}
+            WindowQuantiles::TDigest(sketch) => sketch.extend(values),
+            WindowQuantiles::DdSketch(sketch) => sketch.extend(values),
+        }
+    }
+
+    /// Evicts the window's oldest value. The t-digest ages out whole blocks
+    /// on insert instead.
+    pub fn remove(&mut self, value: f64) {
+        if let WindowQuantiles::DdSketch(sketch) = self {
+            sketch.remove(value);
+        }
+    }
+
+    pub fn remove_many(&mut self, values: &[f64]) {
+        if let WindowQuantiles::DdSketch(sketch) = self {
+            values.iter().for_each(|&value| sketch.remove(value));
+        }
+    }
+
+    /// Quantiles of the window, whose current contents are `values`.
+    pub fn quantiles(&self, qs: &[f64], values: impl Iterator<Item = f64>) -> Vec<f64> {
+        match self {
+            WindowQuantiles::Exact => {
+                let mut sorted: Vec<f64> = values.collect();
+                sorted.sort_by(f64::total_cmp);
+                qs.iter().map(|&q| exact_quantile(&sorted, q)).collect()
+            }
+            WindowQuantiles::TDigest(sketch) => sketch.quantiles(qs),
+            WindowQuantiles::DdSketch(sketch) => qs.iter().map(|&q| sketch.quantile(q)).collect(),
+        }
+    }
+}
+
+/// Linearly interpolated quantile of sorted values; 0.0 when empty.
+fn exact_quantile(sorted: &[f64], q: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = q.clamp(0.0, 1.0) * (sorted.len() - 1) as f64;
+    let lo = rank.floor() as usize;
+    let hi = rank.ceil() as usize;
+    sorted[lo] + (sorted[hi] - sorted[lo]) * (rank - lo as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tdigest_uniform_quantiles() {
+        let mut digest = TDigest::new(COMPRESSION);
+        for i in 1..=10_000 {
+            digest.insert(i as f64);
+        }
+        digest.compress();
+
+        assert!((digest.quantile(0.5) - 5000.0).abs() < 50.0);
+        assert!((digest.quantile(0.99) - 9900.0).abs() < 20.0);
+        assert_eq!(1.0, digest.quantile(0.0));
+        assert_eq!(10_000.0, digest.quantile(1.0));
+    }
+
+    #[test]
+    fn test_sliding_quantiles_forget_old_values() {
+        let mut sketch = SlidingQuantiles::new(100);
+        for _ in 0..1000 {
+            sketch.insert(1000.0);
+        }
+        for i in 0..100 {
+            sketch.insert(i as f64);
+        }
+
+        let q = sketch.quantiles(&[0.5]);
+        assert!(q[0] < 100.0, "median {} still sees evicted values", q[0]);
+    }
+
+    #[test]
+    fn test_sliding_quantiles_extend_matches_inserts() {
+        let values: Vec<f64> = (0..5000).map(|i| ((i * 7919) % 1000) as f64).collect();
+        let (mut inserted, mut extended) = (SlidingQuantiles::new(2000), SlidingQuantiles::new(2000));
+        values.iter().for_each(|&v| inserted.insert(v));
+        extended.extend(&values[..7]);
+        extended.extend(&values[7..]);
+
+        let qs = [0.01, 0.5, 0.99];
+        assert_eq!(inserted.quantiles(&qs), extended.quantiles(&qs));
+        assert_eq!(inserted.count, extended.count);
+    }
+
+    #[test]
+    fn test_ddsketch_relative_accuracy() {
+        let mut sketch = DdSketch::new(0.01);
+        for i in 1..=10_000 {
+            sketch.insert(i as f64);
+        }
+        for q in [0.01f64, 0.5, 0.99] {
+            let expected = (q * 9999.0).floor() + 1.0;
+            let estimate = sketch.quantile(q);
+            assert!((estimate - expected).abs() <= expected * 0.01, "q{} = {}", q, estimate);
+        }
+    }
+
+    #[test]
+    fn test_ddsketch_remove_and_negatives() {
+        let mut sketch = DdSketch::new(0.01);
+        for v in [-5.0, 0.0, 3.0, 1000.0] {
+            sketch.insert(v);
+        }
+        sketch.remove(1000.0);
+        assert!((sketch.quantile(0.0) + 5.0).abs() < 0.05);
+        assert_eq!(0.0, sketch.quantile(0.5));
+        assert!((sketch.quantile(1.0) - 3.0).abs() < 0.03);
+    }
+
+    #[test]
+    fn test_exact_window_quantiles() {
+        let quantiles = WindowQuantiles::new(&QuantileConfig::default(), 10);
+        let values = [4.0, 1.0, 3.0, 2.0, 5.0];
+        assert_eq!(vec![1.0, 3.0, 4.5], quantiles.quantiles(&[0.0, 0.5, 0.875], values.into_iter()));
+    }
+}
@@ -0,0 +1,329 @@
+//! Streamed uploads to `/add_batch?stream=true`: one batch in the
+//! `/add_batch` object form, of any size, whose `values` or `points` are
+//! added in parts of up to `max_batch_size` as they are parsed, so memory
+//! stays bounded however large the body.
+//!
+//! The body takes `symbol`, optionally `venue`, then one `values` or
+//! `points` array; per-value data goes on the points.
+
+use actix_web::dev::Decompress;
+use actix_web::guard::GuardContext;
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use futures::StreamExt;
+use serde::Serialize;
+use trading_service::{Batch, Point, ServiceError};
+
+use crate::body::check_encoding;
+use crate::{error_response, Tenant, IDEMPOTENCY_KEY_HEADER};
+
+/// Largest single member or array element of an upload, in bytes.
+const MAX_ELEMENT_BYTES: usize = 64 * 1024;
+
+/// Whether a request to `/add_batch` asks for a streamed upload.
+pub fn is_streamed(ctx: &GuardContext) -> bool {
+    ctx.head().uri.query().is_some_and(|query| query.split('&').any(|param| param == "stream=true"))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Array {
+    Values,
+    Points,
+}
+
+#[derive(Debug, Default, PartialEq)]
+enum State {
+    #[default]
+    Start,
+    Key,
+    Colon(String),
+    Member(String),
+    AfterMember,
+    Element(Array),
+    AfterElement(Array),
+    Done,
+}
+
+fn is_whitespace(byte: u8) -> bool {
+    matches!(byte, b' ' | b'\t' | b'\r' | b'\n')
+}
+
+/// The length of the JSON value the bytes start with, `None` until it's
+/// complete.
+fn value_len(bytes: &[u8]) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, &byte) in bytes.iter().enumerate() {
+        if in_string {
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => {
+                    in_string = false;
+                    if depth == 0 {
+                        return Some(i + 1);
+                    }
+                }
+                _ => {}
+            }
+            continue;
+        }
+        match byte {
+            b'"' => in_string = true,
+            b'{' | b'[' => depth += 1,
+            b'}' | b']' if depth > 0 => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i + 1);
+                }
+            }
+            // A number or literal ends where the next token starts.
+            b',' | b'}' | b']' if depth == 0 => return Some(i),
+            _ if depth == 0 && is_whitespace(byte) => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// An upload parsed as it arrives, its values gathered into batches.
+#[derive(Debug, Default)]
+pub struct Upload {
+    state: State,
+    pending: Vec<u8>,
+    symbol: Option<String>,
+    venue: Option<String>,
+    streamed: bool,
+    values: Vec<f64>,
+    points: Vec<Point>,
+    /// Batches completed and not yet taken.
+    ready: Vec<Batch>,
+}
+
+impl Upload {
+    pub fn symbol(&self) -> Option<&str> {
+        self.symbol.as_deref()
+    }
+
+    /// The batches completed so far, of `batch_size` values but the last.
+    pub fn batches(&mut self) -> Vec<Batch> {
+        std::mem::take(&mut self.ready)
+    }
+
+    /// Parses the next chunk. On an error the batches completed before it
+    /// are still there to take.
+    pub fn feed(&mut self, chunk: &[u8], batch_size: usize) -> Result<(), String> {
+        let mut pending = std::mem::take(&mut self.pending);
+        pending.extend_from_slice(chunk);
+        let mut pos = 0;
+        while let Some(used) = self.step(&pending[pos..])? {
+            pos += used;
+            if self.values.len() + self.points.len() >= batch_size {
+                let batch = self.take()?;
+                self.ready.push(batch);
+            }
+        }
+        pending.drain(..pos);
+        self.pending = pending;
+        if self.pending.len() > MAX_ELEMENT_BYTES {
+            return Err(format!("Invalid streamed upload. A member or element exceeds {} bytes.", MAX_ELEMENT_BYTES));
+        }
+        Ok(())
+    }
+
+    /// Checks the body ended where the object did, completing a batch of
+    /// the values left over.
+    pub fn finish(&mut self) -> Result<(), String> {
+        if self.state != State::Done || !self.pending.iter().copied().all(is_whitespace) {
+            return Err("Invalid streamed upload. The body ended before the object did.".to_string());
+        }
+        if !self.streamed {
+            return Err("Invalid streamed upload. Expected values or points.".to_string());
+        }
+        if !self.values.is_empty() || !self.points.is_empty() {
+            let batch = self.take()?;
+            self.ready.push(batch);
+        }
+        Ok(())
+    }
+
+    fn take(&mut self) -> Result<Batch, String> {
+        let batch = if self.points.is_empty() {
+            Batch { values: std::mem::take(&mut self.values), ..Batch::default() }
+        } else {
+            Batch::from_points(&std::mem::take(&mut self.points))?
+        };
+        Ok(Batch { venue: self.venue.clone(), ..batch })
+    }
+
+    /// Takes one token or value off the bytes, returning how many it used,
+    /// or `None` when they end before it does.
+    fn step(&mut self, bytes: &[u8]) -> Result<Option<usize>, String> {
+        let invalid = |expected: &str| Err(format!("Invalid streamed upload. Expected {}.", expected));
+        let skipped = bytes.iter().take_while(|&&byte| is_whitespace(byte)).count();
+        let Some(&next) = bytes.get(skipped) else {
+            return Ok(None);
+        };
+        let rest = &bytes[skipped..];
+        let state = std::mem::replace(&mut self.state, State::Done);
+        let (state, used) = match state {
+            State::Done => return Ok(None),
+            State::Start if next == b'{' => (State::Key, 1),
+            State::Start => return invalid("an object"),
+            State::Key | State::AfterMember if next == b'}' => (State::Done, 1),
+            State::AfterMember if next == b',' => (State::Key, 1),
+            State::AfterMember => return invalid("',' or '}'"),
+            State::Key if next != b'"' => return invalid("a member name"),
+            State::Key => match value_len(rest) {
+                None => (State::Key, 0),
+                Some(len) => {
+                    let key = serde_json::from_slice(&rest[..len]).map_err(|e| format!("Invalid streamed upload: {}", e))?;
+                    (State::Colon(key), len)
+                }
+            },
+            State::Colon(key) if next == b':' => (State::Member(key), 1),
+            State::Colon(_) => return invalid("':'"),
+            State::Member(key) if key == "values" || key == "points" => {
+                if next != b'[' {
+                    return invalid(&format!("an array of {}", key));
+                }
+                if self.symbol.is_none() {
+                    return Err(format!("Invalid streamed upload. symbol must come before {}.", key));
+                }
+                if self.streamed {
+                    return Err("Invalid streamed upload. Send either values or points, not both.".to_string());
+                }
+                self.streamed = true;
+                (State::Element(if key == "values" { Array::Values } else { Array::Points }), 1)
+            }
+            State::Member(key) => match value_len(rest) {
+                None => (State::Member(key), 0),
+                Some(len) => {
+                    let value = &rest[..len];
+                    let invalid_member = |e: serde_json::Error| format!("Invalid {} input: {}", key, e);
+                    match key.as_str() {
+                        "symbol" => self.symbol = Some(serde_json::from_slice(value).map_err(invalid_member)?),
+                        "venue" => self.venue = serde_json::from_slice(value).map_err(invalid_member)?,
+                        _ => {
+                            let e = format!("{} can't be streamed. Give per-value data on points.", key);
+                            return Err(format!("Invalid streamed upload. {}", e));
+                        }
+                    }
+                    (State::AfterMember, len)
+                }
+            },
+            State::Element(_) | State::AfterElement(_) if next == b']' => (State::AfterMember, 1),
+            State::AfterElement(array) if next == b',' => (State::Element(array), 1),
+            State::AfterElement(_) => return invalid("',' or ']'"),
+            State::Element(array) => match value_len(rest) {
+                None => (State::Element(array), 0),
+                Some(len) => {
+                    let element = &rest[..len];
+                    let parsed = match array {
+                        Array::Values => serde_json::from_slice(element).map(|value| self.values.push(value)),
+                        Array::Points => serde_json::from_slice(element).map(|point| self.points.push(point)),
+                    };
+                    parsed.map_err(|e| format!("Invalid streamed upload: {}", e))?;
+                    (State::AfterElement(array), len)
+                }
+            },
+        };
+        let incomplete = used == 0;
+        self.state = state;
+        Ok(if incomplete { None } else { Some(skipped + used) })
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+struct UploadSummary {
+    /// Values added, which stay added if the upload fails part way.
+    added: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<&'static str>,
+}
+
+/// Adds a streamed upload part by part, answering with the values added
+/// and, if it failed part way, why.
+pub async fn add_batch_stream(service: Tenant, http_req: HttpRequest, payload: web::Payload) -> impl Responder {
+    if http_req.headers().contains_key(IDEMPOTENCY_KEY_HEADER) {
+        return error_response("Idempotency-Key doesn't apply to streamed uploads, which are added in parts".to_string());
+    }
+    if http_req.query_string().split('&').any(|param| param == "dry_run=true") {
+        return error_response("dry_run doesn't apply to streamed uploads, which are added in parts".to_string());
+    }
+    if let Err(e) = check_encoding(&http_req) {
+        return error_response(e);
+    }
+    let mut stream = Decompress::from_headers(payload.into_inner(), http_req.headers());
+    let batch_size = service.settings().max_batch_size;
+    let mut upload = Upload::default();
+    let mut summary = UploadSummary::default();
+
+    let error = loop {
+        let (parsed, last) = match stream.next().await {
+            Some(Ok(chunk)) => (upload.feed(&chunk, batch_size), false),
+            Some(Err(e)) => (Err(format!("Invalid request body: {}", e)), true),
+            None => (upload.finish(), true),
+        };
+        let symbol = upload.symbol().unwrap_or_default().to_string();
+        let mut failed = None;
+        for batch in upload.batches() {
+            let len = batch.values.len();
+            if let Err(e) = service.add_batch(symbol.clone(), batch).await {
+                failed = Some(e);
+                break;
+            }
+            summary.added += len;
+        }
+        let failed = failed.or(parsed.err().map(ServiceError::from));
+        if failed.is_some() || last {
+            break failed;
+        }
+    };
+    let mut response = HttpResponse::Ok();
+    if let Some(e) = error {
+        response = HttpResponse::build(error_response(e.clone()).status());
+        summary.error = Some(e.to_string());
+        summary.code = Some(e.code());
+    }
+    response.json(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feeds the body a few bytes at a time.
+    fn parse(body: &str, batch_size: usize) -> Result<Vec<Batch>, String> {
+        let mut upload = Upload::default();
+        for chunk in body.as_bytes().chunks(3) {
+            upload.feed(chunk, batch_size)?;
+        }
+        upload.finish()?;
+        Ok(upload.batches())
+    }
+
+    #[test]
+    fn test_upload() {
+        let batches = parse(r#" {"symbol": "A\"B", "venue": "X", "values": [1, 2.5, -3e2 , 4,5]} "#, 2).unwrap();
+        let values: Vec<Vec<f64>> = batches.iter().map(|batch| batch.values.clone()).collect();
+        assert_eq!(vec![vec![1.0, 2.5], vec![-300.0, 4.0], vec![5.0]], values);
+        assert_eq!(Some("X".to_string()), batches[2].venue);
+
+        let batches = parse(r#"{"symbol": "A", "points": [{"value": 1, "timestamp": 5}, {"value": 2, "timestamp": 6}]}"#, 10);
+        assert_eq!(Some(vec![5, 6]), batches.unwrap()[0].timestamps);
+
+        assert_eq!(0, parse(r#"{"symbol": "A", "values": []}"#, 2).unwrap().len());
+        assert!(parse(r#"{"values": [1], "symbol": "A"}"#, 2).is_err());
+        assert!(parse(r#"{"symbol": "A", "weights": [1], "values": [1]}"#, 2).is_err());
+        assert!(parse(r#"{"symbol": "A", "values": [1, "x"]}"#, 2).is_err());
+        assert!(parse(r#"{"symbol": "A", "values": [1, 2"#, 2).is_err());
+
+        // Batches completed before an error are kept.
+        let mut upload = Upload::default();
+        assert!(upload.feed(br#"{"symbol": "A", "values": [1, 2, 3, "x"]}"#, 2).is_err());
+        assert_eq!(vec![vec![1.0, 2.0]], upload.batches().into_iter().map(|batch| batch.values).collect::<Vec<_>>());
+    }
+}
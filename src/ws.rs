@@ -0,0 +1,291 @@
+//! WebSocket pushes of window stats to subscribed clients.
+//!
+//! Clients send `{"action": "subscribe", "symbol": "AAPL", "k": 3}` (with an
+//! optional `max_hz`) or `{"action": "unsubscribe", "symbol": "AAPL", "k": 3}`
+//! as text messages. Each subscription then gets a `stats` message whenever
+//! the symbol's windows change, at most `max_hz` times a second; changes in
+//! between are folded into the next push.
+//!
+//! A subscription with a `when` condition over window stats, e.g.
+//! `{"action": "subscribe", "symbol": "AAPL", "k": 2, "when": "abs(avg_2 -
+//! avg_6) > 3 * std_dev_6"}`, is confirmed with a `subscribed` message and
+//! only pushed when the condition comes to hold, checked on every change.
+//!
+//! Clients can also add data over the same socket with `{"action":
+//! "add_batch", "seq": 1, ...}` frames in the `/add_batch` object form, each
+//! acknowledged with its sequence number once added.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_ws::{Message, Session};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::Instant;
+use trading_service::expr::Condition;
+use trading_service::{ServiceError, StatsResponse, TradingDataService, CONDITION_STATS};
+
+use crate::body::MAX_BODY_BYTES;
+use crate::{error_response, to_batch, AddBatchRequest, Tenant};
+
+/// Most subscriptions one connection holds.
+const MAX_SUBSCRIPTIONS: usize = 100;
+
+/// Highest push rate a subscription can ask for.
+const MAX_HZ: f64 = 1000.0;
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+enum Request {
+    Subscribe {
+        symbol: String,
+        k: u8,
+        /// Most pushes per second; every change is pushed when absent.
+        max_hz: Option<f64>,
+        /// Condition the window is only pushed when it comes to hold.
+        when: Option<String>,
+    },
+    Unsubscribe {
+        symbol: String,
+        k: u8,
+    },
+    AddBatch {
+        /// Number the ack echoes; frames without one are numbered in order.
+        seq: Option<u64>,
+        #[serde(flatten)]
+        batch: Box<AddBatchRequest>,
+    },
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Push<'a> {
+    Stats { symbol: &'a str, k: u8, stats: Box<StatsResponse> },
+    Subscribed { symbol: &'a str, k: u8 },
+    Unsubscribed { symbol: &'a str, k: u8 },
+    Ack { seq: u64 },
+    Error {
+        /// Sequence number of the failed `add_batch` frame.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        seq: Option<u64>,
+        error: String,
+        code: &'static str,
+    },
+}
+
+impl Push<'_> {
+    fn error(e: ServiceError) -> Push<'static> {
+        Push::Error { seq: None, error: e.to_string(), code: e.code() }
+    }
+}
+
+struct Subscription {
+    min_gap: Duration,
+    last_sent: Instant,
+    /// Whether the window changed since the last push.
+    dirty: bool,
+    condition: Option<Condition>,
+}
+
+impl Subscription {
+    fn due_at(&self) -> Instant {
+        self.last_sent + self.min_gap
+    }
+}
+
+/// A connection's subscriptions, keyed by canonical symbol and k.
+#[derive(Default)]
+struct Subscriptions(HashMap<(String, u8), Subscription>);
+
+/// A connection's state.
+#[derive(Default)]
+struct Connection {
+    subscriptions: Subscriptions,
+    /// `add_batch` frames received so far.
+    batches: u64,
+}
+
+impl Subscriptions {
+    fn mark(&mut self, symbol: Option<&str>) {
+        for ((subscribed, _), subscription) in self.0.iter_mut() {
+            if symbol.is_none_or(|symbol| symbol == subscribed) {
+                subscription.dirty = true;
+            }
+        }
+    }
+
+    /// When the earliest changed window may be pushed next.
+    fn next_due(&self) -> Option<Instant> {
+        self.0.values().filter(|s| s.dirty).map(Subscription::due_at).min()
+    }
+
+    fn take_due(&mut self, now: Instant) -> Vec<(String, u8)> {
+        let mut due = Vec::new();
+        for (key, subscription) in self.0.iter_mut() {
+            if subscription.dirty && subscription.due_at() <= now {
+                subscription.dirty = false;
+                subscription.last_sent = now;
+                due.push(key.clone());
+            }
+        }
+        due
+    }
+}
+
+async fn send(session: &mut Session, push: &Push<'_>) -> bool {
+    let text = serde_json::to_string(push).unwrap_or_default();
+    session.text(text).await.is_ok()
+}
+
+async fn handle(
+    service: &TradingDataService,
+    connection: &mut Connection,
+    session: &mut Session,
+    request: Request,
+) -> bool {
+    let subscriptions = &mut connection.subscriptions;
+    match request {
+        Request::Subscribe { symbol, k, max_hz, when } => {
+            if max_hz.is_some_and(|hz| !(hz > 0.0 && hz <= MAX_HZ)) {
+                let e = format!("Invalid max_hz input. Only values above 0 up to {} are accepted.", MAX_HZ);
+                return send(session, &Push::error(e.into())).await;
+            }
+            let condition = match when {
+                Some(when) => match Condition::parse(&when, |stat| CONDITION_STATS.contains(&stat)) {
+                    Ok(condition) => Some(condition),
+                    Err(e) => return send(session, &Push::error(e.into())).await,
+                },
+                None => None,
+            };
+            let symbol = service.canonical(symbol);
+            let key = (symbol.clone(), k);
+            if !subscriptions.0.contains_key(&key) && subscriptions.0.len() >= MAX_SUBSCRIPTIONS {
+                let e = format!("Maximum of {} subscriptions reached", MAX_SUBSCRIPTIONS);
+                return send(session, &Push::error(ServiceError::LimitReached(e))).await;
+            }
+            // The first push doubles as the subscription's confirmation.
+            let stats = match service.get_stats(symbol.clone(), k as usize).await {
+                Ok(stats) => stats,
+                Err(e) => return send(session, &Push::error(e)).await,
+            };
+            let min_gap = max_hz.map_or(Duration::ZERO, |hz| Duration::from_secs_f64(1.0 / hz));
+            let conditional = condition.is_some();
+            // Checked right away, so a condition that already holds is pushed.
+            let subscription = Subscription { min_gap, last_sent: Instant::now(), dirty: conditional, condition };
+            subscriptions.0.insert(key, subscription);
+            if conditional {
+                send(session, &Push::Subscribed { symbol: &symbol, k }).await
+            } else {
+                send(session, &Push::Stats { symbol: &symbol, k, stats: Box::new(stats) }).await
+            }
+        }
+        Request::Unsubscribe { symbol, k } => {
+            let symbol = service.canonical(symbol);
+            match subscriptions.0.remove(&(symbol.clone(), k)) {
+                Some(_) => send(session, &Push::Unsubscribed { symbol: &symbol, k }).await,
+                None => {
+                    let e = ServiceError::NotFound(format!("No subscription to {} with k {}", symbol, k));
+                    send(session, &Push::error(e)).await
+                }
+            }
+        }
+        Request::AddBatch { seq, batch: req } => {
+            connection.batches += 1;
+            let seq = seq.unwrap_or(connection.batches);
+            let (symbol, batch) = to_batch(*req);
+            let result = match batch {
+                Ok(batch) => service.add_batch(symbol, batch).await,
+                Err(e) => Err(e.into()),
+            };
+            let push = match result {
+                Ok(_) => Push::Ack { seq },
+                Err(e) => Push::Error { seq: Some(seq), error: e.to_string(), code: e.code() },
+            };
+            send(session, &push).await
+        }
+    }
+}
+
+/// Pushes the changed windows that are due and whose condition, if any,
+/// just came to hold, dropping subscriptions whose symbol went away.
+async fn flush(service: &TradingDataService, subscriptions: &mut Subscriptions, session: &mut Session) -> bool {
+    for (symbol, k) in subscriptions.take_due(Instant::now()) {
+        let key = (symbol.clone(), k);
+        if let Some(condition) = subscriptions.0.get_mut(&key).and_then(|s| s.condition.as_mut()) {
+            match service.check_condition(symbol.clone(), condition).await {
+                Ok(true) => {}
+                Ok(false) => continue,
+                Err(e) => {
+                    subscriptions.0.remove(&key);
+                    if !send(session, &Push::error(e)).await {
+                        return false;
+                    }
+                    continue;
+                }
+            }
+        }
+        let push = match service.get_stats(symbol.clone(), k as usize).await {
+            Ok(stats) => Push::Stats { symbol: &symbol, k, stats: Box::new(stats) },
+            Err(e) => {
+                subscriptions.0.remove(&(symbol.clone(), k));
+                Push::error(e)
+            }
+        };
+        if !send(session, &push).await {
+            return false;
+        }
+    }
+    true
+}
+
+async fn serve(service: &TradingDataService, mut session: Session, mut messages: actix_ws::MessageStream) {
+    let mut updates = service.subscribe();
+    let mut connection = Connection::default();
+    loop {
+        let next_due = connection.subscriptions.next_due();
+        let open = tokio::select! {
+            message = messages.recv() => match message {
+                Some(Ok(Message::Text(text))) => match serde_json::from_str::<Request>(&text) {
+                    Ok(request) => handle(service, &mut connection, &mut session, request).await,
+                    Err(e) => send(&mut session, &Push::error(format!("Invalid message: {}", e).into())).await,
+                },
+                Some(Ok(Message::Ping(bytes))) => session.pong(&bytes).await.is_ok(),
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => false,
+                Some(Ok(_)) => true,
+            },
+            update = updates.recv() => match update {
+                Ok(symbol) => {
+                    connection.subscriptions.mark(Some(&symbol));
+                    true
+                }
+                // Notices were missed, so any window may have changed.
+                Err(RecvError::Lagged(_)) => {
+                    connection.subscriptions.mark(None);
+                    true
+                }
+                Err(RecvError::Closed) => false,
+            },
+            _ = tokio::time::sleep_until(next_due.unwrap_or_else(Instant::now)), if next_due.is_some() => true,
+        };
+        if !open || !flush(service, &mut connection.subscriptions, &mut session).await {
+            break;
+        }
+    }
+    let _ = session.close(None).await;
+}
+
+/// Upgrades the request to a WebSocket serving stats subscriptions and
+/// batch ingestion.
+pub async fn stats_ws(service: Tenant, req: HttpRequest, body: web::Payload) -> HttpResponse {
+    match actix_ws::handle(&req, body) {
+        Ok((response, session, messages)) => {
+            // Big enough for a full batch in one frame.
+            let messages = messages.max_frame_size(MAX_BODY_BYTES);
+            let service = service.0;
+            actix_web::rt::spawn(async move { serve(&service, session, messages).await });
+            response
+        }
+        Err(e) => error_response(format!("Invalid WebSocket request: {}", e)),
+    }
+}